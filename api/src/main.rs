@@ -1,16 +1,23 @@
-use std::{net::SocketAddr, panic};
+use std::{io::IsTerminal, net::SocketAddr, panic};
 
 use anyhow::{Context, Result};
 use axum::{Router, routing::get};
 use chrono::{DateTime, Utc};
 use tokio::net;
 use tower_http::trace::TraceLayer;
-use tracing::info;
+use tracing::{info, warn};
 
-use common::{ServerConfig, init_tracing, load_env};
+use common::{
+    LocalCaConfig, MqttConfig, ServerConfig, StationAuthConfig, StorageConfig, TlsConfig,
+    init_tracing, load_env,
+};
 
-use occp_ws::routes::{healthcheck_route, upgrade_to_ws};
-use occp_ws::state::START_TIME;
+use occp_ws::auth::StationCredentials;
+use occp_ws::pki::LocalCa;
+use occp_ws::routes::{
+    ca_certificate_route, healthcheck_route, registration_redirects, upgrade_to_ws,
+};
+use occp_ws::state::{START_TIME, STATION_CREDENTIALS};
 
 async fn run() -> Result<()> {
     async fn time_now() -> DateTime<Utc> {
@@ -27,27 +34,212 @@ async fn run() -> Result<()> {
     }));
 
     let config = ServerConfig::from_env()?;
+    let tls_config = TlsConfig::from_env()?;
+    let local_ca_config = LocalCaConfig::from_env()?;
+    let mqtt_config = MqttConfig::from_env()?;
+    let station_auth_config = StationAuthConfig::from_env()?;
+    // Refuse to start rather than let stations in without their passwords.
+    let credentials = StationCredentials::load(
+        station_auth_config.required,
+        station_auth_config.credentials_file,
+    )
+    .context("Failed to read STATION_CREDENTIALS_FILE")?;
+    STATION_CREDENTIALS.set(credentials).ok();
     let tcp_listener = net::TcpListener::bind(config.socket_addr())
         .await
         .with_context(|| format!("Failed to bind to address: {}", config.socket_addr()))?;
     info!("Server listening on {}", config.socket_addr());
 
+    let storage_config = StorageConfig::from_env();
+    occp_ws::persistence::open(&storage_config).with_context(|| {
+        format!(
+            "Failed to open database {}",
+            storage_config.database_path.display()
+        )
+    })?;
+    let restored = occp_ws::persistence::restore().context("Failed to restore saved state")?;
+    info!(
+        "Restored {} station(s), {} connector(s) and {} transaction(s), {} open",
+        restored.stations, restored.connectors, restored.transactions, restored.open_transactions
+    );
+    if restored.webhooks > 0 {
+        info!(
+            "Restored {} webhook(s) with {} delivery(ies) still to send",
+            restored.webhooks, restored.pending_deliveries
+        );
+    }
+    if restored.api_tokens == 0 && restored.users == 0 {
+        warn!(
+            "No API tokens or users yet, create one with `create-api-token <name>` or \
+             `create-owner <username>` to use /api/v1"
+        );
+    }
+
+    occp_ws::presence::spawn_watchdog(occp_ws::presence::DEFAULT_WATCHDOG_PERIOD);
+    let resync_stations = occp_ws::resync::schedule_startup_resync();
+    if resync_stations > 0 {
+        info!("{resync_stations} station(s) with open transactions will be resynced on connect");
+    }
+
+    if let Some(mqtt_config) = mqtt_config {
+        occp_ws::mqtt::spawn(mqtt_config);
+    }
+    occp_ws::webhooks::spawn();
+    occp_ws::audit::spawn_pruner();
+    occp_ws::api_tokens::spawn_usage_flusher();
+
     let router = Router::new()
         .route("/:station_id", get(upgrade_to_ws))
+        .route("/pki/ca.pem", get(ca_certificate_route))
+        .nest("/api/v1", occp_ws::rest::v1_router_with_auth())
+        .merge(registration_redirects())
         .route("/", get(healthcheck_route))
         .layer(TraceLayer::new_for_http());
 
-    axum::serve(
-        tcp_listener,
-        router.into_make_service_with_connect_info::<SocketAddr>(),
-    )
-    .await
-    .with_context(|| format!("Failed to start server on {}", config.socket_addr()))?;
+    let tls_server_config = match tls_config {
+        Some(tls_config) => Some(
+            occp_ws::tls::server_config(&tls_config).context("Failed to load TLS configuration")?,
+        ),
+        None if local_ca_config.enabled => Some(
+            occp_ws::pki::server_config(&local_ca_config)
+                .context("Failed to set up the local CA")?,
+        ),
+        None => None,
+    };
+
+    let server = async {
+        match tls_server_config {
+            Some(server_config) => occp_ws::tls::serve(tcp_listener, router, server_config).await,
+            None => {
+                axum::serve(
+                    tcp_listener,
+                    router.into_make_service_with_connect_info::<SocketAddr>(),
+                )
+                .await
+            }
+        }
+        .with_context(|| format!("Failed to start server on {}", config.socket_addr()))
+    };
+
+    // Open WebSockets would hold up a graceful axum shutdown forever, so stop
+    // serving outright, then save token usage and the queued writes.
+    tokio::select! {
+        result = server => result?,
+        () = shutdown_signal() => info!("Shutting down"),
+    }
+    occp_ws::api_tokens::flush_usage();
+    occp_ws::persistence::flush();
+
+    Ok(())
+}
+
+/// Resolve on Ctrl-C or SIGTERM, e.g. from systemd.
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        if let Err(err) = tokio::signal::ctrl_c().await {
+            warn!("Failed to listen for Ctrl-C: {err}");
+            std::future::pending::<()>().await;
+        }
+    };
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut signal) => {
+                signal.recv().await;
+            }
+            Err(err) => {
+                warn!("Failed to listen for SIGTERM: {err}");
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        () = ctrl_c => {}
+        () = terminate => {}
+    }
+}
 
+/// Print the argon2 hash of a station password for `STATION_CREDENTIALS_FILE`.
+/// The password is read from stdin, like the one for `create-owner`.
+fn hash_password() -> Result<()> {
+    let hash = occp_ws::auth::hash_password(&read_password()?)?;
+    println!("{hash}");
     Ok(())
 }
 
+/// Issue a client certificate from the local CA for a station using mutual TLS.
+fn issue_client_cert(station_id: &str) -> Result<()> {
+    load_env();
+    let config = LocalCaConfig::from_env()?;
+    let ca = LocalCa::load_or_create(&config.dir)?;
+    ca.issue_client_certificate(station_id)?;
+    println!(
+        "Wrote {0}/clients/{station_id}.pem and {0}/clients/{station_id}.key",
+        config.dir.display()
+    );
+    Ok(())
+}
+
+/// Open the configured database and load what it holds.
+fn open_store() -> Result<()> {
+    load_env();
+    let storage_config = StorageConfig::from_env();
+    occp_ws::persistence::open(&storage_config).with_context(|| {
+        format!(
+            "Failed to open database {}",
+            storage_config.database_path.display()
+        )
+    })?;
+    occp_ws::persistence::restore().context("Failed to restore saved state")?;
+    Ok(())
+}
+
+/// Create an admin API token and print it; it is not shown again.
+fn create_api_token(name: &str) -> Result<()> {
+    open_store()?;
+    let (_, token) =
+        occp_ws::api_tokens::create(name, vec![occp_ws::api_tokens::Scope::Admin], None)?;
+    occp_ws::persistence::flush();
+    println!("{token}");
+    Ok(())
+}
+
+/// Create a user with the owner role, e.g. the first one. The password is
+/// read from stdin so it stays out of the process list and shell history.
+async fn create_owner(username: &str) -> Result<()> {
+    let password = read_password()?;
+    open_store()?;
+    let user = occp_ws::users::create(username, &password, occp_ws::users::Role::Owner, Vec::new())
+        .await?;
+    occp_ws::persistence::flush();
+    println!("Created owner {} ({})", user.username, user.user_id);
+    Ok(())
+}
+
+/// One line from stdin, prompting for it when stdin is a terminal.
+fn read_password() -> Result<String> {
+    let stdin = std::io::stdin();
+    if stdin.is_terminal() {
+        eprint!("Password: ");
+    }
+    let mut line = String::new();
+    stdin
+        .read_line(&mut line)
+        .context("Failed to read the password from stdin")?;
+    Ok(line.trim_end_matches(['\r', '\n']).to_string())
+}
+
 #[tokio::main]
 async fn main() -> Result<()> {
-    run().await
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match args.as_slice() {
+        [command] if command == "hash-password" => hash_password(),
+        [command, station_id] if command == "issue-client-cert" => issue_client_cert(station_id),
+        [command, name] if command == "create-api-token" => create_api_token(name),
+        [command, username] if command == "create-owner" => create_owner(username).await,
+        _ => run().await,
+    }
 }
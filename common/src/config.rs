@@ -1,6 +1,6 @@
 //! Configuration helpers shared across crates.
 
-use std::env;
+use std::{env, path::PathBuf, str::FromStr, time::Duration};
 
 use anyhow::{Context, Result};
 
@@ -38,14 +38,15 @@ impl ServerConfig {
 }
 
 /// List of allowed charger serial numbers parsed from `ALLOWED_SERIAL_NUMBERS`.
-/// Comma-separated; empty or missing disables the check.
+/// Comma-separated. Listed chargers are accepted without operator approval.
 pub fn allowed_serial_numbers() -> Result<Vec<String>> {
-    let raw = env::var("ALLOWED_SERIAL_NUMBERS").unwrap_or_default();
-    if raw.trim().is_empty() {
-        return Ok(Vec::new());
-    }
-    let serials = raw
-        .split(',')
+    Ok(env_list("ALLOWED_SERIAL_NUMBERS"))
+}
+
+/// Parse a comma-separated environment variable, skipping empty entries.
+pub fn env_list(name: &str) -> Vec<String> {
+    let raw = env::var(name).unwrap_or_default();
+    raw.split(',')
         .filter_map(|s| {
             let trimmed = s.trim();
             if trimmed.is_empty() {
@@ -54,6 +55,578 @@ pub fn allowed_serial_numbers() -> Result<Vec<String>> {
                 Some(trimmed.to_string())
             }
         })
-        .collect();
-    Ok(serials)
+        .collect()
+}
+
+/// Charger authentication settings (OCPP Security Profile 1).
+#[derive(Debug, Clone, Default)]
+pub struct StationAuthConfig {
+    /// Reject stations that have no password on file instead of letting them
+    /// connect unauthenticated.
+    pub required: bool,
+    /// File holding `station_id:password_hash` lines. Without it, passwords
+    /// only live in memory.
+    pub credentials_file: Option<PathBuf>,
+}
+
+impl StationAuthConfig {
+    /// Build `StationAuthConfig` from environment variables.
+    ///
+    /// Optional:
+    /// - `STATION_AUTH_REQUIRED` (`true`/`false`, defaults to `false`)
+    /// - `STATION_CREDENTIALS_FILE`
+    pub fn from_env() -> Result<Self> {
+        let required = env_flag("STATION_AUTH_REQUIRED")?.unwrap_or(false);
+        let credentials_file = env_path("STATION_CREDENTIALS_FILE");
+
+        Ok(Self {
+            required,
+            credentials_file,
+        })
+    }
+}
+
+/// Parse a boolean environment variable. Missing or empty yields `None`.
+pub fn env_flag(name: &str) -> Result<Option<bool>> {
+    let raw = env::var(name).unwrap_or_default();
+    match raw.trim().to_ascii_lowercase().as_str() {
+        "" => Ok(None),
+        "1" | "true" | "yes" | "on" => Ok(Some(true)),
+        "0" | "false" | "no" | "off" => Ok(Some(false)),
+        other => Err(anyhow::anyhow!("{name} must be a boolean, got `{other}`")),
+    }
+}
+
+/// Parse a numeric environment variable. Missing or empty yields `None`.
+pub fn env_number<T: FromStr>(name: &str) -> Result<Option<T>>
+where
+    T::Err: std::fmt::Display,
+{
+    let raw = env::var(name).unwrap_or_default();
+    let raw = raw.trim();
+    if raw.is_empty() {
+        return Ok(None);
+    }
+    raw.parse()
+        .map(Some)
+        .map_err(|err| anyhow::anyhow!("{name} must be a number, got `{raw}`: {err}"))
+}
+
+/// How BootNotifications from chargers are answered.
+#[derive(Debug, Clone)]
+pub struct RegistrationConfig {
+    /// Put chargers that are neither allow-listed nor approved into the
+    /// approval queue instead of accepting them.
+    pub approval_required: bool,
+    /// Seconds a Pending charger waits before booting again.
+    pub pending_retry_interval: u32,
+    /// Seconds a Rejected charger waits before booting again.
+    pub rejected_retry_interval: u32,
+}
+
+impl Default for RegistrationConfig {
+    fn default() -> Self {
+        Self {
+            approval_required: false,
+            pending_retry_interval: 60,
+            rejected_retry_interval: 3600,
+        }
+    }
+}
+
+impl RegistrationConfig {
+    /// Build `RegistrationConfig` from environment variables.
+    ///
+    /// Optional:
+    /// - `STATION_APPROVAL_REQUIRED` (`true`/`false`, defaults to `true` when
+    ///   `ALLOWED_SERIAL_NUMBERS` is set)
+    /// - `PENDING_RETRY_INTERVAL` (seconds, defaults to 60)
+    /// - `REJECTED_RETRY_INTERVAL` (seconds, defaults to 3600)
+    pub fn from_env() -> Result<Self> {
+        let defaults = Self::default();
+        let approval_required = match env_flag("STATION_APPROVAL_REQUIRED")? {
+            Some(required) => required,
+            None => !allowed_serial_numbers()?.is_empty(),
+        };
+
+        Ok(Self {
+            approval_required,
+            pending_retry_interval: env_number("PENDING_RETRY_INTERVAL")?
+                .unwrap_or(defaults.pending_retry_interval),
+            rejected_retry_interval: env_number("REJECTED_RETRY_INTERVAL")?
+                .unwrap_or(defaults.rejected_retry_interval),
+        })
+    }
+}
+
+/// Heartbeat interval handed to chargers and the offline watchdog threshold.
+#[derive(Debug, Clone)]
+pub struct HeartbeatConfig {
+    /// Seconds between Heartbeats for stations without their own interval.
+    pub interval: u32,
+    /// A station is offline after this many heartbeat intervals of silence.
+    pub offline_multiplier: u32,
+}
+
+impl Default for HeartbeatConfig {
+    fn default() -> Self {
+        Self {
+            interval: 300,
+            offline_multiplier: 3,
+        }
+    }
+}
+
+impl HeartbeatConfig {
+    /// Build `HeartbeatConfig` from environment variables.
+    ///
+    /// Optional:
+    /// - `HEARTBEAT_INTERVAL` (seconds, defaults to 300)
+    /// - `HEARTBEAT_OFFLINE_MULTIPLIER` (defaults to 3)
+    pub fn from_env() -> Result<Self> {
+        let defaults = Self::default();
+        let interval = env_number("HEARTBEAT_INTERVAL")?.unwrap_or(defaults.interval);
+        let offline_multiplier =
+            env_number("HEARTBEAT_OFFLINE_MULTIPLIER")?.unwrap_or(defaults.offline_multiplier);
+        if interval == 0 || offline_multiplier == 0 {
+            anyhow::bail!("HEARTBEAT_INTERVAL and HEARTBEAT_OFFLINE_MULTIPLIER must be positive");
+        }
+
+        Ok(Self {
+            interval,
+            offline_multiplier,
+        })
+    }
+}
+
+/// Server-initiated WebSocket pings used to detect dead connections.
+#[derive(Debug, Clone)]
+pub struct KeepaliveConfig {
+    /// Time between pings; `None` disables them.
+    pub ping_interval: Option<Duration>,
+    /// Close the socket when a ping is not answered within this time.
+    pub pong_timeout: Duration,
+}
+
+impl Default for KeepaliveConfig {
+    fn default() -> Self {
+        Self {
+            ping_interval: Some(Duration::from_secs(30)),
+            pong_timeout: Duration::from_secs(10),
+        }
+    }
+}
+
+impl KeepaliveConfig {
+    /// Build `KeepaliveConfig` from environment variables.
+    ///
+    /// Optional:
+    /// - `WS_PING_INTERVAL` (seconds, defaults to 30; `0` disables pings)
+    /// - `WS_PONG_TIMEOUT` (seconds, positive, defaults to 10)
+    pub fn from_env() -> Result<Self> {
+        let defaults = Self::default();
+        let ping_interval = match env_number::<u64>("WS_PING_INTERVAL")? {
+            Some(0) => None,
+            Some(secs) => Some(Duration::from_secs(secs)),
+            None => defaults.ping_interval,
+        };
+        let pong_timeout = match env_number::<u64>("WS_PONG_TIMEOUT")? {
+            Some(0) => anyhow::bail!("WS_PONG_TIMEOUT must be positive"),
+            Some(secs) => Duration::from_secs(secs),
+            None => defaults.pong_timeout,
+        };
+
+        Ok(Self {
+            ping_interval,
+            pong_timeout,
+        })
+    }
+}
+
+/// What to do when a station connects while it still has a live session.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DuplicateConnectionPolicy {
+    /// Close the old session and keep the new one.
+    #[default]
+    NewestWins,
+    /// Refuse the new connection while the old one is open.
+    RejectNewcomer,
+}
+
+impl FromStr for DuplicateConnectionPolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(raw: &str) -> Result<Self> {
+        match raw.trim().to_ascii_lowercase().replace('-', "_").as_str() {
+            "newest_wins" => Ok(Self::NewestWins),
+            "reject_newcomer" => Ok(Self::RejectNewcomer),
+            other => anyhow::bail!(
+                "unknown duplicate connection policy `{other}`, expected newest_wins or reject_newcomer"
+            ),
+        }
+    }
+}
+
+/// Duplicate connection policy from `DUPLICATE_CONNECTION_POLICY`
+/// (`newest_wins` or `reject_newcomer`, defaults to `newest_wins`).
+pub fn duplicate_connection_policy() -> Result<DuplicateConnectionPolicy> {
+    match env::var("DUPLICATE_CONNECTION_POLICY") {
+        Ok(raw) if !raw.trim().is_empty() => raw.parse(),
+        _ => Ok(DuplicateConnectionPolicy::default()),
+    }
+}
+
+/// TLS listener settings (OCPP Security Profiles 2 and 3).
+#[derive(Debug, Clone)]
+pub struct TlsConfig {
+    pub cert_file: PathBuf,
+    pub key_file: PathBuf,
+    /// CA bundle used to verify charger client certificates (Security Profile 3).
+    pub client_ca_file: Option<PathBuf>,
+    /// Refuse handshakes without a client certificate when `client_ca_file` is set.
+    pub client_cert_required: bool,
+}
+
+impl TlsConfig {
+    /// Build `TlsConfig` from environment variables. Returns `None` when TLS is
+    /// not configured.
+    ///
+    /// Required together:
+    /// - `TLS_CERT_FILE`
+    /// - `TLS_KEY_FILE`
+    ///
+    /// Optional:
+    /// - `TLS_CLIENT_CA_FILE`
+    /// - `TLS_CLIENT_CERT_REQUIRED` (`true`/`false`, defaults to `true`)
+    pub fn from_env() -> Result<Option<Self>> {
+        let cert_file = env_path("TLS_CERT_FILE");
+        let key_file = env_path("TLS_KEY_FILE");
+        let (cert_file, key_file) = match (cert_file, key_file) {
+            (Some(cert), Some(key)) => (cert, key),
+            (None, None) => return Ok(None),
+            _ => anyhow::bail!("TLS_CERT_FILE and TLS_KEY_FILE must be set together"),
+        };
+
+        Ok(Some(Self {
+            cert_file,
+            key_file,
+            client_ca_file: env_path("TLS_CLIENT_CA_FILE"),
+            client_cert_required: env_flag("TLS_CLIENT_CERT_REQUIRED")?.unwrap_or(true),
+        }))
+    }
+}
+
+fn env_path(name: &str) -> Option<PathBuf> {
+    env::var(name)
+        .ok()
+        .filter(|path| !path.trim().is_empty())
+        .map(PathBuf::from)
+}
+
+/// Built-in certificate authority settings. Used for the TLS listener when
+/// `TLS_CERT_FILE`/`TLS_KEY_FILE` are not set.
+#[derive(Debug, Clone)]
+pub struct LocalCaConfig {
+    pub enabled: bool,
+    /// Directory holding the CA, the server certificate and issued client
+    /// certificates.
+    pub dir: PathBuf,
+    /// Hostnames and IP addresses the server certificate must cover.
+    pub server_names: Vec<String>,
+    /// Verify charger client certificates against the local CA (Profile 3).
+    pub client_auth: bool,
+    pub client_cert_required: bool,
+}
+
+impl LocalCaConfig {
+    /// Build `LocalCaConfig` from environment variables.
+    ///
+    /// Optional:
+    /// - `LOCAL_CA_ENABLED` (`true`/`false`, defaults to `false`)
+    /// - `LOCAL_CA_DIR` (defaults to `pki`)
+    /// - `LOCAL_CA_SERVER_NAMES` (comma-separated; defaults to `localhost` and `ADDR`)
+    /// - `LOCAL_CA_CLIENT_AUTH` (`true`/`false`, defaults to `false`)
+    /// - `TLS_CLIENT_CERT_REQUIRED` (`true`/`false`, defaults to `true`)
+    pub fn from_env() -> Result<Self> {
+        let mut server_names = env_list("LOCAL_CA_SERVER_NAMES");
+        if server_names.is_empty() {
+            server_names.push("localhost".to_string());
+            let addr = env::var("ADDR").unwrap_or_default();
+            let addr = addr.trim();
+            if !addr.is_empty() && addr != "0.0.0.0" && addr != "::" && addr != "localhost" {
+                server_names.push(addr.to_string());
+            }
+        }
+
+        Ok(Self {
+            enabled: env_flag("LOCAL_CA_ENABLED")?.unwrap_or(false),
+            dir: env_path("LOCAL_CA_DIR").unwrap_or_else(|| PathBuf::from("pki")),
+            server_names,
+            client_auth: env_flag("LOCAL_CA_CLIENT_AUTH")?.unwrap_or(false),
+            client_cert_required: env_flag("TLS_CLIENT_CERT_REQUIRED")?.unwrap_or(true),
+        })
+    }
+}
+
+/// Where the server keeps its state between restarts.
+#[derive(Debug, Clone)]
+pub struct StorageConfig {
+    /// SQLite database file; `:memory:` keeps everything in memory.
+    pub database_path: PathBuf,
+}
+
+impl StorageConfig {
+    /// Build `StorageConfig` from environment variables.
+    ///
+    /// Optional:
+    /// - `DATABASE_PATH` (defaults to `plughome.db`)
+    pub fn from_env() -> Self {
+        Self {
+            database_path: env_path("DATABASE_PATH")
+                .unwrap_or_else(|| PathBuf::from("plughome.db")),
+        }
+    }
+}
+
+/// Operator commands sent to chargers through the API.
+#[derive(Debug, Clone)]
+pub struct CommandConfig {
+    /// How long a command waits for the charger's answer.
+    pub timeout: Duration,
+}
+
+impl Default for CommandConfig {
+    fn default() -> Self {
+        Self {
+            timeout: Duration::from_secs(30),
+        }
+    }
+}
+
+impl CommandConfig {
+    /// Build `CommandConfig` from environment variables.
+    ///
+    /// Optional:
+    /// - `COMMAND_TIMEOUT` (seconds, defaults to 30)
+    pub fn from_env() -> Result<Self> {
+        let timeout = match env_number::<u64>("COMMAND_TIMEOUT")? {
+            Some(0) => anyhow::bail!("COMMAND_TIMEOUT must be positive"),
+            Some(secs) => Duration::from_secs(secs),
+            None => Self::default().timeout,
+        };
+        Ok(Self { timeout })
+    }
+}
+
+/// MQTT bridge settings.
+#[derive(Debug, Clone)]
+pub struct MqttConfig {
+    pub host: String,
+    pub port: u16,
+    pub client_id: String,
+    /// Every topic starts with this, e.g. `plughome/stations/...`.
+    pub topic_prefix: String,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    pub keep_alive: Duration,
+    /// Home Assistant discovery prefix; `None` turns discovery off.
+    pub discovery_prefix: Option<String>,
+    /// Id tag for charging started from Home Assistant's switch.
+    pub remote_id_tag: String,
+}
+
+impl MqttConfig {
+    /// Build `MqttConfig` from environment variables. Returns `None` when
+    /// `MQTT_HOST` is not set.
+    ///
+    /// Optional:
+    /// - `MQTT_PORT` (defaults to 1883)
+    /// - `MQTT_CLIENT_ID` (defaults to `plughome-server`)
+    /// - `MQTT_TOPIC_PREFIX` (defaults to `plughome`)
+    /// - `MQTT_USERNAME` and `MQTT_PASSWORD`, set together
+    /// - `MQTT_KEEP_ALIVE` (seconds, defaults to 30)
+    /// - `MQTT_HA_DISCOVERY` (`true`/`false`, defaults to `true`)
+    /// - `MQTT_HA_DISCOVERY_PREFIX` (defaults to `homeassistant`)
+    /// - `MQTT_HA_ID_TAG` (defaults to `HomeAssistant`, at most 20 characters)
+    pub fn from_env() -> Result<Option<Self>> {
+        let Some(host) = env_text("MQTT_HOST") else {
+            return Ok(None);
+        };
+        let (username, password) = match (env_text("MQTT_USERNAME"), env_text("MQTT_PASSWORD")) {
+            (Some(username), Some(password)) => (Some(username), Some(password)),
+            (None, None) => (None, None),
+            _ => anyhow::bail!("MQTT_USERNAME and MQTT_PASSWORD must be set together"),
+        };
+        let topic_prefix = env_text("MQTT_TOPIC_PREFIX")
+            .map(|prefix| prefix.trim_matches('/').to_string())
+            .unwrap_or_else(|| "plughome".to_string());
+        if topic_prefix.is_empty() || topic_prefix.contains(['+', '#']) {
+            anyhow::bail!("MQTT_TOPIC_PREFIX must be a topic without wildcards");
+        }
+        let keep_alive = match env_number::<u64>("MQTT_KEEP_ALIVE")? {
+            Some(secs) if secs < 5 => anyhow::bail!("MQTT_KEEP_ALIVE must be at least 5 seconds"),
+            Some(secs) => Duration::from_secs(secs),
+            None => Duration::from_secs(30),
+        };
+        let discovery_prefix = if env_flag("MQTT_HA_DISCOVERY")?.unwrap_or(true) {
+            let prefix = env_text("MQTT_HA_DISCOVERY_PREFIX")
+                .map(|prefix| prefix.trim_matches('/').to_string())
+                .unwrap_or_else(|| "homeassistant".to_string());
+            if prefix.is_empty() || prefix.contains(['+', '#']) {
+                anyhow::bail!("MQTT_HA_DISCOVERY_PREFIX must be a topic without wildcards");
+            }
+            Some(prefix)
+        } else {
+            None
+        };
+        let remote_id_tag =
+            env_text("MQTT_HA_ID_TAG").unwrap_or_else(|| "HomeAssistant".to_string());
+        if remote_id_tag.chars().count() > 20 {
+            anyhow::bail!("MQTT_HA_ID_TAG must be at most 20 characters");
+        }
+        Ok(Some(Self {
+            host,
+            port: env_number("MQTT_PORT")?.unwrap_or(1883),
+            client_id: env_text("MQTT_CLIENT_ID").unwrap_or_else(|| "plughome-server".to_string()),
+            topic_prefix,
+            username,
+            password,
+            keep_alive,
+            discovery_prefix,
+            remote_id_tag,
+        }))
+    }
+}
+
+/// Delivery of outgoing webhooks.
+#[derive(Debug, Clone)]
+pub struct WebhookConfig {
+    /// Attempts per delivery before it is given up.
+    pub max_attempts: u32,
+    /// Wait before the first retry; doubles with every further attempt.
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+    /// How long one request may take.
+    pub timeout: Duration,
+}
+
+impl Default for WebhookConfig {
+    fn default() -> Self {
+        Self {
+            max_attempts: 8,
+            initial_backoff: Duration::from_secs(10),
+            max_backoff: Duration::from_secs(3600),
+            timeout: Duration::from_secs(10),
+        }
+    }
+}
+
+impl WebhookConfig {
+    /// Build `WebhookConfig` from environment variables.
+    ///
+    /// Optional:
+    /// - `WEBHOOK_MAX_ATTEMPTS` (defaults to 8)
+    /// - `WEBHOOK_RETRY_BACKOFF` (seconds before the first retry, defaults to 10)
+    /// - `WEBHOOK_MAX_BACKOFF` (seconds, defaults to 3600)
+    /// - `WEBHOOK_TIMEOUT` (seconds, defaults to 10)
+    pub fn from_env() -> Result<Self> {
+        let defaults = Self::default();
+        let max_attempts = match env_number::<u32>("WEBHOOK_MAX_ATTEMPTS")? {
+            Some(0) => anyhow::bail!("WEBHOOK_MAX_ATTEMPTS must be positive"),
+            Some(attempts) => attempts,
+            None => defaults.max_attempts,
+        };
+        let seconds = |name: &str, default: Duration| -> Result<Duration> {
+            match env_number::<u64>(name)? {
+                Some(0) => anyhow::bail!("{name} must be positive"),
+                Some(secs) => Ok(Duration::from_secs(secs)),
+                None => Ok(default),
+            }
+        };
+        let initial_backoff = seconds("WEBHOOK_RETRY_BACKOFF", defaults.initial_backoff)?;
+        let max_backoff = seconds("WEBHOOK_MAX_BACKOFF", defaults.max_backoff)?;
+        if max_backoff < initial_backoff {
+            anyhow::bail!("WEBHOOK_MAX_BACKOFF must not be below WEBHOOK_RETRY_BACKOFF");
+        }
+        Ok(Self {
+            max_attempts,
+            initial_backoff,
+            max_backoff,
+            timeout: seconds("WEBHOOK_TIMEOUT", defaults.timeout)?,
+        })
+    }
+}
+
+/// Login sessions of household users.
+#[derive(Debug, Clone)]
+pub struct LoginConfig {
+    /// How long a login lasts.
+    pub session_ttl: Duration,
+    /// Mark the session cookie `Secure`, for when the API is served over TLS.
+    pub secure_cookie: bool,
+}
+
+impl Default for LoginConfig {
+    fn default() -> Self {
+        Self {
+            session_ttl: Duration::from_secs(30 * 24 * 3600),
+            secure_cookie: false,
+        }
+    }
+}
+
+impl LoginConfig {
+    /// Build `LoginConfig` from environment variables.
+    ///
+    /// Optional:
+    /// - `LOGIN_SESSION_DAYS` (defaults to 30)
+    /// - `LOGIN_COOKIE_SECURE` (defaults to false)
+    pub fn from_env() -> Result<Self> {
+        let defaults = Self::default();
+        let session_ttl = match env_number::<u64>("LOGIN_SESSION_DAYS")? {
+            Some(0) => anyhow::bail!("LOGIN_SESSION_DAYS must be positive"),
+            Some(days) => Duration::from_secs(days * 24 * 3600),
+            None => defaults.session_ttl,
+        };
+        Ok(Self {
+            session_ttl,
+            secure_cookie: env_flag("LOGIN_COOKIE_SECURE")?.unwrap_or(defaults.secure_cookie),
+        })
+    }
+}
+
+/// How long audit records are kept.
+#[derive(Debug, Clone)]
+pub struct AuditConfig {
+    pub retention: Duration,
+}
+
+impl Default for AuditConfig {
+    fn default() -> Self {
+        Self {
+            retention: Duration::from_secs(365 * 24 * 3600),
+        }
+    }
+}
+
+impl AuditConfig {
+    /// Build `AuditConfig` from environment variables.
+    ///
+    /// Optional:
+    /// - `AUDIT_RETENTION_DAYS` (defaults to 365)
+    pub fn from_env() -> Result<Self> {
+        let retention = match env_number::<u64>("AUDIT_RETENTION_DAYS")? {
+            Some(0) => anyhow::bail!("AUDIT_RETENTION_DAYS must be positive"),
+            Some(days) => Duration::from_secs(days * 24 * 3600),
+            None => Self::default().retention,
+        };
+        Ok(Self { retention })
+    }
+}
+
+fn env_text(name: &str) -> Option<String> {
+    env::var(name)
+        .ok()
+        .map(|value| value.trim().to_string())
+        .filter(|value| !value.is_empty())
 }
@@ -1,5 +1,10 @@
 pub mod config;
 pub mod logging;
 
-pub use config::{ServerConfig, allowed_serial_numbers, load_env};
+pub use config::{
+    AuditConfig, CommandConfig, DuplicateConnectionPolicy, HeartbeatConfig, KeepaliveConfig,
+    LocalCaConfig, LoginConfig, MqttConfig, RegistrationConfig, ServerConfig, StationAuthConfig,
+    StorageConfig, TlsConfig, WebhookConfig, allowed_serial_numbers, duplicate_connection_policy,
+    env_flag, env_list, env_number, load_env,
+};
 pub use logging::init_tracing;
@@ -0,0 +1,290 @@
+//! Bearer tokens for the HTTP API.
+//!
+//! A token carries scopes and, optionally, the stations it is limited to.
+//! Only a SHA-256 hash of each token is kept; the token itself is shown once,
+//! when it is created. Revoked tokens stay listed with their usage, which is
+//! counted in memory and written to storage every minute.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::{LazyLock, Mutex, RwLock},
+    time::Duration,
+};
+
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use chrono::{DateTime, Utc};
+use ring::digest;
+use serde::{Deserialize, Serialize};
+use tokio::task::JoinHandle;
+use tracing::info;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::persistence;
+
+/// Every token starts with this, so leaked ones are easy to search for.
+pub const TOKEN_PREFIX: &str = "ph_";
+
+/// Random bytes in a token.
+const TOKEN_BYTES: usize = 32;
+
+/// Characters of a token kept in the clear to tell tokens apart.
+const SHOWN_PREFIX_LEN: usize = 10;
+
+const MAX_NAME_LEN: usize = 100;
+
+/// How often usage is written to storage.
+const USAGE_FLUSH_PERIOD: Duration = Duration::from_secs(60);
+
+static TOKENS: LazyLock<RwLock<BTreeMap<String, ApiToken>>> =
+    LazyLock::new(|| RwLock::new(BTreeMap::new()));
+
+/// Uses since the last flush by token id, kept apart so that authenticating
+/// only reads `TOKENS`. Lock it before `TOKENS` when holding both.
+static USAGE: LazyLock<Mutex<HashMap<String, Usage>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+#[derive(Debug, Clone, Copy)]
+struct Usage {
+    count: u64,
+    last_used_at: DateTime<Utc>,
+}
+
+/// What a token may do. Each scope includes the ones before it.
+#[derive(Serialize, Deserialize, ToSchema, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum Scope {
+    /// Read stations, sessions, commands and events.
+    Read,
+    /// Send commands and edit stations.
+    Control,
+    /// Manage tokens, webhooks and registrations.
+    Admin,
+}
+
+#[derive(Serialize, ToSchema, Debug, Clone, PartialEq)]
+pub struct ApiToken {
+    pub token_id: String,
+    pub name: String,
+    #[serde(skip)]
+    pub token_hash: String,
+    /// The token's first characters.
+    pub prefix: String,
+    pub scopes: Vec<Scope>,
+    /// Stations the token is limited to; `None` for all of them.
+    pub station_ids: Option<Vec<String>>,
+    pub created_at: DateTime<Utc>,
+    pub last_used_at: Option<DateTime<Utc>>,
+    pub use_count: u64,
+    pub revoked_at: Option<DateTime<Utc>>,
+}
+
+impl ApiToken {
+    fn add_usage(&mut self, usage: Usage) {
+        self.use_count += usage.count;
+        self.last_used_at = Some(usage.last_used_at);
+    }
+
+    pub fn grants(&self, needed: Scope) -> bool {
+        self.scopes.iter().any(|scope| *scope >= needed)
+    }
+
+    pub fn allows_station(&self, station_id: &str) -> bool {
+        self.station_ids
+            .as_ref()
+            .is_none_or(|station_ids| station_ids.iter().any(|id| id == station_id))
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum TokenError {
+    #[error("{0}")]
+    Invalid(String),
+    #[error("token {0} not found")]
+    NotFound(String),
+}
+
+/// Issue a token. Returns it with the token text, which is not kept.
+pub fn create(
+    name: &str,
+    scopes: Vec<Scope>,
+    station_ids: Option<Vec<String>>,
+) -> Result<(ApiToken, String), TokenError> {
+    let name = name.trim();
+    if name.is_empty() || name.chars().count() > MAX_NAME_LEN {
+        return Err(TokenError::Invalid(format!(
+            "name must be 1 to {MAX_NAME_LEN} characters"
+        )));
+    }
+    if scopes.is_empty() {
+        return Err(TokenError::Invalid(
+            "scopes must name at least one scope".to_string(),
+        ));
+    }
+    let mut scopes = scopes;
+    scopes.sort();
+    scopes.dedup();
+    let station_ids = match station_ids {
+        Some(station_ids) => {
+            let mut station_ids: Vec<String> = station_ids
+                .iter()
+                .map(|id| id.trim().to_string())
+                .filter(|id| !id.is_empty())
+                .collect();
+            if station_ids.is_empty() {
+                return Err(TokenError::Invalid(
+                    "station_ids must name at least one station, or be left out".to_string(),
+                ));
+            }
+            station_ids.sort();
+            station_ids.dedup();
+            Some(station_ids)
+        }
+        None => None,
+    };
+
+    let mut bytes = [0u8; TOKEN_BYTES];
+    OsRng.fill_bytes(&mut bytes);
+    let secret: String = bytes.iter().map(|b| format!("{b:02x}")).collect();
+    let text = format!("{TOKEN_PREFIX}{secret}");
+    let token = ApiToken {
+        token_id: Uuid::new_v4().to_string(),
+        name: name.to_string(),
+        token_hash: hash(&text),
+        prefix: text[..SHOWN_PREFIX_LEN].to_string(),
+        scopes,
+        station_ids,
+        created_at: Utc::now(),
+        last_used_at: None,
+        use_count: 0,
+        revoked_at: None,
+    };
+    TOKENS
+        .write()
+        .expect("API tokens lock poisoned")
+        .insert(token.token_id.clone(), token.clone());
+    persistence::save_api_token(&token);
+    info!(token_id = token.token_id, name, "API token created");
+    Ok((token, text))
+}
+
+/// Every token, revoked ones included, ordered by id.
+pub fn list() -> Vec<ApiToken> {
+    let usage = USAGE.lock().expect("API token usage lock poisoned");
+    TOKENS
+        .read()
+        .expect("API tokens lock poisoned")
+        .values()
+        .map(|token| with_usage(token, &usage))
+        .collect()
+}
+
+pub fn get(token_id: &str) -> Option<ApiToken> {
+    let usage = USAGE.lock().expect("API token usage lock poisoned");
+    TOKENS
+        .read()
+        .expect("API tokens lock poisoned")
+        .get(token_id)
+        .map(|token| with_usage(token, &usage))
+}
+
+/// Stop accepting a token. Revoking it again changes nothing.
+pub fn revoke(token_id: &str) -> Result<ApiToken, TokenError> {
+    let token = {
+        let mut usage = USAGE.lock().expect("API token usage lock poisoned");
+        let mut tokens = TOKENS.write().expect("API tokens lock poisoned");
+        let token = tokens
+            .get_mut(token_id)
+            .ok_or_else(|| TokenError::NotFound(token_id.to_string()))?;
+        if token.revoked_at.is_some() {
+            return Ok(token.clone());
+        }
+        if let Some(used) = usage.remove(token_id) {
+            token.add_usage(used);
+        }
+        token.revoked_at = Some(Utc::now());
+        token.clone()
+    };
+    persistence::save_api_token(&token);
+    info!(token_id, name = token.name, "API token revoked");
+    Ok(token)
+}
+
+/// The unrevoked token matching `text`, after counting its use.
+pub fn authenticate(text: &str) -> Option<ApiToken> {
+    let token_hash = hash(text);
+    let token = TOKENS
+        .read()
+        .expect("API tokens lock poisoned")
+        .values()
+        .find(|token| token.token_hash == token_hash && token.revoked_at.is_none())?
+        .clone();
+    let mut usage = USAGE.lock().expect("API token usage lock poisoned");
+    let used = usage.entry(token.token_id.clone()).or_insert(Usage {
+        count: 0,
+        last_used_at: Utc::now(),
+    });
+    used.count += 1;
+    used.last_used_at = Utc::now();
+    Some(with_usage(&token, &usage))
+}
+
+/// Write the usage counted since the last flush to storage.
+pub fn flush_usage() {
+    let mut usage = USAGE.lock().expect("API token usage lock poisoned");
+    if usage.is_empty() {
+        return;
+    }
+    let used: Vec<ApiToken> = {
+        let mut tokens = TOKENS.write().expect("API tokens lock poisoned");
+        usage
+            .drain()
+            .filter_map(|(token_id, used)| {
+                let token = tokens.get_mut(&token_id)?;
+                token.add_usage(used);
+                Some(token.clone())
+            })
+            .collect()
+    };
+    drop(usage);
+    for token in &used {
+        persistence::save_api_token(token);
+    }
+}
+
+/// Flush usage every minute.
+pub fn spawn_usage_flusher() -> JoinHandle<()> {
+    tokio::spawn(async {
+        let mut interval = tokio::time::interval(USAGE_FLUSH_PERIOD);
+        loop {
+            interval.tick().await;
+            flush_usage();
+        }
+    })
+}
+
+fn with_usage(token: &ApiToken, usage: &HashMap<String, Usage>) -> ApiToken {
+    let mut token = token.clone();
+    if let Some(used) = usage.get(&token.token_id) {
+        token.add_usage(*used);
+    }
+    token
+}
+
+/// Replace the tokens with ones loaded from storage.
+pub fn restore(tokens: Vec<ApiToken>) {
+    *TOKENS.write().expect("API tokens lock poisoned") = tokens
+        .into_iter()
+        .map(|token| (token.token_id.clone(), token))
+        .collect();
+}
+
+/// Hex SHA-256 of a token. Tokens are random, so a slow password hash would
+/// add nothing but latency.
+pub(crate) fn hash(text: &str) -> String {
+    digest::digest(&digest::SHA256, text.as_bytes())
+        .as_ref()
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect()
+}
@@ -0,0 +1,171 @@
+//! Append-only audit log of what was done to the chargers and the server's
+//! configuration, by whom and from where.
+//!
+//! Every OCPP call the server sends is recorded with its payload and the
+//! charger's answer. Changes made through the API (station details,
+//! registration decisions, users and their id tags, tokens and webhooks) are
+//! recorded once they succeed. Records only live in the store and are pruned
+//! after `AUDIT_RETENTION_DAYS`.
+
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use storage::AuditFilter;
+use tokio::task::JoinHandle;
+use utoipa::ToSchema;
+
+use crate::api_tokens::ApiToken;
+use crate::auth::AUTHORIZATION_KEY;
+use crate::persistence;
+use crate::state::load_audit_config;
+use crate::users::User;
+
+/// How often records past retention are deleted.
+const PRUNE_PERIOD: Duration = Duration::from_secs(3600);
+
+#[derive(Serialize, Deserialize, ToSchema, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum ActorKind {
+    User,
+    Token,
+    /// Something the server did on its own, e.g. resyncing a station.
+    Automation,
+    /// A request that went through an API router without authentication.
+    Anonymous,
+}
+
+/// Who did something.
+#[derive(Serialize, ToSchema, Debug, Clone, PartialEq)]
+pub struct Actor {
+    pub kind: ActorKind,
+    /// User or token id, or the name of the automation.
+    pub id: String,
+    /// Username or token name.
+    pub name: String,
+    /// Address the request came from.
+    pub remote_addr: Option<String>,
+}
+
+impl Actor {
+    pub fn user(user: &User, remote_addr: Option<String>) -> Self {
+        Self {
+            kind: ActorKind::User,
+            id: user.user_id.clone(),
+            name: user.username.clone(),
+            remote_addr,
+        }
+    }
+
+    pub fn token(token: &ApiToken, remote_addr: Option<String>) -> Self {
+        Self {
+            kind: ActorKind::Token,
+            id: token.token_id.clone(),
+            name: token.name.clone(),
+            remote_addr,
+        }
+    }
+
+    pub fn automation(name: &str) -> Self {
+        Self {
+            kind: ActorKind::Automation,
+            id: name.to_string(),
+            name: name.to_string(),
+            remote_addr: None,
+        }
+    }
+
+    pub fn anonymous(remote_addr: Option<String>) -> Self {
+        Self {
+            kind: ActorKind::Anonymous,
+            id: String::new(),
+            name: "anonymous".to_string(),
+            remote_addr,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, ToSchema, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Outcome {
+    /// The change was made, or the charger accepted the call.
+    Ok,
+    /// The charger refused, with a CALLERROR or a negative status.
+    Rejected,
+    /// No answer, e.g. a timeout or a dropped connection.
+    Failed,
+}
+
+#[derive(Serialize, ToSchema, Debug, Clone, PartialEq)]
+pub struct AuditRecord {
+    pub audit_id: i64,
+    pub at: DateTime<Utc>,
+    pub actor: Actor,
+    /// OCPP action sent, e.g. `RemoteStartTransaction`, or the change made
+    /// through the API, e.g. `ApproveRegistration`.
+    pub action: String,
+    pub station_id: Option<String>,
+    /// The exact OCPP payload sent, or the change asked for.
+    pub request: Option<Value>,
+    /// The charger's answer, or the result of the change.
+    pub response: Option<Value>,
+    pub outcome: Outcome,
+}
+
+/// Append a record made now. A station's `AuthorizationKey` is left out.
+pub fn record(
+    actor: &Actor,
+    action: &str,
+    station_id: Option<&str>,
+    request: Option<Value>,
+    response: Option<Value>,
+    outcome: Outcome,
+) {
+    persistence::append_audit_record(&AuditRecord {
+        audit_id: 0,
+        at: Utc::now(),
+        actor: actor.clone(),
+        action: action.to_string(),
+        station_id: station_id.map(str::to_string),
+        request: request.map(redact),
+        response,
+        outcome,
+    });
+}
+
+/// How a charger's answer to a call went: positive unless its `status`
+/// says otherwise.
+pub fn answer_outcome(response: &Value) -> Outcome {
+    match response.get("status").and_then(Value::as_str) {
+        None
+        | Some("Accepted" | "AcceptedCanceled" | "Unlocked" | "Scheduled" | "RebootRequired") => {
+            Outcome::Ok
+        }
+        Some(_) => Outcome::Rejected,
+    }
+}
+
+/// Blank out the password in a ChangeConfiguration of the AuthorizationKey.
+fn redact(mut request: Value) -> Value {
+    if request.get("key").and_then(Value::as_str) == Some(AUTHORIZATION_KEY) {
+        request["value"] = Value::String("[redacted]".to_string());
+    }
+    request
+}
+
+/// Matching records, newest first.
+pub async fn search(filter: &AuditFilter, limit: usize) -> Vec<AuditRecord> {
+    persistence::audit_records(filter, limit).await
+}
+
+/// Delete records past retention now and every hour.
+pub fn spawn_pruner() -> JoinHandle<()> {
+    tokio::spawn(async {
+        let retention = load_audit_config().await.retention;
+        loop {
+            persistence::prune_audit_records(Utc::now() - retention);
+            tokio::time::sleep(PRUNE_PERIOD).await;
+        }
+    })
+}
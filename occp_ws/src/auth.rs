@@ -0,0 +1,270 @@
+//! OCPP Security Profile 1: HTTP Basic authentication of chargers.
+//!
+//! The Basic username is the station id and the password is the charger's
+//! `AuthorizationKey`. Only argon2 hashes of the passwords are kept, either in
+//! memory or in the file named by `STATION_CREDENTIALS_FILE`.
+
+use std::{
+    collections::HashMap,
+    fs, io,
+    path::{Path, PathBuf},
+    sync::RwLock,
+};
+
+use argon2::{
+    Argon2, PasswordHash, PasswordHasher, PasswordVerifier,
+    password_hash::{
+        SaltString,
+        rand_core::{OsRng, RngCore},
+    },
+};
+use headers::authorization::Basic;
+use rust_ocpp::v1_6::{
+    messages::change_configuration::{ChangeConfigurationRequest, ChangeConfigurationResponse},
+    types::ConfigurationStatus,
+};
+use tracing::{info, warn};
+
+use crate::audit::Actor;
+use crate::connections::{self, CallError};
+use crate::state::load_station_credentials;
+use crate::types::*;
+
+/// Configuration key chargers use to store their Basic auth password.
+pub const AUTHORIZATION_KEY: &str = "AuthorizationKey";
+
+/// Length in bytes of generated keys; hex encoded this gives 40 characters.
+const AUTHORIZATION_KEY_BYTES: usize = 20;
+
+#[derive(Debug, thiserror::Error)]
+pub enum AuthError {
+    #[error("missing Basic credentials")]
+    MissingCredentials,
+    #[error("Basic username does not match station id")]
+    UsernameMismatch,
+    #[error("invalid password")]
+    InvalidPassword,
+    #[error("station has no password on file")]
+    UnknownStation,
+    #[error("client certificate CN does not match station id")]
+    CertificateMismatch,
+    #[error("failed to hash password: {0}")]
+    Hash(String),
+    #[error("failed to persist credentials: {0}")]
+    Io(#[from] io::Error),
+    #[error("failed to send AuthorizationKey: {0}")]
+    Call(#[from] CallError),
+    #[error("station refused the new AuthorizationKey: {0:?}")]
+    KeyRejected(ConfigurationStatus),
+}
+
+/// Per-station password hashes.
+#[derive(Debug, Default)]
+pub struct StationCredentials {
+    required: bool,
+    path: Option<PathBuf>,
+    hashes: RwLock<HashMap<String, String>>,
+}
+
+impl StationCredentials {
+    /// Load credentials from `path` if given. A missing file starts empty.
+    pub fn load(required: bool, path: Option<PathBuf>) -> io::Result<Self> {
+        let hashes = match &path {
+            Some(path) => read_credentials_file(path)?,
+            None => HashMap::new(),
+        };
+        Ok(Self {
+            required,
+            path,
+            hashes: RwLock::new(hashes),
+        })
+    }
+
+    /// Credentials that are never written to disk.
+    pub fn in_memory(required: bool) -> Self {
+        Self {
+            required,
+            ..Self::default()
+        }
+    }
+
+    pub fn is_required(&self) -> bool {
+        self.required
+    }
+
+    pub fn has_password(&self, station_id: &str) -> bool {
+        self.hashes
+            .read()
+            .expect("credentials lock poisoned")
+            .contains_key(station_id)
+    }
+
+    /// Check a station's Basic credentials.
+    ///
+    /// Stations without a stored password are let through unless
+    /// authentication is required.
+    pub async fn authenticate(
+        &self,
+        station_id: &str,
+        credentials: Option<&Basic>,
+    ) -> Result<(), AuthError> {
+        let stored = self
+            .hashes
+            .read()
+            .expect("credentials lock poisoned")
+            .get(station_id)
+            .cloned();
+
+        let Some(stored) = stored else {
+            return if self.required {
+                Err(AuthError::UnknownStation)
+            } else {
+                Ok(())
+            };
+        };
+
+        let credentials = credentials.ok_or(AuthError::MissingCredentials)?;
+        if credentials.username() != station_id {
+            return Err(AuthError::UsernameMismatch);
+        }
+
+        let password = credentials.password().to_string();
+        let valid = tokio::task::spawn_blocking(move || verify_password(&password, &stored))
+            .await
+            .unwrap_or(false);
+        if valid {
+            Ok(())
+        } else {
+            Err(AuthError::InvalidPassword)
+        }
+    }
+
+    /// Hash and store a new password for `station_id`.
+    pub async fn set_password(&self, station_id: &str, password: &str) -> Result<(), AuthError> {
+        let password = password.to_string();
+        let hash = tokio::task::spawn_blocking(move || hash_password(&password))
+            .await
+            .map_err(|err| AuthError::Hash(err.to_string()))??;
+        self.set_password_hash(station_id, hash)?;
+        Ok(())
+    }
+
+    /// Store an already hashed password for `station_id`.
+    pub fn set_password_hash(&self, station_id: &str, hash: String) -> io::Result<()> {
+        let mut hashes = self.hashes.write().expect("credentials lock poisoned");
+        hashes.insert(station_id.to_string(), hash);
+        if let Some(path) = &self.path {
+            write_credentials_file(path, &hashes)?;
+        }
+        Ok(())
+    }
+}
+
+/// Hash a password into an argon2 PHC string.
+pub fn hash_password(password: &str) -> Result<String, AuthError> {
+    let salt = SaltString::generate(&mut OsRng);
+    Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .map(|hash| hash.to_string())
+        .map_err(|err| AuthError::Hash(err.to_string()))
+}
+
+/// Check a password against an argon2 PHC string.
+pub fn verify_password(password: &str, hash: &str) -> bool {
+    match PasswordHash::new(hash) {
+        Ok(parsed) => Argon2::default()
+            .verify_password(password.as_bytes(), &parsed)
+            .is_ok(),
+        Err(err) => {
+            warn!("Stored password hash is malformed: {err}");
+            false
+        }
+    }
+}
+
+/// Generate a random hex `AuthorizationKey`.
+pub fn generate_authorization_key() -> String {
+    let mut bytes = [0u8; AUTHORIZATION_KEY_BYTES];
+    OsRng.fill_bytes(&mut bytes);
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+/// Roll out a freshly generated `AuthorizationKey` to a connected station.
+///
+/// The new password only replaces the stored hash once the charger accepts
+/// the ChangeConfiguration. Returns the new key.
+pub async fn rotate_authorization_key(
+    station_id: &str,
+    actor: &Actor,
+) -> Result<String, AuthError> {
+    let key = generate_authorization_key();
+    set_authorization_key(station_id, &key, actor).await?;
+    Ok(key)
+}
+
+/// Send `key` to the station as its `AuthorizationKey` and store its hash.
+pub async fn set_authorization_key(
+    station_id: &str,
+    key: &str,
+    actor: &Actor,
+) -> Result<(), AuthError> {
+    let response: ChangeConfigurationResponse = connections::call(
+        station_id,
+        OcppActionEnum::ChangeConfiguration,
+        OcppPayload::ChangeConfiguration(ChangeConfigurationKind::Request(
+            ChangeConfigurationRequest {
+                key: AUTHORIZATION_KEY.to_string(),
+                value: key.to_string(),
+            },
+        )),
+        actor,
+    )
+    .await?;
+
+    match response.status {
+        ConfigurationStatus::Accepted | ConfigurationStatus::RebootRequired => {
+            load_station_credentials()
+                .await
+                .set_password(station_id, key)
+                .await?;
+            info!(station_id, "AuthorizationKey updated");
+            Ok(())
+        }
+        status => Err(AuthError::KeyRejected(status)),
+    }
+}
+
+fn read_credentials_file(path: &Path) -> io::Result<HashMap<String, String>> {
+    let raw = match fs::read_to_string(path) {
+        Ok(raw) => raw,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(HashMap::new()),
+        Err(err) => return Err(err),
+    };
+
+    let hashes = raw
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .filter_map(|line| match line.rsplit_once(':') {
+            Some((station_id, hash)) => Some((station_id.to_string(), hash.to_string())),
+            None => {
+                warn!("Skipping malformed credentials line in {}", path.display());
+                None
+            }
+        })
+        .collect();
+    Ok(hashes)
+}
+
+fn write_credentials_file(path: &Path, hashes: &HashMap<String, String>) -> io::Result<()> {
+    let mut entries: Vec<_> = hashes.iter().collect();
+    entries.sort();
+    let contents: String = entries
+        .into_iter()
+        .map(|(station_id, hash)| format!("{station_id}:{hash}\n"))
+        .collect();
+
+    let tmp = path.with_extension("tmp");
+    fs::write(&tmp, contents)?;
+    fs::rename(tmp, path)
+}
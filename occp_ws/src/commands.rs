@@ -0,0 +1,530 @@
+//! Operator commands to chargers, run as asynchronous jobs.
+//!
+//! `submit` hands back a job straight away and sends the OCPP call in the
+//! background. A job moves from `queued` to `sent` and then to `accepted`,
+//! `rejected`, `timed_out` or `failed`. Remote starts, remote stops and
+//! resets become `confirmed` once the charger reports the effect: the
+//! StartTransaction, the StopTransaction or the BootNotification after the
+//! reset.
+//!
+//! A submission may carry an idempotency key. Repeating the same command
+//! with the same key returns the original job instead of sending it again.
+//! Keys are per caller, so two callers never share a job through one.
+
+use std::{
+    collections::HashMap,
+    sync::{LazyLock, RwLock},
+};
+
+use chrono::{DateTime, Duration as ChronoDuration, Utc};
+use rust_decimal::Decimal;
+use rust_ocpp::v1_6::{
+    messages::{
+        change_availability::ChangeAvailabilityRequest,
+        remote_start_transaction::RemoteStartTransactionRequest,
+        remote_stop_transaction::RemoteStopTransactionRequest, reset::ResetRequest,
+        set_charging_profile::SetChargingProfileRequest, unlock_connector::UnlockConnectorRequest,
+    },
+    types::{
+        AvailabilityType, ChargingProfile, ChargingProfileKindType, ChargingProfilePurposeType,
+        ChargingRateUnitType, ChargingSchedule, ChargingSchedulePeriod, ResetRequestStatus,
+    },
+};
+use serde::Serialize;
+use serde_json::Value;
+use tracing::{info, warn};
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::audit::{Actor, ActorKind};
+use crate::connections::{self, CallError};
+use crate::events::{self, StationEvent};
+use crate::rest::openapi;
+use crate::state::load_command_config;
+use crate::transactions::{self, Transaction};
+use crate::types::*;
+
+/// Jobs and idempotency keys are forgotten this long after they were made.
+const JOB_RETENTION_HOURS: i64 = 24;
+
+/// Longest id tag OCPP 1.6 allows (IdToken).
+const MAX_ID_TAG_LEN: usize = 20;
+const MAX_CURRENT_LIMIT_A: f64 = 1000.0;
+
+/// Charging profile id used for current limits, so a new limit replaces
+/// the previous one.
+const CURRENT_LIMIT_PROFILE_ID: i32 = 1;
+
+static JOBS: LazyLock<RwLock<Jobs>> = LazyLock::new(|| RwLock::new(Jobs::default()));
+
+#[derive(Default)]
+struct Jobs {
+    by_id: HashMap<String, CommandJob>,
+    /// Idempotency key to the job it created.
+    by_key: HashMap<IdempotencyKey, String>,
+}
+
+/// An idempotency key with the kind and id of the caller that sent it.
+type IdempotencyKey = (ActorKind, String, String);
+
+/// What an operator asked a charger to do.
+#[derive(Serialize, ToSchema, Debug, Clone, PartialEq)]
+#[serde(tag = "command", rename_all = "snake_case")]
+pub enum Command {
+    RemoteStart {
+        /// `None` lets the charger pick a connector.
+        connector_id: Option<u32>,
+        id_tag: String,
+    },
+    RemoteStop {
+        transaction_id: i32,
+    },
+    Reset {
+        #[schema(value_type = openapi::ResetType)]
+        reset_type: ResetRequestStatus,
+    },
+    Unlock {
+        connector_id: u32,
+    },
+    ChangeAvailability {
+        /// `0` is the whole charge point.
+        connector_id: u32,
+        #[schema(value_type = openapi::AvailabilityType)]
+        availability: AvailabilityType,
+    },
+    /// Cap the charging current through a default charging profile.
+    SetCurrentLimit {
+        /// `0` applies the limit to the whole charge point.
+        connector_id: u32,
+        limit_a: f64,
+    },
+}
+
+#[derive(Serialize, ToSchema, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum JobStatus {
+    Queued,
+    Sent,
+    Accepted,
+    Rejected,
+    /// The charger did not answer in time.
+    TimedOut,
+    /// The call could not be made, e.g. because the charger went away.
+    Failed,
+    /// The charger reported the effect of an accepted command.
+    Confirmed,
+}
+
+#[derive(Serialize, ToSchema, Debug, Clone, PartialEq)]
+pub struct JobTransition {
+    pub status: JobStatus,
+    pub at: DateTime<Utc>,
+}
+
+#[derive(Serialize, ToSchema, Debug, Clone, PartialEq)]
+pub struct CommandJob {
+    pub job_id: String,
+    pub station_id: String,
+    #[serde(flatten)]
+    pub command: Command,
+    pub status: JobStatus,
+    /// Every status the job went through, oldest first.
+    pub history: Vec<JobTransition>,
+    /// The charger's answer to the OCPP call.
+    pub response: Option<Value>,
+    /// Why the job was rejected or failed.
+    pub error: Option<String>,
+    /// Transaction the command started or stopped, once confirmed.
+    pub transaction_id: Option<i32>,
+    /// Who submitted the job; kept for the audit log.
+    #[serde(skip)]
+    pub requested_by: Actor,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl CommandJob {
+    /// Whether the job starts a session for one of `id_tags`, or stops one.
+    pub fn concerns(&self, id_tags: &[String]) -> bool {
+        match &self.command {
+            Command::RemoteStart { id_tag, .. } => id_tags.contains(id_tag),
+            Command::RemoteStop { transaction_id } => {
+                transactions::id_tag(*transaction_id).is_some_and(|tag| id_tags.contains(&tag))
+            }
+            _ => false,
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum SubmitError {
+    #[error("station {0} is not connected")]
+    Offline(String),
+    #[error("idempotency key {0:?} was already used for a different command")]
+    KeyReused(String),
+    /// The command's values are out of range, or there is nothing to stop.
+    #[error("{0}")]
+    Invalid(String),
+}
+
+impl Command {
+    fn validate(&self) -> Result<(), SubmitError> {
+        let invalid = |message: String| Err(SubmitError::Invalid(message));
+        match self {
+            Self::RemoteStart { id_tag, .. }
+                if id_tag.is_empty() || id_tag.chars().count() > MAX_ID_TAG_LEN =>
+            {
+                invalid(format!("id_tag must be 1 to {MAX_ID_TAG_LEN} characters"))
+            }
+            Self::RemoteStart {
+                connector_id: Some(0),
+                ..
+            }
+            | Self::Unlock { connector_id: 0 } => {
+                invalid("connector_id must be 1 or higher".to_string())
+            }
+            Self::SetCurrentLimit { limit_a, .. }
+                if !(0.0..=MAX_CURRENT_LIMIT_A).contains(limit_a) =>
+            {
+                invalid(format!(
+                    "limit_a must be between 0 and {MAX_CURRENT_LIMIT_A}"
+                ))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn action(&self) -> OcppActionEnum {
+        match self {
+            Self::RemoteStart { .. } => OcppActionEnum::RemoteStartTransaction,
+            Self::RemoteStop { .. } => OcppActionEnum::RemoteStopTransaction,
+            Self::Reset { .. } => OcppActionEnum::Reset,
+            Self::Unlock { .. } => OcppActionEnum::UnlockConnector,
+            Self::ChangeAvailability { .. } => OcppActionEnum::ChangeAvailability,
+            Self::SetCurrentLimit { .. } => OcppActionEnum::SetChargingProfile,
+        }
+    }
+
+    fn payload(&self) -> OcppPayload {
+        match self.clone() {
+            Self::RemoteStart {
+                connector_id,
+                id_tag,
+            } => OcppPayload::RemoteStartTransaction(RemoteStartTransactionKind::Request(
+                RemoteStartTransactionRequest {
+                    connector_id,
+                    id_tag,
+                    charging_profile: None,
+                },
+            )),
+            Self::RemoteStop { transaction_id } => OcppPayload::RemoteStopTransaction(
+                RemoteStopTransactionKind::Request(RemoteStopTransactionRequest { transaction_id }),
+            ),
+            Self::Reset { reset_type } => {
+                OcppPayload::Reset(ResetKind::Request(ResetRequest { kind: reset_type }))
+            }
+            Self::Unlock { connector_id } => {
+                OcppPayload::UnlockConnector(UnlockConnectorKind::Request(UnlockConnectorRequest {
+                    connector_id,
+                }))
+            }
+            Self::ChangeAvailability {
+                connector_id,
+                availability,
+            } => OcppPayload::ChangeAvailability(ChangeAvailabilityKind::Request(
+                ChangeAvailabilityRequest {
+                    connector_id,
+                    kind: availability,
+                },
+            )),
+            Self::SetCurrentLimit {
+                connector_id,
+                limit_a,
+            } => OcppPayload::SetChargingProfile(SetChargingProfileKind::Request(
+                SetChargingProfileRequest {
+                    connector_id: connector_id as i32,
+                    cs_charging_profiles: current_limit_profile(limit_a),
+                },
+            )),
+        }
+    }
+
+    /// Whether the charger reports the command's effect later on.
+    fn awaits_confirmation(&self) -> bool {
+        matches!(
+            self,
+            Self::RemoteStart { .. } | Self::RemoteStop { .. } | Self::Reset { .. }
+        )
+    }
+}
+
+fn current_limit_profile(limit_a: f64) -> ChargingProfile {
+    ChargingProfile {
+        charging_profile_id: CURRENT_LIMIT_PROFILE_ID,
+        transaction_id: None,
+        stack_level: 0,
+        charging_profile_purpose: ChargingProfilePurposeType::TxDefaultProfile,
+        charging_profile_kind: ChargingProfileKindType::Absolute,
+        recurrency_kind: None,
+        valid_from: None,
+        valid_to: None,
+        charging_schedule: ChargingSchedule {
+            duration: None,
+            start_schedule: Some(Utc::now()),
+            charging_rate_unit: ChargingRateUnitType::A,
+            charging_schedule_period: vec![ChargingSchedulePeriod {
+                start_period: 0,
+                // One decimal is as fine as chargers regulate current.
+                limit: Decimal::from_f64_retain(limit_a)
+                    .unwrap_or_default()
+                    .round_dp(1),
+                number_phases: None,
+            }],
+            min_charging_rate: None,
+        },
+    }
+}
+
+/// The transaction a remote stop should end: `transaction_id`, the one
+/// running on `connector_id`, or the station's only running transaction.
+pub fn stop_target(
+    station_id: &str,
+    transaction_id: Option<i32>,
+    connector_id: Option<u32>,
+) -> Result<i32, SubmitError> {
+    let candidates: Vec<i32> = transactions::open_transactions(Some(station_id))
+        .iter()
+        .filter(|transaction| {
+            transaction_id.is_none_or(|id| transaction.transaction_id == id)
+                && connector_id.is_none_or(|id| transaction.connector_id == id)
+        })
+        .map(|transaction| transaction.transaction_id)
+        .collect();
+    match candidates.as_slice() {
+        [transaction_id] => Ok(*transaction_id),
+        [] => Err(SubmitError::Invalid(format!(
+            "no matching transaction is running on station {station_id}"
+        ))),
+        _ => Err(SubmitError::Invalid(
+            "several transactions are running, pass transaction_id or connector_id".to_string(),
+        )),
+    }
+}
+
+/// Queue `command` for a connected station and send it in the background on
+/// behalf of `actor`.
+pub fn submit(
+    station_id: &str,
+    command: Command,
+    idempotency_key: Option<&str>,
+    actor: &Actor,
+) -> Result<CommandJob, SubmitError> {
+    command.validate()?;
+    let now = Utc::now();
+    let key = idempotency_key.map(|key| (actor.kind, actor.id.clone(), key.to_string()));
+    let job = {
+        let mut jobs = JOBS.write().expect("command jobs lock poisoned");
+        jobs.prune(now);
+        if let Some(key) = &key
+            && let Some(job) = jobs.by_key.get(key).and_then(|id| jobs.by_id.get(id))
+        {
+            if job.station_id != station_id || job.command != command {
+                return Err(SubmitError::KeyReused(key.2.clone()));
+            }
+            return Ok(job.clone());
+        }
+        if !connections::is_connected(station_id) {
+            return Err(SubmitError::Offline(station_id.to_string()));
+        }
+
+        let job = CommandJob {
+            job_id: Uuid::new_v4().to_string(),
+            station_id: station_id.to_string(),
+            command,
+            status: JobStatus::Queued,
+            history: vec![JobTransition {
+                status: JobStatus::Queued,
+                at: now,
+            }],
+            response: None,
+            error: None,
+            transaction_id: None,
+            requested_by: actor.clone(),
+            created_at: now,
+            updated_at: now,
+        };
+        jobs.by_id.insert(job.job_id.clone(), job.clone());
+        if let Some(key) = key {
+            jobs.by_key.insert(key, job.job_id.clone());
+        }
+        job
+    };
+    info!(station_id, job_id = %job.job_id, command = ?job.command, "Command queued");
+    publish(&job);
+
+    let job_id = job.job_id.clone();
+    tokio::spawn(async move { run(&job_id).await });
+    Ok(job)
+}
+
+async fn run(job_id: &str) {
+    let Some(job) = update(job_id, |job| job.set_status(JobStatus::Sent)) else {
+        return;
+    };
+    let timeout = load_command_config().await.timeout;
+    let result: Result<Value, CallError> = match connections::get(&job.station_id) {
+        Some(connection) => {
+            connection
+                .call(
+                    job.command.action(),
+                    job.command.payload(),
+                    timeout,
+                    &job.requested_by,
+                )
+                .await
+        }
+        None => Err(CallError::NotConnected(job.station_id.clone())),
+    };
+
+    update(job_id, |job| match result {
+        Ok(response) => {
+            let status = response.get("status").and_then(Value::as_str);
+            // Unlocked and Scheduled are the positive answers to
+            // UnlockConnector and ChangeAvailability.
+            if matches!(status, Some("Accepted" | "Unlocked" | "Scheduled")) {
+                job.set_status(JobStatus::Accepted);
+            } else {
+                job.error = Some(format!(
+                    "charger answered {}",
+                    status.unwrap_or("without a status")
+                ));
+                job.set_status(JobStatus::Rejected);
+            }
+            job.response = Some(response);
+        }
+        Err(err) => {
+            warn!(station_id = %job.station_id, job_id = %job.job_id, "Command failed: {err}");
+            job.error = Some(err.to_string());
+            job.set_status(match err {
+                CallError::Timeout(_) => JobStatus::TimedOut,
+                CallError::Rejected { .. } => JobStatus::Rejected,
+                _ => JobStatus::Failed,
+            });
+        }
+    });
+}
+
+impl CommandJob {
+    fn set_status(&mut self, status: JobStatus) {
+        let now = Utc::now();
+        self.status = status;
+        self.updated_at = now;
+        self.history.push(JobTransition { status, at: now });
+    }
+}
+
+impl Jobs {
+    fn prune(&mut self, now: DateTime<Utc>) {
+        let cutoff = now - ChronoDuration::hours(JOB_RETENTION_HOURS);
+        self.by_id.retain(|_, job| job.created_at >= cutoff);
+        let by_id = &self.by_id;
+        self.by_key.retain(|_, job_id| by_id.contains_key(job_id));
+    }
+}
+
+/// Change a job and tell subscribers. Returns the updated job.
+fn update(job_id: &str, change: impl FnOnce(&mut CommandJob)) -> Option<CommandJob> {
+    let job = {
+        let mut jobs = JOBS.write().expect("command jobs lock poisoned");
+        let job = jobs.by_id.get_mut(job_id)?;
+        change(job);
+        job.clone()
+    };
+    publish(&job);
+    Some(job)
+}
+
+fn publish(job: &CommandJob) {
+    events::publish(StationEvent::CommandUpdated {
+        station_id: job.station_id.clone(),
+        at: job.updated_at,
+        job_id: job.job_id.clone(),
+        status: job.status,
+    });
+}
+
+pub fn get(job_id: &str) -> Option<CommandJob> {
+    JOBS.read()
+        .expect("command jobs lock poisoned")
+        .by_id
+        .get(job_id)
+        .cloned()
+}
+
+/// Recent jobs, optionally for one station, newest first.
+pub fn list(station_id: Option<&str>) -> Vec<CommandJob> {
+    let mut jobs: Vec<_> = JOBS
+        .read()
+        .expect("command jobs lock poisoned")
+        .by_id
+        .values()
+        .filter(|job| station_id.is_none_or(|id| job.station_id == id))
+        .cloned()
+        .collect();
+    jobs.sort_by_key(|job| std::cmp::Reverse(job.created_at));
+    jobs
+}
+
+/// Confirm the oldest accepted job matching `matches`.
+fn confirm(
+    station_id: &str,
+    transaction_id: Option<i32>,
+    matches: impl Fn(&Command) -> bool,
+) -> Option<CommandJob> {
+    let job_id = JOBS
+        .read()
+        .expect("command jobs lock poisoned")
+        .by_id
+        .values()
+        .filter(|job| {
+            job.station_id == station_id
+                && job.status == JobStatus::Accepted
+                && job.command.awaits_confirmation()
+                && matches(&job.command)
+        })
+        .min_by_key(|job| job.created_at)
+        .map(|job| job.job_id.clone())?;
+    update(&job_id, |job| {
+        job.transaction_id = transaction_id.or(job.transaction_id);
+        job.set_status(JobStatus::Confirmed);
+    })
+}
+
+/// A StartTransaction arrived; confirm the remote start that caused it.
+pub fn transaction_started(station_id: &str, transaction: &Transaction) -> Option<CommandJob> {
+    confirm(station_id, Some(transaction.transaction_id), |command| {
+        matches!(
+            command,
+            Command::RemoteStart { connector_id, id_tag }
+                if *id_tag == transaction.id_tag
+                    && connector_id.is_none_or(|id| id == transaction.connector_id)
+        )
+    })
+}
+
+/// A StopTransaction arrived; confirm the remote stop that asked for it.
+pub fn transaction_stopped(station_id: &str, transaction: &Transaction) -> Option<CommandJob> {
+    confirm(station_id, Some(transaction.transaction_id), |command| {
+        matches!(
+            command,
+            Command::RemoteStop { transaction_id } if *transaction_id == transaction.transaction_id
+        )
+    })
+}
+
+/// The station booted; confirm the reset that restarted it.
+pub fn station_booted(station_id: &str) -> Option<CommandJob> {
+    confirm(station_id, None, |command| {
+        matches!(command, Command::Reset { .. })
+    })
+}
@@ -0,0 +1,351 @@
+//! Registry of live charger connections and server-initiated OCPP calls.
+
+use std::{
+    collections::HashMap,
+    net::SocketAddr,
+    sync::{
+        Arc, LazyLock, Mutex, RwLock,
+        atomic::{AtomicBool, AtomicU64, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+use axum::extract::ws::{CloseFrame, Message as AxumWSMessage};
+use common::DuplicateConnectionPolicy;
+use serde::de::DeserializeOwned;
+use serde_json::json;
+use storage::CommandStatus;
+use tokio::{
+    sync::{Notify, mpsc, oneshot},
+    time::timeout,
+};
+use tracing::{debug, warn};
+use uuid::Uuid;
+
+use crate::audit::{self, Actor, Outcome};
+use crate::handlers::CALL_MESSAGE_TYPE_ID;
+use crate::persistence;
+use crate::types::*;
+
+/// How long a server-initiated call waits for the charger's CallResult.
+pub const DEFAULT_CALL_TIMEOUT: Duration = Duration::from_secs(30);
+
+static CONNECTIONS: LazyLock<RwLock<HashMap<String, Arc<StationConnection>>>> =
+    LazyLock::new(|| RwLock::new(HashMap::new()));
+
+/// Answer received from a charger for a call the server sent.
+#[derive(Debug, Clone, PartialEq)]
+pub enum CallResponse {
+    Result(serde_json::Value),
+    Error {
+        code: OcppErrorCode,
+        description: OcppErrorDescription,
+        details: OcppErrorDetails,
+    },
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum CallError {
+    #[error("station {0} is not connected")]
+    NotConnected(String),
+    #[error("connection closed before the station responded")]
+    ConnectionClosed,
+    #[error("station did not respond within {0:?}")]
+    Timeout(Duration),
+    #[error("station returned CallError {code}: {description}")]
+    Rejected {
+        code: OcppErrorCode,
+        description: OcppErrorDescription,
+        details: OcppErrorDetails,
+    },
+    #[error("failed to encode or decode OCPP payload: {0}")]
+    Serde(#[from] serde_json::Error),
+}
+
+/// A single open WebSocket session with a charger.
+#[derive(Debug)]
+pub struct StationConnection {
+    pub station_id: String,
+    pub addr: SocketAddr,
+    sender: mpsc::Sender<AxumWSMessage>,
+    pending: Mutex<HashMap<OcppMessageId, oneshot::Sender<CallResponse>>>,
+    ping_counter: AtomicU64,
+    /// Payload and send time of the ping still waiting for its pong.
+    outstanding_ping: Mutex<Option<(Vec<u8>, Instant)>>,
+    /// Signalled when the server ends this session, e.g. on takeover.
+    closing: Notify,
+    seen_call: AtomicBool,
+}
+
+impl StationConnection {
+    pub fn new(station_id: String, addr: SocketAddr, sender: mpsc::Sender<AxumWSMessage>) -> Self {
+        Self {
+            station_id,
+            addr,
+            sender,
+            pending: Mutex::new(HashMap::new()),
+            ping_counter: AtomicU64::new(0),
+            outstanding_ping: Mutex::new(None),
+            closing: Notify::new(),
+            seen_call: AtomicBool::new(false),
+        }
+    }
+
+    /// Queue a frame for the charger. Returns `false` if the socket is gone.
+    pub async fn send(&self, message: AxumWSMessage) -> bool {
+        self.sender.send(message).await.is_ok()
+    }
+
+    /// Returns `true` for the first Call the charger sends on this session.
+    pub fn take_first_call(&self) -> bool {
+        !self.seen_call.swap(true, Ordering::Relaxed)
+    }
+
+    /// End the session from the server side: fail outstanding calls, wake up
+    /// the socket task and queue a Close frame for it to flush.
+    ///
+    /// Never waits: the socket being replaced may be half-open, with a full
+    /// queue its writer cannot drain. The frame is dropped then.
+    pub fn close(&self, code: u16, reason: &str) {
+        self.fail_pending();
+        self.closing.notify_one();
+        let frame = AxumWSMessage::Close(Some(CloseFrame {
+            code,
+            reason: reason.to_string().into(),
+        }));
+        if self.sender.try_send(frame).is_err() {
+            debug!(station_id = %self.station_id, "Dropped Close frame for a stuck connection");
+        }
+    }
+
+    /// Resolves once `close` has been called.
+    pub async fn closed(&self) {
+        self.closing.notified().await
+    }
+
+    /// Send a WebSocket ping and start waiting for its pong.
+    pub async fn ping(&self) -> bool {
+        let payload = self
+            .ping_counter
+            .fetch_add(1, Ordering::Relaxed)
+            .to_be_bytes()
+            .to_vec();
+        *self
+            .outstanding_ping
+            .lock()
+            .expect("outstanding ping lock poisoned") = Some((payload.clone(), Instant::now()));
+        self.send(AxumWSMessage::Ping(payload)).await
+    }
+
+    /// Match a pong against the outstanding ping. Returns the round-trip time
+    /// if it answers that ping.
+    pub fn pong(&self, payload: &[u8]) -> Option<Duration> {
+        let mut outstanding = self
+            .outstanding_ping
+            .lock()
+            .expect("outstanding ping lock poisoned");
+        match outstanding.as_ref() {
+            Some((expected, sent_at)) if expected == payload => {
+                let rtt = sent_at.elapsed();
+                *outstanding = None;
+                Some(rtt)
+            }
+            _ => None,
+        }
+    }
+
+    /// Whether the last ping has gone unanswered.
+    pub fn awaiting_pong(&self) -> bool {
+        self.outstanding_ping
+            .lock()
+            .expect("outstanding ping lock poisoned")
+            .is_some()
+    }
+
+    /// Send an OCPP Call to the charger on behalf of `actor` and wait for
+    /// its response. The call is recorded in the audit log.
+    pub async fn call<R: DeserializeOwned>(
+        &self,
+        action: OcppActionEnum,
+        payload: OcppPayload,
+        wait: Duration,
+        actor: &Actor,
+    ) -> Result<R, CallError> {
+        let message_id = Uuid::new_v4().to_string();
+        let request = serde_json::to_value(&payload)?;
+        let frame = serde_json::to_string(&OcppCall(
+            CALL_MESSAGE_TYPE_ID,
+            message_id.clone(),
+            action.clone(),
+            payload,
+        ))?;
+        persistence::command_sent(&message_id, &self.station_id, &action.to_string(), &request);
+
+        let response = self.exchange(&message_id, frame, wait).await;
+        let (status, outcome, record) = match &response {
+            Ok(CallResponse::Result(value)) => (
+                CommandStatus::Completed,
+                audit::answer_outcome(value),
+                value.clone(),
+            ),
+            Ok(CallResponse::Error {
+                code,
+                description,
+                details,
+            }) => (
+                CommandStatus::Rejected,
+                Outcome::Rejected,
+                json!({ "code": code, "description": description, "details": details }),
+            ),
+            Err(err) => (
+                CommandStatus::Failed,
+                Outcome::Failed,
+                json!({ "error": err.to_string() }),
+            ),
+        };
+        audit::record(
+            actor,
+            &action.to_string(),
+            Some(&self.station_id),
+            Some(request),
+            Some(record.clone()),
+            outcome,
+        );
+        persistence::command_finished(&message_id, status, record);
+
+        match response? {
+            CallResponse::Result(value) => Ok(serde_json::from_value(value)?),
+            CallResponse::Error {
+                code,
+                description,
+                details,
+            } => Err(CallError::Rejected {
+                code,
+                description,
+                details,
+            }),
+        }
+    }
+
+    /// Send a Call frame and wait for the matching response.
+    async fn exchange(
+        &self,
+        message_id: &str,
+        frame: String,
+        wait: Duration,
+    ) -> Result<CallResponse, CallError> {
+        let (tx, rx) = oneshot::channel();
+        self.pending
+            .lock()
+            .expect("pending calls lock poisoned")
+            .insert(message_id.to_string(), tx);
+
+        debug!(station_id = %self.station_id, "Outgoing call: {frame}");
+        if self.sender.send(AxumWSMessage::Text(frame)).await.is_err() {
+            self.forget(message_id);
+            return Err(CallError::ConnectionClosed);
+        }
+
+        match timeout(wait, rx).await {
+            Ok(Ok(response)) => Ok(response),
+            Ok(Err(_)) => Err(CallError::ConnectionClosed),
+            Err(_) => {
+                self.forget(message_id);
+                Err(CallError::Timeout(wait))
+            }
+        }
+    }
+
+    /// Hand a CallResult/CallError to the call waiting on `message_id`.
+    ///
+    /// Returns `false` if no server call with that id is outstanding.
+    pub fn complete(&self, message_id: &str, response: CallResponse) -> bool {
+        let waiter = self
+            .pending
+            .lock()
+            .expect("pending calls lock poisoned")
+            .remove(message_id);
+        match waiter {
+            Some(tx) => {
+                let _ = tx.send(response);
+                true
+            }
+            None => {
+                warn!(station_id = %self.station_id, message_id, "Response for unknown call");
+                false
+            }
+        }
+    }
+
+    /// Fail every outstanding server call, e.g. when the socket closes.
+    pub fn fail_pending(&self) {
+        self.pending
+            .lock()
+            .expect("pending calls lock poisoned")
+            .clear();
+    }
+
+    fn forget(&self, message_id: &str) {
+        self.pending
+            .lock()
+            .expect("pending calls lock poisoned")
+            .remove(message_id);
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("station {0} already has an active connection")]
+pub struct AlreadyConnected(pub String);
+
+/// Track a freshly upgraded connection under its station id. Under
+/// `NewestWins` the connection it displaced is returned so the caller can
+/// close it.
+pub fn register(
+    connection: Arc<StationConnection>,
+    policy: DuplicateConnectionPolicy,
+) -> Result<Option<Arc<StationConnection>>, AlreadyConnected> {
+    let mut connections = CONNECTIONS.write().expect("connections lock poisoned");
+    let station_id = connection.station_id.clone();
+    if policy == DuplicateConnectionPolicy::RejectNewcomer && connections.contains_key(&station_id)
+    {
+        return Err(AlreadyConnected(station_id));
+    }
+    Ok(connections.insert(station_id, connection))
+}
+
+/// Drop a connection from the registry if it is still the active one.
+pub fn unregister(connection: &Arc<StationConnection>) {
+    let mut connections = CONNECTIONS.write().expect("connections lock poisoned");
+    if connections
+        .get(&connection.station_id)
+        .is_some_and(|current| Arc::ptr_eq(current, connection))
+    {
+        connections.remove(&connection.station_id);
+    }
+}
+
+pub fn get(station_id: &str) -> Option<Arc<StationConnection>> {
+    CONNECTIONS
+        .read()
+        .expect("connections lock poisoned")
+        .get(station_id)
+        .cloned()
+}
+
+pub fn is_connected(station_id: &str) -> bool {
+    get(station_id).is_some()
+}
+
+/// Send an OCPP Call to a connected station on behalf of `actor`, using the
+/// default timeout.
+pub async fn call<R: DeserializeOwned>(
+    station_id: &str,
+    action: OcppActionEnum,
+    payload: OcppPayload,
+    actor: &Actor,
+) -> Result<R, CallError> {
+    let connection = get(station_id).ok_or_else(|| CallError::NotConnected(station_id.into()))?;
+    connection
+        .call(action, payload, DEFAULT_CALL_TIMEOUT, actor)
+        .await
+}
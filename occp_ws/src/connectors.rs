@@ -0,0 +1,103 @@
+//! Latest reported state of every connector, from StatusNotification.
+
+use std::{
+    collections::HashMap,
+    sync::{LazyLock, RwLock},
+};
+
+use chrono::{DateTime, Utc};
+use rust_ocpp::v1_6::{
+    messages::status_notification::StatusNotificationRequest,
+    types::{ChargePointErrorCode, ChargePointStatus},
+};
+use serde::Serialize;
+
+use crate::events::{self, StationEvent};
+use crate::persistence;
+
+static CONNECTORS: LazyLock<RwLock<HashMap<(String, u32), ConnectorState>>> =
+    LazyLock::new(|| RwLock::new(HashMap::new()));
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct ConnectorState {
+    pub station_id: String,
+    /// `0` is the charge point as a whole.
+    pub connector_id: u32,
+    pub status: ChargePointStatus,
+    pub error_code: ChargePointErrorCode,
+    pub info: Option<String>,
+    /// When the charger says the status changed, if it told us.
+    pub timestamp: Option<DateTime<Utc>>,
+    /// When the server received the notification.
+    pub updated_at: DateTime<Utc>,
+}
+
+impl ConnectorState {
+    /// Whether the connector reports energy flowing or about to flow again.
+    pub fn is_in_session(&self) -> bool {
+        matches!(
+            self.status,
+            ChargePointStatus::Charging
+                | ChargePointStatus::SuspendedEV
+                | ChargePointStatus::SuspendedEVSE
+        )
+    }
+}
+
+pub fn update_status(station_id: &str, notification: &StatusNotificationRequest) -> ConnectorState {
+    let state = ConnectorState {
+        station_id: station_id.to_string(),
+        connector_id: notification.connector_id,
+        status: notification.status.clone(),
+        error_code: notification.error_code.clone(),
+        info: notification.info.clone(),
+        timestamp: notification.timestamp,
+        updated_at: Utc::now(),
+    };
+    CONNECTORS
+        .write()
+        .expect("connectors lock poisoned")
+        .insert(
+            (station_id.to_string(), notification.connector_id),
+            state.clone(),
+        );
+    persistence::save_connector(&state);
+    events::publish(StationEvent::ConnectorStatus {
+        station_id: state.station_id.clone(),
+        at: state.timestamp.unwrap_or(state.updated_at),
+        connector_id: state.connector_id,
+        status: state.status.clone(),
+        error_code: state.error_code.clone(),
+        info: state.info.clone(),
+    });
+    state
+}
+
+pub fn get(station_id: &str, connector_id: u32) -> Option<ConnectorState> {
+    CONNECTORS
+        .read()
+        .expect("connectors lock poisoned")
+        .get(&(station_id.to_string(), connector_id))
+        .cloned()
+}
+
+/// Load connector states from storage.
+pub fn restore(states: Vec<ConnectorState>) {
+    let mut connectors = CONNECTORS.write().expect("connectors lock poisoned");
+    for state in states {
+        connectors.insert((state.station_id.clone(), state.connector_id), state);
+    }
+}
+
+/// All known connectors of a station, ordered by connector id.
+pub fn for_station(station_id: &str) -> Vec<ConnectorState> {
+    let mut connectors: Vec<_> = CONNECTORS
+        .read()
+        .expect("connectors lock poisoned")
+        .values()
+        .filter(|state| state.station_id == station_id)
+        .cloned()
+        .collect();
+    connectors.sort_by_key(|state| state.connector_id);
+    connectors
+}
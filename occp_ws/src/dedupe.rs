@@ -0,0 +1,83 @@
+//! Replay protection for transaction messages.
+//!
+//! Chargers queue StartTransaction, MeterValues and StopTransaction while
+//! offline and may send the same Call again after a reconnect. A Call whose
+//! message id and payload match one already handled gets the original
+//! response back instead of being processed twice.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{LazyLock, Mutex},
+};
+
+use axum::extract::ws::Message as AxumWSMessage;
+use tracing::warn;
+
+use crate::types::*;
+
+/// Recent transaction Calls remembered per station.
+const REMEMBERED_CALLS: usize = 128;
+
+static HANDLED: LazyLock<Mutex<HashMap<String, VecDeque<HandledCall>>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+struct HandledCall {
+    message_id: OcppMessageId,
+    action: OcppActionEnum,
+    payload: serde_json::Value,
+    response: Vec<AxumWSMessage>,
+}
+
+/// Whether replays of `action` are detected.
+pub fn is_tracked(action: &OcppActionEnum) -> bool {
+    matches!(
+        action,
+        OcppActionEnum::StartTransaction
+            | OcppActionEnum::MeterValues
+            | OcppActionEnum::StopTransaction
+    )
+}
+
+/// The response sent the first time this exact Call was handled.
+pub fn replayed_response(
+    station_id: &str,
+    message_id: &OcppMessageId,
+    action: &OcppActionEnum,
+    payload: &serde_json::Value,
+) -> Option<Vec<AxumWSMessage>> {
+    let handled = HANDLED.lock().expect("dedupe lock poisoned");
+    let call = handled
+        .get(station_id)?
+        .iter()
+        .find(|call| &call.message_id == message_id)?;
+    if &call.action != action || &call.payload != payload {
+        // Some chargers restart their message ids after a reboot.
+        warn!(
+            station_id,
+            message_id, "Reused message id with a different payload"
+        );
+        return None;
+    }
+    Some(call.response.clone())
+}
+
+pub fn remember(
+    station_id: &str,
+    message_id: OcppMessageId,
+    action: OcppActionEnum,
+    payload: serde_json::Value,
+    response: &[AxumWSMessage],
+) {
+    let mut handled = HANDLED.lock().expect("dedupe lock poisoned");
+    let calls = handled.entry(station_id.to_string()).or_default();
+    calls.retain(|call| call.message_id != message_id);
+    if calls.len() == REMEMBERED_CALLS {
+        calls.pop_front();
+    }
+    calls.push_back(HandledCall {
+        message_id,
+        action,
+        payload,
+        response: response.to_vec(),
+    });
+}
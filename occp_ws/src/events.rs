@@ -0,0 +1,350 @@
+//! In-process bus for domain events: what happened to stations, connectors,
+//! sessions and commands.
+//!
+//! The modules that own the state publish here; the event stream API and
+//! integrations subscribe, so they never hook into the OCPP handlers. Every
+//! event gets a sequence number and is kept in a short backlog, so
+//! subscribers that reconnect can pick up where they left off.
+
+use std::{
+    collections::VecDeque,
+    net::SocketAddr,
+    sync::{LazyLock, Mutex},
+};
+
+use chrono::{DateTime, Utc};
+use futures::{Stream, stream};
+use rust_ocpp::v1_6::types::{
+    AuthorizationStatus, ChargePointErrorCode, ChargePointStatus, RegistrationStatus,
+};
+use serde::Serialize;
+use tokio::sync::broadcast::{self, error::RecvError};
+use tracing::warn;
+
+use crate::commands::{self, JobStatus};
+use crate::sessions::Session;
+use crate::transactions;
+
+/// Events buffered per subscriber before slow receivers start lagging.
+const EVENT_CHANNEL_CAPACITY: usize = 256;
+
+/// Recent events kept for clients resuming with a last event id.
+pub const BACKLOG_CAPACITY: usize = 1000;
+
+static EVENTS: LazyLock<broadcast::Sender<StationEvent>> =
+    LazyLock::new(|| broadcast::channel(EVENT_CHANNEL_CAPACITY).0);
+static RECORDS: LazyLock<broadcast::Sender<EventRecord>> =
+    LazyLock::new(|| broadcast::channel(EVENT_CHANNEL_CAPACITY).0);
+static BACKLOG: LazyLock<Mutex<Backlog>> = LazyLock::new(|| Mutex::new(Backlog::default()));
+
+#[derive(Default)]
+struct Backlog {
+    last_id: u64,
+    events: VecDeque<EventRecord>,
+}
+
+/// A published event with its sequence number.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct EventRecord {
+    /// Increases by one per event; restarts with the server.
+    pub id: u64,
+    #[serde(flatten)]
+    pub event: StationEvent,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum StationEvent {
+    /// The station sent a BootNotification.
+    Booted {
+        station_id: String,
+        at: DateTime<Utc>,
+        vendor: String,
+        model: String,
+        firmware_version: Option<String>,
+        registration_status: RegistrationStatus,
+    },
+    /// An operator approved or rejected the station.
+    RegistrationChanged {
+        station_id: String,
+        at: DateTime<Utc>,
+        registration_status: RegistrationStatus,
+    },
+    Online {
+        station_id: String,
+        at: DateTime<Utc>,
+    },
+    /// The station opened a new session while its previous one was still
+    /// open; the previous one was closed.
+    Reconnected {
+        station_id: String,
+        at: DateTime<Utc>,
+        previous_addr: SocketAddr,
+        addr: SocketAddr,
+    },
+    Offline {
+        station_id: String,
+        at: DateTime<Utc>,
+        /// When the station was last heard from.
+        last_seen: DateTime<Utc>,
+        reason: OfflineReason,
+    },
+    /// An operator command changed status, see `commands`.
+    CommandUpdated {
+        station_id: String,
+        at: DateTime<Utc>,
+        job_id: String,
+        status: JobStatus,
+    },
+    /// A connector reported a new status.
+    ConnectorStatus {
+        station_id: String,
+        at: DateTime<Utc>,
+        connector_id: u32,
+        status: ChargePointStatus,
+        error_code: ChargePointErrorCode,
+        info: Option<String>,
+    },
+    SessionStarted {
+        station_id: String,
+        at: DateTime<Utc>,
+        session: Session,
+    },
+    SessionStopped {
+        station_id: String,
+        at: DateTime<Utc>,
+        session: Session,
+    },
+    /// The latest reading of a MeterValues message.
+    MeterValues {
+        station_id: String,
+        /// When the charger took the reading.
+        at: DateTime<Utc>,
+        connector_id: u32,
+        transaction_id: Option<i32>,
+        /// Energy import register, in Wh.
+        energy_wh: Option<f64>,
+        /// Active power import, in W.
+        power_w: Option<f64>,
+    },
+    /// An id tag was presented at the charger.
+    Authorized {
+        station_id: String,
+        at: DateTime<Utc>,
+        id_tag: String,
+        status: AuthorizationStatus,
+    },
+    /// A SecurityEventNotification, see `security`.
+    SecurityEvent {
+        station_id: String,
+        at: DateTime<Utc>,
+        event_type: String,
+        tech_info: Option<String>,
+        critical: bool,
+    },
+    /// A (Signed)FirmwareStatusNotification.
+    FirmwareStatus {
+        station_id: String,
+        at: DateTime<Utc>,
+        status: String,
+        /// Whether the status reports a failed download or installation.
+        failed: bool,
+    },
+}
+
+/// Every event `type`.
+pub const EVENT_TYPES: [&str; 13] = [
+    "booted",
+    "registration_changed",
+    "online",
+    "reconnected",
+    "offline",
+    "command_updated",
+    "connector_status",
+    "session_started",
+    "session_stopped",
+    "meter_values",
+    "authorized",
+    "security_event",
+    "firmware_status",
+];
+
+impl StationEvent {
+    pub fn station_id(&self) -> &str {
+        match self {
+            Self::Booted { station_id, .. }
+            | Self::RegistrationChanged { station_id, .. }
+            | Self::Online { station_id, .. }
+            | Self::Reconnected { station_id, .. }
+            | Self::Offline { station_id, .. }
+            | Self::CommandUpdated { station_id, .. }
+            | Self::ConnectorStatus { station_id, .. }
+            | Self::SessionStarted { station_id, .. }
+            | Self::SessionStopped { station_id, .. }
+            | Self::MeterValues { station_id, .. }
+            | Self::Authorized { station_id, .. }
+            | Self::SecurityEvent { station_id, .. }
+            | Self::FirmwareStatus { station_id, .. } => station_id,
+        }
+    }
+
+    /// The event's `type`, as serialised.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::Booted { .. } => "booted",
+            Self::RegistrationChanged { .. } => "registration_changed",
+            Self::Online { .. } => "online",
+            Self::Reconnected { .. } => "reconnected",
+            Self::Offline { .. } => "offline",
+            Self::CommandUpdated { .. } => "command_updated",
+            Self::ConnectorStatus { .. } => "connector_status",
+            Self::SessionStarted { .. } => "session_started",
+            Self::SessionStopped { .. } => "session_stopped",
+            Self::MeterValues { .. } => "meter_values",
+            Self::Authorized { .. } => "authorized",
+            Self::SecurityEvent { .. } => "security_event",
+            Self::FirmwareStatus { .. } => "firmware_status",
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum OfflineReason {
+    /// The WebSocket closed.
+    Disconnected,
+    /// Nothing arrived within the heartbeat deadline.
+    HeartbeatTimeout,
+    /// A server ping went unanswered and the socket was closed.
+    PongTimeout,
+}
+
+pub fn subscribe() -> broadcast::Receiver<StationEvent> {
+    EVENTS.subscribe()
+}
+
+/// Which events a subscriber wants; empty lists match everything.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct EventFilter {
+    pub station_ids: Vec<String>,
+    /// Event types, see [`EVENT_TYPES`].
+    pub kinds: Vec<String>,
+    /// Limits session, meter, authorization and command events to these id
+    /// tags; `None` passes all of them.
+    pub id_tags: Option<Vec<String>>,
+}
+
+impl EventFilter {
+    pub fn matches(&self, event: &StationEvent) -> bool {
+        (self.station_ids.is_empty() || self.station_ids.iter().any(|id| id == event.station_id()))
+            && (self.kinds.is_empty() || self.kinds.iter().any(|kind| kind == event.kind()))
+            && self
+                .id_tags
+                .as_deref()
+                .is_none_or(|id_tags| concerns(event, id_tags))
+    }
+}
+
+/// Whether an event may be shown to someone owning only `id_tags`.
+fn concerns(event: &StationEvent, id_tags: &[String]) -> bool {
+    match event {
+        StationEvent::SessionStarted { session, .. }
+        | StationEvent::SessionStopped { session, .. } => id_tags.contains(&session.id_tag),
+        StationEvent::Authorized { id_tag, .. } => id_tags.contains(id_tag),
+        StationEvent::MeterValues { transaction_id, .. } => transaction_id
+            .and_then(transactions::id_tag)
+            .is_some_and(|id_tag| id_tags.contains(&id_tag)),
+        StationEvent::CommandUpdated { job_id, .. } => {
+            commands::get(job_id).is_some_and(|job| job.concerns(id_tags))
+        }
+        _ => true,
+    }
+}
+
+struct Cursor {
+    receiver: broadcast::Receiver<EventRecord>,
+    backlog: VecDeque<EventRecord>,
+    /// Id of the newest event already passed on or filtered out.
+    last_id: u64,
+    filter: EventFilter,
+}
+
+/// Matching events in id order: backlogged ones after `last_id`, then live
+/// ones. A subscriber that falls behind is caught up from the backlog
+/// instead of missing events.
+pub fn stream(filter: EventFilter, last_id: Option<u64>) -> impl Stream<Item = EventRecord> {
+    // Subscribe first so nothing published while reading the backlog is lost.
+    let receiver = RECORDS.subscribe();
+    let (backlog, newest) = since(last_id);
+    let cursor = Cursor {
+        receiver,
+        last_id: backlog.first().map_or(newest, |record| record.id - 1),
+        backlog: backlog.into(),
+        filter,
+    };
+    stream::unfold(cursor, |mut cursor| async move {
+        loop {
+            let record = match cursor.backlog.pop_front() {
+                Some(record) => record,
+                None => match cursor.receiver.recv().await {
+                    Ok(record) if record.id <= cursor.last_id => continue,
+                    Ok(record) => record,
+                    Err(RecvError::Lagged(skipped)) => {
+                        warn!(skipped, "Event subscriber lagged, replaying backlog");
+                        cursor.backlog = since(Some(cursor.last_id)).0.into();
+                        continue;
+                    }
+                    Err(RecvError::Closed) => return None,
+                },
+            };
+            cursor.last_id = record.id;
+            if cursor.filter.matches(&record.event) {
+                return Some((record, cursor));
+            }
+        }
+    })
+}
+
+pub fn publish(event: StationEvent) {
+    let record = {
+        let mut backlog = BACKLOG.lock().expect("event backlog lock poisoned");
+        backlog.last_id += 1;
+        let record = EventRecord {
+            id: backlog.last_id,
+            event,
+        };
+        if backlog.events.len() == BACKLOG_CAPACITY {
+            backlog.events.pop_front();
+        }
+        backlog.events.push_back(record.clone());
+        // Send while holding the lock so records go out in id order.
+        let _ = RECORDS.send(record.clone());
+        record
+    };
+    // No subscribers is fine; the event is simply dropped.
+    let _ = EVENTS.send(record.event);
+}
+
+/// Backlogged events after `last_id`, none without one, and the id of the
+/// newest event. An id from before a server restart, i.e. newer than anything
+/// published since, returns the whole backlog.
+pub fn since(last_id: Option<u64>) -> (Vec<EventRecord>, u64) {
+    let backlog = BACKLOG.lock().expect("event backlog lock poisoned");
+    let records = match last_id {
+        Some(last_id) => {
+            let last_id = if last_id > backlog.last_id {
+                0
+            } else {
+                last_id
+            };
+            backlog
+                .events
+                .iter()
+                .filter(|record| record.id > last_id)
+                .cloned()
+                .collect()
+        }
+        None => Vec::new(),
+    };
+    (records, backlog.last_id)
+}
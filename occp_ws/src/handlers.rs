@@ -1,43 +1,100 @@
-use std::{net::SocketAddr, str::FromStr};
+use std::{net::SocketAddr, str::FromStr, sync::Arc, time::Duration};
 
-use axum::extract::ws::{Message as AxumWSMessage, WebSocket};
+use axum::extract::ws::{CloseFrame, Message as AxumWSMessage, WebSocket, close_code};
 use chrono::Utc;
+use common::KeepaliveConfig;
 use futures::{SinkExt, StreamExt};
 use rust_ocpp::v1_6::messages::{
     authorize::AuthorizeResponse, boot_notification::BootNotificationResponse,
-    data_transfer::DataTransferResponse, heart_beat::HeartbeatResponse,
+    data_transfer::DataTransferResponse,
+    firmware_status_notification::FirmwareStatusNotificationResponse,
+    heart_beat::HeartbeatResponse, meter_values::MeterValuesResponse,
+    start_transaction::StartTransactionResponse, status_notification::StatusNotificationResponse,
     stop_transaction::StopTransactionResponse,
 };
+use rust_ocpp::v1_6::types::{FirmwareStatus, RegistrationStatus};
 use serde::Serialize;
 use serde_json::json;
 use tokio::{
     select,
     sync::{mpsc, oneshot},
+    time::timeout,
 };
 use tracing::{debug, error, info, warn};
 
-use crate::state::load_allowed_serial_numbers;
+use crate::commands;
+use crate::connections::{self, CallResponse, StationConnection};
+use crate::connectors;
+use crate::dedupe;
+use crate::events::{self, OfflineReason, StationEvent};
+use crate::persistence;
+use crate::presence;
+use crate::resync;
+use crate::security::{
+    self, GenericStatus, LogStatusNotificationResponse, SecurityEventNotificationResponse,
+    SignCertificateResponse, SignedFirmwareStatusNotificationResponse,
+};
+use crate::state::{
+    load_allowed_serial_numbers, load_duplicate_connection_policy, load_heartbeat_config,
+    load_keepalive_config, load_registration_config,
+};
+use crate::stations;
+use crate::transactions;
 use crate::types::*;
 
 // OCPP 1.6 JSON framing message type identifiers
-const CALL_MESSAGE_TYPE_ID: OcppMessageTypeId = 2;
+pub(crate) const CALL_MESSAGE_TYPE_ID: OcppMessageTypeId = 2;
 const CALL_RESULT_MESSAGE_TYPE_ID: OcppMessageTypeId = 3;
 const CALL_ERROR_MESSAGE_TYPE_ID: OcppMessageTypeId = 4;
 
-enum OcppOutcome {
-    Continue(Vec<AxumWSMessage>),
-    Close(Vec<AxumWSMessage>),
-}
+/// How long a closing session may spend flushing its last frames.
+const CLOSE_FLUSH_TIMEOUT: Duration = Duration::from_secs(2);
 
-pub async fn handle_socket(socket: WebSocket, addr: SocketAddr) {
-    info!(addr = %addr, "New WebSocket connection: {addr}");
+pub async fn handle_socket(mut socket: WebSocket, addr: SocketAddr, station_id: String) {
+    info!(addr = %addr, station_id, "New WebSocket connection: {addr}");
 
-    let (mut ws_tx, mut ws_rx) = socket.split();
     let (out_tx, mut out_rx) = mpsc::channel::<AxumWSMessage>(64);
     let (shutdown_tx, mut shutdown_rx) = oneshot::channel::<()>();
 
-    let reader = {
+    let connection = Arc::new(StationConnection::new(
+        station_id.clone(),
+        addr,
+        out_tx.clone(),
+    ));
+    let policy = load_duplicate_connection_policy().await;
+    match connections::register(connection.clone(), policy) {
+        Ok(Some(replaced)) => {
+            info!(
+                station_id,
+                previous_addr = %replaced.addr,
+                "Station reconnected, closing its previous connection"
+            );
+            replaced.close(close_code::NORMAL, "Replaced by a new connection");
+            events::publish(StationEvent::Reconnected {
+                station_id: station_id.clone(),
+                at: Utc::now(),
+                previous_addr: replaced.addr,
+                addr,
+            });
+        }
+        Ok(None) => {}
+        Err(err) => {
+            warn!(addr = %addr, station_id, "Refusing WebSocket connection: {err}");
+            let frame = CloseFrame {
+                code: close_code::POLICY,
+                reason: "Station already connected".into(),
+            };
+            let _ = socket.send(AxumWSMessage::Close(Some(frame))).await;
+            return;
+        }
+    }
+    presence::touch(&station_id);
+
+    let (mut ws_tx, mut ws_rx) = socket.split();
+
+    let mut reader = {
         let out_tx = out_tx;
+        let connection = connection.clone();
         tokio::spawn(async move {
             while let Some(msg) = ws_rx.next().await {
                 let msg = match msg {
@@ -47,22 +104,16 @@ pub async fn handle_socket(socket: WebSocket, addr: SocketAddr) {
                         break;
                     }
                 };
+                presence::touch(&connection.station_id);
 
                 match msg {
                     AxumWSMessage::Text(text) => {
                         info!("\nINCOMING CALL\nFROM CHARGER\n\tMessage: {text}\n\tAddr: {addr}\n");
-                        let outcome = handle_ocpp_messages(text).await;
-                        let (outgoing, should_close) = match outcome {
-                            OcppOutcome::Continue(out) => (out, false),
-                            OcppOutcome::Close(out) => (out, true),
-                        };
+                        let outgoing = handle_ocpp_messages(&connection, text).await;
 
                         if !send_outgoing(&out_tx, outgoing).await {
                             break;
                         }
-                        if should_close {
-                            break;
-                        }
                     }
                     AxumWSMessage::Binary(_) => warn!("Unexpected binary message"),
                     AxumWSMessage::Ping(payload) => {
@@ -71,7 +122,13 @@ pub async fn handle_socket(socket: WebSocket, addr: SocketAddr) {
                             break;
                         }
                     }
-                    AxumWSMessage::Pong(_) => debug!("Received WebSocket Pong"),
+                    AxumWSMessage::Pong(payload) => match connection.pong(&payload) {
+                        Some(rtt) => {
+                            debug!(station_id = %connection.station_id, ?rtt, "Received WebSocket Pong");
+                            presence::record_rtt(&connection.station_id, rtt);
+                        }
+                        None => debug!("Received unsolicited WebSocket Pong"),
+                    },
                     AxumWSMessage::Close(frame) => {
                         let _ = out_tx.send(AxumWSMessage::Close(frame)).await;
                         break;
@@ -86,7 +143,19 @@ pub async fn handle_socket(socket: WebSocket, addr: SocketAddr) {
             select! {
                 biased;
 
-                _ = &mut shutdown_rx => break,
+                _ = &mut shutdown_rx => {
+                    // Deliver what is already queued, such as a Close frame,
+                    // without hanging on a dead peer.
+                    let _ = timeout(CLOSE_FLUSH_TIMEOUT, async {
+                        while let Ok(msg) = out_rx.try_recv() {
+                            if ws_tx.send(msg).await.is_err() {
+                                break;
+                            }
+                        }
+                    })
+                    .await;
+                    break;
+                }
 
                 maybe_msg = out_rx.recv() => {
                     match maybe_msg {
@@ -103,11 +172,46 @@ pub async fn handle_socket(socket: WebSocket, addr: SocketAddr) {
         }
     });
 
-    let _ = reader.await;
+    let offline_reason = select! {
+        _ = &mut reader => OfflineReason::Disconnected,
+        _ = keepalive(&connection, load_keepalive_config().await) => {
+            warn!(addr = %addr, station_id, "No pong from charger, closing dead connection");
+            reader.abort();
+            OfflineReason::PongTimeout
+        }
+        _ = connection.closed() => {
+            reader.abort();
+            OfflineReason::Disconnected
+        }
+    };
+    connections::unregister(&connection);
+    connection.fail_pending();
+    if !connections::is_connected(&station_id) {
+        presence::disconnected(&station_id, offline_reason);
+    }
     let _ = shutdown_tx.send(());
     let _ = writer.await;
 
-    info!(addr = %addr, "WebSocket connection closed");
+    info!(addr = %addr, station_id, "WebSocket connection closed");
+}
+
+/// Ping the charger periodically. Returns once a ping goes unanswered past
+/// the pong deadline; never returns when pings are disabled.
+async fn keepalive(connection: &StationConnection, config: &KeepaliveConfig) {
+    let Some(ping_interval) = config.ping_interval else {
+        return std::future::pending().await;
+    };
+    loop {
+        tokio::time::sleep(ping_interval).await;
+        if !connection.ping().await {
+            // The writer is gone; the reader notices the close on its own.
+            return std::future::pending().await;
+        }
+        tokio::time::sleep(config.pong_timeout).await;
+        if connection.awaiting_pong() {
+            return;
+        }
+    }
 }
 
 async fn send_outgoing(out_tx: &mpsc::Sender<AxumWSMessage>, outgoing: Vec<AxumWSMessage>) -> bool {
@@ -119,7 +223,10 @@ async fn send_outgoing(out_tx: &mpsc::Sender<AxumWSMessage>, outgoing: Vec<AxumW
     true
 }
 
-async fn handle_ocpp_messages(message: String) -> OcppOutcome {
+async fn handle_ocpp_messages(
+    connection: &StationConnection,
+    message: String,
+) -> Vec<AxumWSMessage> {
     match serde_json::from_str(&message) {
         Ok(ocpp_message) => match ocpp_message {
             OcppMessageType::Call(message_type_id, message_id, action, payload) => {
@@ -139,7 +246,7 @@ async fn handle_ocpp_messages(message: String) -> OcppOutcome {
                         &mut outgoing,
                     )
                     .await;
-                    return OcppOutcome::Continue(outgoing);
+                    return outgoing;
                 }
 
                 let action = match OcppActionEnum::from_str(&action) {
@@ -159,12 +266,12 @@ async fn handle_ocpp_messages(message: String) -> OcppOutcome {
                             &mut outgoing,
                         )
                         .await;
-                        return OcppOutcome::Continue(outgoing);
+                        return outgoing;
                     }
                 };
-                handle_ocpp_call(message_id, action, payload).await
+                handle_ocpp_call(connection, message_id, action, payload).await
             }
-            OcppMessageType::CallResult(message_type_id, _message_id, payload) => {
+            OcppMessageType::CallResult(message_type_id, message_id, payload) => {
                 if message_type_id != CALL_RESULT_MESSAGE_TYPE_ID {
                     warn!(
                         expected = CALL_RESULT_MESSAGE_TYPE_ID,
@@ -172,8 +279,8 @@ async fn handle_ocpp_messages(message: String) -> OcppOutcome {
                         "Invalid MessageTypeId for CallResult"
                     );
                 }
-                handle_ocpp_call_result(payload).await;
-                OcppOutcome::Continue(Vec::new())
+                handle_ocpp_call_result(connection, message_id, payload).await;
+                Vec::new()
             }
             OcppMessageType::CallError(
                 message_type_id,
@@ -189,6 +296,14 @@ async fn handle_ocpp_messages(message: String) -> OcppOutcome {
                         "Invalid MessageTypeId for CallError"
                     );
                 }
+                connection.complete(
+                    &message_id,
+                    CallResponse::Error {
+                        code: error_code.clone(),
+                        description: error_description.clone(),
+                        details: error_details.clone(),
+                    },
+                );
                 handle_ocpp_call_error(
                     message_type_id,
                     message_id,
@@ -198,7 +313,7 @@ async fn handle_ocpp_messages(message: String) -> OcppOutcome {
                     &mut Vec::new(),
                 )
                 .await;
-                OcppOutcome::Continue(Vec::new())
+                Vec::new()
             }
         },
         Err(err) => {
@@ -213,17 +328,64 @@ async fn handle_ocpp_messages(message: String) -> OcppOutcome {
                 &mut outgoing,
             )
             .await;
-            OcppOutcome::Continue(outgoing)
+            outgoing
         }
     }
 }
 
 async fn handle_ocpp_call(
+    connection: &StationConnection,
     message_id: OcppMessageId,
     action: OcppActionEnum,
     payload: serde_json::Value,
-) -> OcppOutcome {
-    let payload = match serde_json::from_value::<OcppPayload>(payload) {
+) -> Vec<AxumWSMessage> {
+    let station_id = connection.station_id.as_str();
+    let accepted = stations::is_accepted(station_id, load_registration_config().await);
+    let resync_due =
+        connection.take_first_call() && accepted && resync::is_due(station_id, &action);
+
+    // Refusals are not remembered so that a replay after approval is handled.
+    let outgoing = if accepted && dedupe::is_tracked(&action) {
+        match dedupe::replayed_response(station_id, &message_id, &action, &payload) {
+            Some(response) => {
+                info!(station_id, message_id, "Answering replayed {action}");
+                response
+            }
+            None => {
+                let response = dispatch_ocpp_call(
+                    connection,
+                    message_id.clone(),
+                    action.clone(),
+                    payload.clone(),
+                )
+                .await;
+                dedupe::remember(station_id, message_id, action, payload, &response);
+                response
+            }
+        }
+    } else {
+        dispatch_ocpp_call(connection, message_id, action, payload).await
+    };
+    if !resync_due {
+        return outgoing;
+    }
+
+    // Answer the charger before the resync starts sending it TriggerMessages.
+    for msg in outgoing {
+        connection.send(msg).await;
+    }
+    resync::spawn_resync(station_id);
+    Vec::new()
+}
+
+async fn dispatch_ocpp_call(
+    connection: &StationConnection,
+    message_id: OcppMessageId,
+    action: OcppActionEnum,
+    payload: serde_json::Value,
+) -> Vec<AxumWSMessage> {
+    let station_id = connection.station_id.as_str();
+    let payload = match OcppPayload::from_request(&action, payload) {
         Ok(ocpp_payload) => ocpp_payload,
         Err(err) => {
             error!("Failed to parse OCPP Payload: {err:?}");
@@ -237,23 +399,50 @@ async fn handle_ocpp_call(
                 &mut outgoing,
             )
             .await;
-            return OcppOutcome::Continue(outgoing);
+            return outgoing;
         }
     };
 
     use OcppActionEnum::*;
     let mut outgoing = Vec::new();
 
+    // Until a station is Accepted, OCPP only lets it send BootNotification.
+    if action != BootNotification
+        && !stations::is_accepted(station_id, load_registration_config().await)
+    {
+        warn!(
+            station_id,
+            "Refusing {action} from a station that is not accepted"
+        );
+        handle_ocpp_call_error(
+            CALL_ERROR_MESSAGE_TYPE_ID,
+            message_id,
+            "SecurityError".to_string(),
+            "Station is not accepted by the Central System".to_string(),
+            json!({ "action": action.to_string() }),
+            &mut outgoing,
+        )
+        .await;
+        return outgoing;
+    }
+
     match action {
         Authorize => {
             if let OcppPayload::Authorize(AuthorizeKind::Request(authorize)) = payload {
                 info!("CALL REQUEST:\n{authorize:#?}");
+                let status = rust_ocpp::v1_6::types::AuthorizationStatus::Accepted;
+                events::publish(StationEvent::Authorized {
+                    station_id: station_id.to_string(),
+                    at: Utc::now(),
+                    id_tag: authorize.id_tag.clone(),
+                    status: status.clone(),
+                });
                 let response = OcppCallResult(
                     CALL_RESULT_MESSAGE_TYPE_ID,
                     message_id,
                     OcppPayload::Authorize(AuthorizeKind::Response(AuthorizeResponse {
                         id_tag_info: rust_ocpp::v1_6::types::IdTagInfo {
-                            status: rust_ocpp::v1_6::types::AuthorizationStatus::Accepted,
+                            status,
                             expiry_date: None,
                             parent_id_tag: None,
                         },
@@ -261,50 +450,51 @@ async fn handle_ocpp_call(
                 );
                 push_json(&response, &mut outgoing, "Authorize response");
             }
-            OcppOutcome::Continue(outgoing)
+            outgoing
         }
         BootNotification => {
             if let OcppPayload::BootNotification(BootNotificationKind::Request(boot_notification)) =
                 payload
             {
+                info!("CALL REQUEST:\n{boot_notification:#?}");
+                let config = load_registration_config().await;
                 let allowed_serials = load_allowed_serial_numbers().await;
-                let serial_is_allowed = {
-                    let serial = boot_notification.charge_point_serial_number.as_ref();
-                    if allowed_serials.is_empty() {
-                        true
-                    } else {
-                        serial
-                            .map(|s| allowed_serials.iter().any(|allowed| allowed == s))
-                            .unwrap_or(false)
+                let status = stations::register_boot(
+                    station_id,
+                    &boot_notification,
+                    config,
+                    allowed_serials,
+                );
+                commands::station_booted(station_id);
+                let interval = match status {
+                    RegistrationStatus::Accepted => {
+                        stations::heartbeat_interval(station_id, load_heartbeat_config().await)
+                    }
+                    RegistrationStatus::Pending => config.pending_retry_interval,
+                    RegistrationStatus::Rejected => {
+                        warn!(
+                            station_id,
+                            "Rejecting BootNotification: {boot_notification:?}"
+                        );
+                        config.rejected_retry_interval
                     }
                 };
-
-                if serial_is_allowed {
-                    info!("CALL REQUEST:\n{boot_notification:#?}");
-                    let response = OcppCallResult(
-                        CALL_RESULT_MESSAGE_TYPE_ID,
-                        message_id,
-                        OcppPayload::BootNotification(BootNotificationKind::Response(
-                            BootNotificationResponse {
-                                status: rust_ocpp::v1_6::types::RegistrationStatus::Accepted,
-                                current_time: Utc::now(),
-                                interval: 300,
-                            },
-                        )),
-                    );
-                    push_json(&response, &mut outgoing, "BootNotification response");
-                    OcppOutcome::Continue(outgoing)
-                } else {
-                    error!(
-                        "Invalid Charger Serial Number. BootNotification: \
-                             {boot_notification:?}"
-                    );
-                    outgoing.push(AxumWSMessage::Close(None));
-                    OcppOutcome::Close(outgoing)
-                }
+                let response = OcppCallResult(
+                    CALL_RESULT_MESSAGE_TYPE_ID,
+                    message_id,
+                    OcppPayload::BootNotification(BootNotificationKind::Response(
+                        BootNotificationResponse {
+                            status,
+                            current_time: Utc::now(),
+                            interval,
+                        },
+                    )),
+                );
+                push_json(&response, &mut outgoing, "BootNotification response");
+                outgoing
             } else {
                 error!("Invalid OCPP BootNotification payload");
-                OcppOutcome::Continue(outgoing)
+                outgoing
             }
         }
         DataTransfer => {
@@ -320,7 +510,7 @@ async fn handle_ocpp_call(
                 );
                 push_json(&response, &mut outgoing, "DataTransfer response");
             }
-            OcppOutcome::Continue(outgoing)
+            outgoing
         }
         Heartbeat => {
             if let OcppPayload::Heartbeat(HeartbeatKind::Request(heartbeat)) = &payload {
@@ -337,7 +527,51 @@ async fn handle_ocpp_call(
                 })),
             );
             push_json(&response, &mut outgoing, "Heartbeat response");
-            OcppOutcome::Continue(outgoing)
+            outgoing
+        }
+        MeterValues => {
+            if let OcppPayload::MeterValues(MeterValuesKind::Request(meter_values)) = payload {
+                info!("CALL REQUEST:\n{meter_values:#?}");
+                if transactions::record_meter_values(station_id, &meter_values).is_none() {
+                    debug!(station_id, "MeterValues outside a known transaction");
+                }
+                let response = OcppCallResult(
+                    CALL_RESULT_MESSAGE_TYPE_ID,
+                    message_id,
+                    OcppPayload::MeterValues(MeterValuesKind::Response(MeterValuesResponse {})),
+                );
+                push_json(&response, &mut outgoing, "MeterValues response");
+            }
+            outgoing
+        }
+        StartTransaction => {
+            if let OcppPayload::StartTransaction(StartTransactionKind::Request(start_transaction)) =
+                payload
+            {
+                info!("CALL REQUEST:\n{start_transaction:#?}");
+                let transaction = transactions::start(station_id, &start_transaction);
+                commands::transaction_started(station_id, &transaction);
+                persistence::save_id_tag(
+                    &start_transaction.id_tag,
+                    &rust_ocpp::v1_6::types::AuthorizationStatus::Accepted,
+                );
+                let response = OcppCallResult(
+                    CALL_RESULT_MESSAGE_TYPE_ID,
+                    message_id,
+                    OcppPayload::StartTransaction(StartTransactionKind::Response(
+                        StartTransactionResponse {
+                            id_tag_info: rust_ocpp::v1_6::types::IdTagInfo {
+                                status: rust_ocpp::v1_6::types::AuthorizationStatus::Accepted,
+                                expiry_date: None,
+                                parent_id_tag: None,
+                            },
+                            transaction_id: transaction.transaction_id,
+                        },
+                    )),
+                );
+                push_json(&response, &mut outgoing, "StartTransaction response");
+            }
+            outgoing
         }
         StatusNotification => {
             if let OcppPayload::StatusNotification(StatusNotificationKind::Request(
@@ -345,14 +579,25 @@ async fn handle_ocpp_call(
             )) = payload
             {
                 info!("CALL REQUEST:\n{status_notification:#?}");
+                connectors::update_status(station_id, &status_notification);
+                let response = OcppCallResult(
+                    CALL_RESULT_MESSAGE_TYPE_ID,
+                    message_id,
+                    OcppPayload::StatusNotification(StatusNotificationKind::Response(
+                        StatusNotificationResponse {},
+                    )),
+                );
+                push_json(&response, &mut outgoing, "StatusNotification response");
             }
-            OcppOutcome::Continue(outgoing)
+            outgoing
         }
         StopTransaction => {
             if let OcppPayload::StopTransaction(StopTransactionKind::Request(stop_transaction)) =
                 payload
             {
                 info!("CALL REQUEST:\n{stop_transaction:#?}");
+                let transaction = transactions::stop(station_id, &stop_transaction);
+                commands::transaction_stopped(station_id, &transaction);
                 let response = OcppCallResult(
                     CALL_RESULT_MESSAGE_TYPE_ID,
                     message_id,
@@ -368,7 +613,159 @@ async fn handle_ocpp_call(
                 );
                 push_json(&response, &mut outgoing, "StopTransaction response");
             }
-            OcppOutcome::Continue(outgoing)
+            outgoing
+        }
+        SecurityEventNotification => {
+            if let OcppPayload::SecurityEventNotification(SecurityEventNotificationKind::Request(
+                security_event,
+            )) = payload
+            {
+                info!("CALL REQUEST:\n{security_event:#?}");
+                security::record_security_event(station_id, &security_event);
+                let response = OcppCallResult(
+                    CALL_RESULT_MESSAGE_TYPE_ID,
+                    message_id,
+                    OcppPayload::SecurityEventNotification(
+                        SecurityEventNotificationKind::Response(
+                            SecurityEventNotificationResponse {},
+                        ),
+                    ),
+                );
+                push_json(
+                    &response,
+                    &mut outgoing,
+                    "SecurityEventNotification response",
+                );
+            }
+            outgoing
+        }
+        SignCertificate => {
+            if let OcppPayload::SignCertificate(SignCertificateKind::Request(sign_certificate)) =
+                payload
+            {
+                info!("CALL REQUEST:\n{sign_certificate:#?}");
+                let chain = security::sign_station_csr(station_id, &sign_certificate.csr);
+                let status = if chain.is_some() {
+                    GenericStatus::Accepted
+                } else {
+                    GenericStatus::Rejected
+                };
+                let response = OcppCallResult(
+                    CALL_RESULT_MESSAGE_TYPE_ID,
+                    message_id,
+                    OcppPayload::SignCertificate(SignCertificateKind::Response(
+                        SignCertificateResponse { status },
+                    )),
+                );
+                push_json(&response, &mut outgoing, "SignCertificate response");
+
+                if let Some(chain) = chain {
+                    // The CallResult has to reach the charger before CertificateSigned.
+                    for msg in std::mem::take(&mut outgoing) {
+                        connection.send(msg).await;
+                    }
+                    let station_id = station_id.to_string();
+                    tokio::spawn(async move {
+                        if let Err(err) =
+                            security::send_certificate_signed(&station_id, chain).await
+                        {
+                            warn!(station_id, "CertificateSigned failed: {err}");
+                        }
+                    });
+                }
+            }
+            outgoing
+        }
+        LogStatusNotification => {
+            if let OcppPayload::LogStatusNotification(LogStatusNotificationKind::Request(
+                log_status,
+            )) = payload
+            {
+                info!("CALL REQUEST:\n{log_status:#?}");
+                let response = OcppCallResult(
+                    CALL_RESULT_MESSAGE_TYPE_ID,
+                    message_id,
+                    OcppPayload::LogStatusNotification(LogStatusNotificationKind::Response(
+                        LogStatusNotificationResponse {},
+                    )),
+                );
+                push_json(&response, &mut outgoing, "LogStatusNotification response");
+            }
+            outgoing
+        }
+        FirmwareStatusNotification => {
+            if let OcppPayload::FirmwareStatusNotification(
+                FirmwareStatusNotificationKind::Request(firmware_status),
+            ) = payload
+            {
+                let failed = matches!(
+                    firmware_status.status,
+                    FirmwareStatus::DownloadFailed | FirmwareStatus::InstallationFailed
+                );
+                if failed {
+                    warn!(station_id, "Firmware update failed: {firmware_status:?}");
+                } else {
+                    info!("CALL REQUEST:\n{firmware_status:#?}");
+                }
+                events::publish(StationEvent::FirmwareStatus {
+                    station_id: station_id.to_string(),
+                    at: Utc::now(),
+                    status: format!("{:?}", firmware_status.status),
+                    failed,
+                });
+                let response = OcppCallResult(
+                    CALL_RESULT_MESSAGE_TYPE_ID,
+                    message_id,
+                    OcppPayload::FirmwareStatusNotification(
+                        FirmwareStatusNotificationKind::Response(
+                            FirmwareStatusNotificationResponse {},
+                        ),
+                    ),
+                );
+                push_json(
+                    &response,
+                    &mut outgoing,
+                    "FirmwareStatusNotification response",
+                );
+            }
+            outgoing
+        }
+        SignedFirmwareStatusNotification => {
+            if let OcppPayload::SignedFirmwareStatusNotification(
+                SignedFirmwareStatusNotificationKind::Request(firmware_status),
+            ) = payload
+            {
+                let failed = firmware_status.status.is_failure();
+                if failed {
+                    warn!(
+                        station_id,
+                        "Signed firmware update failed: {firmware_status:?}"
+                    );
+                } else {
+                    info!("CALL REQUEST:\n{firmware_status:#?}");
+                }
+                events::publish(StationEvent::FirmwareStatus {
+                    station_id: station_id.to_string(),
+                    at: Utc::now(),
+                    status: format!("{:?}", firmware_status.status),
+                    failed,
+                });
+                let response = OcppCallResult(
+                    CALL_RESULT_MESSAGE_TYPE_ID,
+                    message_id,
+                    OcppPayload::SignedFirmwareStatusNotification(
+                        SignedFirmwareStatusNotificationKind::Response(
+                            SignedFirmwareStatusNotificationResponse {},
+                        ),
+                    ),
+                );
+                push_json(
+                    &response,
+                    &mut outgoing,
+                    "SignedFirmwareStatusNotification response",
+                );
+            }
+            outgoing
         }
         _ => {
             warn!("OCPP action {action:?} not implemented");
@@ -381,13 +778,17 @@ async fn handle_ocpp_call(
                 &mut outgoing,
             )
             .await;
-            OcppOutcome::Continue(outgoing)
+            outgoing
         }
     }
 }
 
-async fn handle_ocpp_call_result(payload: serde_json::Value) {
-    match serde_json::from_value::<OcppPayload>(payload) {
+async fn handle_ocpp_call_result(
+    connection: &StationConnection,
+    message_id: OcppMessageId,
+    payload: serde_json::Value,
+) {
+    match serde_json::from_value::<OcppPayload>(payload.clone()) {
         Ok(ocpp_payload) => {
             info!("Parsed OCPP Payload: {ocpp_payload:?}");
         }
@@ -395,6 +796,7 @@ async fn handle_ocpp_call_result(payload: serde_json::Value) {
             warn!("Failed to parse OCPP Payload: {err:?}");
         }
     }
+    connection.complete(&message_id, CallResponse::Result(payload));
 }
 
 async fn handle_ocpp_call_error(
@@ -1,4 +1,25 @@
+pub mod api_tokens;
+pub mod audit;
+pub mod auth;
+pub mod commands;
+pub mod connections;
+pub mod connectors;
+pub mod dedupe;
+pub mod events;
 pub mod handlers;
+pub mod mqtt;
+pub mod persistence;
+pub mod pki;
+pub mod presence;
+pub mod rest;
+pub mod resync;
 pub mod routes;
+pub mod security;
+pub mod sessions;
 pub mod state;
+pub mod stations;
+pub mod tls;
+pub mod transactions;
 pub mod types;
+pub mod users;
+pub mod webhooks;
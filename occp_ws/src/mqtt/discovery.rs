@@ -0,0 +1,202 @@
+//! Home Assistant MQTT discovery configs.
+//!
+//! Every charger becomes one device, named after the station or its
+//! BootNotification vendor and model, holding an online sensor and, per
+//! connector, status, power and energy sensors, a current-limit number and a
+//! charging switch. The entities read the bridge's own state topics.
+
+use serde_json::{Value, json};
+
+use super::Topics;
+use crate::stations::StationRecord;
+
+/// Upper bound of the current-limit slider, the most a type 2 connector
+/// carries.
+const MAX_LIMIT_A: u32 = 63;
+
+pub(super) struct Discovery {
+    prefix: String,
+}
+
+impl Discovery {
+    pub(super) fn new(prefix: &str) -> Self {
+        Self {
+            prefix: prefix.to_string(),
+        }
+    }
+
+    /// Discovery topics and configs for the station's own entities.
+    pub(super) fn station(
+        &self,
+        topics: &Topics,
+        record: &StationRecord,
+        name: Option<String>,
+    ) -> Vec<(String, Value)> {
+        let device = Device::new(record, name);
+        let online = json!({
+            "name": "Online",
+            "device_class": "connectivity",
+            "state_topic": topics.station(&record.station_id, "online"),
+            "payload_on": "true",
+            "payload_off": "false",
+            "availability_topic": topics.status(),
+        });
+        vec![self.entity(&device, "binary_sensor", "online", online)]
+    }
+
+    /// Discovery topics and configs for one connector's entities.
+    pub(super) fn connector(
+        &self,
+        topics: &Topics,
+        record: &StationRecord,
+        name: Option<String>,
+        connector_id: u32,
+    ) -> Vec<(String, Value)> {
+        let device = Device::new(record, name);
+        let station_id = &record.station_id;
+        let state = |name| topics.connector(station_id, connector_id, name);
+        let label = |entity: &str| format!("Connector {connector_id} {entity}");
+        let object = |entity: &str| format!("connector_{connector_id}_{entity}");
+        let availability = json!([
+            {
+                "topic": topics.status(),
+                "payload_available": "online",
+                "payload_not_available": "offline",
+            },
+            {
+                "topic": topics.station(station_id, "online"),
+                "payload_available": "true",
+                "payload_not_available": "false",
+            },
+        ]);
+        let entities = [
+            (
+                "sensor",
+                "status",
+                json!({
+                    "name": label("status"),
+                    "state_topic": state("status"),
+                    "value_template": "{{ value_json.status }}",
+                    "json_attributes_topic": state("status"),
+                    "icon": "mdi:ev-station",
+                }),
+            ),
+            (
+                "sensor",
+                "power",
+                json!({
+                    "name": label("power"),
+                    "state_topic": state("power_w"),
+                    "device_class": "power",
+                    "state_class": "measurement",
+                    "unit_of_measurement": "W",
+                }),
+            ),
+            (
+                "sensor",
+                "energy",
+                json!({
+                    "name": label("session energy"),
+                    "state_topic": state("energy_wh"),
+                    "device_class": "energy",
+                    "state_class": "total_increasing",
+                    "unit_of_measurement": "Wh",
+                }),
+            ),
+            (
+                "number",
+                "current_limit",
+                json!({
+                    "name": label("current limit"),
+                    "command_topic": topics.station(station_id, "command/current_limit"),
+                    "command_template": format!(
+                        "{{\"connector_id\": {connector_id}, \"limit_a\": {{{{ value }}}}}}"
+                    ),
+                    "device_class": "current",
+                    "unit_of_measurement": "A",
+                    "min": 0,
+                    "max": MAX_LIMIT_A,
+                    "step": 1,
+                    "mode": "box",
+                    "optimistic": true,
+                }),
+            ),
+            (
+                "switch",
+                "charging",
+                json!({
+                    "name": label("charging"),
+                    "command_topic": topics.station(station_id, "command/charging"),
+                    "payload_on": json!({ "connector_id": connector_id, "charging": true }).to_string(),
+                    "payload_off": json!({ "connector_id": connector_id, "charging": false }).to_string(),
+                    "state_topic": state("charging"),
+                    "state_on": "ON",
+                    "state_off": "OFF",
+                    "icon": "mdi:ev-plug-type2",
+                }),
+            ),
+        ];
+        entities
+            .into_iter()
+            .map(|(component, entity, mut config)| {
+                config["availability"] = availability.clone();
+                config["availability_mode"] = json!("all");
+                self.entity(&device, component, &object(entity), config)
+            })
+            .collect()
+    }
+
+    fn entity(
+        &self,
+        device: &Device,
+        component: &str,
+        object_id: &str,
+        mut config: Value,
+    ) -> (String, Value) {
+        let unique_id = format!("plughome_{}_{object_id}", device.slug);
+        config["unique_id"] = json!(unique_id);
+        config["object_id"] = json!(unique_id);
+        config["device"] = device.config.clone();
+        config["origin"] = json!({ "name": "PlugHome-server" });
+        let topic = format!(
+            "{}/{component}/plughome_{}/{object_id}/config",
+            self.prefix, device.slug
+        );
+        (topic, config)
+    }
+}
+
+struct Device {
+    /// The station id reduced to what discovery ids allow.
+    slug: String,
+    config: Value,
+}
+
+impl Device {
+    fn new(record: &StationRecord, name: Option<String>) -> Self {
+        let slug = record
+            .station_id
+            .chars()
+            .map(|c| {
+                if c.is_ascii_alphanumeric() {
+                    c.to_ascii_lowercase()
+                } else {
+                    '_'
+                }
+            })
+            .collect::<String>();
+        let mut config = json!({
+            "identifiers": [format!("plughome_{slug}")],
+            "name": name.unwrap_or_else(|| format!("{} {}", record.vendor, record.model)),
+            "manufacturer": record.vendor,
+            "model": record.model,
+        });
+        if let Some(firmware_version) = &record.firmware_version {
+            config["sw_version"] = json!(firmware_version);
+        }
+        if let Some(serial_number) = &record.serial_number {
+            config["serial_number"] = json!(serial_number);
+        }
+        Self { slug, config }
+    }
+}
@@ -0,0 +1,531 @@
+//! Bridge between the event bus and an MQTT broker.
+//!
+//! State is published under the configured prefix, retained so that new
+//! subscribers see it straight away:
+//!
+//! - `{prefix}/status`: `online`, or `offline` through the Last Will
+//! - `{prefix}/stations/{station}/online`: `true` or `false`
+//! - `{prefix}/stations/{station}/info`: vendor, model, firmware, registration
+//! - `{prefix}/stations/{station}/connectors/{connector}/status`
+//! - `{prefix}/stations/{station}/connectors/{connector}/session`
+//! - `{prefix}/stations/{station}/connectors/{connector}/power_w` and
+//!   `energy_wh`: live power and the energy of the running session
+//! - `{prefix}/stations/{station}/connectors/{connector}/charging`: `ON`
+//!   while a session runs, else `OFF`
+//!
+//! Commands are taken from `{prefix}/stations/{station}/command/{start,
+//! stop,current_limit}` with the same JSON bodies as the REST API, and
+//! `command/charging` with `{"connector_id": 1, "charging": true}`. Their
+//! progress is published, not retained, to `.../command/result`.
+//!
+//! With Home Assistant discovery on, every charger also gets discovery
+//! configs, see [`discovery`].
+
+use std::{collections::HashSet, time::Duration};
+
+use common::MqttConfig;
+use futures::StreamExt;
+use rumqttc::{AsyncClient, Event, LastWill, MqttOptions, Packet, Publish, QoS};
+use rust_ocpp::v1_6::types::RegistrationStatus;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tokio::task::JoinHandle;
+use tracing::{debug, info, warn};
+
+use crate::audit::Actor;
+use crate::commands::{self, Command};
+use crate::connectors::{self, ConnectorState};
+use crate::events::{self, EventFilter, StationEvent};
+use crate::presence;
+use crate::sessions::Session;
+use crate::stations::{self, StationRecord};
+use crate::transactions::{self, Transaction};
+
+mod discovery;
+
+use discovery::Discovery;
+
+/// Requests queued for the broker before new publishes are dropped.
+const REQUEST_CAPACITY: usize = 256;
+
+/// Wait between attempts to reach the broker.
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct StartPayload {
+    connector_id: Option<u32>,
+    id_tag: String,
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+struct StopPayload {
+    transaction_id: Option<i32>,
+    connector_id: Option<u32>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct ChargingPayload {
+    connector_id: u32,
+    charging: bool,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct CurrentLimitPayload {
+    #[serde(default)]
+    connector_id: u32,
+    limit_a: f64,
+}
+
+/// Retained description of a station.
+#[derive(Serialize, Debug)]
+struct StationInfo<'a> {
+    station_id: &'a str,
+    name: Option<String>,
+    vendor: &'a str,
+    model: &'a str,
+    serial_number: Option<&'a str>,
+    firmware_version: Option<&'a str>,
+    registration_status: &'a RegistrationStatus,
+}
+
+/// Connect to the broker and keep the bridge running until the process
+/// exits. Broker outages are retried; state is republished on reconnect.
+pub fn spawn(config: MqttConfig) -> JoinHandle<()> {
+    tokio::spawn(run(config))
+}
+
+async fn run(config: MqttConfig) {
+    let topics = Topics::new(&config.topic_prefix);
+    let mut options = MqttOptions::new(&config.client_id, &config.host, config.port);
+    options
+        .set_keep_alive(config.keep_alive)
+        .set_last_will(LastWill::new(
+            topics.status(),
+            "offline",
+            QoS::AtLeastOnce,
+            true,
+        ));
+    if let (Some(username), Some(password)) = (&config.username, &config.password) {
+        options.set_credentials(username, password);
+    }
+    let (client, mut eventloop) = AsyncClient::new(options, REQUEST_CAPACITY);
+    let mut bridge = Bridge {
+        client,
+        topics,
+        discovery: config.discovery_prefix.as_deref().map(Discovery::new),
+        remote_id_tag: config.remote_id_tag.clone(),
+        discovered: HashSet::new(),
+    };
+
+    // Everything the broker missed while unreachable is covered by the
+    // snapshot published on every (re)connect.
+    let events = events::stream(EventFilter::default(), None);
+    futures::pin_mut!(events);
+    info!(host = %config.host, port = config.port, "Starting MQTT bridge");
+    loop {
+        tokio::select! {
+            notification = eventloop.poll() => match notification {
+                Ok(Event::Incoming(Packet::ConnAck(_))) => {
+                    info!("Connected to MQTT broker");
+                    bridge.connected();
+                }
+                Ok(Event::Incoming(Packet::Publish(publish))) => bridge.command(&publish),
+                Ok(_) => {}
+                Err(err) => {
+                    warn!("MQTT connection failed, retrying in {RECONNECT_DELAY:?}: {err}");
+                    tokio::time::sleep(RECONNECT_DELAY).await;
+                }
+            },
+            record = events.next() => match record {
+                Some(record) => bridge.event(&record.event),
+                None => break,
+            },
+        }
+    }
+}
+
+struct Bridge {
+    client: AsyncClient,
+    topics: Topics,
+    discovery: Option<Discovery>,
+    /// Id tag for starts from the `charging` command.
+    remote_id_tag: String,
+    /// Stations and connectors (`None` for the station itself) whose
+    /// discovery configs went out on this connection.
+    discovered: HashSet<(String, Option<u32>)>,
+}
+
+impl Bridge {
+    /// Announce the bridge, subscribe to commands and publish current state.
+    fn connected(&mut self) {
+        self.discovered.clear();
+        self.publish(self.topics.status(), true, "online");
+        if let Err(err) = self
+            .client
+            .try_subscribe(self.topics.commands(), QoS::AtLeastOnce)
+        {
+            warn!("Failed to subscribe to MQTT commands: {err}");
+        }
+        for record in stations::all() {
+            self.station_info(&record);
+            self.discover(&record.station_id, None);
+        }
+        let open = transactions::open_transactions(None);
+        for presence in presence::all() {
+            self.publish(
+                self.topics.station(&presence.station_id, "online"),
+                true,
+                presence.online.to_string(),
+            );
+            for state in connectors::for_station(&presence.station_id) {
+                self.connector_status(&state);
+                let charging = open.iter().any(|transaction| {
+                    transaction.station_id == state.station_id
+                        && transaction.connector_id == state.connector_id
+                });
+                self.charging(&state.station_id, state.connector_id, charging);
+                self.discover(&state.station_id, Some(state.connector_id));
+            }
+        }
+        for transaction in &open {
+            self.session(transaction);
+        }
+    }
+
+    fn event(&mut self, event: &StationEvent) {
+        match event {
+            StationEvent::Booted { station_id, .. } => {
+                if let Some(record) = stations::get(station_id) {
+                    self.station_info(&record);
+                }
+                // Vendor, model or firmware may have changed.
+                self.discovered.retain(|(id, _)| id != station_id);
+                self.discover(station_id, None);
+                for state in connectors::for_station(station_id) {
+                    self.discover(station_id, Some(state.connector_id));
+                }
+            }
+            StationEvent::RegistrationChanged { station_id, .. } => {
+                if let Some(record) = stations::get(station_id) {
+                    self.station_info(&record);
+                }
+            }
+            StationEvent::Online { station_id, .. }
+            | StationEvent::Reconnected { station_id, .. } => {
+                self.publish(self.topics.station(station_id, "online"), true, "true");
+            }
+            StationEvent::Offline { station_id, .. } => {
+                self.publish(self.topics.station(station_id, "online"), true, "false");
+            }
+            StationEvent::ConnectorStatus {
+                station_id,
+                connector_id,
+                ..
+            } => {
+                if let Some(state) = connectors::get(station_id, *connector_id) {
+                    self.connector_status(&state);
+                }
+                self.discover(station_id, Some(*connector_id));
+            }
+            StationEvent::SessionStarted { session, .. } => {
+                let topic = |name| {
+                    self.topics
+                        .connector(&session.station_id, session.connector_id, name)
+                };
+                self.publish(topic("session"), true, to_json(session));
+                self.publish(topic("energy_wh"), true, "0");
+                self.charging(&session.station_id, session.connector_id, true);
+            }
+            StationEvent::SessionStopped { session, .. } => {
+                let topic = |name| {
+                    self.topics
+                        .connector(&session.station_id, session.connector_id, name)
+                };
+                self.publish(topic("session"), true, to_json(session));
+                self.publish(topic("power_w"), true, "0");
+                self.charging(&session.station_id, session.connector_id, false);
+            }
+            StationEvent::MeterValues {
+                station_id,
+                connector_id,
+                transaction_id,
+                power_w,
+                ..
+            } => {
+                if let Some(power_w) = power_w {
+                    self.publish(
+                        self.topics.connector(station_id, *connector_id, "power_w"),
+                        true,
+                        format!("{power_w:.0}"),
+                    );
+                }
+                if let Some(transaction) = transaction_id.and_then(transactions::get) {
+                    self.session(&transaction);
+                }
+            }
+            StationEvent::CommandUpdated {
+                station_id, job_id, ..
+            } => {
+                if let Some(job) = commands::get(job_id) {
+                    self.publish(
+                        self.topics.station(station_id, "command/result"),
+                        false,
+                        to_json(&job),
+                    );
+                }
+            }
+            StationEvent::Authorized { .. }
+            | StationEvent::SecurityEvent { .. }
+            | StationEvent::FirmwareStatus { .. } => {}
+        }
+    }
+
+    fn station_info(&self, record: &StationRecord) {
+        let info = StationInfo {
+            station_id: &record.station_id,
+            name: stations::metadata(&record.station_id).name,
+            vendor: &record.vendor,
+            model: &record.model,
+            serial_number: record.serial_number.as_deref(),
+            firmware_version: record.firmware_version.as_deref(),
+            registration_status: &record.status,
+        };
+        self.publish(
+            self.topics.station(&record.station_id, "info"),
+            true,
+            to_json(&info),
+        );
+    }
+
+    fn connector_status(&self, state: &ConnectorState) {
+        let status = json!({
+            "status": state.status,
+            "error_code": state.error_code,
+            "info": state.info,
+            "at": state.timestamp.unwrap_or(state.updated_at),
+        });
+        self.publish(
+            self.topics
+                .connector(&state.station_id, state.connector_id, "status"),
+            true,
+            status.to_string(),
+        );
+    }
+
+    fn charging(&self, station_id: &str, connector_id: u32, charging: bool) {
+        self.publish(
+            self.topics.connector(station_id, connector_id, "charging"),
+            true,
+            if charging { "ON" } else { "OFF" },
+        );
+    }
+
+    /// Publish Home Assistant discovery configs for a booted station, or one
+    /// of its connectors (`connector_id` 0 is the charge point itself).
+    fn discover(&mut self, station_id: &str, connector_id: Option<u32>) {
+        let Some(discovery) = &self.discovery else {
+            return;
+        };
+        if connector_id == Some(0)
+            || !self
+                .discovered
+                .insert((station_id.to_string(), connector_id))
+        {
+            return;
+        }
+        let Some(record) = stations::get(station_id) else {
+            self.discovered
+                .remove(&(station_id.to_string(), connector_id));
+            return;
+        };
+        let name = stations::metadata(station_id).name;
+        let configs = match connector_id {
+            Some(connector_id) => discovery.connector(&self.topics, &record, name, connector_id),
+            None => discovery.station(&self.topics, &record, name),
+        };
+        for (topic, config) in configs {
+            self.publish(topic, true, config.to_string());
+        }
+    }
+
+    /// The session and the energy it has delivered so far.
+    fn session(&self, transaction: &Transaction) {
+        let session = Session::from(transaction);
+        let topic = |name| {
+            self.topics
+                .connector(&session.station_id, session.connector_id, name)
+        };
+        self.publish(topic("session"), true, to_json(&session));
+        if let Some(energy_wh) = session.energy_wh {
+            self.publish(topic("energy_wh"), true, format!("{energy_wh:.0}"));
+        }
+    }
+
+    /// Run a command published to `.../command/{name}` and report the
+    /// outcome on `.../command/result`.
+    fn command(&self, publish: &Publish) {
+        let Some((segment, name)) = self.topics.parse_command(&publish.topic) else {
+            debug!(topic = publish.topic, "Ignoring MQTT message");
+            return;
+        };
+        let station_id = self.topics.station_id(segment);
+        let result_topic = self.topics.station(&station_id, "command/result");
+        match self
+            .parse_command(&station_id, name, &publish.payload)
+            .and_then(|command| {
+                commands::submit(&station_id, command, None, &Actor::automation("mqtt"))
+                    .map_err(|err| err.to_string())
+            }) {
+            Ok(job) => {
+                info!(
+                    station_id,
+                    job_id = job.job_id,
+                    "Command received over MQTT"
+                );
+                self.publish(result_topic, false, to_json(&job));
+            }
+            Err(message) => {
+                warn!(station_id, "Refused MQTT command {name}: {message}");
+                let error = json!({ "command": name, "error": message });
+                self.publish(result_topic, false, error.to_string());
+            }
+        }
+    }
+
+    fn parse_command(
+        &self,
+        station_id: &str,
+        name: &str,
+        payload: &[u8],
+    ) -> Result<Command, String> {
+        let invalid = |err: serde_json::Error| format!("invalid {name} payload: {err}");
+        match name {
+            "start" => {
+                let payload: StartPayload = serde_json::from_slice(payload).map_err(invalid)?;
+                Ok(Command::RemoteStart {
+                    connector_id: payload.connector_id,
+                    id_tag: payload.id_tag.trim().to_string(),
+                })
+            }
+            "stop" => {
+                let payload: StopPayload = if payload.iter().all(u8::is_ascii_whitespace) {
+                    StopPayload::default()
+                } else {
+                    serde_json::from_slice(payload).map_err(invalid)?
+                };
+                let transaction_id =
+                    commands::stop_target(station_id, payload.transaction_id, payload.connector_id)
+                        .map_err(|err| err.to_string())?;
+                Ok(Command::RemoteStop { transaction_id })
+            }
+            "charging" => {
+                let payload: ChargingPayload = serde_json::from_slice(payload).map_err(invalid)?;
+                if payload.charging {
+                    Ok(Command::RemoteStart {
+                        connector_id: Some(payload.connector_id),
+                        id_tag: self.remote_id_tag.clone(),
+                    })
+                } else {
+                    let transaction_id =
+                        commands::stop_target(station_id, None, Some(payload.connector_id))
+                            .map_err(|err| err.to_string())?;
+                    Ok(Command::RemoteStop { transaction_id })
+                }
+            }
+            "current_limit" => {
+                let payload: CurrentLimitPayload =
+                    serde_json::from_slice(payload).map_err(invalid)?;
+                Ok(Command::SetCurrentLimit {
+                    connector_id: payload.connector_id,
+                    limit_a: payload.limit_a,
+                })
+            }
+            other => Err(format!(
+                "unknown command {other:?}, expected start, stop, charging or current_limit"
+            )),
+        }
+    }
+
+    /// Queue a publish without waiting; while the broker is unreachable and
+    /// the queue is full, updates are dropped until the next snapshot.
+    fn publish(&self, topic: String, retain: bool, payload: impl Into<Vec<u8>>) {
+        if let Err(err) = self
+            .client
+            .try_publish(&topic, QoS::AtLeastOnce, retain, payload)
+        {
+            debug!(topic, "Dropped MQTT publish: {err}");
+        }
+    }
+}
+
+fn to_json<T: Serialize>(value: &T) -> String {
+    serde_json::to_string(value).expect("MQTT payload serializes to JSON")
+}
+
+/// Topic names under the configured prefix.
+struct Topics {
+    prefix: String,
+}
+
+impl Topics {
+    fn new(prefix: &str) -> Self {
+        Self {
+            prefix: prefix.to_string(),
+        }
+    }
+
+    fn status(&self) -> String {
+        format!("{}/status", self.prefix)
+    }
+
+    fn commands(&self) -> String {
+        format!("{}/stations/+/command/+", self.prefix)
+    }
+
+    fn station(&self, station_id: &str, name: &str) -> String {
+        format!(
+            "{}/stations/{}/{name}",
+            self.prefix,
+            topic_segment(station_id)
+        )
+    }
+
+    fn connector(&self, station_id: &str, connector_id: u32, name: &str) -> String {
+        self.station(station_id, &format!("connectors/{connector_id}/{name}"))
+    }
+
+    /// The station segment and command name of a command topic.
+    fn parse_command<'a>(&self, topic: &'a str) -> Option<(&'a str, &'a str)> {
+        let rest = topic
+            .strip_prefix(self.prefix.as_str())?
+            .strip_prefix("/stations/")?;
+        match rest.split('/').collect::<Vec<_>>().as_slice() {
+            [segment, "command", name] if *name != "result" => Some((segment, name)),
+            _ => None,
+        }
+    }
+
+    /// The station a topic segment stands for.
+    fn station_id(&self, segment: &str) -> String {
+        stations::all()
+            .into_iter()
+            .map(|record| record.station_id)
+            .chain(
+                presence::all()
+                    .into_iter()
+                    .map(|presence| presence.station_id),
+            )
+            .find(|station_id| topic_segment(station_id) == segment)
+            .unwrap_or_else(|| segment.to_string())
+    }
+}
+
+/// A station id usable as one topic level: `/` and the wildcards `+` and
+/// `#` are replaced with `_`.
+fn topic_segment(station_id: &str) -> String {
+    station_id.replace(['/', '+', '#'], "_")
+}
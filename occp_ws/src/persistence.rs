@@ -0,0 +1,744 @@
+//! Write-through persistence of station, connector, transaction, webhook,
+//! API token and user state to the SQLite store, and restoring it at startup.
+//! The audit log is only kept in the store.
+//!
+//! The in-memory modules stay the source of truth while the server runs.
+//! Every change is queued for a writer thread as it happens and written in
+//! order; a failed write is logged and the server carries on. Without a
+//! store every function here is a no-op.
+
+use std::{
+    sync::{OnceLock, mpsc},
+    thread,
+};
+
+use chrono::{DateTime, Utc};
+use common::StorageConfig;
+use rust_ocpp::v1_6::types::{AuthorizationStatus, MeterValue, SampledValue};
+use serde::{Serialize, de::DeserializeOwned};
+use storage::{
+    ApiTokenRow, AuditFilter, AuditRow, CommandRow, CommandStatus, ConnectorRow,
+    DeliveryStatus as DeliveryRowStatus, IdTagRow, MeterReading, SqliteStore, StationMetadataRow,
+    StationRow, Storage, StorageError, TransactionRow, UserRow, UserSessionRow, WebhookDeliveryRow,
+    WebhookRow,
+};
+use tokio::sync::oneshot;
+use tracing::{debug, info, warn};
+
+use crate::api_tokens::{self, ApiToken};
+use crate::audit::{Actor, AuditRecord};
+use crate::connectors::{self, ConnectorState};
+use crate::state::STORE;
+use crate::stations::{self, StationMetadata, StationRecord};
+use crate::transactions::{self, EnergySample, PowerSample, Transaction};
+use crate::users::{self, LoginSession, User};
+use crate::webhooks::{self, DELIVERY_LOG_CAPACITY, Delivery, DeliveryStatus, Webhook};
+
+/// A store operation for the writer thread.
+type Job = Box<dyn FnOnce(&dyn Storage) + Send>;
+
+/// Queue of the thread that does all store I/O while the server runs, so
+/// that slow disks or a locked database never stall the async runtime.
+static WRITER: OnceLock<mpsc::Sender<Job>> = OnceLock::new();
+
+/// What `restore` loaded back into memory.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RestoreSummary {
+    pub stations: usize,
+    pub connectors: usize,
+    pub transactions: usize,
+    pub open_transactions: usize,
+    pub webhooks: usize,
+    pub pending_deliveries: usize,
+    /// Tokens that have not been revoked.
+    pub api_tokens: usize,
+    pub users: usize,
+}
+
+/// Open the configured database, run its migrations and use it from now on.
+/// Returns the schema version.
+pub fn open(config: &StorageConfig) -> Result<usize, StorageError> {
+    let store = SqliteStore::open(&config.database_path)?;
+    let version = store.schema_version()?;
+    info!(path = %config.database_path.display(), version, "Opened database");
+    install(store);
+    Ok(version)
+}
+
+/// Use `store` for persistence. Only the first store installed is used.
+pub fn install(store: impl Storage + 'static) -> bool {
+    if STORE.set(Box::new(store)).is_err() {
+        return false;
+    }
+    let (jobs, queue) = mpsc::channel::<Job>();
+    let writer = thread::Builder::new()
+        .name("storage-writer".to_string())
+        .spawn(move || {
+            if let Some(store) = STORE.get() {
+                for job in queue {
+                    job(store.as_ref());
+                }
+            }
+        });
+    match writer {
+        Ok(_) => {
+            WRITER.set(jobs).ok();
+        }
+        Err(err) => warn!("Failed to start the storage writer, writing inline: {err}"),
+    }
+    true
+}
+
+/// The store, once the writes queued so far are in it. Blocks, so async
+/// code goes through the functions here instead.
+pub fn store() -> Option<&'static dyn Storage> {
+    flush();
+    STORE.get().map(Box::as_ref)
+}
+
+/// Wait until the writes queued so far are in the store, e.g. before the
+/// process exits.
+pub fn flush() {
+    let (done, finished) = mpsc::channel();
+    submit(Box::new(move |_| {
+        let _ = done.send(());
+    }));
+    let _ = finished.recv();
+}
+
+/// Run `job` on the writer thread, or right here if there is none.
+fn submit(job: Job) {
+    let job = match WRITER.get() {
+        Some(writer) => match writer.send(job) {
+            Ok(()) => return,
+            Err(mpsc::SendError(job)) => job,
+        },
+        None => job,
+    };
+    if let Some(store) = STORE.get() {
+        job(store.as_ref());
+    }
+}
+
+fn write(
+    what: &'static str,
+    save: impl FnOnce(&dyn Storage) -> Result<(), StorageError> + Send + 'static,
+) {
+    if STORE.get().is_none() {
+        return;
+    }
+    submit(Box::new(move |store| {
+        if let Err(err) = save(store) {
+            warn!("Failed to persist {what}: {err}");
+        }
+    }));
+}
+
+/// Run `query` on the writer thread after the writes queued before it.
+/// `None` without a store.
+async fn read<T: Send + 'static>(
+    query: impl FnOnce(&dyn Storage) -> Result<T, StorageError> + Send + 'static,
+) -> Option<Result<T, StorageError>> {
+    STORE.get()?;
+    let (answer, answered) = oneshot::channel();
+    submit(Box::new(move |store| {
+        let _ = answer.send(query(store));
+    }));
+    answered.await.ok()
+}
+
+/// Load stations, connectors, transactions, webhooks, API tokens and users
+/// from the store into memory.
+pub fn restore() -> Result<RestoreSummary, StorageError> {
+    let Some(store) = store() else {
+        return Ok(RestoreSummary::default());
+    };
+
+    let stations = store
+        .stations()?
+        .into_iter()
+        .map(station_record)
+        .collect::<Result<Vec<_>, _>>()?;
+    let metadata = store
+        .station_metadata()?
+        .into_iter()
+        .map(|row| {
+            let metadata = StationMetadata {
+                name: row.name,
+                location: row.location,
+                notes: row.notes,
+                heartbeat_interval: row.heartbeat_interval,
+                updated_at: Some(row.updated_at),
+            };
+            (row.station_id, metadata)
+        })
+        .collect();
+    let connectors = store
+        .connectors()?
+        .into_iter()
+        .map(connector_state)
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut transactions = Vec::new();
+    for row in store.transactions()? {
+        let readings = store.meter_readings(row.transaction_id)?;
+        transactions.push(transaction(row, &readings)?);
+    }
+
+    let webhooks: Vec<Webhook> = store.webhooks()?.into_iter().map(webhook).collect();
+    let mut deliveries = store.pending_webhook_deliveries()?;
+    let pending_deliveries = deliveries.len();
+    for webhook in &webhooks {
+        deliveries.extend(
+            store
+                .webhook_deliveries(&webhook.webhook_id, DELIVERY_LOG_CAPACITY)?
+                .into_iter()
+                .rev()
+                .filter(|row| row.status != DeliveryRowStatus::Pending),
+        );
+    }
+    deliveries.sort_by_key(|row| row.created_at);
+    let deliveries: Vec<Delivery> = deliveries.into_iter().map(delivery).collect();
+    let tokens = store
+        .api_tokens()?
+        .into_iter()
+        .map(api_token)
+        .collect::<Result<Vec<_>, _>>()?;
+    let users = store
+        .users()?
+        .into_iter()
+        .map(user)
+        .collect::<Result<Vec<_>, _>>()?;
+    let now = Utc::now();
+    let login_sessions: Vec<LoginSession> = store
+        .user_sessions()?
+        .into_iter()
+        .filter(|row| row.expires_at > now)
+        .map(|row| LoginSession {
+            session_hash: row.session_hash,
+            user_id: row.user_id,
+            created_at: row.created_at,
+            expires_at: row.expires_at,
+        })
+        .collect();
+
+    let summary = RestoreSummary {
+        stations: stations.len(),
+        connectors: connectors.len(),
+        transactions: transactions.len(),
+        open_transactions: transactions
+            .iter()
+            .filter(|transaction| transaction.status == transactions::TransactionStatus::Active)
+            .count(),
+        webhooks: webhooks.len(),
+        pending_deliveries,
+        api_tokens: tokens
+            .iter()
+            .filter(|token| token.revoked_at.is_none())
+            .count(),
+        users: users.len(),
+    };
+    stations::restore(stations);
+    stations::restore_metadata(metadata);
+    connectors::restore(connectors);
+    transactions::restore(transactions);
+    webhooks::restore(webhooks, deliveries);
+    api_tokens::restore(tokens);
+    users::restore(users, login_sessions);
+    Ok(summary)
+}
+
+pub fn save_station(record: &StationRecord) {
+    let row = StationRow {
+        station_id: record.station_id.clone(),
+        registration_status: to_text(&record.status),
+        vendor: record.vendor.clone(),
+        model: record.model.clone(),
+        serial_number: record.serial_number.clone(),
+        firmware_version: record.firmware_version.clone(),
+        first_seen: record.first_seen,
+        last_boot: record.last_boot,
+    };
+    write("station", move |store| store.save_station(&row));
+}
+
+pub fn save_station_metadata(station_id: &str, metadata: &StationMetadata) {
+    let row = StationMetadataRow {
+        station_id: station_id.to_string(),
+        name: metadata.name.clone(),
+        location: metadata.location.clone(),
+        notes: metadata.notes.clone(),
+        heartbeat_interval: metadata.heartbeat_interval,
+        updated_at: metadata.updated_at.unwrap_or_else(Utc::now),
+    };
+    write("station metadata", move |store| {
+        store.save_station_metadata(&row)
+    });
+}
+
+pub fn save_connector(state: &ConnectorState) {
+    let row = ConnectorRow {
+        station_id: state.station_id.clone(),
+        connector_id: state.connector_id,
+        status: to_text(&state.status),
+        error_code: to_text(&state.error_code),
+        info: state.info.clone(),
+        timestamp: state.timestamp,
+        updated_at: state.updated_at,
+    };
+    write("connector status", move |store| {
+        store.save_connector_status(&row)
+    });
+}
+
+pub fn save_id_tag(id_tag: &str, status: &AuthorizationStatus) {
+    let now = Utc::now();
+    let row = IdTagRow {
+        id_tag: id_tag.to_string(),
+        status: to_text(status),
+        expiry_date: None,
+        parent_id_tag: None,
+        first_seen: now,
+        last_seen: now,
+    };
+    write("id tag", move |store| store.save_id_tag(&row));
+}
+
+pub fn save_transaction(transaction: &Transaction) {
+    let row = TransactionRow {
+        transaction_id: transaction.transaction_id,
+        station_id: transaction.station_id.clone(),
+        connector_id: transaction.connector_id,
+        id_tag: transaction.id_tag.clone(),
+        meter_start: transaction.meter_start,
+        started_at: transaction.started_at,
+        last_meter_value: transaction.last_meter_value,
+        last_meter_at: transaction.last_meter_at,
+        peak_power_w: transaction.peak_power_w,
+        status: to_text(&transaction.status),
+        meter_stop: transaction.meter_stop,
+        stopped_at: transaction.stopped_at,
+        stop_reason: transaction.stop_reason.as_ref().map(to_text),
+        flag: transaction.flag.clone(),
+    };
+    write("transaction", move |store| store.save_transaction(&row));
+}
+
+pub fn save_meter_values(
+    station_id: &str,
+    connector_id: u32,
+    transaction_id: Option<i32>,
+    meter_values: &[MeterValue],
+) {
+    let readings: Vec<MeterReading> = meter_values
+        .iter()
+        .flat_map(|meter_value| {
+            meter_value
+                .sampled_value
+                .iter()
+                .map(move |sample| MeterReading {
+                    station_id: station_id.to_string(),
+                    connector_id,
+                    transaction_id,
+                    timestamp: meter_value.timestamp,
+                    measurand: to_text(&sample.measurand.clone().unwrap_or_default()),
+                    phase: sample.phase.as_ref().map(to_text),
+                    location: sample.location.as_ref().map(to_text),
+                    context: sample.context.as_ref().map(to_text),
+                    format: sample.format.as_ref().map(to_text),
+                    unit: sample.unit.as_ref().map(to_text),
+                    value: sample.value.clone(),
+                })
+        })
+        .collect();
+    if readings.is_empty() {
+        return;
+    }
+    write("meter values", move |store| {
+        store.save_meter_readings(&readings).map(|_| ())
+    });
+}
+
+pub fn command_sent(message_id: &str, station_id: &str, action: &str, request: &serde_json::Value) {
+    let row = CommandRow {
+        command_id: message_id.to_string(),
+        station_id: station_id.to_string(),
+        action: action.to_string(),
+        request: request.to_string(),
+        status: CommandStatus::Pending,
+        response: None,
+        created_at: Utc::now(),
+        completed_at: None,
+    };
+    write("command", move |store| store.save_command(&row));
+}
+
+pub fn command_finished(message_id: &str, status: CommandStatus, response: serde_json::Value) {
+    let message_id = message_id.to_string();
+    let completed_at = Utc::now();
+    write("command result", move |store| {
+        let Some(mut command) = store.command(&message_id)? else {
+            return Ok(());
+        };
+        command.status = status;
+        command.response = Some(response.to_string());
+        command.completed_at = Some(completed_at);
+        store.save_command(&command)
+    });
+}
+
+pub fn append_audit_record(record: &AuditRecord) {
+    let row = AuditRow {
+        audit_id: 0,
+        at: record.at,
+        actor_kind: to_text(&record.actor.kind),
+        actor_id: record.actor.id.clone(),
+        actor_name: record.actor.name.clone(),
+        remote_addr: record.actor.remote_addr.clone(),
+        action: record.action.clone(),
+        station_id: record.station_id.clone(),
+        request: record.request.as_ref().map(ToString::to_string),
+        response: record.response.as_ref().map(ToString::to_string),
+        outcome: to_text(&record.outcome),
+    };
+    write("audit record", move |store| {
+        store.append_audit_record(&row).map(|_| ())
+    });
+}
+
+/// Matching audit records, newest first. Empty without a store.
+pub async fn audit_records(filter: &AuditFilter, limit: usize) -> Vec<AuditRecord> {
+    let filter = filter.clone();
+    let rows = match read(move |store| store.audit_records(&filter, limit)).await {
+        None => return Vec::new(),
+        Some(Ok(rows)) => rows,
+        Some(Err(err)) => {
+            warn!("Failed to read the audit log: {err}");
+            return Vec::new();
+        }
+    };
+    rows.into_iter()
+        .filter_map(|row| match audit_record(row) {
+            Ok(record) => Some(record),
+            Err(err) => {
+                warn!("Skipping unreadable audit record: {err}");
+                None
+            }
+        })
+        .collect()
+}
+
+pub fn prune_audit_records(before: DateTime<Utc>) {
+    write("audit log pruning", move |store| {
+        let pruned = store.prune_audit_records(before)?;
+        if pruned > 0 {
+            info!(pruned, "Pruned audit records past retention");
+        }
+        Ok(())
+    });
+}
+
+pub fn save_webhook(webhook: &Webhook) {
+    let row = WebhookRow {
+        webhook_id: webhook.webhook_id.clone(),
+        url: webhook.url.clone(),
+        events: webhook.events.clone(),
+        secret: webhook.secret.clone(),
+        created_at: webhook.created_at,
+    };
+    write("webhook", move |store| store.save_webhook(&row));
+}
+
+pub fn delete_webhook(webhook_id: &str) {
+    let webhook_id = webhook_id.to_string();
+    write("webhook removal", move |store| {
+        store.delete_webhook(&webhook_id)
+    });
+}
+
+pub fn save_webhook_delivery(delivery: &Delivery) {
+    let row = WebhookDeliveryRow {
+        delivery_id: delivery.delivery_id.clone(),
+        webhook_id: delivery.webhook_id.clone(),
+        event: delivery.event.clone(),
+        payload: delivery.payload.clone(),
+        status: match delivery.status {
+            DeliveryStatus::Pending => DeliveryRowStatus::Pending,
+            DeliveryStatus::Delivered => DeliveryRowStatus::Delivered,
+            DeliveryStatus::Failed => DeliveryRowStatus::Failed,
+        },
+        attempts: delivery.attempts,
+        next_attempt_at: delivery.next_attempt_at,
+        last_attempt_at: delivery.last_attempt_at,
+        response_status: delivery.response_status,
+        last_error: delivery.last_error.clone(),
+        created_at: delivery.created_at,
+    };
+    write("webhook delivery", move |store| {
+        store.save_webhook_delivery(&row)
+    });
+}
+
+pub fn prune_webhook_deliveries(webhook_id: String, keep: usize) {
+    write("webhook delivery pruning", move |store| {
+        let pruned = store.prune_webhook_deliveries(&webhook_id, keep)?;
+        if pruned > 0 {
+            debug!(webhook_id, pruned, "Pruned webhook deliveries");
+        }
+        Ok(())
+    });
+}
+
+pub fn save_api_token(token: &ApiToken) {
+    let row = ApiTokenRow {
+        token_id: token.token_id.clone(),
+        name: token.name.clone(),
+        token_hash: token.token_hash.clone(),
+        prefix: token.prefix.clone(),
+        scopes: token.scopes.iter().map(to_text).collect(),
+        station_ids: token.station_ids.clone(),
+        created_at: token.created_at,
+        last_used_at: token.last_used_at,
+        use_count: token.use_count,
+        revoked_at: token.revoked_at,
+    };
+    write("API token", move |store| store.save_api_token(&row));
+}
+
+pub fn save_user(user: &User) {
+    let row = UserRow {
+        user_id: user.user_id.clone(),
+        username: user.username.clone(),
+        password_hash: user.password_hash.clone(),
+        role: to_text(&user.role),
+        id_tags: user.id_tags.clone(),
+        created_at: user.created_at,
+    };
+    write("user", move |store| store.save_user(&row));
+}
+
+pub fn delete_user(user_id: &str) {
+    let user_id = user_id.to_string();
+    write("user deletion", move |store| store.delete_user(&user_id));
+}
+
+pub fn save_login_session(session: &LoginSession) {
+    let row = UserSessionRow {
+        session_hash: session.session_hash.clone(),
+        user_id: session.user_id.clone(),
+        created_at: session.created_at,
+        expires_at: session.expires_at,
+    };
+    write("login session", move |store| store.save_user_session(&row));
+}
+
+pub fn delete_login_session(session_hash: &str) {
+    let session_hash = session_hash.to_string();
+    write("logout", move |store| {
+        store.delete_user_session(&session_hash)
+    });
+}
+
+/// OCPP enums are stored as their JSON spelling, e.g. `SuspendedEV`.
+fn to_text<T: Serialize>(value: &T) -> String {
+    match serde_json::to_value(value) {
+        Ok(serde_json::Value::String(text)) => text,
+        Ok(other) => other.to_string(),
+        Err(err) => {
+            warn!("Failed to encode value for storage: {err}");
+            String::new()
+        }
+    }
+}
+
+fn from_text<T: DeserializeOwned>(column: &'static str, text: &str) -> Result<T, StorageError> {
+    serde_json::from_value(serde_json::Value::String(text.to_string())).map_err(|_| {
+        StorageError::InvalidValue {
+            column,
+            value: text.to_string(),
+        }
+    })
+}
+
+fn optional_text<T: DeserializeOwned>(
+    column: &'static str,
+    text: Option<&str>,
+) -> Result<Option<T>, StorageError> {
+    text.map(|text| from_text(column, text)).transpose()
+}
+
+fn station_record(row: StationRow) -> Result<StationRecord, StorageError> {
+    Ok(StationRecord {
+        status: from_text("stations.registration_status", &row.registration_status)?,
+        station_id: row.station_id,
+        vendor: row.vendor,
+        model: row.model,
+        serial_number: row.serial_number,
+        firmware_version: row.firmware_version,
+        first_seen: row.first_seen,
+        last_boot: row.last_boot,
+    })
+}
+
+fn connector_state(row: ConnectorRow) -> Result<ConnectorState, StorageError> {
+    Ok(ConnectorState {
+        status: from_text("connectors.status", &row.status)?,
+        error_code: from_text("connectors.error_code", &row.error_code)?,
+        station_id: row.station_id,
+        connector_id: row.connector_id,
+        info: row.info,
+        timestamp: row.timestamp,
+        updated_at: row.updated_at,
+    })
+}
+
+fn transaction(
+    row: TransactionRow,
+    readings: &[MeterReading],
+) -> Result<Transaction, StorageError> {
+    let mut meter_samples = Vec::new();
+    let mut power_samples = Vec::new();
+    for reading in readings {
+        let meter_value = MeterValue {
+            timestamp: reading.timestamp,
+            sampled_value: vec![sampled_value(reading)?],
+        };
+        if let Some(wh) = transactions::energy_register_wh(&meter_value)
+            && meter_samples
+                .last()
+                .is_none_or(|last: &EnergySample| last.at < reading.timestamp)
+        {
+            meter_samples.push(EnergySample {
+                at: reading.timestamp,
+                wh,
+            });
+        }
+        if let Some(w) = transactions::active_power_w(&meter_value)
+            && power_samples
+                .last()
+                .is_none_or(|last: &PowerSample| last.at < reading.timestamp)
+        {
+            power_samples.push(PowerSample {
+                at: reading.timestamp,
+                w,
+            });
+        }
+    }
+
+    Ok(Transaction {
+        status: from_text("transactions.status", &row.status)?,
+        stop_reason: optional_text("transactions.stop_reason", row.stop_reason.as_deref())?,
+        transaction_id: row.transaction_id,
+        station_id: row.station_id,
+        connector_id: row.connector_id,
+        id_tag: row.id_tag,
+        meter_start: row.meter_start,
+        started_at: row.started_at,
+        last_meter_value: row.last_meter_value,
+        last_meter_at: row.last_meter_at,
+        meter_samples,
+        power_samples,
+        peak_power_w: row.peak_power_w,
+        meter_stop: row.meter_stop,
+        stopped_at: row.stopped_at,
+        flag: row.flag,
+    })
+}
+
+fn sampled_value(reading: &MeterReading) -> Result<SampledValue, StorageError> {
+    Ok(SampledValue {
+        value: reading.value.clone(),
+        context: optional_text("meter_values.context", reading.context.as_deref())?,
+        format: optional_text("meter_values.format", reading.format.as_deref())?,
+        measurand: Some(from_text("meter_values.measurand", &reading.measurand)?),
+        phase: optional_text("meter_values.phase", reading.phase.as_deref())?,
+        location: optional_text("meter_values.location", reading.location.as_deref())?,
+        unit: optional_text("meter_values.unit", reading.unit.as_deref())?,
+    })
+}
+
+fn webhook(row: WebhookRow) -> Webhook {
+    Webhook {
+        webhook_id: row.webhook_id,
+        url: row.url,
+        events: row.events,
+        secret: row.secret,
+        created_at: row.created_at,
+    }
+}
+
+fn delivery(row: WebhookDeliveryRow) -> Delivery {
+    Delivery {
+        status: match row.status {
+            DeliveryRowStatus::Pending => DeliveryStatus::Pending,
+            DeliveryRowStatus::Delivered => DeliveryStatus::Delivered,
+            DeliveryRowStatus::Failed => DeliveryStatus::Failed,
+        },
+        delivery_id: row.delivery_id,
+        webhook_id: row.webhook_id,
+        event: row.event,
+        payload: row.payload,
+        attempts: row.attempts,
+        next_attempt_at: row.next_attempt_at,
+        last_attempt_at: row.last_attempt_at,
+        response_status: row.response_status,
+        last_error: row.last_error,
+        created_at: row.created_at,
+    }
+}
+
+fn api_token(row: ApiTokenRow) -> Result<ApiToken, StorageError> {
+    Ok(ApiToken {
+        scopes: row
+            .scopes
+            .iter()
+            .map(|scope| from_text("api_tokens.scopes", scope))
+            .collect::<Result<_, _>>()?,
+        token_id: row.token_id,
+        name: row.name,
+        token_hash: row.token_hash,
+        prefix: row.prefix,
+        station_ids: row.station_ids,
+        created_at: row.created_at,
+        last_used_at: row.last_used_at,
+        use_count: row.use_count,
+        revoked_at: row.revoked_at,
+    })
+}
+
+fn user(row: UserRow) -> Result<User, StorageError> {
+    Ok(User {
+        role: from_text("users.role", &row.role)?,
+        user_id: row.user_id,
+        username: row.username,
+        password_hash: row.password_hash,
+        id_tags: row.id_tags,
+        created_at: row.created_at,
+    })
+}
+
+fn audit_record(row: AuditRow) -> Result<AuditRecord, StorageError> {
+    let json = |column: &'static str, text: Option<String>| {
+        text.map(|text| {
+            serde_json::from_str(&text).map_err(|_| StorageError::InvalidValue {
+                column,
+                value: text,
+            })
+        })
+        .transpose()
+    };
+    Ok(AuditRecord {
+        audit_id: row.audit_id,
+        at: row.at,
+        actor: Actor {
+            kind: from_text("audit_log.actor_kind", &row.actor_kind)?,
+            id: row.actor_id,
+            name: row.actor_name,
+            remote_addr: row.remote_addr,
+        },
+        action: row.action,
+        station_id: row.station_id,
+        request: json("audit_log.request", row.request)?,
+        response: json("audit_log.response", row.response)?,
+        outcome: from_text("audit_log.outcome", &row.outcome)?,
+    })
+}
@@ -0,0 +1,365 @@
+//! Built-in certificate authority for TLS on the home LAN.
+//!
+//! Home chargers reach the server on private addresses that no public CA will
+//! certify. On first start the server creates its own CA in `LOCAL_CA_DIR`,
+//! issues itself a server certificate for the configured names and keeps it
+//! renewed. The CA certificate is what gets loaded onto chargers; stations using
+//! mutual TLS get client certificates signed by the same CA.
+
+use std::{
+    collections::BTreeSet,
+    fs,
+    io::{self, Write},
+    net::IpAddr,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration as StdDuration,
+};
+
+use common::{LocalCaConfig, TlsConfig};
+use rcgen::{
+    BasicConstraints, Certificate, CertificateParams, DistinguishedName, DnType,
+    ExtendedKeyUsagePurpose, IsCa, KeyPair, KeyUsagePurpose,
+};
+use time::{Duration, OffsetDateTime};
+use tracing::{info, warn};
+use x509_parser::{
+    extensions::GeneralName,
+    prelude::{FromDer, X509Certificate},
+};
+
+use rustls::ServerConfig;
+
+use crate::state::LOCAL_CA;
+use crate::tls::{self, ServerCertResolver, TlsError};
+
+const CA_CERT_FILE: &str = "ca.pem";
+const CA_KEY_FILE: &str = "ca.key";
+const SERVER_CERT_FILE: &str = "server.pem";
+const SERVER_KEY_FILE: &str = "server.key";
+const CLIENTS_DIR: &str = "clients";
+
+const CA_VALIDITY: Duration = Duration::days(3650);
+const SERVER_VALIDITY: Duration = Duration::days(365);
+const CLIENT_VALIDITY: Duration = Duration::days(730);
+/// Renew the server certificate once it is this close to expiry.
+const RENEW_BEFORE: Duration = Duration::days(30);
+const RENEWAL_CHECK_INTERVAL: StdDuration = StdDuration::from_secs(24 * 60 * 60);
+
+#[derive(Debug, thiserror::Error)]
+pub enum PkiError {
+    #[error("failed to access {path}: {source}")]
+    Io { path: String, source: io::Error },
+    #[error("certificate error: {0}")]
+    Rcgen(#[from] rcgen::Error),
+    #[error("failed to parse certificate {0}")]
+    Parse(String),
+    #[error("station id `{0}` cannot be used as a file name")]
+    InvalidStationId(String),
+    #[error(transparent)]
+    Tls(#[from] TlsError),
+}
+
+/// A certificate and private key issued by the local CA, both PEM encoded.
+#[derive(Debug, Clone)]
+pub struct IssuedCertificate {
+    pub cert_pem: String,
+    pub key_pem: String,
+}
+
+/// Private CA persisted in a directory.
+pub struct LocalCa {
+    dir: PathBuf,
+    cert: Certificate,
+    cert_pem: String,
+    key: KeyPair,
+}
+
+impl std::fmt::Debug for LocalCa {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("LocalCa").field("dir", &self.dir).finish()
+    }
+}
+
+impl LocalCa {
+    /// Load the CA from `dir`, creating a new one on first start.
+    pub fn load_or_create(dir: &Path) -> Result<Self, PkiError> {
+        create_dir(dir)?;
+        let cert_path = dir.join(CA_CERT_FILE);
+        let key_path = dir.join(CA_KEY_FILE);
+
+        if cert_path.exists() && key_path.exists() {
+            let cert_pem = read(&cert_path)?;
+            let key = KeyPair::from_pem(&read(&key_path)?)?;
+            // Re-signing the stored parameters yields an issuer with the same
+            // subject and key, so certificates it signs chain to `ca.pem`.
+            let cert = CertificateParams::from_ca_cert_pem(&cert_pem)?.self_signed(&key)?;
+            return Ok(Self {
+                dir: dir.to_path_buf(),
+                cert,
+                cert_pem,
+                key,
+            });
+        }
+
+        let mut params = CertificateParams::new(Vec::<String>::new())?;
+        params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+        params.distinguished_name = distinguished_name("PlugHome Local CA");
+        params.key_usages = vec![
+            KeyUsagePurpose::KeyCertSign,
+            KeyUsagePurpose::CrlSign,
+            KeyUsagePurpose::DigitalSignature,
+        ];
+        set_validity(&mut params, CA_VALIDITY);
+
+        let key = KeyPair::generate()?;
+        let cert = params.self_signed(&key)?;
+        let cert_pem = cert.pem();
+        write_private(&key_path, &key.serialize_pem())?;
+        write(&cert_path, &cert_pem)?;
+        info!("Created local CA in {}", dir.display());
+
+        Ok(Self {
+            dir: dir.to_path_buf(),
+            cert,
+            cert_pem,
+            key,
+        })
+    }
+
+    /// PEM of the CA certificate, to be installed on chargers.
+    pub fn ca_certificate_pem(&self) -> &str {
+        &self.cert_pem
+    }
+
+    pub fn ca_cert_path(&self) -> PathBuf {
+        self.dir.join(CA_CERT_FILE)
+    }
+
+    pub fn server_cert_path(&self) -> PathBuf {
+        self.dir.join(SERVER_CERT_FILE)
+    }
+
+    pub fn server_key_path(&self) -> PathBuf {
+        self.dir.join(SERVER_KEY_FILE)
+    }
+
+    /// Issue a new server certificate when none exists, it is about to expire
+    /// or it does not cover `names`. Returns whether a certificate was issued.
+    pub fn ensure_server_certificate(&self, names: &[String]) -> Result<bool, PkiError> {
+        let cert_path = self.server_cert_path();
+        if cert_path.exists() && self.server_key_path().exists() {
+            let pem = read(&cert_path)?;
+            if !needs_renewal(&pem, names, OffsetDateTime::now_utc())? {
+                return Ok(false);
+            }
+        }
+
+        let mut params = CertificateParams::new(names.to_vec())?;
+        params.distinguished_name = distinguished_name(
+            names
+                .first()
+                .map(String::as_str)
+                .unwrap_or("PlugHome Server"),
+        );
+        params.extended_key_usages = vec![ExtendedKeyUsagePurpose::ServerAuth];
+        params.key_usages = vec![
+            KeyUsagePurpose::DigitalSignature,
+            KeyUsagePurpose::KeyEncipherment,
+        ];
+        set_validity(&mut params, SERVER_VALIDITY);
+
+        let issued = self.sign(params)?;
+        write_private(&self.server_key_path(), &issued.key_pem)?;
+        write(&cert_path, &issued.cert_pem)?;
+        info!("Issued server certificate for {}", names.join(", "));
+        Ok(true)
+    }
+
+    /// Issue a client certificate for a station using mutual TLS. The CN is the
+    /// station id. A copy is kept under `clients/` in the CA directory.
+    pub fn issue_client_certificate(
+        &self,
+        station_id: &str,
+    ) -> Result<IssuedCertificate, PkiError> {
+        if station_id.is_empty() || station_id.starts_with('.') || station_id.contains(['/', '\\'])
+        {
+            return Err(PkiError::InvalidStationId(station_id.to_string()));
+        }
+
+        let mut params = CertificateParams::new(Vec::<String>::new())?;
+        params.distinguished_name = distinguished_name(station_id);
+        params.extended_key_usages = vec![ExtendedKeyUsagePurpose::ClientAuth];
+        params.key_usages = vec![KeyUsagePurpose::DigitalSignature];
+        set_validity(&mut params, CLIENT_VALIDITY);
+
+        let issued = self.sign(params)?;
+        let clients = self.dir.join(CLIENTS_DIR);
+        create_dir(&clients)?;
+        write_private(&clients.join(format!("{station_id}.key")), &issued.key_pem)?;
+        write(&clients.join(format!("{station_id}.pem")), &issued.cert_pem)?;
+        info!(station_id, "Issued client certificate");
+        Ok(issued)
+    }
+
+    /// Sign a CSR submitted by a charger (SignCertificate). The CN is forced
+    /// to the station id so the certificate can only be used as that station.
+    pub fn sign_csr(&self, station_id: &str, csr_pem: &str) -> Result<String, PkiError> {
+        let mut csr = rcgen::CertificateSigningRequestParams::from_pem(csr_pem)?;
+        csr.params.distinguished_name = distinguished_name(station_id);
+        csr.params.is_ca = IsCa::NoCa;
+        csr.params.subject_alt_names = Vec::new();
+        csr.params.extended_key_usages = vec![ExtendedKeyUsagePurpose::ClientAuth];
+        csr.params.key_usages = vec![KeyUsagePurpose::DigitalSignature];
+        set_validity(&mut csr.params, CLIENT_VALIDITY);
+        let cert = csr.signed_by(&self.cert, &self.key)?;
+        Ok(cert.pem())
+    }
+
+    fn sign(&self, params: CertificateParams) -> Result<IssuedCertificate, PkiError> {
+        let key = KeyPair::generate()?;
+        let cert = params.signed_by(&key, &self.cert, &self.key)?;
+        Ok(IssuedCertificate {
+            cert_pem: cert.pem(),
+            key_pem: key.serialize_pem(),
+        })
+    }
+}
+
+/// Open the local CA, make sure the server certificate is current and build
+/// the TLS listener config from it. Renewal keeps running in the background
+/// and the CA is published for [`crate::routes::ca_certificate_route`].
+pub fn server_config(config: &LocalCaConfig) -> Result<Arc<ServerConfig>, PkiError> {
+    let ca = Arc::new(LocalCa::load_or_create(&config.dir)?);
+    ca.ensure_server_certificate(&config.server_names)?;
+
+    let tls_config = TlsConfig {
+        cert_file: ca.server_cert_path(),
+        key_file: ca.server_key_path(),
+        client_ca_file: config.client_auth.then(|| ca.ca_cert_path()),
+        client_cert_required: config.client_cert_required,
+    };
+    let resolver = Arc::new(ServerCertResolver::from_files(
+        &tls_config.cert_file,
+        &tls_config.key_file,
+    )?);
+    let server_config = tls::server_config_with_certs(&tls_config, resolver.clone())?;
+
+    spawn_renewal(ca.clone(), config.server_names.clone(), resolver);
+    let _ = LOCAL_CA.set(ca);
+    Ok(server_config)
+}
+
+/// Re-check the server certificate once a day and hot-swap it on renewal.
+pub fn spawn_renewal(ca: Arc<LocalCa>, names: Vec<String>, resolver: Arc<ServerCertResolver>) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(RENEWAL_CHECK_INTERVAL);
+        interval.tick().await;
+        loop {
+            interval.tick().await;
+            match ca.ensure_server_certificate(&names) {
+                Ok(true) => {
+                    if let Err(err) = resolver.reload() {
+                        warn!("Failed to load renewed server certificate: {err}");
+                    }
+                }
+                Ok(false) => {}
+                Err(err) => warn!("Server certificate renewal failed: {err}"),
+            }
+        }
+    });
+}
+
+fn needs_renewal(pem: &str, names: &[String], now: OffsetDateTime) -> Result<bool, PkiError> {
+    let (_, pem) = x509_parser::pem::parse_x509_pem(pem.as_bytes())
+        .map_err(|_| PkiError::Parse(SERVER_CERT_FILE.to_string()))?;
+    let (_, cert) = X509Certificate::from_der(&pem.contents)
+        .map_err(|_| PkiError::Parse(SERVER_CERT_FILE.to_string()))?;
+
+    let expires = cert.validity().not_after.to_datetime();
+    if expires - now < RENEW_BEFORE {
+        return Ok(true);
+    }
+
+    let covered: BTreeSet<String> = cert
+        .subject_alternative_name()
+        .ok()
+        .flatten()
+        .map(|san| {
+            san.value
+                .general_names
+                .iter()
+                .filter_map(|name| match name {
+                    GeneralName::DNSName(dns) => Some(dns.to_string()),
+                    GeneralName::IPAddress(octets) => ip_to_string(octets),
+                    _ => None,
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    let wanted: BTreeSet<String> = names
+        .iter()
+        .map(|name| match name.parse::<IpAddr>() {
+            Ok(ip) => ip.to_string(),
+            Err(_) => name.clone(),
+        })
+        .collect();
+    Ok(covered != wanted)
+}
+
+fn ip_to_string(octets: &[u8]) -> Option<String> {
+    let ip = match octets.len() {
+        4 => IpAddr::from(<[u8; 4]>::try_from(octets).ok()?),
+        16 => IpAddr::from(<[u8; 16]>::try_from(octets).ok()?),
+        _ => return None,
+    };
+    Some(ip.to_string())
+}
+
+fn distinguished_name(common_name: &str) -> DistinguishedName {
+    let mut name = DistinguishedName::new();
+    name.push(DnType::OrganizationName, "PlugHome");
+    name.push(DnType::CommonName, common_name);
+    name
+}
+
+fn set_validity(params: &mut CertificateParams, validity: Duration) {
+    let now = OffsetDateTime::now_utc();
+    // Tolerate chargers whose clock is a little behind.
+    params.not_before = now - Duration::days(1);
+    params.not_after = now + validity;
+}
+
+fn create_dir(path: &Path) -> Result<(), PkiError> {
+    fs::create_dir_all(path).map_err(|source| io_error(path, source))
+}
+
+fn read(path: &Path) -> Result<String, PkiError> {
+    fs::read_to_string(path).map_err(|source| io_error(path, source))
+}
+
+fn write(path: &Path, contents: &str) -> Result<(), PkiError> {
+    fs::write(path, contents).map_err(|source| io_error(path, source))
+}
+
+/// Write a private key readable by the owner only.
+fn write_private(path: &Path, contents: &str) -> Result<(), PkiError> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    options
+        .open(path)
+        .and_then(|mut file| file.write_all(contents.as_bytes()))
+        .map_err(|source| io_error(path, source))
+}
+
+fn io_error(path: &Path, source: io::Error) -> PkiError {
+    PkiError::Io {
+        path: path.display().to_string(),
+        source,
+    }
+}
@@ -0,0 +1,187 @@
+//! Online/offline tracking for chargers.
+//!
+//! Every frame from a charger counts as a sign of life. A watchdog marks
+//! stations offline once they stay quiet for `offline_multiplier` heartbeat
+//! intervals, even if the socket still looks open.
+
+use std::{
+    collections::HashMap,
+    sync::{LazyLock, RwLock},
+    time::Duration,
+};
+
+use chrono::{DateTime, Utc};
+use common::HeartbeatConfig;
+use serde::Serialize;
+use tokio::task::JoinHandle;
+use tracing::{info, warn};
+
+use crate::events::{self, OfflineReason, StationEvent};
+use crate::state::load_heartbeat_config;
+use crate::stations;
+
+/// How often the watchdog looks for silent stations.
+pub const DEFAULT_WATCHDOG_PERIOD: Duration = Duration::from_secs(10);
+
+/// Weight of the newest sample in the smoothed round-trip time.
+const RTT_SMOOTHING: f64 = 0.2;
+
+static PRESENCE: LazyLock<RwLock<HashMap<String, Presence>>> =
+    LazyLock::new(|| RwLock::new(HashMap::new()));
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Presence {
+    pub station_id: String,
+    pub online: bool,
+    pub last_seen: DateTime<Utc>,
+    /// When the station last went online or offline.
+    pub since: DateTime<Utc>,
+    /// Round-trip time of the latest server ping, in milliseconds.
+    pub rtt_ms: Option<f64>,
+    /// Smoothed ping round-trip time, in milliseconds.
+    pub avg_rtt_ms: Option<f64>,
+}
+
+/// Record that a frame arrived from the station.
+pub fn touch(station_id: &str) {
+    let now = Utc::now();
+    let came_online = {
+        let mut presence = PRESENCE.write().expect("presence lock poisoned");
+        let entry = presence
+            .entry(station_id.to_string())
+            .or_insert_with(|| Presence {
+                station_id: station_id.to_string(),
+                online: false,
+                last_seen: now,
+                since: now,
+                rtt_ms: None,
+                avg_rtt_ms: None,
+            });
+        entry.last_seen = now;
+        let came_online = !entry.online;
+        if came_online {
+            entry.online = true;
+            entry.since = now;
+        }
+        came_online
+    };
+
+    if came_online {
+        info!(station_id, "Station online");
+        events::publish(StationEvent::Online {
+            station_id: station_id.to_string(),
+            at: now,
+        });
+    }
+}
+
+/// Record that the station's socket closed.
+pub fn disconnected(station_id: &str, reason: OfflineReason) {
+    mark_offline(station_id, reason, Utc::now());
+}
+
+/// Record the round-trip time of a server ping as a link-quality signal.
+pub fn record_rtt(station_id: &str, rtt: Duration) {
+    let rtt_ms = rtt.as_secs_f64() * 1000.0;
+    let mut presence = PRESENCE.write().expect("presence lock poisoned");
+    if let Some(entry) = presence.get_mut(station_id) {
+        entry.rtt_ms = Some(rtt_ms);
+        entry.avg_rtt_ms = Some(match entry.avg_rtt_ms {
+            Some(avg) => avg + RTT_SMOOTHING * (rtt_ms - avg),
+            None => rtt_ms,
+        });
+    }
+}
+
+pub fn get(station_id: &str) -> Option<Presence> {
+    PRESENCE
+        .read()
+        .expect("presence lock poisoned")
+        .get(station_id)
+        .cloned()
+}
+
+pub fn all() -> Vec<Presence> {
+    let mut all: Vec<_> = PRESENCE
+        .read()
+        .expect("presence lock poisoned")
+        .values()
+        .cloned()
+        .collect();
+    all.sort_by(|a, b| a.station_id.cmp(&b.station_id));
+    all
+}
+
+/// Mark every online station that has been silent past its deadline as
+/// offline. Returns the ids of stations that went offline.
+pub fn check_heartbeats(now: DateTime<Utc>, config: &HeartbeatConfig) -> Vec<String> {
+    let timed_out: Vec<(String, DateTime<Utc>)> = {
+        let mut presence = PRESENCE.write().expect("presence lock poisoned");
+        presence
+            .values_mut()
+            .filter(|entry| entry.online)
+            .filter(|entry| {
+                let interval = stations::heartbeat_interval(&entry.station_id, config);
+                let deadline = i64::from(interval) * i64::from(config.offline_multiplier);
+                now.signed_duration_since(entry.last_seen) > chrono::Duration::seconds(deadline)
+            })
+            .map(|entry| {
+                entry.online = false;
+                entry.since = now;
+                (entry.station_id.clone(), entry.last_seen)
+            })
+            .collect()
+    };
+
+    timed_out
+        .into_iter()
+        .map(|(station_id, last_seen)| {
+            publish_offline(&station_id, OfflineReason::HeartbeatTimeout, now, last_seen);
+            station_id
+        })
+        .collect()
+}
+
+/// Run `check_heartbeats` every `period` until the runtime shuts down.
+pub fn spawn_watchdog(period: Duration) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let config = load_heartbeat_config().await;
+        let mut ticker = tokio::time::interval(period);
+        loop {
+            ticker.tick().await;
+            for station_id in check_heartbeats(Utc::now(), config) {
+                warn!(station_id, "Station missed its heartbeat deadline");
+            }
+        }
+    })
+}
+
+fn mark_offline(station_id: &str, reason: OfflineReason, now: DateTime<Utc>) {
+    let last_seen = {
+        let mut presence = PRESENCE.write().expect("presence lock poisoned");
+        match presence.get_mut(station_id) {
+            Some(entry) if entry.online => {
+                entry.online = false;
+                entry.since = now;
+                entry.last_seen
+            }
+            _ => return,
+        }
+    };
+    publish_offline(station_id, reason, now, last_seen);
+}
+
+fn publish_offline(
+    station_id: &str,
+    reason: OfflineReason,
+    now: DateTime<Utc>,
+    last_seen: DateTime<Utc>,
+) {
+    info!(station_id, ?reason, "Station offline");
+    events::publish(StationEvent::Offline {
+        station_id: station_id.to_string(),
+        at: now,
+        last_seen,
+        reason,
+    });
+}
@@ -0,0 +1,89 @@
+use axum::Json;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use storage::AuditFilter;
+use utoipa::{IntoParams, ToSchema};
+
+use super::{ApiError, ApiQuery, ErrorResponse};
+use crate::audit::{self, AuditRecord};
+
+const DEFAULT_PAGE_SIZE: usize = 100;
+const MAX_PAGE_SIZE: usize = 1000;
+
+/// One page of audit records, newest first.
+#[derive(Serialize, ToSchema, Debug, Clone, PartialEq)]
+pub struct AuditPage {
+    pub records: Vec<AuditRecord>,
+    /// Pass as `cursor` to get the next page; `None` on the last page.
+    pub next_cursor: Option<String>,
+}
+
+/// Query of `GET /audit`.
+#[derive(Deserialize, IntoParams, Debug, Default)]
+#[into_params(parameter_in = Query)]
+#[serde(deny_unknown_fields)]
+pub struct ListQuery {
+    station_id: Option<String>,
+    /// User or token id, or the name of an automation.
+    actor_id: Option<String>,
+    /// OCPP action or API change, e.g. `Reset` or `CreateUser`.
+    action: Option<String>,
+    /// Records made at or after this time.
+    from: Option<DateTime<Utc>>,
+    /// Records made before this time.
+    to: Option<DateTime<Utc>>,
+    /// Page size, 1 to 1000; 100 when left out.
+    limit: Option<usize>,
+    /// `next_cursor` of the previous page.
+    cursor: Option<String>,
+}
+
+/// Audit records newest first, a page at a time.
+#[utoipa::path(
+    get,
+    path = "/audit",
+    operation_id = "list_audit_records",
+    tag = "audit",
+    params(ListQuery),
+    responses(
+        (status = 200, body = AuditPage),
+        (status = 400, description = "Invalid cursor", body = ErrorResponse),
+        (status = 422, description = "Invalid limit", body = ErrorResponse),
+    )
+)]
+pub async fn list(ApiQuery(query): ApiQuery<ListQuery>) -> Result<Json<AuditPage>, ApiError> {
+    let limit = query.limit.unwrap_or(DEFAULT_PAGE_SIZE);
+    if !(1..=MAX_PAGE_SIZE).contains(&limit) {
+        return Err(ApiError::Validation(format!(
+            "limit must be between 1 and {MAX_PAGE_SIZE}"
+        )));
+    }
+    // The cursor is the id of the last record on the previous page.
+    let before_id = query
+        .cursor
+        .map(|cursor| {
+            cursor
+                .parse()
+                .map_err(|_| ApiError::BadRequest(format!("invalid cursor {cursor:?}")))
+        })
+        .transpose()?;
+    let filter = AuditFilter {
+        station_id: query.station_id,
+        actor_id: query.actor_id,
+        action: query.action,
+        from: query.from,
+        to: query.to,
+        before_id,
+    };
+    let mut records = audit::search(&filter, limit + 1).await;
+    let next_cursor = if records.len() > limit {
+        records.truncate(limit);
+        records.last().map(|record| record.audit_id.to_string())
+    } else {
+        None
+    };
+    Ok(Json(AuditPage {
+        records,
+        next_cursor,
+    }))
+}
@@ -0,0 +1,239 @@
+//! Who may call the API: API tokens and logged in users.
+//!
+//! A request carries either `Authorization: Bearer <token>` or the session
+//! cookie from `POST /auth/login`. Handlers find the [`Caller`] in the
+//! request extensions, and the [`Actor`] recorded in the audit log through
+//! its extractor. Logging in, the OpenAPI description and its docs page
+//! need neither.
+//!
+//! For tokens, `GET` requests need the `read` scope, other methods `control`,
+//! and tokens, users, webhooks, registrations, the audit log and station
+//! passwords `admin`. A token limited to some stations may only reach those:
+//! through `/stations/{id}`, single sessions and commands of theirs, and the
+//! session, command and event lists narrowed with `station_id`.
+//!
+//! For users, the owner may do anything. Members and guests may read
+//! everything but the admin paths, and members may also start and stop
+//! charging.
+
+use std::{collections::HashMap, convert::Infallible, net::SocketAddr};
+
+use axum::{
+    async_trait,
+    extract::{ConnectInfo, FromRequestParts, Query, Request},
+    http::{HeaderMap, Method, Uri, header, request::Parts},
+    middleware::Next,
+    response::Response,
+};
+
+use super::ApiError;
+use crate::api_tokens::{self, ApiToken, Scope};
+use crate::audit::Actor;
+use crate::commands;
+use crate::transactions;
+use crate::users::{self, Role, User};
+
+/// Name of the cookie holding a login session.
+pub const SESSION_COOKIE: &str = "plughome_session";
+
+/// Who made a request.
+#[derive(Debug, Clone)]
+pub enum Caller {
+    Token(ApiToken),
+    User(User),
+}
+
+impl Caller {
+    /// Id tags whose sessions the caller may see; `None` for all of them.
+    pub fn id_tags(&self) -> Option<&[String]> {
+        match self {
+            Self::User(user) if user.role != Role::Owner => Some(&user.id_tags),
+            _ => None,
+        }
+    }
+}
+
+pub async fn authenticate(
+    connect_info: Option<ConnectInfo<SocketAddr>>,
+    mut request: Request,
+    next: Next,
+) -> Result<Response, ApiError> {
+    let path = request.uri().path().to_string();
+    let segments: Vec<&str> = path.split('/').filter(|part| !part.is_empty()).collect();
+    if matches!(
+        segments[..],
+        ["auth", "login"] | ["openapi.json"] | ["docs", ..]
+    ) {
+        return Ok(next.run(request).await);
+    }
+
+    let caller = if let Some(value) = request.headers().get(header::AUTHORIZATION) {
+        let text = value
+            .to_str()
+            .ok()
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .map(str::trim)
+            .ok_or_else(|| ApiError::Unauthorized("a bearer token is required".to_string()))?;
+        let token = api_tokens::authenticate(text)
+            .ok_or_else(|| ApiError::Unauthorized("unknown or revoked token".to_string()))?;
+        check_token(&token, request.method(), &segments, request.uri())?;
+        Caller::Token(token)
+    } else if let Some(cookie) = session_cookie(request.headers()) {
+        let user = users::authenticate(&cookie)
+            .ok_or_else(|| ApiError::Unauthorized("login expired, log in again".to_string()))?;
+        check_role(&user, request.method(), &segments)?;
+        Caller::User(user)
+    } else {
+        return Err(ApiError::Unauthorized(
+            "a bearer token or login is required".to_string(),
+        ));
+    };
+    let remote_addr = remote_addr(connect_info);
+    let actor = match &caller {
+        Caller::Token(token) => Actor::token(token, remote_addr),
+        Caller::User(user) => Actor::user(user, remote_addr),
+    };
+    request.extensions_mut().insert(caller);
+    request.extensions_mut().insert(actor);
+    Ok(next.run(request).await)
+}
+
+/// The caller set by [`authenticate`], or an anonymous one without it.
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for Actor {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Infallible> {
+        if let Some(actor) = parts.extensions.get::<Actor>() {
+            return Ok(actor.clone());
+        }
+        let connect_info =
+            Option::<ConnectInfo<SocketAddr>>::from_request_parts(parts, state).await?;
+        Ok(Actor::anonymous(remote_addr(connect_info)))
+    }
+}
+
+fn remote_addr(connect_info: Option<ConnectInfo<SocketAddr>>) -> Option<String> {
+    connect_info.map(|ConnectInfo(addr)| addr.ip().to_string())
+}
+
+/// The login session cookie sent with a request.
+pub fn session_cookie(headers: &HeaderMap) -> Option<String> {
+    headers
+        .get_all(header::COOKIE)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(';'))
+        .filter_map(|pair| pair.trim().split_once('='))
+        .find(|(name, _)| *name == SESSION_COOKIE)
+        .map(|(_, value)| value.to_string())
+}
+
+fn check_token(
+    token: &ApiToken,
+    method: &Method,
+    segments: &[&str],
+    uri: &Uri,
+) -> Result<(), ApiError> {
+    let needed = required_scope(method, segments);
+    if !token.grants(needed) {
+        return Err(ApiError::Forbidden(format!(
+            "this request needs the {} scope",
+            scope_name(needed)
+        )));
+    }
+    if token.station_ids.is_some() && !reaches_only_allowed(token, segments, uri) {
+        return Err(ApiError::Forbidden(
+            "this token is limited to other stations".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+fn check_role(user: &User, method: &Method, segments: &[&str]) -> Result<(), ApiError> {
+    let allowed = match user.role {
+        Role::Owner => true,
+        _ if is_admin_path(segments) => false,
+        _ if method == Method::GET || method == Method::HEAD => true,
+        _ if segments == ["auth", "logout"] => true,
+        Role::Member => {
+            method == Method::POST && matches!(segments, ["stations", _, "start" | "stop"])
+        }
+        Role::Guest => false,
+    };
+    if allowed {
+        Ok(())
+    } else {
+        Err(ApiError::Forbidden(format!(
+            "{} may not do this",
+            role_name(user.role)
+        )))
+    }
+}
+
+fn is_admin_path(segments: &[&str]) -> bool {
+    matches!(
+        segments,
+        [
+            "tokens" | "users" | "webhooks" | "registrations" | "audit",
+            ..
+        ] | ["stations", _, "password"]
+    )
+}
+
+fn required_scope(method: &Method, segments: &[&str]) -> Scope {
+    if is_admin_path(segments) {
+        Scope::Admin
+    } else if method == Method::GET || method == Method::HEAD {
+        Scope::Read
+    } else {
+        Scope::Control
+    }
+}
+
+/// Whether a station-limited token stays within its stations.
+fn reaches_only_allowed(token: &ApiToken, segments: &[&str], uri: &Uri) -> bool {
+    match segments {
+        ["stations", station_id, ..] | ["registrations", station_id, ..] => {
+            token.allows_station(station_id)
+        }
+        ["sessions"] | ["sessions", "export"] | ["commands"] | ["events"] | ["events", "ws"] => {
+            let Ok(Query(query)) = Query::<HashMap<String, String>>::try_from_uri(uri) else {
+                return false;
+            };
+            let station_ids: Vec<&str> = query
+                .get("station_id")
+                .into_iter()
+                .flat_map(|ids| ids.split(','))
+                .map(str::trim)
+                .filter(|id| !id.is_empty())
+                .collect();
+            !station_ids.is_empty() && station_ids.iter().all(|id| token.allows_station(id))
+        }
+        ["sessions", transaction_id] => transaction_id
+            .parse()
+            .ok()
+            .and_then(transactions::get)
+            .is_some_and(|transaction| token.allows_station(&transaction.station_id)),
+        ["commands", job_id] => {
+            commands::get(job_id).is_some_and(|job| token.allows_station(&job.station_id))
+        }
+        _ => false,
+    }
+}
+
+fn scope_name(scope: Scope) -> &'static str {
+    match scope {
+        Scope::Read => "read",
+        Scope::Control => "control",
+        Scope::Admin => "admin",
+    }
+}
+
+fn role_name(role: Role) -> &'static str {
+    match role {
+        Role::Owner => "owners",
+        Role::Member => "members",
+        Role::Guest => "guests",
+    }
+}
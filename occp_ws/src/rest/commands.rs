@@ -0,0 +1,378 @@
+use axum::{
+    Extension, Json,
+    http::{HeaderMap, StatusCode, header},
+    response::{IntoResponse, Response},
+};
+use rust_ocpp::v1_6::types::{AvailabilityType, ResetRequestStatus};
+use serde::Deserialize;
+use utoipa::{
+    IntoParams, PartialSchema, ToSchema,
+    openapi::{
+        Required,
+        path::{Parameter, ParameterBuilder, ParameterIn},
+    },
+};
+
+use super::auth::Caller;
+use super::openapi;
+use super::stations::ensure_known;
+use super::{ApiError, ApiJson, ApiPath, ApiQuery, ErrorResponse};
+use crate::audit::Actor;
+use crate::commands::{self, Command, CommandJob, SubmitError};
+use crate::transactions;
+
+const MAX_IDEMPOTENCY_KEY_LEN: usize = 255;
+
+#[derive(Deserialize, ToSchema, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct StartBody {
+    connector_id: Option<u32>,
+    id_tag: String,
+}
+
+/// Without a transaction id, the one running on `connector_id` is stopped,
+/// or the station's only running transaction.
+#[derive(Deserialize, ToSchema, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub struct StopBody {
+    transaction_id: Option<i32>,
+    connector_id: Option<u32>,
+}
+
+#[derive(Deserialize, ToSchema, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub struct ResetBody {
+    #[serde(default)]
+    #[schema(value_type = openapi::ResetType, default = "Soft")]
+    reset_type: ResetRequestStatus,
+}
+
+#[derive(Deserialize, ToSchema, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct UnlockBody {
+    connector_id: u32,
+}
+
+#[derive(Deserialize, ToSchema, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct AvailabilityBody {
+    /// `0` is the whole charge point.
+    #[serde(default)]
+    connector_id: u32,
+    #[schema(value_type = openapi::AvailabilityType)]
+    availability: AvailabilityType,
+}
+
+#[derive(Deserialize, ToSchema, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct CurrentLimitBody {
+    /// `0` applies the limit to the whole charge point.
+    #[serde(default)]
+    connector_id: u32,
+    /// Maximum current in A.
+    limit_a: f64,
+}
+
+#[derive(Deserialize, IntoParams, Debug, Default)]
+#[into_params(parameter_in = Query)]
+#[serde(deny_unknown_fields)]
+pub struct ListQuery {
+    station_id: Option<String>,
+}
+
+/// Parameters of every command endpoint: the station in the path and an
+/// optional `Idempotency-Key` header.
+pub struct CommandParams;
+
+impl IntoParams for CommandParams {
+    fn into_params(_: impl Fn() -> Option<ParameterIn>) -> Vec<Parameter> {
+        vec![
+            ParameterBuilder::new()
+                .name("station_id")
+                .parameter_in(ParameterIn::Path)
+                .required(Required::True)
+                .description(Some("Charge point identity"))
+                .schema(Some(String::schema()))
+                .build(),
+            ParameterBuilder::new()
+                .name("Idempotency-Key")
+                .parameter_in(ParameterIn::Header)
+                .required(Required::False)
+                .description(Some(
+                    "Repeating a request with the same key returns the first job instead of \
+                     sending the command again",
+                ))
+                .schema(Some(String::schema()))
+                .build(),
+        ]
+    }
+}
+
+/// Start charging. Members may only start with their own id tags.
+#[utoipa::path(
+    post,
+    path = "/stations/{station_id}/start",
+    operation_id = "remote_start",
+    tag = "commands",
+    params(CommandParams),
+    request_body = StartBody,
+    responses(
+        (status = 202, description = "Queued; poll the job at the `Location` header", body = CommandJob),
+        (status = 403, description = "The id tag is not the caller's", body = ErrorResponse),
+        (status = 404, description = "Unknown station", body = ErrorResponse),
+        (status = 409, description = "The station is offline or the key was used for another command", body = ErrorResponse),
+        (status = 422, description = "Invalid values", body = ErrorResponse),
+    )
+)]
+pub async fn start(
+    caller: Option<Extension<Caller>>,
+    actor: Actor,
+    ApiPath(station_id): ApiPath<String>,
+    headers: HeaderMap,
+    ApiJson(body): ApiJson<StartBody>,
+) -> Result<Response, ApiError> {
+    let id_tag = body.id_tag.trim().to_string();
+    if let Some(id_tags) = caller.as_deref().and_then(Caller::id_tags)
+        && !id_tags.contains(&id_tag)
+    {
+        return Err(ApiError::Forbidden(format!(
+            "id tag {id_tag} is not one of yours"
+        )));
+    }
+    let command = Command::RemoteStart {
+        connector_id: body.connector_id,
+        id_tag,
+    };
+    submit(&station_id, &headers, command, &actor)
+}
+
+/// Stop charging. Members may only stop their own sessions.
+#[utoipa::path(
+    post,
+    path = "/stations/{station_id}/stop",
+    operation_id = "remote_stop",
+    tag = "commands",
+    params(CommandParams),
+    request_body = StopBody,
+    responses(
+        (status = 202, description = "Queued; poll the job at the `Location` header", body = CommandJob),
+        (status = 403, description = "The session is not the caller's", body = ErrorResponse),
+        (status = 404, description = "Unknown station", body = ErrorResponse),
+        (status = 409, description = "The station is offline or the key was used for another command", body = ErrorResponse),
+        (status = 422, description = "Invalid values, or no single running transaction to stop", body = ErrorResponse),
+    )
+)]
+pub async fn stop(
+    caller: Option<Extension<Caller>>,
+    actor: Actor,
+    ApiPath(station_id): ApiPath<String>,
+    headers: HeaderMap,
+    ApiJson(body): ApiJson<StopBody>,
+) -> Result<Response, ApiError> {
+    ensure_known(&station_id)?;
+    let transaction_id =
+        commands::stop_target(&station_id, body.transaction_id, body.connector_id)?;
+    if let Some(id_tags) = caller.as_deref().and_then(Caller::id_tags)
+        && !transactions::get(transaction_id)
+            .is_some_and(|transaction| id_tags.contains(&transaction.id_tag))
+    {
+        return Err(ApiError::Forbidden(format!(
+            "transaction {transaction_id} is not one of yours"
+        )));
+    }
+    submit(
+        &station_id,
+        &headers,
+        Command::RemoteStop { transaction_id },
+        &actor,
+    )
+}
+
+#[utoipa::path(
+    post,
+    path = "/stations/{station_id}/reset",
+    operation_id = "reset",
+    tag = "commands",
+    params(CommandParams),
+    request_body = ResetBody,
+    responses(
+        (status = 202, description = "Queued; poll the job at the `Location` header", body = CommandJob),
+        (status = 404, description = "Unknown station", body = ErrorResponse),
+        (status = 409, description = "The station is offline or the key was used for another command", body = ErrorResponse),
+        (status = 422, description = "Invalid values", body = ErrorResponse),
+    )
+)]
+pub async fn reset(
+    actor: Actor,
+    ApiPath(station_id): ApiPath<String>,
+    headers: HeaderMap,
+    ApiJson(body): ApiJson<ResetBody>,
+) -> Result<Response, ApiError> {
+    let command = Command::Reset {
+        reset_type: body.reset_type,
+    };
+    submit(&station_id, &headers, command, &actor)
+}
+
+#[utoipa::path(
+    post,
+    path = "/stations/{station_id}/unlock",
+    operation_id = "unlock_connector",
+    tag = "commands",
+    params(CommandParams),
+    request_body = UnlockBody,
+    responses(
+        (status = 202, description = "Queued; poll the job at the `Location` header", body = CommandJob),
+        (status = 404, description = "Unknown station", body = ErrorResponse),
+        (status = 409, description = "The station is offline or the key was used for another command", body = ErrorResponse),
+        (status = 422, description = "Invalid values", body = ErrorResponse),
+    )
+)]
+pub async fn unlock(
+    actor: Actor,
+    ApiPath(station_id): ApiPath<String>,
+    headers: HeaderMap,
+    ApiJson(body): ApiJson<UnlockBody>,
+) -> Result<Response, ApiError> {
+    let command = Command::Unlock {
+        connector_id: body.connector_id,
+    };
+    submit(&station_id, &headers, command, &actor)
+}
+
+#[utoipa::path(
+    post,
+    path = "/stations/{station_id}/availability",
+    operation_id = "change_availability",
+    tag = "commands",
+    params(CommandParams),
+    request_body = AvailabilityBody,
+    responses(
+        (status = 202, description = "Queued; poll the job at the `Location` header", body = CommandJob),
+        (status = 404, description = "Unknown station", body = ErrorResponse),
+        (status = 409, description = "The station is offline or the key was used for another command", body = ErrorResponse),
+        (status = 422, description = "Invalid values", body = ErrorResponse),
+    )
+)]
+pub async fn availability(
+    actor: Actor,
+    ApiPath(station_id): ApiPath<String>,
+    headers: HeaderMap,
+    ApiJson(body): ApiJson<AvailabilityBody>,
+) -> Result<Response, ApiError> {
+    let command = Command::ChangeAvailability {
+        connector_id: body.connector_id,
+        availability: body.availability,
+    };
+    submit(&station_id, &headers, command, &actor)
+}
+
+#[utoipa::path(
+    post,
+    path = "/stations/{station_id}/current-limit",
+    operation_id = "set_current_limit",
+    tag = "commands",
+    params(CommandParams),
+    request_body = CurrentLimitBody,
+    responses(
+        (status = 202, description = "Queued; poll the job at the `Location` header", body = CommandJob),
+        (status = 404, description = "Unknown station", body = ErrorResponse),
+        (status = 409, description = "The station is offline or the key was used for another command", body = ErrorResponse),
+        (status = 422, description = "Invalid values", body = ErrorResponse),
+    )
+)]
+pub async fn current_limit(
+    actor: Actor,
+    ApiPath(station_id): ApiPath<String>,
+    headers: HeaderMap,
+    ApiJson(body): ApiJson<CurrentLimitBody>,
+) -> Result<Response, ApiError> {
+    let command = Command::SetCurrentLimit {
+        connector_id: body.connector_id,
+        limit_a: body.limit_a,
+    };
+    submit(&station_id, &headers, command, &actor)
+}
+
+/// Recent command jobs, newest first. Members and guests only see starts
+/// and stops for their own id tags.
+#[utoipa::path(
+    get,
+    path = "/commands",
+    operation_id = "list_commands",
+    tag = "commands",
+    params(ListQuery),
+    responses((status = 200, body = Vec<CommandJob>))
+)]
+pub async fn list(
+    caller: Option<Extension<Caller>>,
+    ApiQuery(query): ApiQuery<ListQuery>,
+) -> Json<Vec<CommandJob>> {
+    let id_tags = caller.as_deref().and_then(Caller::id_tags);
+    let mut jobs = commands::list(query.station_id.as_deref());
+    jobs.retain(|job| id_tags.is_none_or(|tags| job.concerns(tags)));
+    Json(jobs)
+}
+
+#[utoipa::path(
+    get,
+    path = "/commands/{job_id}",
+    operation_id = "get_command",
+    tag = "commands",
+    params(("job_id" = String, Path)),
+    responses(
+        (status = 200, body = CommandJob),
+        (status = 404, description = "Unknown job", body = ErrorResponse),
+    )
+)]
+pub async fn show(
+    caller: Option<Extension<Caller>>,
+    ApiPath(job_id): ApiPath<String>,
+) -> Result<Json<CommandJob>, ApiError> {
+    let id_tags = caller.as_deref().and_then(Caller::id_tags);
+    commands::get(&job_id)
+        .filter(|job| id_tags.is_none_or(|tags| job.concerns(tags)))
+        .map(Json)
+        .ok_or_else(|| ApiError::NotFound(format!("command {job_id} not found")))
+}
+
+/// Queue the command and answer 202 with the job and where to poll it.
+fn submit(
+    station_id: &str,
+    headers: &HeaderMap,
+    command: Command,
+    actor: &Actor,
+) -> Result<Response, ApiError> {
+    ensure_known(station_id)?;
+    let key = idempotency_key(headers)?;
+    let job = commands::submit(station_id, command, key, actor)?;
+    let location = format!("/api/v1/commands/{}", job.job_id);
+    Ok((
+        StatusCode::ACCEPTED,
+        [(header::LOCATION, location)],
+        Json(job),
+    )
+        .into_response())
+}
+
+fn idempotency_key(headers: &HeaderMap) -> Result<Option<&str>, ApiError> {
+    let Some(value) = headers.get("idempotency-key") else {
+        return Ok(None);
+    };
+    match value.to_str().map(str::trim) {
+        Ok(key) if !key.is_empty() && key.len() <= MAX_IDEMPOTENCY_KEY_LEN => Ok(Some(key)),
+        _ => Err(ApiError::BadRequest(format!(
+            "Idempotency-Key must be 1 to {MAX_IDEMPOTENCY_KEY_LEN} visible ASCII characters"
+        ))),
+    }
+}
+
+impl From<SubmitError> for ApiError {
+    fn from(err: SubmitError) -> Self {
+        match err {
+            SubmitError::Offline(_) => Self::StationOffline(err.to_string()),
+            SubmitError::KeyReused(_) => Self::Conflict(err.to_string()),
+            SubmitError::Invalid(_) => Self::Validation(err.to_string()),
+        }
+    }
+}
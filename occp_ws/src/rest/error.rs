@@ -0,0 +1,129 @@
+use axum::{
+    Json,
+    extract::rejection::{JsonRejection, PathRejection, QueryRejection},
+    http::{StatusCode, header},
+    response::{IntoResponse, Response},
+};
+use serde::Serialize;
+use utoipa::ToSchema;
+
+#[derive(Debug, thiserror::Error)]
+pub enum ApiError {
+    #[error("{0}")]
+    NotFound(String),
+    /// The request could not be read, e.g. invalid JSON or path parameters.
+    #[error("{0}")]
+    BadRequest(String),
+    /// The request was readable but its values are not acceptable.
+    #[error("{0}")]
+    Validation(String),
+    /// The path exists but does not take the request's method.
+    #[error("{0}")]
+    MethodNotAllowed(String),
+    #[error("{0}")]
+    UnsupportedMediaType(String),
+    /// The request clashes with an earlier one.
+    #[error("{0}")]
+    Conflict(String),
+    /// The station must be connected for this request.
+    #[error("{0}")]
+    StationOffline(String),
+    /// The station refused or did not answer a call made for the request.
+    #[error("{0}")]
+    StationFailed(String),
+    /// No valid bearer token.
+    #[error("{0}")]
+    Unauthorized(String),
+    /// The token lacks the scope or station access the request needs.
+    #[error("{0}")]
+    Forbidden(String),
+}
+
+/// Body of every error response.
+#[derive(Serialize, ToSchema, Debug)]
+pub struct ErrorResponse {
+    pub error: ErrorDetail,
+}
+
+#[derive(Serialize, ToSchema, Debug)]
+pub struct ErrorDetail {
+    /// Stable, machine-readable error kind, e.g. `not_found`.
+    #[schema(value_type = String, example = "not_found")]
+    pub code: &'static str,
+    pub message: String,
+}
+
+impl ApiError {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            Self::NotFound(_) => StatusCode::NOT_FOUND,
+            Self::BadRequest(_) => StatusCode::BAD_REQUEST,
+            Self::Validation(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            Self::MethodNotAllowed(_) => StatusCode::METHOD_NOT_ALLOWED,
+            Self::UnsupportedMediaType(_) => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            Self::Conflict(_) | Self::StationOffline(_) => StatusCode::CONFLICT,
+            Self::StationFailed(_) => StatusCode::BAD_GATEWAY,
+            Self::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+            Self::Forbidden(_) => StatusCode::FORBIDDEN,
+        }
+    }
+
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::NotFound(_) => "not_found",
+            Self::BadRequest(_) => "bad_request",
+            Self::Validation(_) => "validation_failed",
+            Self::MethodNotAllowed(_) => "method_not_allowed",
+            Self::UnsupportedMediaType(_) => "unsupported_media_type",
+            Self::Conflict(_) => "conflict",
+            Self::StationOffline(_) => "station_offline",
+            Self::StationFailed(_) => "station_failed",
+            Self::Unauthorized(_) => "unauthorized",
+            Self::Forbidden(_) => "forbidden",
+        }
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let body = ErrorResponse {
+            error: ErrorDetail {
+                code: self.code(),
+                message: self.to_string(),
+            },
+        };
+        let mut response = (self.status(), Json(body)).into_response();
+        if let Self::Unauthorized(_) = self {
+            response.headers_mut().insert(
+                header::WWW_AUTHENTICATE,
+                header::HeaderValue::from_static("Bearer"),
+            );
+        }
+        response
+    }
+}
+
+impl From<PathRejection> for ApiError {
+    fn from(rejection: PathRejection) -> Self {
+        Self::BadRequest(rejection.body_text())
+    }
+}
+
+impl From<QueryRejection> for ApiError {
+    fn from(rejection: QueryRejection) -> Self {
+        Self::BadRequest(rejection.body_text())
+    }
+}
+
+impl From<JsonRejection> for ApiError {
+    fn from(rejection: JsonRejection) -> Self {
+        match rejection {
+            // Well-formed JSON that does not fit the expected shape.
+            JsonRejection::JsonDataError(err) => Self::Validation(err.body_text()),
+            JsonRejection::MissingJsonContentType(err) => {
+                Self::UnsupportedMediaType(err.body_text())
+            }
+            other => Self::BadRequest(other.body_text()),
+        }
+    }
+}
@@ -0,0 +1,161 @@
+use std::convert::Infallible;
+
+use axum::{
+    Extension,
+    extract::ws::{Message, WebSocket, WebSocketUpgrade},
+    http::HeaderMap,
+    response::{
+        Response,
+        sse::{Event, KeepAlive, Sse},
+    },
+};
+use futures::{Stream, StreamExt};
+use serde::Deserialize;
+use tracing::debug;
+use utoipa::IntoParams;
+
+use super::auth::Caller;
+use super::{ApiError, ApiQuery, ErrorResponse};
+use crate::events::{self, EVENT_TYPES, EventFilter};
+
+/// Query of `GET /events` and `GET /events/ws`.
+#[derive(Deserialize, IntoParams, Debug, Default)]
+#[into_params(parameter_in = Query)]
+#[serde(deny_unknown_fields)]
+pub struct EventQuery {
+    /// Comma-separated station ids.
+    station_id: Option<String>,
+    /// Comma-separated event types.
+    #[serde(rename = "type")]
+    kind: Option<String>,
+    /// Resume after this event, for clients that cannot send `Last-Event-ID`.
+    last_event_id: Option<u64>,
+}
+
+/// Live events as Server-Sent Events, each with its id and type. Members and
+/// guests only get session, meter, authorization and command events for
+/// their own id tags.
+#[utoipa::path(
+    get,
+    path = "/events",
+    operation_id = "stream_events",
+    tag = "events",
+    params(
+        EventQuery,
+        ("Last-Event-ID" = Option<u64>, Header, description = "Resume after this event"),
+    ),
+    responses(
+        (status = 200, description = "Event stream; each event's data is the JSON event record", content_type = "text/event-stream", body = String),
+        (status = 400, description = "Unknown event type or invalid event id", body = ErrorResponse),
+    )
+)]
+pub async fn sse(
+    caller: Option<Extension<Caller>>,
+    headers: HeaderMap,
+    ApiQuery(query): ApiQuery<EventQuery>,
+) -> Result<Sse<impl Stream<Item = Result<Event, Infallible>>>, ApiError> {
+    let (filter, last_id) = parse(caller.as_deref(), &headers, query)?;
+    let stream = events::stream(filter, last_id).map(|record| {
+        Ok(Event::default()
+            .id(record.id.to_string())
+            .event(record.event.kind())
+            .json_data(&record)
+            .expect("event serializes to JSON"))
+    });
+    Ok(Sse::new(stream).keep_alive(KeepAlive::default()))
+}
+
+/// Live events as JSON text frames over a WebSocket, filtered like
+/// `GET /events`.
+#[utoipa::path(
+    get,
+    path = "/events/ws",
+    operation_id = "stream_events_websocket",
+    tag = "events",
+    params(
+        EventQuery,
+        ("Last-Event-ID" = Option<u64>, Header, description = "Resume after this event"),
+    ),
+    responses(
+        (status = 101, description = "Switched to a WebSocket sending one JSON event record per text frame"),
+        (status = 400, description = "Unknown event type or invalid event id", body = ErrorResponse),
+    )
+)]
+pub async fn websocket(
+    caller: Option<Extension<Caller>>,
+    ws: WebSocketUpgrade,
+    headers: HeaderMap,
+    ApiQuery(query): ApiQuery<EventQuery>,
+) -> Result<Response, ApiError> {
+    let (filter, last_id) = parse(caller.as_deref(), &headers, query)?;
+    Ok(ws.on_upgrade(move |socket| forward(socket, filter, last_id)))
+}
+
+async fn forward(mut socket: WebSocket, filter: EventFilter, last_id: Option<u64>) {
+    let records = events::stream(filter, last_id);
+    futures::pin_mut!(records);
+    loop {
+        tokio::select! {
+            record = records.next() => {
+                let Some(record) = record else { break };
+                let text = serde_json::to_string(&record).expect("event serializes to JSON");
+                if socket.send(Message::Text(text)).await.is_err() {
+                    break;
+                }
+            }
+            // Nothing is expected from the client; watch for it leaving.
+            message = socket.recv() => match message {
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                Some(Ok(_)) => {}
+            },
+        }
+    }
+    debug!("Event stream client disconnected");
+}
+
+/// The `Last-Event-ID` header wins over the query parameter, since browsers
+/// resend the original URL when they reconnect.
+fn parse(
+    caller: Option<&Caller>,
+    headers: &HeaderMap,
+    query: EventQuery,
+) -> Result<(EventFilter, Option<u64>), ApiError> {
+    let last_id = match headers.get("last-event-id") {
+        Some(value) => Some(
+            value
+                .to_str()
+                .ok()
+                .and_then(|value| value.trim().parse().ok())
+                .ok_or_else(|| {
+                    ApiError::BadRequest("Last-Event-ID must be an event id".to_string())
+                })?,
+        ),
+        None => query.last_event_id,
+    };
+    let kinds = list(query.kind.as_deref());
+    if let Some(unknown) = kinds
+        .iter()
+        .find(|kind| !EVENT_TYPES.contains(&kind.as_str()))
+    {
+        return Err(ApiError::Validation(format!(
+            "unknown event type {unknown:?}, expected one of {}",
+            EVENT_TYPES.join(", ")
+        )));
+    }
+    let filter = EventFilter {
+        station_ids: list(query.station_id.as_deref()),
+        kinds,
+        id_tags: caller.and_then(Caller::id_tags).map(<[String]>::to_vec),
+    };
+    Ok((filter, last_id))
+}
+
+fn list(value: Option<&str>) -> Vec<String> {
+    value
+        .into_iter()
+        .flat_map(|value| value.split(','))
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .map(str::to_string)
+        .collect()
+}
@@ -0,0 +1,142 @@
+//! Versioned HTTP API for dashboards and apps, mounted at `/api/v1`.
+//!
+//! Responses are JSON unless an export format or the event stream is asked
+//! for. Every error, including unknown routes and malformed requests, has the
+//! shape `{"error": {"code": "...", "message": "..."}}`.
+//!
+//! The server mounts [`v1_router_with_auth`], which requires an API token or
+//! a login, see [`auth`]. The routes are described in [`openapi`]; a test
+//! checks that both agree.
+
+use axum::{
+    Router,
+    extract::{FromRequest, FromRequestParts},
+    http::Method,
+    middleware,
+    routing::{get, post},
+};
+
+mod audit;
+pub mod auth;
+mod commands;
+mod error;
+mod events;
+pub mod openapi;
+mod registrations;
+mod sessions;
+mod stations;
+mod tokens;
+mod users;
+mod webhooks;
+
+pub use audit::AuditPage;
+pub use error::{ApiError, ErrorDetail, ErrorResponse};
+pub use sessions::SessionPage;
+pub use stations::{ConnectorView, StationDetails, StationPassword, StationSummary};
+
+/// Routes of API version 1, relative to `/api/v1`.
+pub fn v1_router() -> Router {
+    Router::new()
+        .route("/stations", get(stations::list))
+        .route(
+            "/stations/:station_id",
+            get(stations::show).patch(stations::update),
+        )
+        .route(
+            "/stations/:station_id/connectors",
+            get(stations::connectors),
+        )
+        .route(
+            "/stations/:station_id/connectors/:connector_id",
+            get(stations::connector),
+        )
+        .route(
+            "/stations/:station_id/password",
+            post(stations::rotate_password),
+        )
+        .route("/stations/:station_id/start", post(commands::start))
+        .route("/stations/:station_id/stop", post(commands::stop))
+        .route("/stations/:station_id/reset", post(commands::reset))
+        .route("/stations/:station_id/unlock", post(commands::unlock))
+        .route(
+            "/stations/:station_id/availability",
+            post(commands::availability),
+        )
+        .route(
+            "/stations/:station_id/current-limit",
+            post(commands::current_limit),
+        )
+        .route("/commands", get(commands::list))
+        .route("/commands/:job_id", get(commands::show))
+        .route("/events", get(events::sse))
+        .route("/events/ws", get(events::websocket))
+        .route("/sessions", get(sessions::list))
+        .route("/sessions/export", get(sessions::export))
+        .route("/sessions/:transaction_id", get(sessions::show))
+        .route("/registrations/pending", get(registrations::pending))
+        .route(
+            "/registrations/:station_id/approve",
+            post(registrations::approve),
+        )
+        .route(
+            "/registrations/:station_id/reject",
+            post(registrations::reject),
+        )
+        .route("/webhooks", get(webhooks::list).post(webhooks::create))
+        .route(
+            "/webhooks/:webhook_id",
+            get(webhooks::show).delete(webhooks::delete),
+        )
+        .route(
+            "/webhooks/:webhook_id/deliveries",
+            get(webhooks::deliveries),
+        )
+        .route("/tokens", get(tokens::list).post(tokens::create))
+        .route(
+            "/tokens/:token_id",
+            get(tokens::show).delete(tokens::revoke),
+        )
+        .route("/audit", get(audit::list))
+        .route("/auth/login", post(users::login))
+        .route("/auth/logout", post(users::logout))
+        .route("/auth/me", get(users::me))
+        .route("/users", get(users::list).post(users::create))
+        .route(
+            "/users/:user_id",
+            get(users::show).patch(users::update).delete(users::delete),
+        )
+        .route("/openapi.json", get(openapi::spec))
+        .route("/docs", get(openapi::docs_redirect))
+        .route("/docs/", get(openapi::docs))
+        .route("/docs/*file", get(openapi::docs))
+        .method_not_allowed_fallback(method_not_allowed)
+        .fallback(not_found)
+}
+
+/// [`v1_router`] behind API token and login checks.
+pub fn v1_router_with_auth() -> Router {
+    v1_router().layer(middleware::from_fn(auth::authenticate))
+}
+
+async fn not_found() -> ApiError {
+    ApiError::NotFound("No such API endpoint".to_string())
+}
+
+async fn method_not_allowed(method: Method) -> ApiError {
+    ApiError::MethodNotAllowed(format!("{method} is not supported on this endpoint"))
+}
+
+/// `axum::extract::Path` answering bad parameters with an [`ApiError`].
+#[derive(FromRequestParts)]
+#[from_request(via(axum::extract::Path), rejection(ApiError))]
+struct ApiPath<T>(T);
+
+/// `axum::extract::Query` answering bad query strings with an [`ApiError`].
+#[derive(FromRequestParts)]
+#[from_request(via(axum::extract::Query), rejection(ApiError))]
+struct ApiQuery<T>(T);
+
+/// `axum::Json` answering malformed bodies with an [`ApiError`].
+#[derive(FromRequest)]
+#[from_request(via(axum::Json), rejection(ApiError))]
+struct ApiJson<T>(T);
@@ -0,0 +1,187 @@
+//! OpenAPI 3 description of the v1 API, generated from the handlers and the
+//! types they take and return, and a page to browse it.
+//!
+//! Both are served without authentication: the description holds no data.
+
+use std::sync::{Arc, LazyLock};
+
+use axum::{
+    Json,
+    extract::Path,
+    http::{StatusCode, header},
+    response::{IntoResponse, Redirect, Response},
+};
+use utoipa::{
+    Modify, OpenApi, ToSchema,
+    openapi::{
+        self,
+        security::{ApiKey, ApiKeyValue, HttpAuthScheme, HttpBuilder, SecurityScheme},
+    },
+};
+use utoipa_swagger_ui::Config;
+
+use super::ApiError;
+use super::auth::SESSION_COOKIE;
+use super::{audit, commands, events, registrations, sessions, stations, tokens, users, webhooks};
+
+/// Swagger UI's configuration: the description next to `docs/`.
+static DOCS_CONFIG: LazyLock<Arc<Config<'static>>> =
+    LazyLock::new(|| Arc::new(Config::from("../openapi.json")));
+
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "PlugHome API",
+        description = "HTTP API for dashboards and apps. Every request needs an API token or a \
+                       login, and fails with `401` without one and `403` when it is not allowed. \
+                       Errors have the shape `{\"error\": {\"code\": \"...\", \"message\": \"...\"}}`."
+    ),
+    servers((url = "/api/v1")),
+    paths(
+        stations::list,
+        stations::show,
+        stations::update,
+        stations::connectors,
+        stations::connector,
+        stations::rotate_password,
+        commands::start,
+        commands::stop,
+        commands::reset,
+        commands::unlock,
+        commands::availability,
+        commands::current_limit,
+        commands::list,
+        commands::show,
+        events::sse,
+        events::websocket,
+        sessions::list,
+        sessions::export,
+        sessions::show,
+        registrations::pending,
+        registrations::approve,
+        registrations::reject,
+        webhooks::list,
+        webhooks::create,
+        webhooks::show,
+        webhooks::delete,
+        webhooks::deliveries,
+        tokens::list,
+        tokens::create,
+        tokens::show,
+        tokens::revoke,
+        audit::list,
+        users::login,
+        users::logout,
+        users::me,
+        users::list,
+        users::create,
+        users::show,
+        users::update,
+        users::delete,
+    ),
+    modifiers(&Authentication),
+    security(("bearer_token" = []), ("session_cookie" = [])),
+    tags(
+        (name = "stations", description = "Chargers and their connectors"),
+        (name = "commands", description = "Remote control of chargers"),
+        (name = "events", description = "Live event stream"),
+        (name = "sessions", description = "Charging sessions"),
+        (name = "registrations", description = "Approval of new chargers"),
+        (name = "webhooks", description = "Event subscriptions"),
+        (name = "tokens", description = "API tokens"),
+        (name = "audit", description = "Audit log"),
+        (name = "users", description = "Household users and logins"),
+    )
+)]
+pub struct ApiDoc;
+
+/// The ways to authenticate, see [`super::auth`].
+struct Authentication;
+
+impl Modify for Authentication {
+    fn modify(&self, openapi: &mut openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.add_security_scheme(
+            "bearer_token",
+            SecurityScheme::Http(HttpBuilder::new().scheme(HttpAuthScheme::Bearer).build()),
+        );
+        components.add_security_scheme(
+            "session_cookie",
+            SecurityScheme::ApiKey(ApiKey::Cookie(ApiKeyValue::new(SESSION_COOKIE))),
+        );
+    }
+}
+
+pub async fn spec() -> Json<openapi::OpenApi> {
+    Json(ApiDoc::openapi())
+}
+
+/// Swagger UI loads its files relative to the page, so it lives at `docs/`.
+pub async fn docs_redirect() -> Redirect {
+    Redirect::to("docs/")
+}
+
+/// Swagger UI, built into the binary so the page needs no CDN.
+pub async fn docs(file: Option<Path<String>>) -> Response {
+    let file = file.as_ref().map_or("", |Path(file)| file.as_str());
+    match utoipa_swagger_ui::serve(file, DOCS_CONFIG.clone()) {
+        Ok(Some(file)) => ([(header::CONTENT_TYPE, file.content_type)], file.bytes).into_response(),
+        Ok(None) => ApiError::NotFound(format!("no docs file {file}")).into_response(),
+        Err(err) => (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()).into_response(),
+    }
+}
+
+// Schemas of the OCPP 1.6 enums the API passes through, which come from
+// rust-ocpp without one.
+
+#[derive(ToSchema)]
+pub enum ResetType {
+    Hard,
+    Soft,
+}
+
+#[derive(ToSchema)]
+pub enum AvailabilityType {
+    Inoperative,
+    Operative,
+}
+
+#[derive(ToSchema)]
+pub enum RegistrationStatus {
+    Accepted,
+    Pending,
+    Rejected,
+}
+
+#[derive(ToSchema)]
+pub enum ChargePointStatus {
+    Available,
+    Preparing,
+    Charging,
+    SuspendedEVSE,
+    SuspendedEV,
+    Finishing,
+    Reserved,
+    Unavailable,
+    Faulted,
+}
+
+#[derive(ToSchema)]
+pub enum ChargePointErrorCode {
+    ConnectorLockFailure,
+    EVCommunicationError,
+    GroundFailure,
+    HighTemperature,
+    InternalError,
+    LocalListConflict,
+    NoError,
+    OtherError,
+    OverCurrentFailure,
+    OverVoltage,
+    PowerMeterFailure,
+    PowerSwitchFailure,
+    ReaderFailure,
+    ResetFailure,
+    UnderVoltage,
+    WeakSignal,
+}
@@ -0,0 +1,78 @@
+use axum::Json;
+
+use super::{ApiError, ApiPath, ErrorResponse};
+use crate::audit::{self, Actor, Outcome};
+use crate::stations::{self, RegistrationError, StationRecord};
+
+/// Chargers waiting for operator approval.
+#[utoipa::path(
+    get,
+    path = "/registrations/pending",
+    operation_id = "list_pending_registrations",
+    tag = "registrations",
+    responses((status = 200, body = Vec<StationRecord>))
+)]
+pub async fn pending() -> Json<Vec<StationRecord>> {
+    Json(stations::pending_registrations())
+}
+
+/// Accept the charger at its next BootNotification.
+#[utoipa::path(
+    post,
+    path = "/registrations/{station_id}/approve",
+    operation_id = "approve_registration",
+    tag = "registrations",
+    params(("station_id" = String, Path, description = "Charge point identity")),
+    responses(
+        (status = 200, body = StationRecord),
+        (status = 404, description = "The station never booted", body = ErrorResponse),
+    )
+)]
+pub async fn approve(
+    actor: Actor,
+    ApiPath(station_id): ApiPath<String>,
+) -> Result<Json<StationRecord>, ApiError> {
+    let record = stations::approve(&station_id)?;
+    audited(&actor, "ApproveRegistration", &record);
+    Ok(Json(record))
+}
+
+/// Reject the charger's BootNotifications from now on.
+#[utoipa::path(
+    post,
+    path = "/registrations/{station_id}/reject",
+    operation_id = "reject_registration",
+    tag = "registrations",
+    params(("station_id" = String, Path, description = "Charge point identity")),
+    responses(
+        (status = 200, body = StationRecord),
+        (status = 404, description = "The station never booted", body = ErrorResponse),
+    )
+)]
+pub async fn reject(
+    actor: Actor,
+    ApiPath(station_id): ApiPath<String>,
+) -> Result<Json<StationRecord>, ApiError> {
+    let record = stations::reject(&station_id)?;
+    audited(&actor, "RejectRegistration", &record);
+    Ok(Json(record))
+}
+
+fn audited(actor: &Actor, action: &str, record: &StationRecord) {
+    audit::record(
+        actor,
+        action,
+        Some(&record.station_id),
+        None,
+        serde_json::to_value(record).ok(),
+        Outcome::Ok,
+    );
+}
+
+impl From<RegistrationError> for ApiError {
+    fn from(err: RegistrationError) -> Self {
+        match err {
+            RegistrationError::UnknownStation(_) => Self::NotFound(err.to_string()),
+        }
+    }
+}
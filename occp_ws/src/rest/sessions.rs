@@ -0,0 +1,345 @@
+use axum::{
+    Extension, Json,
+    http::header,
+    response::{IntoResponse, Response},
+};
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+
+use super::auth::Caller;
+use super::{ApiError, ApiPath, ApiQuery, ErrorResponse};
+use crate::sessions::{self, Session, SessionDetail, SessionFilter, SessionStatus};
+
+const DEFAULT_PAGE_SIZE: usize = 50;
+const MAX_PAGE_SIZE: usize = 500;
+
+const CSV_COLUMNS: [&str; 15] = [
+    "transaction_id",
+    "station_id",
+    "connector_id",
+    "id_tag",
+    "status",
+    "started_at",
+    "stopped_at",
+    "meter_start",
+    "meter_stop",
+    "energy_wh",
+    "energy_kwh",
+    "peak_power_w",
+    "stop_reason",
+    "incomplete_reason",
+    "duration_s",
+];
+
+/// One page of sessions, newest first.
+#[derive(Serialize, ToSchema, Debug, Clone, PartialEq)]
+pub struct SessionPage {
+    pub sessions: Vec<Session>,
+    /// Pass as `cursor` to get the next page; `None` on the last page.
+    pub next_cursor: Option<String>,
+}
+
+/// Query of `GET /sessions`.
+#[derive(Deserialize, IntoParams, Debug, Default)]
+#[into_params(parameter_in = Query)]
+#[serde(deny_unknown_fields)]
+pub struct ListQuery {
+    station_id: Option<String>,
+    connector_id: Option<u32>,
+    id_tag: Option<String>,
+    /// Sessions started at or after this time.
+    from: Option<DateTime<Utc>>,
+    /// Sessions started before this time.
+    to: Option<DateTime<Utc>>,
+    status: Option<SessionStatus>,
+    /// Page size, 1 to 500; 50 when left out.
+    limit: Option<usize>,
+    /// `next_cursor` of the previous page.
+    cursor: Option<String>,
+}
+
+/// Query of `GET /sessions/export`: the same filters, no paging.
+#[derive(Deserialize, IntoParams, Debug, Default)]
+#[into_params(parameter_in = Query)]
+#[serde(deny_unknown_fields)]
+pub struct ExportQuery {
+    station_id: Option<String>,
+    connector_id: Option<u32>,
+    id_tag: Option<String>,
+    from: Option<DateTime<Utc>>,
+    to: Option<DateTime<Utc>>,
+    status: Option<SessionStatus>,
+    #[serde(default)]
+    #[param(inline)]
+    format: ExportFormat,
+}
+
+#[derive(Deserialize, ToSchema, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    #[default]
+    Csv,
+    Json,
+}
+
+/// Where the previous page ended: the last session's start and id.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+struct Cursor {
+    started_at: DateTime<Utc>,
+    transaction_id: i32,
+}
+
+impl Cursor {
+    fn after(session: &Session) -> Self {
+        Self {
+            started_at: session.started_at,
+            transaction_id: session.transaction_id,
+        }
+    }
+
+    fn encode(self) -> String {
+        format!(
+            "{}_{}",
+            self.started_at.timestamp_micros(),
+            self.transaction_id
+        )
+    }
+
+    fn decode(text: &str) -> Result<Self, ApiError> {
+        let invalid = || ApiError::BadRequest(format!("invalid cursor {text:?}"));
+        let (micros, transaction_id) = text.split_once('_').ok_or_else(invalid)?;
+        Ok(Self {
+            started_at: micros
+                .parse()
+                .ok()
+                .and_then(DateTime::from_timestamp_micros)
+                .ok_or_else(invalid)?,
+            transaction_id: transaction_id.parse().map_err(|_| invalid())?,
+        })
+    }
+}
+
+/// Sessions newest first, a page at a time. Members and guests only see
+/// sessions of their own id tags.
+#[utoipa::path(
+    get,
+    path = "/sessions",
+    operation_id = "list_sessions",
+    tag = "sessions",
+    params(ListQuery),
+    responses(
+        (status = 200, body = SessionPage),
+        (status = 400, description = "Invalid cursor", body = ErrorResponse),
+        (status = 422, description = "Invalid limit or time range", body = ErrorResponse),
+    )
+)]
+pub async fn list(
+    caller: Option<Extension<Caller>>,
+    ApiQuery(query): ApiQuery<ListQuery>,
+) -> Result<Json<SessionPage>, ApiError> {
+    let filter = filter(
+        caller.as_deref(),
+        query.station_id,
+        query.connector_id,
+        query.id_tag,
+        query.from,
+        query.to,
+        query.status,
+    )?;
+    let limit = query.limit.unwrap_or(DEFAULT_PAGE_SIZE);
+    if !(1..=MAX_PAGE_SIZE).contains(&limit) {
+        return Err(ApiError::Validation(format!(
+            "limit must be between 1 and {MAX_PAGE_SIZE}"
+        )));
+    }
+    let cursor = query.cursor.as_deref().map(Cursor::decode).transpose()?;
+
+    // Sessions come newest first, so the next page holds those ordered
+    // strictly below the cursor.
+    let mut matching = sessions::search(&filter)
+        .into_iter()
+        .filter(|session| cursor.is_none_or(|cursor| Cursor::after(session) < cursor))
+        .take(limit + 1)
+        .collect::<Vec<_>>();
+    let next_cursor = if matching.len() > limit {
+        matching.truncate(limit);
+        matching
+            .last()
+            .map(|session| Cursor::after(session).encode())
+    } else {
+        None
+    };
+    Ok(Json(SessionPage {
+        sessions: matching,
+        next_cursor,
+    }))
+}
+
+#[utoipa::path(
+    get,
+    path = "/sessions/{transaction_id}",
+    operation_id = "get_session",
+    tag = "sessions",
+    params(("transaction_id" = i32, Path)),
+    responses(
+        (status = 200, body = SessionDetail),
+        (status = 404, description = "Unknown session", body = ErrorResponse),
+    )
+)]
+pub async fn show(
+    caller: Option<Extension<Caller>>,
+    ApiPath(transaction_id): ApiPath<i32>,
+) -> Result<Json<SessionDetail>, ApiError> {
+    let id_tags = caller.as_deref().and_then(Caller::id_tags);
+    sessions::detail(transaction_id)
+        .filter(|detail| id_tags.is_none_or(|tags| tags.contains(&detail.session.id_tag)))
+        .map(Json)
+        .ok_or_else(|| ApiError::NotFound(format!("session {transaction_id} not found")))
+}
+
+/// Every matching session as a file download, newest first.
+#[utoipa::path(
+    get,
+    path = "/sessions/export",
+    operation_id = "export_sessions",
+    tag = "sessions",
+    params(ExportQuery),
+    responses(
+        (status = 200, description = "The sessions as an attachment", content(
+            (String = "text/csv"),
+            (Vec<Session> = "application/json"),
+        )),
+        (status = 422, description = "Invalid time range", body = ErrorResponse),
+    )
+)]
+pub async fn export(
+    caller: Option<Extension<Caller>>,
+    ApiQuery(query): ApiQuery<ExportQuery>,
+) -> Result<Response, ApiError> {
+    let filter = filter(
+        caller.as_deref(),
+        query.station_id,
+        query.connector_id,
+        query.id_tag,
+        query.from,
+        query.to,
+        query.status,
+    )?;
+    let sessions = sessions::search(&filter);
+    let (content_type, filename, body) = match query.format {
+        ExportFormat::Csv => ("text/csv; charset=utf-8", "sessions.csv", to_csv(&sessions)),
+        ExportFormat::Json => (
+            "application/json",
+            "sessions.json",
+            serde_json::to_string_pretty(&sessions).expect("sessions serialize to JSON"),
+        ),
+    };
+    Ok((
+        [
+            (header::CONTENT_TYPE, content_type.to_string()),
+            (
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"{filename}\""),
+            ),
+        ],
+        body,
+    )
+        .into_response())
+}
+
+/// Members and guests only see sessions of their own id tags.
+fn filter(
+    caller: Option<&Caller>,
+    station_id: Option<String>,
+    connector_id: Option<u32>,
+    id_tag: Option<String>,
+    from: Option<DateTime<Utc>>,
+    to: Option<DateTime<Utc>>,
+    status: Option<SessionStatus>,
+) -> Result<SessionFilter, ApiError> {
+    if let (Some(from), Some(to)) = (from, to)
+        && from >= to
+    {
+        return Err(ApiError::Validation("from must be before to".to_string()));
+    }
+    Ok(SessionFilter {
+        station_id,
+        connector_id,
+        id_tag,
+        id_tags: caller.and_then(Caller::id_tags).map(<[String]>::to_vec),
+        from,
+        to,
+        status,
+    })
+}
+
+fn to_csv(sessions: &[Session]) -> String {
+    let mut csv = CSV_COLUMNS.join(",");
+    csv.push_str("\r\n");
+    for session in sessions {
+        let fields = [
+            session.transaction_id.to_string(),
+            csv_text(&session.station_id),
+            session.connector_id.to_string(),
+            csv_text(&session.id_tag),
+            enum_text(&session.status),
+            timestamp(Some(session.started_at)),
+            timestamp(session.stopped_at),
+            session.meter_start.to_string(),
+            optional(session.meter_stop),
+            optional(session.energy_wh),
+            optional(session.energy_wh.map(|wh| wh.round() / 1000.0)),
+            optional(session.peak_power_w.map(f64::round)),
+            session
+                .stop_reason
+                .as_ref()
+                .map(enum_text)
+                .unwrap_or_default(),
+            session
+                .incomplete_reason
+                .as_deref()
+                .map(csv_text)
+                .unwrap_or_default(),
+            optional(
+                session
+                    .stopped_at
+                    .map(|stopped_at| (stopped_at - session.started_at).num_seconds()),
+            ),
+        ];
+        csv.push_str(&fields.join(","));
+        csv.push_str("\r\n");
+    }
+    csv
+}
+
+fn optional<T: ToString>(value: Option<T>) -> String {
+    value.map(|value| value.to_string()).unwrap_or_default()
+}
+
+fn timestamp(at: Option<DateTime<Utc>>) -> String {
+    at.map(|at| at.to_rfc3339_opts(SecondsFormat::Secs, true))
+        .unwrap_or_default()
+}
+
+fn enum_text<T: Serialize>(value: &T) -> String {
+    match serde_json::to_value(value) {
+        Ok(serde_json::Value::String(text)) => text,
+        _ => String::new(),
+    }
+}
+
+/// Quote a text field when needed. Values a spreadsheet would run as a
+/// formula get a leading `'`, since id tags come from chargers.
+fn csv_text(value: &str) -> String {
+    let value = if value.starts_with(['=', '+', '-', '@']) {
+        format!("'{value}")
+    } else {
+        value.to_string()
+    };
+    if value.contains([',', '"', '\r', '\n']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value
+    }
+}
@@ -0,0 +1,391 @@
+use std::{collections::BTreeSet, num::NonZeroU32};
+
+use axum::Json;
+use chrono::{DateTime, Utc};
+use rust_ocpp::v1_6::types::{ChargePointErrorCode, ChargePointStatus, RegistrationStatus};
+use serde::{Deserialize, Deserializer, Serialize};
+use tracing::warn;
+use utoipa::ToSchema;
+
+use super::openapi;
+use super::{ApiError, ApiJson, ApiPath, ErrorResponse};
+use crate::audit::{self, Actor, Outcome};
+use crate::auth::{self, AuthError};
+use crate::connections::{self, CallError};
+use crate::connectors::{self, ConnectorState};
+use crate::presence;
+use crate::state::load_heartbeat_config;
+use crate::stations::{self, MetadataUpdate};
+use crate::transactions;
+
+const MAX_NAME_LEN: usize = 64;
+const MAX_LOCATION_LEN: usize = 128;
+const MAX_NOTES_LEN: usize = 2000;
+/// One day, in seconds.
+const MAX_HEARTBEAT_INTERVAL: u32 = 86_400;
+
+/// A station in the station list.
+#[derive(Serialize, ToSchema, Debug, Clone, PartialEq)]
+pub struct StationSummary {
+    pub station_id: String,
+    /// Friendly name set by the operator.
+    pub name: Option<String>,
+    /// `None` until the station sends a BootNotification.
+    #[schema(value_type = Option<openapi::RegistrationStatus>)]
+    pub registration_status: Option<RegistrationStatus>,
+    pub vendor: Option<String>,
+    pub model: Option<String>,
+    pub online: bool,
+    pub last_seen: Option<DateTime<Utc>>,
+}
+
+#[derive(Serialize, ToSchema, Debug, Clone, PartialEq)]
+pub struct StationDetails {
+    pub station_id: String,
+    pub name: Option<String>,
+    pub location: Option<String>,
+    pub notes: Option<String>,
+    #[schema(value_type = Option<openapi::RegistrationStatus>)]
+    pub registration_status: Option<RegistrationStatus>,
+    pub vendor: Option<String>,
+    pub model: Option<String>,
+    pub serial_number: Option<String>,
+    pub firmware_version: Option<String>,
+    pub first_seen: Option<DateTime<Utc>>,
+    pub last_boot: Option<DateTime<Utc>>,
+    /// Whether the station is considered alive, see the heartbeat watchdog.
+    pub online: bool,
+    /// Whether the station's WebSocket is open right now.
+    pub connected: bool,
+    pub last_seen: Option<DateTime<Utc>>,
+    /// When the station last went online or offline.
+    pub online_since: Option<DateTime<Utc>>,
+    pub rtt_ms: Option<f64>,
+    /// Heartbeat interval in seconds the station is told to use.
+    pub heartbeat_interval: u32,
+    pub connectors: Vec<ConnectorView>,
+}
+
+/// Latest status of one connector.
+#[derive(Serialize, ToSchema, Debug, Clone, PartialEq)]
+pub struct ConnectorView {
+    /// `0` is the charge point as a whole.
+    pub connector_id: u32,
+    #[schema(value_type = openapi::ChargePointStatus)]
+    pub status: ChargePointStatus,
+    #[schema(value_type = openapi::ChargePointErrorCode)]
+    pub error_code: ChargePointErrorCode,
+    pub info: Option<String>,
+    /// When the charger says the status changed, if it told us.
+    pub timestamp: Option<DateTime<Utc>>,
+    pub updated_at: DateTime<Utc>,
+    /// The transaction running on the connector, if any.
+    pub transaction_id: Option<i32>,
+}
+
+/// Body of `PATCH /stations/{station_id}`. A missing field is left as is,
+/// `null` or an empty string clears it. A cleared heartbeat interval goes
+/// back to the server default.
+#[derive(Deserialize, ToSchema, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub struct StationPatch {
+    #[serde(default, deserialize_with = "present")]
+    #[schema(value_type = Option<String>, max_length = 64)]
+    name: Option<Option<String>>,
+    #[serde(default, deserialize_with = "present")]
+    #[schema(value_type = Option<String>, max_length = 128)]
+    location: Option<Option<String>>,
+    #[serde(default, deserialize_with = "present")]
+    #[schema(value_type = Option<String>, max_length = 2000)]
+    notes: Option<Option<String>>,
+    /// Seconds between heartbeats, pushed to the station when it is connected.
+    #[serde(default, deserialize_with = "present")]
+    #[schema(value_type = Option<u32>, minimum = 1, maximum = 86400)]
+    heartbeat_interval: Option<Option<u32>>,
+}
+
+/// Tell a field set to `null` apart from a missing one.
+fn present<'de, T, D>(deserializer: D) -> Result<Option<Option<T>>, D::Error>
+where
+    T: Deserialize<'de>,
+    D: Deserializer<'de>,
+{
+    Option::deserialize(deserializer).map(Some)
+}
+
+/// Every known station, ordered by id.
+#[utoipa::path(
+    get,
+    path = "/stations",
+    operation_id = "list_stations",
+    tag = "stations",
+    responses((status = 200, body = Vec<StationSummary>))
+)]
+pub async fn list() -> Json<Vec<StationSummary>> {
+    let ids: BTreeSet<String> = stations::all()
+        .into_iter()
+        .map(|record| record.station_id)
+        .chain(presence::all().into_iter().map(|p| p.station_id))
+        .collect();
+    Json(ids.iter().map(|id| summary(id)).collect())
+}
+
+#[utoipa::path(
+    get,
+    path = "/stations/{station_id}",
+    operation_id = "get_station",
+    tag = "stations",
+    params(("station_id" = String, Path, description = "Charge point identity")),
+    responses(
+        (status = 200, body = StationDetails),
+        (status = 404, description = "Unknown station", body = ErrorResponse),
+    )
+)]
+pub async fn show(ApiPath(station_id): ApiPath<String>) -> Result<Json<StationDetails>, ApiError> {
+    ensure_known(&station_id)?;
+    Ok(Json(details(&station_id).await))
+}
+
+/// Edit the station's name, location, notes and heartbeat interval.
+#[utoipa::path(
+    patch,
+    path = "/stations/{station_id}",
+    operation_id = "update_station",
+    tag = "stations",
+    params(("station_id" = String, Path, description = "Charge point identity")),
+    request_body = StationPatch,
+    responses(
+        (status = 200, body = StationDetails),
+        (status = 404, description = "Unknown station", body = ErrorResponse),
+        (status = 422, description = "A field is too long or out of range", body = ErrorResponse),
+    )
+)]
+pub async fn update(
+    actor: Actor,
+    ApiPath(station_id): ApiPath<String>,
+    ApiJson(patch): ApiJson<StationPatch>,
+) -> Result<Json<StationDetails>, ApiError> {
+    ensure_known(&station_id)?;
+    let update = MetadataUpdate {
+        name: validated("name", patch.name, MAX_NAME_LEN)?,
+        location: validated("location", patch.location, MAX_LOCATION_LEN)?,
+        notes: validated("notes", patch.notes, MAX_NOTES_LEN)?,
+        heartbeat_interval: validated_interval(patch.heartbeat_interval)?,
+    };
+    let request = serde_json::to_value(&update).ok();
+    let interval_changed = update.heartbeat_interval.is_some();
+    let metadata = stations::update_metadata(&station_id, update);
+    if interval_changed {
+        let config = load_heartbeat_config().await;
+        if let Err(err) = stations::push_heartbeat_interval(&station_id, config, &actor).await {
+            warn!(
+                station_id,
+                "HeartbeatInterval not pushed, it goes out on the next boot: {err}"
+            );
+        }
+    }
+    audit::record(
+        &actor,
+        "UpdateStation",
+        Some(&station_id),
+        request,
+        serde_json::to_value(&metadata).ok(),
+        Outcome::Ok,
+    );
+    Ok(Json(details(&station_id).await))
+}
+
+#[utoipa::path(
+    get,
+    path = "/stations/{station_id}/connectors",
+    operation_id = "list_connectors",
+    tag = "stations",
+    params(("station_id" = String, Path, description = "Charge point identity")),
+    responses(
+        (status = 200, body = Vec<ConnectorView>),
+        (status = 404, description = "Unknown station", body = ErrorResponse),
+    )
+)]
+pub async fn connectors(
+    ApiPath(station_id): ApiPath<String>,
+) -> Result<Json<Vec<ConnectorView>>, ApiError> {
+    ensure_known(&station_id)?;
+    Ok(Json(connector_views(&station_id)))
+}
+
+#[utoipa::path(
+    get,
+    path = "/stations/{station_id}/connectors/{connector_id}",
+    operation_id = "get_connector",
+    tag = "stations",
+    params(
+        ("station_id" = String, Path, description = "Charge point identity"),
+        ("connector_id" = u32, Path, description = "`0` is the charge point as a whole"),
+    ),
+    responses(
+        (status = 200, body = ConnectorView),
+        (status = 404, description = "Unknown station or connector", body = ErrorResponse),
+    )
+)]
+pub async fn connector(
+    ApiPath((station_id, connector_id)): ApiPath<(String, u32)>,
+) -> Result<Json<ConnectorView>, ApiError> {
+    ensure_known(&station_id)?;
+    connector_views(&station_id)
+        .into_iter()
+        .find(|view| view.connector_id == connector_id)
+        .map(Json)
+        .ok_or_else(|| {
+            ApiError::NotFound(format!(
+                "station {station_id} has not reported connector {connector_id}"
+            ))
+        })
+}
+
+/// Answer to `POST /stations/{station_id}/password`.
+#[derive(Serialize, ToSchema, Debug, Clone, PartialEq)]
+pub struct StationPassword {
+    /// The new Basic auth password, now the charger's `AuthorizationKey`.
+    /// It is not shown again.
+    pub password: String,
+}
+
+/// Give a connected station a new random Basic auth password through its
+/// `AuthorizationKey` configuration key. The old password stops working once
+/// the charger accepts the new one.
+#[utoipa::path(
+    post,
+    path = "/stations/{station_id}/password",
+    operation_id = "rotate_station_password",
+    tag = "stations",
+    params(("station_id" = String, Path, description = "Charge point identity")),
+    responses(
+        (status = 200, body = StationPassword),
+        (status = 404, description = "Unknown station", body = ErrorResponse),
+        (status = 409, description = "The station is not connected", body = ErrorResponse),
+        (status = 502, description = "The station refused the key or did not answer", body = ErrorResponse),
+    )
+)]
+pub async fn rotate_password(
+    actor: Actor,
+    ApiPath(station_id): ApiPath<String>,
+) -> Result<Json<StationPassword>, ApiError> {
+    ensure_known(&station_id)?;
+    // The ChangeConfiguration is audited, with the key redacted.
+    let password = auth::rotate_authorization_key(&station_id, &actor).await?;
+    Ok(Json(StationPassword { password }))
+}
+
+/// A station is known once it has booted or sent anything at all.
+pub(super) fn ensure_known(station_id: &str) -> Result<(), ApiError> {
+    if stations::get(station_id).is_some() || presence::get(station_id).is_some() {
+        Ok(())
+    } else {
+        Err(ApiError::NotFound(format!(
+            "station {station_id} not found"
+        )))
+    }
+}
+
+fn summary(station_id: &str) -> StationSummary {
+    let record = stations::get(station_id);
+    let presence = presence::get(station_id);
+    StationSummary {
+        station_id: station_id.to_string(),
+        name: stations::metadata(station_id).name,
+        registration_status: record.as_ref().map(|r| r.status.clone()),
+        vendor: record.as_ref().map(|r| r.vendor.clone()),
+        model: record.map(|r| r.model),
+        online: presence.as_ref().is_some_and(|p| p.online),
+        last_seen: presence.map(|p| p.last_seen),
+    }
+}
+
+async fn details(station_id: &str) -> StationDetails {
+    let record = stations::get(station_id);
+    let presence = presence::get(station_id);
+    let metadata = stations::metadata(station_id);
+    StationDetails {
+        station_id: station_id.to_string(),
+        name: metadata.name,
+        location: metadata.location,
+        notes: metadata.notes,
+        registration_status: record.as_ref().map(|r| r.status.clone()),
+        vendor: record.as_ref().map(|r| r.vendor.clone()),
+        model: record.as_ref().map(|r| r.model.clone()),
+        serial_number: record.as_ref().and_then(|r| r.serial_number.clone()),
+        firmware_version: record.as_ref().and_then(|r| r.firmware_version.clone()),
+        first_seen: record.as_ref().map(|r| r.first_seen),
+        last_boot: record.as_ref().map(|r| r.last_boot),
+        online: presence.as_ref().is_some_and(|p| p.online),
+        connected: connections::is_connected(station_id),
+        last_seen: presence.as_ref().map(|p| p.last_seen),
+        online_since: presence.as_ref().map(|p| p.since),
+        rtt_ms: presence.and_then(|p| p.avg_rtt_ms),
+        heartbeat_interval: stations::heartbeat_interval(station_id, load_heartbeat_config().await),
+        connectors: connector_views(station_id),
+    }
+}
+
+fn connector_views(station_id: &str) -> Vec<ConnectorView> {
+    let open = transactions::open_transactions(Some(station_id));
+    connectors::for_station(station_id)
+        .into_iter()
+        .map(|state: ConnectorState| ConnectorView {
+            transaction_id: open
+                .iter()
+                .find(|transaction| transaction.connector_id == state.connector_id)
+                .map(|transaction| transaction.transaction_id),
+            connector_id: state.connector_id,
+            status: state.status,
+            error_code: state.error_code,
+            info: state.info,
+            timestamp: state.timestamp,
+            updated_at: state.updated_at,
+        })
+        .collect()
+}
+
+/// Trim a metadata edit, turn blank values into a clear and enforce the
+/// length limit.
+fn validated(
+    field: &str,
+    value: Option<Option<String>>,
+    max_len: usize,
+) -> Result<Option<Option<String>>, ApiError> {
+    let Some(value) = value else {
+        return Ok(None);
+    };
+    let value = value
+        .map(|text| text.trim().to_string())
+        .filter(|text| !text.is_empty());
+    if let Some(text) = &value
+        && text.chars().count() > max_len
+    {
+        return Err(ApiError::Validation(format!(
+            "{field} must be at most {max_len} characters"
+        )));
+    }
+    Ok(Some(value))
+}
+
+/// Range-check a heartbeat interval edit; `null` goes back to the default.
+fn validated_interval(value: Option<Option<u32>>) -> Result<Option<Option<NonZeroU32>>, ApiError> {
+    match value {
+        Some(Some(seconds)) if !(1..=MAX_HEARTBEAT_INTERVAL).contains(&seconds) => {
+            Err(ApiError::Validation(format!(
+                "heartbeat_interval must be 1 to {MAX_HEARTBEAT_INTERVAL} seconds"
+            )))
+        }
+        value => Ok(value.map(|seconds| seconds.and_then(NonZeroU32::new))),
+    }
+}
+
+impl From<AuthError> for ApiError {
+    fn from(err: AuthError) -> Self {
+        match err {
+            AuthError::Call(CallError::NotConnected(_)) => Self::StationOffline(err.to_string()),
+            _ => Self::StationFailed(err.to_string()),
+        }
+    }
+}
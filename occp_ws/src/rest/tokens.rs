@@ -0,0 +1,129 @@
+use axum::{
+    Json,
+    http::{StatusCode, header},
+    response::{IntoResponse, Response},
+};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use super::{ApiError, ApiJson, ApiPath, ErrorResponse};
+use crate::api_tokens::{self, ApiToken, Scope, TokenError};
+use crate::audit::{self, Actor, Outcome};
+
+/// Without `station_ids` the token reaches every station.
+#[derive(Deserialize, Serialize, ToSchema, Debug)]
+#[schema(as = CreateTokenBody)]
+#[serde(deny_unknown_fields)]
+pub struct CreateBody {
+    name: String,
+    scopes: Vec<Scope>,
+    station_ids: Option<Vec<String>>,
+}
+
+/// A new token, the only response that includes the token itself.
+#[derive(Serialize, ToSchema, Debug)]
+pub struct CreatedToken {
+    #[serde(flatten)]
+    token: ApiToken,
+    #[serde(rename = "token")]
+    text: String,
+}
+
+#[utoipa::path(
+    get,
+    path = "/tokens",
+    operation_id = "list_tokens",
+    tag = "tokens",
+    responses((status = 200, body = Vec<ApiToken>))
+)]
+pub async fn list() -> Json<Vec<ApiToken>> {
+    Json(api_tokens::list())
+}
+
+#[utoipa::path(
+    post,
+    path = "/tokens",
+    operation_id = "create_token",
+    tag = "tokens",
+    request_body = CreateBody,
+    responses(
+        (status = 201, body = CreatedToken),
+        (status = 422, description = "Invalid name or scopes", body = ErrorResponse),
+    )
+)]
+pub async fn create(
+    actor: Actor,
+    ApiJson(body): ApiJson<CreateBody>,
+) -> Result<Response, ApiError> {
+    let request = serde_json::to_value(&body).ok();
+    let (token, text) = api_tokens::create(&body.name, body.scopes, body.station_ids)?;
+    audit::record(
+        &actor,
+        "CreateToken",
+        None,
+        request,
+        serde_json::to_value(&token).ok(),
+        Outcome::Ok,
+    );
+    let location = format!("/api/v1/tokens/{}", token.token_id);
+    Ok((
+        StatusCode::CREATED,
+        [(header::LOCATION, location)],
+        Json(CreatedToken { token, text }),
+    )
+        .into_response())
+}
+
+#[utoipa::path(
+    get,
+    path = "/tokens/{token_id}",
+    operation_id = "get_token",
+    tag = "tokens",
+    params(("token_id" = String, Path)),
+    responses(
+        (status = 200, body = ApiToken),
+        (status = 404, description = "Unknown token", body = ErrorResponse),
+    )
+)]
+pub async fn show(ApiPath(token_id): ApiPath<String>) -> Result<Json<ApiToken>, ApiError> {
+    api_tokens::get(&token_id)
+        .map(Json)
+        .ok_or_else(|| TokenError::NotFound(token_id).into())
+}
+
+/// Revoke the token; it stays listed with its usage.
+#[utoipa::path(
+    delete,
+    path = "/tokens/{token_id}",
+    operation_id = "revoke_token",
+    tag = "tokens",
+    params(("token_id" = String, Path)),
+    responses(
+        (status = 204, description = "Revoked"),
+        (status = 404, description = "Unknown token", body = ErrorResponse),
+    )
+)]
+pub async fn revoke(
+    actor: Actor,
+    ApiPath(token_id): ApiPath<String>,
+) -> Result<StatusCode, ApiError> {
+    let token = api_tokens::revoke(&token_id)?;
+    audit::record(
+        &actor,
+        "RevokeToken",
+        None,
+        None,
+        serde_json::to_value(&token).ok(),
+        Outcome::Ok,
+    );
+    Ok(StatusCode::NO_CONTENT)
+}
+
+impl From<TokenError> for ApiError {
+    fn from(err: TokenError) -> Self {
+        match err {
+            TokenError::Invalid(_) => Self::Validation(err.to_string()),
+            TokenError::NotFound(_) => Self::NotFound(err.to_string()),
+        }
+    }
+}
@@ -0,0 +1,226 @@
+use axum::{
+    Extension, Json,
+    http::{HeaderMap, StatusCode, header},
+    response::{IntoResponse, Response},
+};
+use serde::Deserialize;
+use serde_json::{Value, json};
+use utoipa::ToSchema;
+
+use super::auth::{Caller, SESSION_COOKIE, session_cookie};
+use super::{ApiError, ApiJson, ApiPath, ErrorResponse};
+use crate::audit::{self, Actor, Outcome};
+use crate::state::load_login_config;
+use crate::users::{self, Role, User, UserError, UserUpdate};
+
+#[derive(Deserialize, ToSchema, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct LoginBody {
+    username: String,
+    password: String,
+}
+
+#[derive(Deserialize, ToSchema, Debug)]
+#[schema(as = CreateUserBody)]
+#[serde(deny_unknown_fields)]
+pub struct CreateBody {
+    username: String,
+    password: String,
+    role: Role,
+    #[serde(default)]
+    id_tags: Vec<String>,
+}
+
+/// Log in and set the session cookie.
+#[utoipa::path(
+    post,
+    path = "/auth/login",
+    operation_id = "login",
+    tag = "users",
+    request_body = LoginBody,
+    security(()),
+    responses(
+        (status = 200, description = "Logged in; the session cookie is set", body = User),
+        (status = 401, description = "Wrong username or password", body = ErrorResponse),
+    )
+)]
+pub async fn login(ApiJson(body): ApiJson<LoginBody>) -> Result<Response, ApiError> {
+    let config = load_login_config().await;
+    let (user, cookie) = users::login(&body.username, &body.password, config.session_ttl)
+        .await
+        .ok_or_else(|| ApiError::Unauthorized("wrong username or password".to_string()))?;
+    let mut cookie = format!(
+        "{SESSION_COOKIE}={cookie}; Path=/; HttpOnly; SameSite=Strict; Max-Age={}",
+        config.session_ttl.as_secs()
+    );
+    if config.secure_cookie {
+        cookie.push_str("; Secure");
+    }
+    Ok(([(header::SET_COOKIE, cookie)], Json(user)).into_response())
+}
+
+/// End the login and clear the cookie.
+#[utoipa::path(
+    post,
+    path = "/auth/logout",
+    operation_id = "logout",
+    tag = "users",
+    responses((status = 204, description = "Logged out"))
+)]
+pub async fn logout(headers: HeaderMap) -> Response {
+    if let Some(cookie) = session_cookie(&headers) {
+        users::logout(&cookie);
+    }
+    let cleared = format!("{SESSION_COOKIE}=; Path=/; HttpOnly; SameSite=Strict; Max-Age=0");
+    (StatusCode::NO_CONTENT, [(header::SET_COOKIE, cleared)]).into_response()
+}
+
+/// The logged in user.
+#[utoipa::path(
+    get,
+    path = "/auth/me",
+    operation_id = "get_current_user",
+    tag = "users",
+    responses(
+        (status = 200, body = User),
+        (status = 404, description = "The request uses an API token", body = ErrorResponse),
+    )
+)]
+pub async fn me(caller: Option<Extension<Caller>>) -> Result<Json<User>, ApiError> {
+    match caller {
+        Some(Extension(Caller::User(user))) => Ok(Json(user)),
+        Some(Extension(Caller::Token(_))) => Err(ApiError::NotFound(
+            "this request uses an API token, not a login".to_string(),
+        )),
+        None => Err(ApiError::Unauthorized("not logged in".to_string())),
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/users",
+    operation_id = "list_users",
+    tag = "users",
+    responses((status = 200, body = Vec<User>))
+)]
+pub async fn list() -> Json<Vec<User>> {
+    Json(users::list())
+}
+
+#[utoipa::path(
+    post,
+    path = "/users",
+    operation_id = "create_user",
+    tag = "users",
+    request_body = CreateBody,
+    responses(
+        (status = 201, body = User),
+        (status = 409, description = "Username or id tag taken", body = ErrorResponse),
+        (status = 422, description = "Invalid username, password or id tag", body = ErrorResponse),
+    )
+)]
+pub async fn create(
+    actor: Actor,
+    ApiJson(body): ApiJson<CreateBody>,
+) -> Result<Response, ApiError> {
+    let user = users::create(&body.username, &body.password, body.role, body.id_tags).await?;
+    audited(&actor, "CreateUser", None, &user);
+    let location = format!("/api/v1/users/{}", user.user_id);
+    Ok((
+        StatusCode::CREATED,
+        [(header::LOCATION, location)],
+        Json(user),
+    )
+        .into_response())
+}
+
+#[utoipa::path(
+    get,
+    path = "/users/{user_id}",
+    operation_id = "get_user",
+    tag = "users",
+    params(("user_id" = String, Path)),
+    responses(
+        (status = 200, body = User),
+        (status = 404, description = "Unknown user", body = ErrorResponse),
+    )
+)]
+pub async fn show(ApiPath(user_id): ApiPath<String>) -> Result<Json<User>, ApiError> {
+    users::get(&user_id)
+        .map(Json)
+        .ok_or_else(|| UserError::NotFound(user_id).into())
+}
+
+/// Change a user's role, id tags or password. A new password ends the
+/// user's logins.
+#[utoipa::path(
+    patch,
+    path = "/users/{user_id}",
+    operation_id = "update_user",
+    tag = "users",
+    params(("user_id" = String, Path)),
+    request_body = UserUpdate,
+    responses(
+        (status = 200, body = User),
+        (status = 404, description = "Unknown user", body = ErrorResponse),
+        (status = 409, description = "Id tag taken, or the last owner would be demoted", body = ErrorResponse),
+        (status = 422, description = "Invalid password or id tag", body = ErrorResponse),
+    )
+)]
+pub async fn update(
+    actor: Actor,
+    ApiPath(user_id): ApiPath<String>,
+    ApiJson(body): ApiJson<UserUpdate>,
+) -> Result<Json<User>, ApiError> {
+    let request = json!({
+        "role": body.role,
+        "id_tags": body.id_tags,
+        "password_changed": body.password.is_some(),
+    });
+    let user = users::update(&user_id, body).await?;
+    audited(&actor, "UpdateUser", Some(request), &user);
+    Ok(Json(user))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/users/{user_id}",
+    operation_id = "delete_user",
+    tag = "users",
+    params(("user_id" = String, Path)),
+    responses(
+        (status = 204, description = "Removed, with their logins"),
+        (status = 404, description = "Unknown user", body = ErrorResponse),
+        (status = 409, description = "The last owner cannot be removed", body = ErrorResponse),
+    )
+)]
+pub async fn delete(
+    actor: Actor,
+    ApiPath(user_id): ApiPath<String>,
+) -> Result<StatusCode, ApiError> {
+    let user = users::delete(&user_id)?;
+    audited(&actor, "DeleteUser", None, &user);
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Record a change to a user. Passwords are never part of it.
+fn audited(actor: &Actor, action: &str, request: Option<Value>, user: &User) {
+    audit::record(
+        actor,
+        action,
+        None,
+        request,
+        serde_json::to_value(user).ok(),
+        Outcome::Ok,
+    );
+}
+
+impl From<UserError> for ApiError {
+    fn from(err: UserError) -> Self {
+        match err {
+            UserError::Invalid(_) => Self::Validation(err.to_string()),
+            UserError::NotFound(_) => Self::NotFound(err.to_string()),
+            UserError::Conflict(_) => Self::Conflict(err.to_string()),
+        }
+    }
+}
@@ -0,0 +1,154 @@
+use axum::{
+    Json,
+    http::{StatusCode, header},
+    response::{IntoResponse, Response},
+};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use super::{ApiError, ApiJson, ApiPath, ErrorResponse};
+use crate::audit::{self, Actor, Outcome};
+use crate::webhooks::{self, Delivery, Webhook, WebhookError};
+
+/// Without `events` the subscription receives every webhook event; without a
+/// `secret` one is generated.
+#[derive(Deserialize, ToSchema, Debug)]
+#[schema(as = CreateWebhookBody)]
+#[serde(deny_unknown_fields)]
+pub struct CreateBody {
+    url: String,
+    events: Option<Vec<String>>,
+    secret: Option<String>,
+}
+
+/// A new subscription, the only response that includes its secret.
+#[derive(Serialize, ToSchema, Debug)]
+pub struct CreatedWebhook {
+    #[serde(flatten)]
+    webhook: Webhook,
+    secret: String,
+}
+
+#[utoipa::path(
+    get,
+    path = "/webhooks",
+    operation_id = "list_webhooks",
+    tag = "webhooks",
+    responses((status = 200, body = Vec<Webhook>))
+)]
+pub async fn list() -> Json<Vec<Webhook>> {
+    Json(webhooks::list())
+}
+
+#[utoipa::path(
+    post,
+    path = "/webhooks",
+    operation_id = "create_webhook",
+    tag = "webhooks",
+    request_body = CreateBody,
+    responses(
+        (status = 201, body = CreatedWebhook),
+        (status = 422, description = "Invalid URL, event or secret", body = ErrorResponse),
+    )
+)]
+pub async fn create(
+    actor: Actor,
+    ApiJson(body): ApiJson<CreateBody>,
+) -> Result<Response, ApiError> {
+    let webhook = webhooks::create(&body.url, body.events, body.secret)?;
+    audit::record(
+        &actor,
+        "CreateWebhook",
+        None,
+        None,
+        serde_json::to_value(&webhook).ok(),
+        Outcome::Ok,
+    );
+    let location = format!("/api/v1/webhooks/{}", webhook.webhook_id);
+    let secret = webhook.secret.clone();
+    Ok((
+        StatusCode::CREATED,
+        [(header::LOCATION, location)],
+        Json(CreatedWebhook { webhook, secret }),
+    )
+        .into_response())
+}
+
+#[utoipa::path(
+    get,
+    path = "/webhooks/{webhook_id}",
+    operation_id = "get_webhook",
+    tag = "webhooks",
+    params(("webhook_id" = String, Path)),
+    responses(
+        (status = 200, body = Webhook),
+        (status = 404, description = "Unknown webhook", body = ErrorResponse),
+    )
+)]
+pub async fn show(ApiPath(webhook_id): ApiPath<String>) -> Result<Json<Webhook>, ApiError> {
+    webhooks::get(&webhook_id)
+        .map(Json)
+        .ok_or_else(|| not_found(&webhook_id))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/webhooks/{webhook_id}",
+    operation_id = "delete_webhook",
+    tag = "webhooks",
+    params(("webhook_id" = String, Path)),
+    responses(
+        (status = 204, description = "Unsubscribed"),
+        (status = 404, description = "Unknown webhook", body = ErrorResponse),
+    )
+)]
+pub async fn delete(
+    actor: Actor,
+    ApiPath(webhook_id): ApiPath<String>,
+) -> Result<StatusCode, ApiError> {
+    if webhooks::delete(&webhook_id) {
+        audit::record(
+            &actor,
+            "DeleteWebhook",
+            None,
+            Some(serde_json::json!({ "webhook_id": webhook_id })),
+            None,
+            Outcome::Ok,
+        );
+        Ok(StatusCode::NO_CONTENT)
+    } else {
+        Err(not_found(&webhook_id))
+    }
+}
+
+/// The subscription's recent deliveries, newest first.
+#[utoipa::path(
+    get,
+    path = "/webhooks/{webhook_id}/deliveries",
+    operation_id = "list_webhook_deliveries",
+    tag = "webhooks",
+    params(("webhook_id" = String, Path)),
+    responses(
+        (status = 200, body = Vec<Delivery>),
+        (status = 404, description = "Unknown webhook", body = ErrorResponse),
+    )
+)]
+pub async fn deliveries(
+    ApiPath(webhook_id): ApiPath<String>,
+) -> Result<Json<Vec<Delivery>>, ApiError> {
+    webhooks::deliveries(&webhook_id)
+        .map(Json)
+        .ok_or_else(|| not_found(&webhook_id))
+}
+
+fn not_found(webhook_id: &str) -> ApiError {
+    ApiError::NotFound(format!("webhook {webhook_id} not found"))
+}
+
+impl From<WebhookError> for ApiError {
+    fn from(err: WebhookError) -> Self {
+        match err {
+            WebhookError::Invalid(_) => Self::Validation(err.to_string()),
+        }
+    }
+}
@@ -0,0 +1,199 @@
+//! Bring the server's view of connectors and transactions back in line with
+//! a charger after a reconnect or a server restart.
+//!
+//! The charger is asked for fresh StatusNotifications and MeterValues through
+//! TriggerMessage. Once the connectors with open transactions have reported,
+//! transactions on idle connectors are closed as orphaned and anything the
+//! server cannot confirm is flagged.
+
+use std::{
+    collections::HashSet,
+    sync::{LazyLock, Mutex},
+    time::Duration,
+};
+
+use chrono::Utc;
+use rust_ocpp::v1_6::{
+    messages::trigger_message::{TriggerMessageRequest, TriggerMessageResponse},
+    types::{ChargePointStatus, MessageTrigger, TriggerMessageStatus},
+};
+use serde::Serialize;
+use tracing::{info, warn};
+
+use crate::audit::Actor;
+use crate::connections::{self, CallError};
+use crate::connectors;
+use crate::stations;
+use crate::transactions;
+use crate::types::*;
+
+/// How long the charger gets to report connector states.
+pub const RESYNC_STATUS_TIMEOUT: Duration = Duration::from_secs(30);
+
+const RESYNC_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Stations with open transactions from before the server started.
+static STARTUP_RESYNC: LazyLock<Mutex<HashSet<String>>> =
+    LazyLock::new(|| Mutex::new(HashSet::new()));
+
+#[derive(Serialize, Debug, Clone, Default, PartialEq)]
+pub struct ResyncReport {
+    pub station_id: String,
+    /// Connectors that sent a StatusNotification during the resync.
+    pub connectors_reported: Vec<u32>,
+    /// Transactions the charger confirmed as still running.
+    pub confirmed: Vec<i32>,
+    /// Transactions closed because their connector is idle.
+    pub closed: Vec<i32>,
+    /// Transactions left open but flagged for the operator.
+    pub flagged: Vec<i32>,
+    /// Connectors charging without a transaction the server knows about.
+    pub unknown_sessions: Vec<u32>,
+}
+
+/// Remember every station with an open transaction so it is resynced on its
+/// first connection. Call once at startup after loading state.
+pub fn schedule_startup_resync() -> usize {
+    let stations: HashSet<String> = transactions::open_transactions(None)
+        .into_iter()
+        .map(|transaction| transaction.station_id)
+        .collect();
+    let count = stations.len();
+    STARTUP_RESYNC
+        .lock()
+        .expect("startup resync lock poisoned")
+        .extend(stations);
+    count
+}
+
+/// Decide from the first Call on a fresh connection whether to resync: the
+/// station was known before and came back without booting, or it had open
+/// transactions when the server started.
+pub fn is_due(station_id: &str, first_action: &OcppActionEnum) -> bool {
+    let startup = STARTUP_RESYNC
+        .lock()
+        .expect("startup resync lock poisoned")
+        .remove(station_id);
+    let known = stations::get(station_id).is_some()
+        || !transactions::open_transactions(Some(station_id)).is_empty();
+    startup || (known && *first_action != OcppActionEnum::BootNotification)
+}
+
+pub fn spawn_resync(station_id: &str) {
+    let station_id = station_id.to_string();
+    tokio::spawn(async move {
+        match resync(&station_id, RESYNC_STATUS_TIMEOUT).await {
+            Ok(report) => info!(station_id, ?report, "Station resync finished"),
+            Err(err) => warn!(station_id, "Station resync failed: {err}"),
+        }
+    });
+}
+
+/// Run the resync routine, waiting up to `wait` for connector states.
+pub async fn resync(station_id: &str, wait: Duration) -> Result<ResyncReport, CallError> {
+    let started = Utc::now();
+    info!(station_id, "Resynchronising station state");
+
+    trigger(station_id, MessageTrigger::StatusNotification, None).await?;
+    let open = transactions::open_transactions(Some(station_id));
+    for transaction in &open {
+        // Not every charger supports triggered MeterValues; the status is
+        // what matters for reconciliation.
+        if let Err(err) = trigger(
+            station_id,
+            MessageTrigger::MeterValues,
+            Some(transaction.connector_id),
+        )
+        .await
+        {
+            warn!(station_id, connector_id = transaction.connector_id, "{err}");
+        }
+    }
+
+    let reported_since_start = |connector_id: u32| {
+        connectors::get(station_id, connector_id).filter(|state| state.updated_at >= started)
+    };
+    let deadline = tokio::time::Instant::now() + wait;
+    while open
+        .iter()
+        .any(|transaction| reported_since_start(transaction.connector_id).is_none())
+        && tokio::time::Instant::now() < deadline
+    {
+        tokio::time::sleep(RESYNC_POLL_INTERVAL).await;
+    }
+
+    let mut report = ResyncReport {
+        station_id: station_id.to_string(),
+        ..ResyncReport::default()
+    };
+    for state in connectors::for_station(station_id) {
+        if state.updated_at >= started {
+            report.connectors_reported.push(state.connector_id);
+            if state.is_in_session()
+                && !open
+                    .iter()
+                    .any(|transaction| transaction.connector_id == state.connector_id)
+            {
+                warn!(
+                    station_id,
+                    connector_id = state.connector_id,
+                    "Charging without a known transaction"
+                );
+                report.unknown_sessions.push(state.connector_id);
+            }
+        }
+    }
+
+    for transaction in open {
+        let id = transaction.transaction_id;
+        match reported_since_start(transaction.connector_id) {
+            Some(state) if state.is_in_session() => report.confirmed.push(id),
+            Some(state)
+                if matches!(
+                    state.status,
+                    ChargePointStatus::Available | ChargePointStatus::Preparing
+                ) =>
+            {
+                transactions::close_orphaned(
+                    id,
+                    &format!("Closed by resync: connector reported {:?}", state.status),
+                );
+                report.closed.push(id);
+            }
+            Some(state) => {
+                transactions::flag(
+                    id,
+                    &format!("Resync: connector reported {:?}", state.status),
+                );
+                report.flagged.push(id);
+            }
+            None => {
+                transactions::flag(id, "Resync: connector state not reported");
+                report.flagged.push(id);
+            }
+        }
+    }
+
+    Ok(report)
+}
+
+async fn trigger(
+    station_id: &str,
+    requested_message: MessageTrigger,
+    connector_id: Option<u32>,
+) -> Result<(), CallError> {
+    let response: TriggerMessageResponse = connections::call(
+        station_id,
+        OcppActionEnum::TriggerMessage,
+        OcppPayload::TriggerMessage(TriggerMessageKind::Request(TriggerMessageRequest {
+            requested_message: requested_message.clone(),
+            connector_id,
+        })),
+        &Actor::automation("resync"),
+    )
+    .await?;
+    if response.status != TriggerMessageStatus::Accepted {
+        warn!(station_id, ?requested_message, status = ?response.status, "TriggerMessage not accepted");
+    }
+    Ok(())
+}
@@ -1,20 +1,91 @@
 use std::net::SocketAddr;
 
 use axum::{
-    extract::{ConnectInfo, ws::WebSocketUpgrade},
-    response::IntoResponse,
+    Extension, Router,
+    extract::{ConnectInfo, OriginalUri, Path, ws::WebSocketUpgrade},
+    http::{StatusCode, header},
+    response::{IntoResponse, Redirect, Response},
+    routing::{get, post},
 };
 use axum_extra::TypedHeader;
+use common::DuplicateConnectionPolicy;
+use headers::{Authorization, authorization::Basic};
+use tracing::warn;
 
+use crate::connections;
 use crate::handlers::handle_socket;
-use crate::state::START_TIME;
+use crate::state::{
+    LOCAL_CA, START_TIME, load_duplicate_connection_policy, load_station_credentials,
+};
+use crate::tls::ClientIdentity;
 
 pub async fn upgrade_to_ws(
     ws: WebSocketUpgrade,
+    Path(station_id): Path<String>,
     _user_agent: Option<TypedHeader<headers::UserAgent>>,
+    authorization: Option<TypedHeader<Authorization<Basic>>>,
+    client_identity: Option<Extension<ClientIdentity>>,
     ConnectInfo(addr): ConnectInfo<SocketAddr>,
-) -> impl IntoResponse {
-    ws.on_upgrade(move |socket| handle_socket(socket, addr))
+) -> Response {
+    // A verified client certificate (Security Profile 3) replaces Basic auth.
+    let authenticated = match client_identity {
+        Some(Extension(identity)) => identity.authorize(&station_id),
+        None => {
+            let credentials = authorization.as_ref().map(|TypedHeader(auth)| &auth.0);
+            load_station_credentials()
+                .await
+                .authenticate(&station_id, credentials)
+                .await
+        }
+    };
+    if let Err(err) = authenticated {
+        warn!(addr = %addr, station_id, "Rejected WebSocket upgrade: {err}");
+        return (
+            StatusCode::UNAUTHORIZED,
+            [(header::WWW_AUTHENTICATE, "Basic realm=\"PlugHome\"")],
+        )
+            .into_response();
+    }
+
+    if load_duplicate_connection_policy().await == DuplicateConnectionPolicy::RejectNewcomer
+        && connections::is_connected(&station_id)
+    {
+        warn!(addr = %addr, station_id, "Rejected WebSocket upgrade: station already connected");
+        return (StatusCode::CONFLICT, "Station already connected").into_response();
+    }
+
+    ws.on_upgrade(move |socket| handle_socket(socket, addr, station_id))
+        .into_response()
+}
+
+/// Serve the local CA certificate so it can be loaded onto chargers.
+pub async fn ca_certificate_route() -> Response {
+    match LOCAL_CA.get() {
+        Some(ca) => (
+            StatusCode::OK,
+            [(header::CONTENT_TYPE, "application/x-pem-file")],
+            ca.ca_certificate_pem().to_string(),
+        )
+            .into_response(),
+        None => (StatusCode::NOT_FOUND, "Local CA is not enabled").into_response(),
+    }
+}
+
+/// The registration endpoints from before the versioned API, redirecting to
+/// their `/api/v1` counterparts, which require a login or token.
+pub fn registration_redirects() -> Router {
+    Router::new()
+        .route("/registrations/pending", get(moved_to_api_v1))
+        .route("/registrations/:station_id/approve", post(moved_to_api_v1))
+        .route("/registrations/:station_id/reject", post(moved_to_api_v1))
+}
+
+/// `308` keeps the method and body, so clients can follow it for `POST`s.
+async fn moved_to_api_v1(OriginalUri(uri): OriginalUri) -> Redirect {
+    let path = uri
+        .path_and_query()
+        .map_or(uri.path(), |path| path.as_str());
+    Redirect::permanent(&format!("/api/v1{path}"))
 }
 
 pub async fn healthcheck_route() -> impl IntoResponse {
@@ -0,0 +1,356 @@
+//! OCPP 1.6 Security Whitepaper extension.
+//!
+//! rust-ocpp only ships the core 1.6 messages, so the extension's payloads are
+//! defined here: security event ingestion, charger certificate renewal through
+//! the local CA, log upload and signed firmware updates.
+
+use std::{
+    collections::VecDeque,
+    sync::{LazyLock, Mutex},
+};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tracing::{error, info, warn};
+
+use crate::audit::Actor;
+use crate::connections::{self, CallError};
+use crate::events::{self, StationEvent};
+use crate::state::LOCAL_CA;
+use crate::types::*;
+
+/// How many security events are kept in memory across all stations.
+const SECURITY_EVENT_HISTORY: usize = 500;
+
+static SECURITY_EVENTS: LazyLock<Mutex<VecDeque<SecurityEvent>>> =
+    LazyLock::new(|| Mutex::new(VecDeque::with_capacity(SECURITY_EVENT_HISTORY)));
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct SecurityEventNotificationRequest {
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub timestamp: DateTime<Utc>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tech_info: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct SecurityEventNotificationResponse {}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct SignCertificateRequest {
+    pub csr: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub enum GenericStatus {
+    #[default]
+    Accepted,
+    Rejected,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct SignCertificateResponse {
+    pub status: GenericStatus,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct CertificateSignedRequest {
+    pub certificate_chain: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct CertificateSignedResponse {
+    pub status: GenericStatus,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub enum LogType {
+    #[default]
+    DiagnosticsLog,
+    SecurityLog,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct LogParameters {
+    pub remote_location: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub oldest_timestamp: Option<DateTime<Utc>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub latest_timestamp: Option<DateTime<Utc>>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct GetLogRequest {
+    pub log: LogParameters,
+    pub log_type: LogType,
+    pub request_id: i32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub retries: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub retry_interval: Option<u32>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub enum LogStatus {
+    #[default]
+    Accepted,
+    Rejected,
+    AcceptedCanceled,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct GetLogResponse {
+    pub status: LogStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub filename: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub enum UploadLogStatus {
+    BadMessage,
+    #[default]
+    Idle,
+    NotSupportedOperation,
+    PermissionDenied,
+    Uploaded,
+    UploadFailure,
+    Uploading,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct LogStatusNotificationRequest {
+    pub status: UploadLogStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<i32>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct LogStatusNotificationResponse {}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct SignedFirmware {
+    pub location: String,
+    pub retrieve_date_time: DateTime<Utc>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub install_date_time: Option<DateTime<Utc>>,
+    pub signing_certificate: String,
+    pub signature: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct SignedUpdateFirmwareRequest {
+    pub firmware: SignedFirmware,
+    pub request_id: i32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub retries: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub retry_interval: Option<u32>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub enum UpdateFirmwareStatus {
+    #[default]
+    Accepted,
+    Rejected,
+    AcceptedCanceled,
+    InvalidCertificate,
+    RevokedCertificate,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct SignedUpdateFirmwareResponse {
+    pub status: UpdateFirmwareStatus,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub enum SignedFirmwareStatus {
+    Downloaded,
+    DownloadFailed,
+    Downloading,
+    DownloadScheduled,
+    DownloadPaused,
+    #[default]
+    Idle,
+    InstallationFailed,
+    Installing,
+    Installed,
+    InstallRebooting,
+    InstallScheduled,
+    InstallVerificationFailed,
+    InvalidSignature,
+    SignatureVerified,
+}
+
+impl SignedFirmwareStatus {
+    pub fn is_failure(&self) -> bool {
+        matches!(
+            self,
+            Self::DownloadFailed
+                | Self::InstallationFailed
+                | Self::InstallVerificationFailed
+                | Self::InvalidSignature
+        )
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct SignedFirmwareStatusNotificationRequest {
+    pub status: SignedFirmwareStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<i32>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct SignedFirmwareStatusNotificationResponse {}
+
+/// A security event reported by a charger.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct SecurityEvent {
+    pub station_id: String,
+    pub kind: String,
+    pub timestamp: DateTime<Utc>,
+    pub received_at: DateTime<Utc>,
+    pub tech_info: Option<String>,
+    pub critical: bool,
+}
+
+/// Whether the whitepaper's security event list marks `kind` as critical,
+/// i.e. worth pushing to the operator rather than just logging.
+pub fn is_critical_event(kind: &str) -> bool {
+    matches!(
+        kind,
+        "FirmwareUpdated"
+            | "SettingSystemTime"
+            | "StartupOfTheDevice"
+            | "ResetOrReboot"
+            | "SecurityLogWasCleared"
+            | "MemoryExhaustion"
+            | "TamperDetectionActivated"
+    )
+}
+
+/// Store a SecurityEventNotification and raise an alert for critical events.
+pub fn record_security_event(
+    station_id: &str,
+    request: &SecurityEventNotificationRequest,
+) -> SecurityEvent {
+    let event = SecurityEvent {
+        station_id: station_id.to_string(),
+        kind: request.kind.clone(),
+        timestamp: request.timestamp,
+        received_at: Utc::now(),
+        tech_info: request.tech_info.clone(),
+        critical: is_critical_event(&request.kind),
+    };
+
+    if event.critical {
+        error!(
+            station_id,
+            kind = %event.kind,
+            tech_info = ?event.tech_info,
+            "SECURITY ALERT from charger"
+        );
+    } else {
+        info!(station_id, kind = %event.kind, "Security event from charger");
+    }
+    events::publish(StationEvent::SecurityEvent {
+        station_id: station_id.to_string(),
+        at: event.timestamp,
+        event_type: event.kind.clone(),
+        tech_info: event.tech_info.clone(),
+        critical: event.critical,
+    });
+
+    let mut history = SECURITY_EVENTS
+        .lock()
+        .expect("security events lock poisoned");
+    if history.len() == SECURITY_EVENT_HISTORY {
+        history.pop_front();
+    }
+    history.push_back(event.clone());
+    event
+}
+
+/// Most recent security events, newest first, optionally for one station.
+pub fn recent_security_events(station_id: Option<&str>) -> Vec<SecurityEvent> {
+    SECURITY_EVENTS
+        .lock()
+        .expect("security events lock poisoned")
+        .iter()
+        .rev()
+        .filter(|event| station_id.is_none_or(|id| event.station_id == id))
+        .cloned()
+        .collect()
+}
+
+/// Sign a charger's CSR with the local CA and return the PEM chain to send in
+/// CertificateSigned. `None` when no local CA is running or the CSR is invalid.
+pub fn sign_station_csr(station_id: &str, csr: &str) -> Option<String> {
+    let ca = LOCAL_CA.get()?;
+    match ca.sign_csr(station_id, csr) {
+        Ok(cert_pem) => Some(format!("{cert_pem}{}", ca.ca_certificate_pem())),
+        Err(err) => {
+            warn!(station_id, "Rejecting SignCertificate: {err}");
+            None
+        }
+    }
+}
+
+/// Deliver a signed certificate chain to the station.
+pub async fn send_certificate_signed(
+    station_id: &str,
+    certificate_chain: String,
+) -> Result<CertificateSignedResponse, CallError> {
+    let response: CertificateSignedResponse = connections::call(
+        station_id,
+        OcppActionEnum::CertificateSigned,
+        OcppPayload::CertificateSigned(CertificateSignedKind::Request(CertificateSignedRequest {
+            certificate_chain,
+        })),
+        &Actor::automation("pki"),
+    )
+    .await?;
+    if response.status == GenericStatus::Rejected {
+        warn!(station_id, "Charger rejected its new certificate");
+    }
+    Ok(response)
+}
+
+/// Ask a station to upload its diagnostics or security log.
+pub async fn get_log(
+    station_id: &str,
+    request: GetLogRequest,
+    actor: &Actor,
+) -> Result<GetLogResponse, CallError> {
+    connections::call(
+        station_id,
+        OcppActionEnum::GetLog,
+        OcppPayload::GetLog(GetLogKind::Request(request)),
+        actor,
+    )
+    .await
+}
+
+/// Start a signed firmware update on a station.
+pub async fn signed_update_firmware(
+    station_id: &str,
+    request: SignedUpdateFirmwareRequest,
+    actor: &Actor,
+) -> Result<SignedUpdateFirmwareResponse, CallError> {
+    connections::call(
+        station_id,
+        OcppActionEnum::SignedUpdateFirmware,
+        OcppPayload::SignedUpdateFirmware(SignedUpdateFirmwareKind::Request(request)),
+        actor,
+    )
+    .await
+}
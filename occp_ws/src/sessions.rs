@@ -0,0 +1,239 @@
+//! Charging sessions as users see them, built from the transactions that
+//! StartTransaction, MeterValues and StopTransaction record.
+
+use chrono::{DateTime, Utc};
+use rust_ocpp::v1_6::types::Reason;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::transactions::{self, Transaction, TransactionStatus};
+
+#[derive(Serialize, ToSchema, Debug, Clone, PartialEq)]
+pub struct Session {
+    pub transaction_id: i32,
+    pub station_id: String,
+    pub connector_id: u32,
+    pub id_tag: String,
+    pub status: SessionStatus,
+    /// Why an incomplete session could not be recorded in full.
+    pub incomplete_reason: Option<String>,
+    pub started_at: DateTime<Utc>,
+    pub stopped_at: Option<DateTime<Utc>>,
+    /// Energy register at the start, in Wh.
+    pub meter_start: i32,
+    /// Energy register at the stop, in Wh.
+    pub meter_stop: Option<i32>,
+    /// Energy delivered so far, in Wh.
+    pub energy_wh: Option<f64>,
+    /// Highest power drawn, in W.
+    pub peak_power_w: Option<f64>,
+    pub stop_reason: Option<StopReason>,
+}
+
+#[derive(Serialize, Deserialize, ToSchema, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SessionStatus {
+    InProgress,
+    Completed,
+    /// The session ended but the server is missing part of it, e.g. its
+    /// start or its stop.
+    Incomplete,
+}
+
+/// Why a session ended, with OCPP's reset variants folded together.
+#[derive(Serialize, ToSchema, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StopReason {
+    /// The cable was unplugged.
+    #[serde(rename = "EVDisconnected")]
+    EvDisconnected,
+    /// Stopped through the server, including a remote connector unlock.
+    Remote,
+    /// Stopped at the charger, e.g. with the RFID card or a button.
+    Local,
+    PowerLoss,
+    /// The charger rebooted or was reset.
+    Reboot,
+    EmergencyStop,
+    /// The id tag was no longer authorised.
+    DeAuthorized,
+    Other,
+}
+
+/// A session together with its power curve.
+#[derive(Serialize, ToSchema, Debug, Clone, PartialEq)]
+pub struct SessionDetail {
+    #[serde(flatten)]
+    pub session: Session,
+    pub power_curve: Vec<PowerPoint>,
+    /// Whether the curve was derived from energy readings because the
+    /// charger did not report power.
+    pub power_curve_estimated: bool,
+}
+
+#[derive(Serialize, ToSchema, Debug, Clone, Copy, PartialEq)]
+pub struct PowerPoint {
+    pub at: DateTime<Utc>,
+    pub power_w: f64,
+}
+
+/// Which sessions to return. Unset fields match everything.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SessionFilter {
+    pub station_id: Option<String>,
+    pub connector_id: Option<u32>,
+    pub id_tag: Option<String>,
+    /// Sessions with one of these id tags, e.g. the ones a user owns.
+    pub id_tags: Option<Vec<String>>,
+    /// Sessions started at or after this time.
+    pub from: Option<DateTime<Utc>>,
+    /// Sessions started before this time.
+    pub to: Option<DateTime<Utc>>,
+    pub status: Option<SessionStatus>,
+}
+
+impl SessionFilter {
+    pub fn matches(&self, session: &Session) -> bool {
+        self.station_id
+            .as_ref()
+            .is_none_or(|id| session.station_id == *id)
+            && self
+                .connector_id
+                .is_none_or(|id| session.connector_id == id)
+            && self
+                .id_tag
+                .as_ref()
+                .is_none_or(|tag| session.id_tag == *tag)
+            && self
+                .id_tags
+                .as_ref()
+                .is_none_or(|tags| tags.contains(&session.id_tag))
+            && self.from.is_none_or(|from| session.started_at >= from)
+            && self.to.is_none_or(|to| session.started_at < to)
+            && self.status.is_none_or(|status| session.status == status)
+    }
+}
+
+impl From<&Reason> for StopReason {
+    fn from(reason: &Reason) -> Self {
+        match reason {
+            Reason::EVDisconnected => Self::EvDisconnected,
+            Reason::Remote | Reason::UnlockCommand => Self::Remote,
+            Reason::Local => Self::Local,
+            Reason::PowerLoss => Self::PowerLoss,
+            Reason::HardReset | Reason::SoftReset | Reason::Reboot => Self::Reboot,
+            Reason::EmergencyStop => Self::EmergencyStop,
+            Reason::DeAuthorized => Self::DeAuthorized,
+            Reason::Other => Self::Other,
+        }
+    }
+}
+
+impl From<&Transaction> for Session {
+    fn from(transaction: &Transaction) -> Self {
+        // A completed transaction keeps a flag only when its start was never
+        // seen; an orphaned one was closed by the server without a stop.
+        let status = match transaction.status {
+            TransactionStatus::Active => SessionStatus::InProgress,
+            TransactionStatus::Completed if transaction.flag.is_none() => SessionStatus::Completed,
+            TransactionStatus::Completed | TransactionStatus::Orphaned => SessionStatus::Incomplete,
+        };
+        let meter_end = transaction
+            .meter_stop
+            .map(f64::from)
+            .or(transaction.last_meter_value);
+
+        Self {
+            transaction_id: transaction.transaction_id,
+            station_id: transaction.station_id.clone(),
+            connector_id: transaction.connector_id,
+            id_tag: transaction.id_tag.clone(),
+            status,
+            incomplete_reason: (status == SessionStatus::Incomplete)
+                .then(|| transaction.flag.clone())
+                .flatten(),
+            started_at: transaction.started_at,
+            stopped_at: transaction.stopped_at,
+            meter_start: transaction.meter_start,
+            meter_stop: transaction.meter_stop,
+            // A register that went backwards was replaced or reset; the
+            // difference says nothing about the energy delivered.
+            energy_wh: meter_end
+                .map(|end| end - f64::from(transaction.meter_start))
+                .filter(|energy| *energy >= 0.0),
+            peak_power_w: transaction
+                .peak_power_w
+                .or_else(|| peak_power_from_energy(transaction)),
+            stop_reason: transaction.stop_reason.as_ref().map(StopReason::from),
+        }
+    }
+}
+
+/// Estimate power from consecutive energy readings when the charger does not
+/// report it. Each point is the average over the window ending at it.
+fn power_from_energy(transaction: &Transaction) -> Vec<PowerPoint> {
+    transaction
+        .meter_samples
+        .windows(2)
+        .filter_map(|pair| {
+            let hours = (pair[1].at - pair[0].at).num_milliseconds() as f64 / 3_600_000.0;
+            let wh = pair[1].wh - pair[0].wh;
+            (hours > 0.0 && wh >= 0.0).then(|| PowerPoint {
+                at: pair[1].at,
+                power_w: wh / hours,
+            })
+        })
+        .collect()
+}
+
+fn peak_power_from_energy(transaction: &Transaction) -> Option<f64> {
+    power_from_energy(transaction)
+        .into_iter()
+        .map(|point| point.power_w)
+        .reduce(f64::max)
+}
+
+pub fn get(transaction_id: i32) -> Option<Session> {
+    transactions::get(transaction_id).map(|transaction| Session::from(&transaction))
+}
+
+/// A session with its measured power curve, or one estimated from energy
+/// readings.
+pub fn detail(transaction_id: i32) -> Option<SessionDetail> {
+    let transaction = transactions::get(transaction_id)?;
+    let measured: Vec<PowerPoint> = transaction
+        .power_samples
+        .iter()
+        .map(|sample| PowerPoint {
+            at: sample.at,
+            power_w: sample.w,
+        })
+        .collect();
+    let power_curve_estimated = measured.is_empty();
+    Some(SessionDetail {
+        session: Session::from(&transaction),
+        power_curve: if power_curve_estimated {
+            power_from_energy(&transaction)
+        } else {
+            measured
+        },
+        power_curve_estimated,
+    })
+}
+
+/// Sessions, optionally for one station, newest first.
+pub fn list(station_id: Option<&str>) -> Vec<Session> {
+    let mut sessions: Vec<Session> = transactions::all(station_id)
+        .iter()
+        .map(Session::from)
+        .collect();
+    sessions.sort_by_key(|session| std::cmp::Reverse((session.started_at, session.transaction_id)));
+    sessions
+}
+
+/// Sessions matching `filter`, newest first.
+pub fn search(filter: &SessionFilter) -> Vec<Session> {
+    list(filter.station_id.as_deref())
+        .into_iter()
+        .filter(|session| filter.matches(session))
+        .collect()
+}
@@ -1,10 +1,33 @@
+use std::sync::Arc;
+
 use chrono::{DateTime, Utc};
-use common::allowed_serial_numbers;
+use common::{
+    AuditConfig, CommandConfig, DuplicateConnectionPolicy, HeartbeatConfig, KeepaliveConfig,
+    LoginConfig, RegistrationConfig, StationAuthConfig, WebhookConfig, allowed_serial_numbers,
+    duplicate_connection_policy,
+};
+use storage::Storage;
 use tokio::sync::OnceCell;
 use tracing::warn;
 
+use crate::auth::StationCredentials;
+use crate::pki::LocalCa;
+
 pub static START_TIME: OnceCell<DateTime<Utc>> = OnceCell::const_new();
 pub static ALLOWED_SERIAL_NUMBERS: OnceCell<Vec<String>> = OnceCell::const_new();
+pub static STATION_CREDENTIALS: OnceCell<StationCredentials> = OnceCell::const_new();
+pub static REGISTRATION_CONFIG: OnceCell<RegistrationConfig> = OnceCell::const_new();
+pub static HEARTBEAT_CONFIG: OnceCell<HeartbeatConfig> = OnceCell::const_new();
+pub static KEEPALIVE_CONFIG: OnceCell<KeepaliveConfig> = OnceCell::const_new();
+pub static COMMAND_CONFIG: OnceCell<CommandConfig> = OnceCell::const_new();
+pub static DUPLICATE_CONNECTION_POLICY: OnceCell<DuplicateConnectionPolicy> = OnceCell::const_new();
+pub static WEBHOOK_CONFIG: OnceCell<WebhookConfig> = OnceCell::const_new();
+pub static LOGIN_CONFIG: OnceCell<LoginConfig> = OnceCell::const_new();
+pub static AUDIT_CONFIG: OnceCell<AuditConfig> = OnceCell::const_new();
+/// Set when the TLS listener runs on the built-in CA.
+pub static LOCAL_CA: std::sync::OnceLock<Arc<LocalCa>> = std::sync::OnceLock::new();
+/// Set at startup; without it state lives only in memory.
+pub static STORE: std::sync::OnceLock<Box<dyn Storage>> = std::sync::OnceLock::new();
 
 pub fn get_allowed_serial_numbers() -> Option<&'static Vec<String>> {
     ALLOWED_SERIAL_NUMBERS.get()
@@ -23,3 +46,114 @@ pub async fn load_allowed_serial_numbers() -> &'static Vec<String> {
         })
         .await
 }
+
+pub async fn load_station_credentials() -> &'static StationCredentials {
+    STATION_CREDENTIALS
+        .get_or_init(|| async {
+            let config = StationAuthConfig::from_env().unwrap_or_else(|err| {
+                warn!("Failed to load station auth config, requiring auth: {err}");
+                StationAuthConfig {
+                    required: true,
+                    ..StationAuthConfig::default()
+                }
+            });
+            StationCredentials::load(config.required, config.credentials_file).unwrap_or_else(
+                |err| {
+                    warn!("Failed to load station credentials, requiring auth: {err}");
+                    StationCredentials::in_memory(true)
+                },
+            )
+        })
+        .await
+}
+
+pub async fn load_registration_config() -> &'static RegistrationConfig {
+    REGISTRATION_CONFIG
+        .get_or_init(|| async {
+            RegistrationConfig::from_env().unwrap_or_else(|err| {
+                warn!("Failed to load registration config, requiring approval: {err}");
+                RegistrationConfig {
+                    approval_required: true,
+                    ..RegistrationConfig::default()
+                }
+            })
+        })
+        .await
+}
+
+pub async fn load_heartbeat_config() -> &'static HeartbeatConfig {
+    HEARTBEAT_CONFIG
+        .get_or_init(|| async {
+            HeartbeatConfig::from_env().unwrap_or_else(|err| {
+                warn!("Failed to load heartbeat config, using defaults: {err}");
+                HeartbeatConfig::default()
+            })
+        })
+        .await
+}
+
+pub async fn load_keepalive_config() -> &'static KeepaliveConfig {
+    KEEPALIVE_CONFIG
+        .get_or_init(|| async {
+            KeepaliveConfig::from_env().unwrap_or_else(|err| {
+                warn!("Failed to load keepalive config, using defaults: {err}");
+                KeepaliveConfig::default()
+            })
+        })
+        .await
+}
+
+pub async fn load_duplicate_connection_policy() -> DuplicateConnectionPolicy {
+    *DUPLICATE_CONNECTION_POLICY
+        .get_or_init(|| async {
+            duplicate_connection_policy().unwrap_or_else(|err| {
+                warn!("Failed to load DUPLICATE_CONNECTION_POLICY, using default: {err}");
+                DuplicateConnectionPolicy::default()
+            })
+        })
+        .await
+}
+
+pub async fn load_command_config() -> &'static CommandConfig {
+    COMMAND_CONFIG
+        .get_or_init(|| async {
+            CommandConfig::from_env().unwrap_or_else(|err| {
+                warn!("Failed to load command config, using defaults: {err}");
+                CommandConfig::default()
+            })
+        })
+        .await
+}
+
+pub async fn load_webhook_config() -> &'static WebhookConfig {
+    WEBHOOK_CONFIG
+        .get_or_init(|| async {
+            WebhookConfig::from_env().unwrap_or_else(|err| {
+                warn!("Failed to load webhook config, using defaults: {err}");
+                WebhookConfig::default()
+            })
+        })
+        .await
+}
+
+pub async fn load_login_config() -> &'static LoginConfig {
+    LOGIN_CONFIG
+        .get_or_init(|| async {
+            LoginConfig::from_env().unwrap_or_else(|err| {
+                warn!("Failed to load login config, using defaults: {err}");
+                LoginConfig::default()
+            })
+        })
+        .await
+}
+
+pub async fn load_audit_config() -> &'static AuditConfig {
+    AUDIT_CONFIG
+        .get_or_init(|| async {
+            AuditConfig::from_env().unwrap_or_else(|err| {
+                warn!("Failed to load audit config, using defaults: {err}");
+                AuditConfig::default()
+            })
+        })
+        .await
+}
@@ -0,0 +1,344 @@
+//! Charger registration state and the operator approval queue.
+//!
+//! A BootNotification is Accepted when the station was approved before, its
+//! serial number is allow-listed, or approval is not required. Anything else
+//! lands in the queue as Pending until an operator approves or rejects it.
+
+use std::{
+    collections::HashMap,
+    num::NonZeroU32,
+    sync::{LazyLock, RwLock},
+};
+
+use chrono::{DateTime, Utc};
+use common::{HeartbeatConfig, RegistrationConfig};
+use rust_ocpp::v1_6::{
+    messages::{
+        boot_notification::BootNotificationRequest,
+        change_configuration::{ChangeConfigurationRequest, ChangeConfigurationResponse},
+        trigger_message::{TriggerMessageRequest, TriggerMessageResponse},
+    },
+    types::{ConfigurationStatus, MessageTrigger, RegistrationStatus},
+};
+use serde::Serialize;
+use tracing::{info, warn};
+use utoipa::ToSchema;
+
+use crate::audit::Actor;
+use crate::connections::{self, CallError};
+use crate::events::{self, StationEvent};
+use crate::persistence;
+use crate::rest::openapi;
+use crate::types::*;
+
+static STATIONS: LazyLock<RwLock<HashMap<String, StationRecord>>> =
+    LazyLock::new(|| RwLock::new(HashMap::new()));
+static METADATA: LazyLock<RwLock<HashMap<String, StationMetadata>>> =
+    LazyLock::new(|| RwLock::new(HashMap::new()));
+
+/// What the server knows about a charger from its BootNotifications.
+#[derive(Serialize, ToSchema, Debug, Clone, PartialEq)]
+pub struct StationRecord {
+    pub station_id: String,
+    #[schema(value_type = openapi::RegistrationStatus)]
+    pub status: RegistrationStatus,
+    pub vendor: String,
+    pub model: String,
+    pub serial_number: Option<String>,
+    pub firmware_version: Option<String>,
+    pub first_seen: DateTime<Utc>,
+    pub last_boot: DateTime<Utc>,
+}
+
+/// What an operator records about a station.
+#[derive(Serialize, Debug, Clone, Default, PartialEq)]
+pub struct StationMetadata {
+    /// Friendly name shown instead of the station id.
+    pub name: Option<String>,
+    pub location: Option<String>,
+    pub notes: Option<String>,
+    /// The station's own heartbeat interval in seconds; `None` uses the
+    /// configured default.
+    pub heartbeat_interval: Option<u32>,
+    pub updated_at: Option<DateTime<Utc>>,
+}
+
+/// A partial metadata edit: `None` keeps a field, `Some(None)` clears it.
+#[derive(Serialize, Debug, Clone, Default, PartialEq)]
+pub struct MetadataUpdate {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<Option<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub location: Option<Option<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub notes: Option<Option<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub heartbeat_interval: Option<Option<NonZeroU32>>,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum RegistrationError {
+    #[error("station {0} has never sent a BootNotification")]
+    UnknownStation(String),
+}
+
+/// Decide how to answer a BootNotification and remember the charger's details.
+pub fn register_boot(
+    station_id: &str,
+    boot: &BootNotificationRequest,
+    config: &RegistrationConfig,
+    allowed_serials: &[String],
+) -> RegistrationStatus {
+    let now = Utc::now();
+    let serial = boot.charge_point_serial_number.clone();
+    let allow_listed = serial
+        .as_ref()
+        .is_some_and(|serial| allowed_serials.iter().any(|allowed| allowed == serial));
+
+    let mut stations = STATIONS.write().expect("stations lock poisoned");
+    let previous = stations.get(station_id);
+    let status = match previous.map(|record| &record.status) {
+        Some(RegistrationStatus::Rejected) => RegistrationStatus::Rejected,
+        _ if allow_listed || !config.approval_required => RegistrationStatus::Accepted,
+        // An approval covers the hardware it was given for, not whatever
+        // later connects under the same station id.
+        Some(RegistrationStatus::Accepted)
+            if previous.is_some_and(|record| record.serial_number == serial) =>
+        {
+            RegistrationStatus::Accepted
+        }
+        Some(RegistrationStatus::Accepted) => {
+            warn!(
+                station_id,
+                "Approved station booted with a different serial number, queueing for approval"
+            );
+            RegistrationStatus::Pending
+        }
+        _ => RegistrationStatus::Pending,
+    };
+
+    let record = StationRecord {
+        station_id: station_id.to_string(),
+        status: status.clone(),
+        vendor: boot.charge_point_vendor.clone(),
+        model: boot.charge_point_model.clone(),
+        serial_number: serial,
+        firmware_version: boot.firmware_version.clone(),
+        first_seen: previous.map_or(now, |record| record.first_seen),
+        last_boot: now,
+    };
+    if status == RegistrationStatus::Pending && previous.is_none_or(|r| r.status != status) {
+        info!(station_id, vendor = %record.vendor, model = %record.model, "Charger awaiting approval");
+    }
+    stations.insert(station_id.to_string(), record.clone());
+    drop(stations);
+    persistence::save_station(&record);
+    events::publish(StationEvent::Booted {
+        station_id: station_id.to_string(),
+        at: now,
+        vendor: record.vendor,
+        model: record.model,
+        firmware_version: record.firmware_version,
+        registration_status: status.clone(),
+    });
+    status
+}
+
+/// Load station records from storage.
+pub fn restore(records: Vec<StationRecord>) {
+    let mut stations = STATIONS.write().expect("stations lock poisoned");
+    for record in records {
+        stations.insert(record.station_id.clone(), record);
+    }
+}
+
+/// Every station that has sent a BootNotification, ordered by id.
+pub fn all() -> Vec<StationRecord> {
+    let mut all: Vec<_> = STATIONS
+        .read()
+        .expect("stations lock poisoned")
+        .values()
+        .cloned()
+        .collect();
+    all.sort_by(|a, b| a.station_id.cmp(&b.station_id));
+    all
+}
+
+/// Whether the station may send messages other than BootNotification.
+pub fn is_accepted(station_id: &str, config: &RegistrationConfig) -> bool {
+    match get(station_id) {
+        Some(record) => record.status == RegistrationStatus::Accepted,
+        None => !config.approval_required,
+    }
+}
+
+pub fn get(station_id: &str) -> Option<StationRecord> {
+    STATIONS
+        .read()
+        .expect("stations lock poisoned")
+        .get(station_id)
+        .cloned()
+}
+
+/// Chargers waiting for an operator decision, oldest first.
+pub fn pending_registrations() -> Vec<StationRecord> {
+    let mut pending: Vec<_> = STATIONS
+        .read()
+        .expect("stations lock poisoned")
+        .values()
+        .filter(|record| record.status == RegistrationStatus::Pending)
+        .cloned()
+        .collect();
+    pending.sort_by_key(|record| record.first_seen);
+    pending
+}
+
+/// Accept the station on its next BootNotification.
+pub fn approve(station_id: &str) -> Result<StationRecord, RegistrationError> {
+    set_status(station_id, RegistrationStatus::Accepted)
+}
+
+/// Answer the station's next BootNotification with Rejected.
+pub fn reject(station_id: &str) -> Result<StationRecord, RegistrationError> {
+    set_status(station_id, RegistrationStatus::Rejected)
+}
+
+fn set_status(
+    station_id: &str,
+    status: RegistrationStatus,
+) -> Result<StationRecord, RegistrationError> {
+    let record = {
+        let mut stations = STATIONS.write().expect("stations lock poisoned");
+        let record = stations
+            .get_mut(station_id)
+            .ok_or_else(|| RegistrationError::UnknownStation(station_id.to_string()))?;
+        record.status = status;
+        record.clone()
+    };
+    persistence::save_station(&record);
+    info!(station_id, status = ?record.status, "Operator updated station registration");
+    events::publish(StationEvent::RegistrationChanged {
+        station_id: station_id.to_string(),
+        at: Utc::now(),
+        registration_status: record.status.clone(),
+    });
+
+    // Ask a connected charger to boot again so it learns the decision now
+    // rather than after its retry interval.
+    if connections::is_connected(station_id) {
+        let station_id = station_id.to_string();
+        tokio::spawn(async move {
+            let request = TriggerMessageRequest {
+                requested_message: MessageTrigger::BootNotification,
+                connector_id: None,
+            };
+            let result: Result<TriggerMessageResponse, _> = connections::call(
+                &station_id,
+                OcppActionEnum::TriggerMessage,
+                OcppPayload::TriggerMessage(TriggerMessageKind::Request(request)),
+                &Actor::automation("registration"),
+            )
+            .await;
+            if let Err(err) = result {
+                warn!(station_id, "TriggerMessage BootNotification failed: {err}");
+            }
+        });
+    }
+    Ok(record)
+}
+
+pub fn metadata(station_id: &str) -> StationMetadata {
+    METADATA
+        .read()
+        .expect("station metadata lock poisoned")
+        .get(station_id)
+        .cloned()
+        .unwrap_or_default()
+}
+
+pub fn update_metadata(station_id: &str, update: MetadataUpdate) -> StationMetadata {
+    let metadata = {
+        let mut all = METADATA.write().expect("station metadata lock poisoned");
+        let metadata = all.entry(station_id.to_string()).or_default();
+        if let Some(name) = update.name {
+            metadata.name = name;
+        }
+        if let Some(location) = update.location {
+            metadata.location = location;
+        }
+        if let Some(notes) = update.notes {
+            metadata.notes = notes;
+        }
+        if let Some(interval) = update.heartbeat_interval {
+            metadata.heartbeat_interval = interval.map(NonZeroU32::get);
+        }
+        metadata.updated_at = Some(Utc::now());
+        metadata.clone()
+    };
+    persistence::save_station_metadata(station_id, &metadata);
+    metadata
+}
+
+/// Load operator metadata from storage.
+pub fn restore_metadata(restored: Vec<(String, StationMetadata)>) {
+    METADATA
+        .write()
+        .expect("station metadata lock poisoned")
+        .extend(restored);
+}
+
+/// Heartbeat interval in seconds for the station: its own setting if it has
+/// one, otherwise the configured default.
+pub fn heartbeat_interval(station_id: &str, config: &HeartbeatConfig) -> u32 {
+    METADATA
+        .read()
+        .expect("station metadata lock poisoned")
+        .get(station_id)
+        .and_then(|metadata| metadata.heartbeat_interval)
+        .unwrap_or(config.interval)
+}
+
+/// Give the station its own heartbeat interval, or go back to the default
+/// with `None`, and push it with [`push_heartbeat_interval`].
+pub async fn set_heartbeat_interval(
+    station_id: &str,
+    interval: Option<NonZeroU32>,
+    config: &HeartbeatConfig,
+    actor: &Actor,
+) -> Result<Option<ConfigurationStatus>, CallError> {
+    let update = MetadataUpdate {
+        heartbeat_interval: Some(interval),
+        ..MetadataUpdate::default()
+    };
+    update_metadata(station_id, update);
+    push_heartbeat_interval(station_id, config, actor).await
+}
+
+/// Tell a connected station its heartbeat interval right away through
+/// ChangeConfiguration(HeartbeatInterval) and return the charger's answer.
+/// Otherwise the interval goes out with the next BootNotification.
+pub async fn push_heartbeat_interval(
+    station_id: &str,
+    config: &HeartbeatConfig,
+    actor: &Actor,
+) -> Result<Option<ConfigurationStatus>, CallError> {
+    if !connections::is_connected(station_id) {
+        return Ok(None);
+    }
+    let response: ChangeConfigurationResponse = connections::call(
+        station_id,
+        OcppActionEnum::ChangeConfiguration,
+        OcppPayload::ChangeConfiguration(ChangeConfigurationKind::Request(
+            ChangeConfigurationRequest {
+                key: "HeartbeatInterval".to_string(),
+                value: heartbeat_interval(station_id, config).to_string(),
+            },
+        )),
+        actor,
+    )
+    .await?;
+    if response.status != ConfigurationStatus::Accepted {
+        warn!(station_id, status = ?response.status, "Charger did not apply HeartbeatInterval");
+    }
+    Ok(Some(response.status))
+}
@@ -0,0 +1,274 @@
+//! Native TLS listener for wss:// (OCPP Security Profiles 2 and 3).
+//!
+//! Profile 2 is server-side TLS with Basic auth on top. Profile 3 adds client
+//! certificates; the certificate's CN must equal the station id the charger
+//! connects as.
+
+use std::{
+    fs::File,
+    io::{self, BufReader},
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock},
+    time::Duration,
+};
+
+use axum::{Router, extract::ConnectInfo};
+use common::TlsConfig;
+use hyper::body::Incoming;
+use hyper_util::{
+    rt::{TokioExecutor, TokioIo},
+    server::conn::auto,
+};
+use rustls::{
+    RootCertStore, ServerConfig,
+    crypto::ring::sign::any_supported_type,
+    pki_types::{CertificateDer, PrivateKeyDer},
+    server::{ClientHello, ResolvesServerCert, WebPkiClientVerifier},
+    sign::CertifiedKey,
+};
+use tokio::{net::TcpListener, time::timeout};
+use tokio_rustls::TlsAcceptor;
+use tower::Service;
+use tracing::{debug, info, warn};
+use x509_parser::prelude::{FromDer, X509Certificate};
+
+use crate::auth::AuthError;
+
+/// How long a client gets to finish the TLS handshake before it is dropped.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, thiserror::Error)]
+pub enum TlsError {
+    #[error("failed to read {path}: {source}")]
+    Read { path: String, source: io::Error },
+    #[error("no certificates found in {0}")]
+    NoCertificates(String),
+    #[error("no private key found in {0}")]
+    NoPrivateKey(String),
+    #[error("invalid TLS configuration: {0}")]
+    Rustls(#[from] rustls::Error),
+    #[error("invalid client CA configuration: {0}")]
+    ClientVerifier(#[from] rustls::server::VerifierBuilderError),
+}
+
+/// Identity presented by a charger through its TLS client certificate.
+///
+/// Inserted as a request extension by [`serve`] when the charger sent a
+/// certificate that chained to the configured client CA.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClientIdentity {
+    pub common_name: Option<String>,
+}
+
+impl ClientIdentity {
+    pub fn from_certificate(cert: &CertificateDer<'_>) -> Self {
+        let common_name = X509Certificate::from_der(cert.as_ref())
+            .ok()
+            .and_then(|(_, parsed)| {
+                parsed
+                    .subject()
+                    .iter_common_name()
+                    .next()
+                    .and_then(|cn| cn.as_str().ok())
+                    .map(str::to_string)
+            });
+        Self { common_name }
+    }
+
+    /// Check that the certificate was issued to `station_id`.
+    pub fn authorize(&self, station_id: &str) -> Result<(), AuthError> {
+        match &self.common_name {
+            Some(cn) if cn == station_id => Ok(()),
+            _ => Err(AuthError::CertificateMismatch),
+        }
+    }
+}
+
+/// Server certificate that can be swapped while the listener keeps running,
+/// e.g. after the local CA renewed it.
+#[derive(Debug)]
+pub struct ServerCertResolver {
+    cert_file: PathBuf,
+    key_file: PathBuf,
+    current: RwLock<Arc<CertifiedKey>>,
+}
+
+impl ServerCertResolver {
+    pub fn from_files(cert_file: &Path, key_file: &Path) -> Result<Self, TlsError> {
+        let current = load_certified_key(cert_file, key_file)?;
+        Ok(Self {
+            cert_file: cert_file.to_path_buf(),
+            key_file: key_file.to_path_buf(),
+            current: RwLock::new(current),
+        })
+    }
+
+    /// Re-read the certificate and key files. New handshakes use them at once.
+    pub fn reload(&self) -> Result<(), TlsError> {
+        let fresh = load_certified_key(&self.cert_file, &self.key_file)?;
+        *self.current.write().expect("certificate lock poisoned") = fresh;
+        info!(
+            "Reloaded server certificate from {}",
+            self.cert_file.display()
+        );
+        Ok(())
+    }
+}
+
+impl ResolvesServerCert for ServerCertResolver {
+    fn resolve(&self, _client_hello: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+        Some(
+            self.current
+                .read()
+                .expect("certificate lock poisoned")
+                .clone(),
+        )
+    }
+}
+
+/// Build a rustls server config from PEM files.
+pub fn server_config(config: &TlsConfig) -> Result<Arc<ServerConfig>, TlsError> {
+    let certs = ServerCertResolver::from_files(&config.cert_file, &config.key_file)?;
+    server_config_with_certs(config, Arc::new(certs))
+}
+
+/// Build a rustls server config whose certificate comes from `certs`.
+/// `config.cert_file` and `config.key_file` are not read.
+pub fn server_config_with_certs(
+    config: &TlsConfig,
+    certs: Arc<ServerCertResolver>,
+) -> Result<Arc<ServerConfig>, TlsError> {
+    install_crypto_provider();
+
+    let builder = ServerConfig::builder();
+    let builder = match &config.client_ca_file {
+        Some(ca_file) => {
+            let mut roots = RootCertStore::empty();
+            for cert in load_certs(ca_file)? {
+                roots.add(cert)?;
+            }
+            let verifier = WebPkiClientVerifier::builder(Arc::new(roots));
+            let verifier = if config.client_cert_required {
+                verifier.build()?
+            } else {
+                verifier.allow_unauthenticated().build()?
+            };
+            builder.with_client_cert_verifier(verifier)
+        }
+        None => builder.with_no_client_auth(),
+    };
+
+    let mut server_config = builder.with_cert_resolver(certs);
+    server_config.alpn_protocols = vec![b"http/1.1".to_vec()];
+    Ok(Arc::new(server_config))
+}
+
+/// Accept TLS connections on `listener` and serve `router` over them.
+///
+/// Each request gets `ConnectInfo<SocketAddr>` and, when the charger sent a
+/// client certificate, a [`ClientIdentity`] extension.
+pub async fn serve(
+    listener: TcpListener,
+    router: Router,
+    server_config: Arc<ServerConfig>,
+) -> io::Result<()> {
+    let acceptor = TlsAcceptor::from(server_config);
+    info!("TLS enabled on {}", listener.local_addr()?);
+
+    loop {
+        let (stream, addr) = match listener.accept().await {
+            Ok(conn) => conn,
+            Err(err) => {
+                warn!("Failed to accept TCP connection: {err}");
+                continue;
+            }
+        };
+
+        let acceptor = acceptor.clone();
+        let router = router.clone();
+        tokio::spawn(async move {
+            let stream = match timeout(HANDSHAKE_TIMEOUT, acceptor.accept(stream)).await {
+                Ok(Ok(stream)) => stream,
+                Ok(Err(err)) => {
+                    warn!(addr = %addr, "TLS handshake failed: {err}");
+                    return;
+                }
+                Err(_) => {
+                    warn!(addr = %addr, "TLS handshake not finished within {HANDSHAKE_TIMEOUT:?}, closing");
+                    return;
+                }
+            };
+
+            let identity = stream
+                .get_ref()
+                .1
+                .peer_certificates()
+                .and_then(|certs| certs.first())
+                .map(ClientIdentity::from_certificate);
+            if let Some(identity) = &identity {
+                debug!(addr = %addr, "Client certificate CN: {:?}", identity.common_name);
+            }
+
+            let service =
+                hyper::service::service_fn(move |mut request: hyper::Request<Incoming>| {
+                    request.extensions_mut().insert(ConnectInfo(addr));
+                    if let Some(identity) = &identity {
+                        request.extensions_mut().insert(identity.clone());
+                    }
+                    router.clone().call(request)
+                });
+
+            if let Err(err) = auto::Builder::new(TokioExecutor::new())
+                .serve_connection_with_upgrades(TokioIo::new(stream), service)
+                .await
+            {
+                debug!(addr = %addr, "TLS connection ended with error: {err}");
+            }
+        });
+    }
+}
+
+pub(crate) fn install_crypto_provider() {
+    // Pin ring so a dependency enabling another rustls provider cannot make
+    // the default ambiguous.
+    let _ = rustls::crypto::ring::default_provider().install_default();
+}
+
+fn load_certified_key(cert_file: &Path, key_file: &Path) -> Result<Arc<CertifiedKey>, TlsError> {
+    install_crypto_provider();
+    let certs = load_certs(cert_file)?;
+    let key = load_private_key(key_file)?;
+    let signing_key = any_supported_type(&key)?;
+    Ok(Arc::new(CertifiedKey::new(certs, signing_key)))
+}
+
+fn load_certs(path: &Path) -> Result<Vec<CertificateDer<'static>>, TlsError> {
+    let mut reader = open(path)?;
+    let certs = rustls_pemfile::certs(&mut reader)
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|source| read_error(path, source))?;
+    if certs.is_empty() {
+        return Err(TlsError::NoCertificates(path.display().to_string()));
+    }
+    Ok(certs)
+}
+
+fn load_private_key(path: &Path) -> Result<PrivateKeyDer<'static>, TlsError> {
+    let mut reader = open(path)?;
+    rustls_pemfile::private_key(&mut reader)
+        .map_err(|source| read_error(path, source))?
+        .ok_or_else(|| TlsError::NoPrivateKey(path.display().to_string()))
+}
+
+fn open(path: &Path) -> Result<BufReader<File>, TlsError> {
+    File::open(path)
+        .map(BufReader::new)
+        .map_err(|source| read_error(path, source))
+}
+
+fn read_error(path: &Path, source: io::Error) -> TlsError {
+    TlsError::Read {
+        path: path.display().to_string(),
+        source,
+    }
+}
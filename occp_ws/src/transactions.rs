@@ -0,0 +1,468 @@
+//! Charging transactions as reported by StartTransaction, MeterValues and
+//! StopTransaction.
+
+use std::{
+    collections::BTreeMap,
+    sync::{LazyLock, RwLock},
+};
+
+use chrono::{DateTime, Utc};
+use rust_ocpp::v1_6::{
+    messages::{
+        meter_values::MeterValuesRequest, start_transaction::StartTransactionRequest,
+        stop_transaction::StopTransactionRequest,
+    },
+    types::{Measurand, MeterValue, Reason, UnitOfMeasure, ValueFormat},
+};
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+use crate::events::{self, StationEvent};
+use crate::persistence;
+use crate::sessions::Session;
+
+static TRANSACTIONS: LazyLock<RwLock<Transactions>> =
+    LazyLock::new(|| RwLock::new(Transactions::default()));
+
+#[derive(Default)]
+struct Transactions {
+    next_id: i32,
+    by_id: BTreeMap<i32, Transaction>,
+}
+
+impl Transactions {
+    /// The next unused id. Ids count up and wrap back to 1 after
+    /// `i32::MAX`, skipping ones already taken, e.g. by a charger's own id
+    /// in a StopTransaction for a start the server never saw.
+    fn allocate_id(&mut self) -> i32 {
+        loop {
+            self.next_id = self.next_id.checked_add(1).unwrap_or(1);
+            if !self.by_id.contains_key(&self.next_id) {
+                return self.next_id;
+            }
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum TransactionStatus {
+    Active,
+    Completed,
+    /// Closed by the server because the charger no longer reports it.
+    Orphaned,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Transaction {
+    pub transaction_id: i32,
+    pub station_id: String,
+    pub connector_id: u32,
+    pub id_tag: String,
+    pub meter_start: i32,
+    pub started_at: DateTime<Utc>,
+    /// Latest energy register reading in Wh.
+    pub last_meter_value: Option<f64>,
+    /// When the charger took `last_meter_value`.
+    pub last_meter_at: Option<DateTime<Utc>>,
+    /// Energy register readings ordered by the charger's timestamp.
+    pub meter_samples: Vec<EnergySample>,
+    /// Power.Active.Import readings ordered by the charger's timestamp.
+    pub power_samples: Vec<PowerSample>,
+    /// Highest Power.Active.Import the charger reported, in W.
+    pub peak_power_w: Option<f64>,
+    pub status: TransactionStatus,
+    pub meter_stop: Option<i32>,
+    pub stopped_at: Option<DateTime<Utc>>,
+    pub stop_reason: Option<Reason>,
+    /// Why the transaction needs an operator's attention.
+    pub flag: Option<String>,
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+pub struct EnergySample {
+    pub at: DateTime<Utc>,
+    pub wh: f64,
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+pub struct PowerSample {
+    pub at: DateTime<Utc>,
+    pub w: f64,
+}
+
+/// Open a transaction and assign it an id. A replayed StartTransaction gets
+/// the transaction it opened the first time.
+pub fn start(station_id: &str, request: &StartTransactionRequest) -> Transaction {
+    let mut transactions = TRANSACTIONS.write().expect("transactions lock poisoned");
+    if let Some(existing) = transactions.by_id.values().find(|transaction| {
+        transaction.station_id == station_id
+            && transaction.connector_id == request.connector_id
+            && transaction.id_tag == request.id_tag
+            && transaction.meter_start == request.meter_start
+            && transaction.started_at == request.timestamp
+    }) {
+        info!(
+            station_id,
+            transaction_id = existing.transaction_id,
+            "Duplicate StartTransaction"
+        );
+        return existing.clone();
+    }
+    let transaction = Transaction {
+        transaction_id: transactions.allocate_id(),
+        station_id: station_id.to_string(),
+        connector_id: request.connector_id,
+        id_tag: request.id_tag.clone(),
+        meter_start: request.meter_start,
+        started_at: request.timestamp,
+        last_meter_value: None,
+        last_meter_at: None,
+        meter_samples: Vec::new(),
+        power_samples: Vec::new(),
+        peak_power_w: None,
+        status: TransactionStatus::Active,
+        meter_stop: None,
+        stopped_at: None,
+        stop_reason: None,
+        flag: None,
+    };
+    transactions
+        .by_id
+        .insert(transaction.transaction_id, transaction.clone());
+    drop(transactions);
+    persistence::save_transaction(&transaction);
+    events::publish(StationEvent::SessionStarted {
+        station_id: station_id.to_string(),
+        at: transaction.started_at,
+        session: Session::from(&transaction),
+    });
+    transaction
+}
+
+/// Apply MeterValues to the transaction they belong to. Returns its id.
+pub fn record_meter_values(station_id: &str, request: &MeterValuesRequest) -> Option<i32> {
+    let transaction = {
+        let mut transactions = TRANSACTIONS.write().expect("transactions lock poisoned");
+        let transaction = match request.transaction_id {
+            Some(id) => transactions
+                .by_id
+                .get_mut(&id)
+                .filter(|transaction| transaction.station_id == station_id),
+            None => transactions.by_id.values_mut().find(|transaction| {
+                transaction.station_id == station_id
+                    && transaction.connector_id == request.connector_id
+                    && transaction.status == TransactionStatus::Active
+            }),
+        };
+        transaction.map(|transaction| {
+            transaction.add_meter_values(&request.meter_value);
+            transaction.clone()
+        })
+    };
+
+    let transaction_id = transaction
+        .as_ref()
+        .map(|transaction| transaction.transaction_id);
+    persistence::save_meter_values(
+        station_id,
+        request.connector_id,
+        transaction_id.or(request.transaction_id),
+        &request.meter_value,
+    );
+    if let Some(transaction) = &transaction {
+        persistence::save_transaction(transaction);
+    }
+    if let Some(latest) = request
+        .meter_value
+        .iter()
+        .max_by_key(|value| value.timestamp)
+    {
+        events::publish(StationEvent::MeterValues {
+            station_id: station_id.to_string(),
+            at: latest.timestamp,
+            connector_id: request.connector_id,
+            transaction_id: transaction_id.or(request.transaction_id),
+            energy_wh: energy_register_wh(latest),
+            power_w: active_power_w(latest),
+        });
+    }
+    transaction_id
+}
+
+/// Close a transaction from a StopTransaction. A transaction the server never
+/// saw start, e.g. because its StartTransaction was lost while the charger
+/// was offline, is recorded from the stop alone and flagged.
+pub fn stop(station_id: &str, request: &StopTransactionRequest) -> Transaction {
+    let (transaction, stored) = apply_stop(station_id, request);
+    if stored {
+        persistence::save_transaction(&transaction);
+        if let Some(transaction_data) = &request.transaction_data {
+            persistence::save_meter_values(
+                station_id,
+                transaction.connector_id,
+                Some(transaction.transaction_id),
+                transaction_data,
+            );
+        }
+        publish_stopped(&transaction);
+    }
+    transaction
+}
+
+fn publish_stopped(transaction: &Transaction) {
+    events::publish(StationEvent::SessionStopped {
+        station_id: transaction.station_id.clone(),
+        at: transaction.stopped_at.unwrap_or_else(Utc::now),
+        session: Session::from(transaction),
+    });
+}
+
+/// Update the in-memory record for a StopTransaction. The flag says whether
+/// the returned transaction is the one stored under its id and changed.
+fn apply_stop(station_id: &str, request: &StopTransactionRequest) -> (Transaction, bool) {
+    let mut transactions = TRANSACTIONS.write().expect("transactions lock poisoned");
+    let known = transactions
+        .by_id
+        .get(&request.transaction_id)
+        .is_some_and(|transaction| transaction.station_id == station_id);
+    if !known {
+        warn!(
+            station_id,
+            transaction_id = request.transaction_id,
+            "StopTransaction for unknown transaction"
+        );
+        if transactions.by_id.contains_key(&request.transaction_id) {
+            // The id belongs to another station; keep that record intact.
+            return (stopped_without_start(station_id, request), false);
+        }
+        // The charger's id is not one the server handed out, so it does not
+        // move the id sequence.
+        let transaction = stopped_without_start(station_id, request);
+        transactions
+            .by_id
+            .insert(transaction.transaction_id, transaction.clone());
+        return (transaction, true);
+    }
+
+    let transaction = transactions
+        .by_id
+        .get_mut(&request.transaction_id)
+        .expect("transaction checked above");
+    // The same stop again, e.g. resent under a new message id or after a
+    // restart, changes nothing.
+    if transaction.status == TransactionStatus::Completed
+        && transaction.meter_stop == Some(request.meter_stop)
+        && transaction.stopped_at == Some(request.timestamp)
+    {
+        info!(
+            station_id,
+            transaction_id = request.transaction_id,
+            "Duplicate StopTransaction"
+        );
+        return (transaction.clone(), false);
+    }
+    if let Some(transaction_data) = &request.transaction_data {
+        transaction.add_meter_values(transaction_data);
+    }
+    transaction.status = TransactionStatus::Completed;
+    transaction.meter_stop = Some(request.meter_stop);
+    transaction.stopped_at = Some(request.timestamp);
+    transaction.stop_reason = Some(request.reason.clone().unwrap_or(Reason::Local));
+    transaction.flag = None;
+    (transaction.clone(), true)
+}
+
+fn stopped_without_start(station_id: &str, request: &StopTransactionRequest) -> Transaction {
+    let mut transaction = Transaction {
+        transaction_id: request.transaction_id,
+        station_id: station_id.to_string(),
+        connector_id: 0,
+        id_tag: request.id_tag.clone().unwrap_or_default(),
+        meter_start: request.meter_stop,
+        started_at: request.timestamp,
+        last_meter_value: None,
+        last_meter_at: None,
+        meter_samples: Vec::new(),
+        power_samples: Vec::new(),
+        peak_power_w: None,
+        status: TransactionStatus::Completed,
+        meter_stop: Some(request.meter_stop),
+        stopped_at: Some(request.timestamp),
+        stop_reason: Some(request.reason.clone().unwrap_or(Reason::Local)),
+        flag: Some("Stopped without a known start".to_string()),
+    };
+    if let Some(transaction_data) = &request.transaction_data {
+        transaction.add_meter_values(transaction_data);
+    }
+    // The earliest reading is the best estimate of where the session began.
+    if let Some(first) = transaction.meter_samples.first() {
+        transaction.meter_start = first.wh.round() as i32;
+        transaction.started_at = first.at;
+    }
+    transaction
+}
+
+impl Transaction {
+    /// Merge energy readings in charger-timestamp order, ignoring readings
+    /// already recorded for the same instant.
+    fn add_meter_values(&mut self, meter_values: &[MeterValue]) {
+        for meter_value in meter_values {
+            let at = meter_value.timestamp;
+            if let Some(w) = active_power_w(meter_value) {
+                self.peak_power_w = Some(self.peak_power_w.map_or(w, |peak| peak.max(w)));
+                if let Err(index) = self
+                    .power_samples
+                    .binary_search_by(|sample| sample.at.cmp(&at))
+                {
+                    self.power_samples.insert(index, PowerSample { at, w });
+                }
+            }
+            let Some(wh) = energy_register_wh(meter_value) else {
+                continue;
+            };
+            if let Err(index) = self
+                .meter_samples
+                .binary_search_by(|sample| sample.at.cmp(&at))
+            {
+                self.meter_samples.insert(index, EnergySample { at, wh });
+            }
+        }
+        if let Some(last) = self.meter_samples.last() {
+            self.last_meter_value = Some(last.wh);
+            self.last_meter_at = Some(last.at);
+        }
+    }
+}
+
+pub fn get(transaction_id: i32) -> Option<Transaction> {
+    TRANSACTIONS
+        .read()
+        .expect("transactions lock poisoned")
+        .by_id
+        .get(&transaction_id)
+        .cloned()
+}
+
+/// The id tag a transaction was started with.
+pub fn id_tag(transaction_id: i32) -> Option<String> {
+    TRANSACTIONS
+        .read()
+        .expect("transactions lock poisoned")
+        .by_id
+        .get(&transaction_id)
+        .map(|transaction| transaction.id_tag.clone())
+}
+
+/// Every transaction, optionally for one station, oldest first.
+pub fn all(station_id: Option<&str>) -> Vec<Transaction> {
+    TRANSACTIONS
+        .read()
+        .expect("transactions lock poisoned")
+        .by_id
+        .values()
+        .filter(|transaction| station_id.is_none_or(|id| transaction.station_id == id))
+        .cloned()
+        .collect()
+}
+
+/// Active transactions, optionally for one station, oldest first.
+pub fn open_transactions(station_id: Option<&str>) -> Vec<Transaction> {
+    TRANSACTIONS
+        .read()
+        .expect("transactions lock poisoned")
+        .by_id
+        .values()
+        .filter(|transaction| transaction.status == TransactionStatus::Active)
+        .filter(|transaction| station_id.is_none_or(|id| transaction.station_id == id))
+        .cloned()
+        .collect()
+}
+
+/// Close a transaction the charger no longer knows about, at its last meter
+/// reading.
+pub fn close_orphaned(transaction_id: i32, note: &str) -> Option<Transaction> {
+    let transaction = {
+        let mut transactions = TRANSACTIONS.write().expect("transactions lock poisoned");
+        let transaction = transactions
+            .by_id
+            .get_mut(&transaction_id)
+            .filter(|transaction| transaction.status == TransactionStatus::Active)?;
+
+        transaction.status = TransactionStatus::Orphaned;
+        transaction.meter_stop = Some(
+            transaction
+                .last_meter_value
+                .map_or(transaction.meter_start, |wh| wh.round() as i32),
+        );
+        transaction.stopped_at = Some(transaction.last_meter_at.unwrap_or_else(Utc::now));
+        transaction.stop_reason = Some(Reason::Other);
+        transaction.flag = Some(note.to_string());
+        transaction.clone()
+    };
+    persistence::save_transaction(&transaction);
+    publish_stopped(&transaction);
+    Some(transaction)
+}
+
+/// Mark an active transaction for operator attention.
+pub fn flag(transaction_id: i32, note: &str) -> Option<Transaction> {
+    let transaction = {
+        let mut transactions = TRANSACTIONS.write().expect("transactions lock poisoned");
+        let transaction = transactions.by_id.get_mut(&transaction_id)?;
+        transaction.flag = Some(note.to_string());
+        transaction.clone()
+    };
+    persistence::save_transaction(&transaction);
+    Some(transaction)
+}
+
+/// Replace the in-memory transactions with ones loaded from storage. New
+/// transactions get ids above every restored one, wrapping around past
+/// `i32::MAX`.
+pub fn restore(restored: Vec<Transaction>) {
+    let mut transactions = TRANSACTIONS.write().expect("transactions lock poisoned");
+    for transaction in restored {
+        transactions.next_id = transactions.next_id.max(transaction.transaction_id);
+        transactions
+            .by_id
+            .insert(transaction.transaction_id, transaction);
+    }
+}
+
+/// The main energy import register of a meter value, in Wh.
+pub fn energy_register_wh(meter_value: &MeterValue) -> Option<f64> {
+    meter_value.sampled_value.iter().find_map(|sample| {
+        let measurand = sample.measurand.clone().unwrap_or_default();
+        if measurand != Measurand::EnergyActiveImportRegister
+            || sample.phase.is_some()
+            || sample.format == Some(ValueFormat::SignedData)
+        {
+            return None;
+        }
+        let value: f64 = sample.value.trim().parse().ok()?;
+        match sample.unit.clone().unwrap_or_default() {
+            UnitOfMeasure::Wh => Some(value),
+            UnitOfMeasure::KWh => Some(value * 1000.0),
+            _ => None,
+        }
+    })
+}
+
+/// The total active power import of a meter value, in W.
+pub fn active_power_w(meter_value: &MeterValue) -> Option<f64> {
+    meter_value.sampled_value.iter().find_map(|sample| {
+        if sample.measurand != Some(Measurand::PowerActiveImport)
+            || sample.phase.is_some()
+            || sample.format == Some(ValueFormat::SignedData)
+        {
+            return None;
+        }
+        let value: f64 = sample.value.trim().parse().ok()?;
+        match sample.unit.clone().unwrap_or(UnitOfMeasure::W) {
+            UnitOfMeasure::W => Some(value),
+            UnitOfMeasure::Kw => Some(value * 1000.0),
+            _ => None,
+        }
+    })
+}
@@ -36,6 +36,14 @@ use rust_ocpp::v1_6::messages::{
 };
 use strum_macros::{Display, EnumString};
 
+use crate::security::{
+    CertificateSignedRequest, CertificateSignedResponse, GetLogRequest, GetLogResponse,
+    LogStatusNotificationRequest, LogStatusNotificationResponse, SecurityEventNotificationRequest,
+    SecurityEventNotificationResponse, SignCertificateRequest, SignCertificateResponse,
+    SignedFirmwareStatusNotificationRequest, SignedFirmwareStatusNotificationResponse,
+    SignedUpdateFirmwareRequest, SignedUpdateFirmwareResponse,
+};
+
 pub type OcppMessageTypeId = usize;
 pub type OcppMessageId = String;
 pub type OcppErrorCode = String;
@@ -75,6 +83,14 @@ pub enum OcppActionEnum {
     TriggerMessage,
     UnlockConnector,
     UpdateFirmware,
+    // Security Whitepaper extension
+    CertificateSigned,
+    GetLog,
+    LogStatusNotification,
+    SecurityEventNotification,
+    SignCertificate,
+    SignedFirmwareStatusNotification,
+    SignedUpdateFirmware,
 }
 
 #[derive(serde::Serialize, serde::Deserialize, Debug, Clone, PartialEq, Display)]
@@ -273,6 +289,55 @@ pub enum UpdateFirmwareKind {
     Response(UpdateFirmwareResponse),
 }
 
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, PartialEq, Display)]
+#[serde(untagged)]
+pub enum CertificateSignedKind {
+    Request(CertificateSignedRequest),
+    Response(CertificateSignedResponse),
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, PartialEq, Display)]
+#[serde(untagged)]
+pub enum GetLogKind {
+    Request(GetLogRequest),
+    Response(GetLogResponse),
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, PartialEq, Display)]
+#[serde(untagged)]
+pub enum LogStatusNotificationKind {
+    Request(LogStatusNotificationRequest),
+    Response(LogStatusNotificationResponse),
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, PartialEq, Display)]
+#[serde(untagged)]
+pub enum SecurityEventNotificationKind {
+    Request(SecurityEventNotificationRequest),
+    Response(SecurityEventNotificationResponse),
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, PartialEq, Display)]
+#[serde(untagged)]
+pub enum SignCertificateKind {
+    Request(SignCertificateRequest),
+    Response(SignCertificateResponse),
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, PartialEq, Display)]
+#[serde(untagged)]
+pub enum SignedFirmwareStatusNotificationKind {
+    Request(SignedFirmwareStatusNotificationRequest),
+    Response(SignedFirmwareStatusNotificationResponse),
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, PartialEq, Display)]
+#[serde(untagged)]
+pub enum SignedUpdateFirmwareKind {
+    Request(SignedUpdateFirmwareRequest),
+    Response(SignedUpdateFirmwareResponse),
+}
+
 #[derive(serde::Serialize, serde::Deserialize, Debug, Clone, PartialEq)]
 #[serde(untagged)]
 pub enum OcppPayload {
@@ -306,6 +371,121 @@ pub enum OcppPayload {
     TriggerMessage(TriggerMessageKind),                         // Server -> Charger
     UnlockConnector(UnlockConnectorKind),                       // Server -> Charger
     UpdateFirmware(UpdateFirmwareKind),                         // Server -> Charger
+    // Security Whitepaper extension
+    CertificateSigned(CertificateSignedKind),                   // Server -> Charger
+    GetLog(GetLogKind),                                         // Server -> Charger
+    LogStatusNotification(LogStatusNotificationKind),           // Charger -> Server
+    SecurityEventNotification(SecurityEventNotificationKind),   // Charger -> Server
+    SignCertificate(SignCertificateKind),                       // Charger -> Server
+    SignedFirmwareStatusNotification(SignedFirmwareStatusNotificationKind), // Charger -> Server
+    SignedUpdateFirmware(SignedUpdateFirmwareKind),             // Server -> Charger
+}
+
+impl OcppPayload {
+    /// Parse the payload of a Call carrying `action`.
+    ///
+    /// Deserializing `OcppPayload` directly picks the first untagged variant
+    /// whose fields fit, and an empty request such as ClearCache fits any
+    /// object. The action tells which request type to expect instead.
+    pub fn from_request(
+        action: &OcppActionEnum,
+        payload: serde_json::Value,
+    ) -> Result<Self, serde_json::Error> {
+        use OcppActionEnum as A;
+        use serde_json::from_value;
+
+        Ok(match action {
+            A::Authorize => Self::Authorize(AuthorizeKind::Request(from_value(payload)?)),
+            A::BootNotification => {
+                Self::BootNotification(BootNotificationKind::Request(from_value(payload)?))
+            }
+            A::CancelReservation => {
+                Self::CancelReservation(CancelReservationKind::Request(from_value(payload)?))
+            }
+            A::ChangeAvailability => {
+                Self::ChangeAvailability(ChangeAvailabilityKind::Request(from_value(payload)?))
+            }
+            A::ChangeConfiguration => {
+                Self::ChangeConfiguration(ChangeConfigurationKind::Request(from_value(payload)?))
+            }
+            A::ClearCache => Self::ClearCache(ClearCacheKind::Request(from_value(payload)?)),
+            A::ClearChargingProfile => {
+                Self::ClearChargingProfile(ClearChargingProfileKind::Request(from_value(payload)?))
+            }
+            A::DataTransfer => Self::DataTransfer(DataTransferKind::Request(from_value(payload)?)),
+            A::DiagnosticsStatusNotification => Self::DiagnosticsStatusNotification(
+                DiagnosticsStatusNotificationKind::Request(from_value(payload)?),
+            ),
+            A::FirmwareStatusNotification => Self::FirmwareStatusNotification(
+                FirmwareStatusNotificationKind::Request(from_value(payload)?),
+            ),
+            A::GetCompositeSchedule => {
+                Self::GetCompositeSchedule(GetCompositeScheduleKind::Request(from_value(payload)?))
+            }
+            A::GetConfiguration => {
+                Self::GetConfiguration(GetConfigurationKind::Request(from_value(payload)?))
+            }
+            A::GetDiagnostics => {
+                Self::GetDiagnostics(GetDiagnosticsKind::Request(from_value(payload)?))
+            }
+            A::GetLocalListVersion => {
+                Self::GetLocalListVersion(GetLocalListVersionKind::Request(from_value(payload)?))
+            }
+            A::Heartbeat => Self::Heartbeat(HeartbeatKind::Request(from_value(payload)?)),
+            A::MeterValues => Self::MeterValues(MeterValuesKind::Request(from_value(payload)?)),
+            A::RemoteStartTransaction => Self::RemoteStartTransaction(
+                RemoteStartTransactionKind::Request(from_value(payload)?),
+            ),
+            A::RemoteStopTransaction => Self::RemoteStopTransaction(
+                RemoteStopTransactionKind::Request(from_value(payload)?),
+            ),
+            A::ReserveNow => Self::ReserveNow(ReserveNowKind::Request(from_value(payload)?)),
+            A::Reset => Self::Reset(ResetKind::Request(from_value(payload)?)),
+            A::SendLocalList => {
+                Self::SendLocalList(SendLocalListKind::Request(from_value(payload)?))
+            }
+            A::SetChargingProfile => {
+                Self::SetChargingProfile(SetChargingProfileKind::Request(from_value(payload)?))
+            }
+            A::StartTransaction => {
+                Self::StartTransaction(StartTransactionKind::Request(from_value(payload)?))
+            }
+            A::StatusNotification => {
+                Self::StatusNotification(StatusNotificationKind::Request(from_value(payload)?))
+            }
+            A::StopTransaction => {
+                Self::StopTransaction(StopTransactionKind::Request(from_value(payload)?))
+            }
+            A::TriggerMessage => {
+                Self::TriggerMessage(TriggerMessageKind::Request(from_value(payload)?))
+            }
+            A::UnlockConnector => {
+                Self::UnlockConnector(UnlockConnectorKind::Request(from_value(payload)?))
+            }
+            A::UpdateFirmware => {
+                Self::UpdateFirmware(UpdateFirmwareKind::Request(from_value(payload)?))
+            }
+            A::CertificateSigned => {
+                Self::CertificateSigned(CertificateSignedKind::Request(from_value(payload)?))
+            }
+            A::GetLog => Self::GetLog(GetLogKind::Request(from_value(payload)?)),
+            A::LogStatusNotification => Self::LogStatusNotification(
+                LogStatusNotificationKind::Request(from_value(payload)?),
+            ),
+            A::SecurityEventNotification => Self::SecurityEventNotification(
+                SecurityEventNotificationKind::Request(from_value(payload)?),
+            ),
+            A::SignCertificate => {
+                Self::SignCertificate(SignCertificateKind::Request(from_value(payload)?))
+            }
+            A::SignedFirmwareStatusNotification => Self::SignedFirmwareStatusNotification(
+                SignedFirmwareStatusNotificationKind::Request(from_value(payload)?),
+            ),
+            A::SignedUpdateFirmware => {
+                Self::SignedUpdateFirmware(SignedUpdateFirmwareKind::Request(from_value(payload)?))
+            }
+        })
+    }
 }
 
 #[derive(serde::Serialize, serde::Deserialize, Debug, Clone, PartialEq)]
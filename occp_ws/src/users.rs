@@ -0,0 +1,389 @@
+//! Household users who log in to the HTTP API with a password.
+//!
+//! The owner administers the server. Members start and stop charging and see
+//! their own sessions; guests only look. Each user owns the id tags they
+//! charge with, which is how sessions are matched to people.
+//!
+//! A login hands out a random session cookie. Like API tokens, only its
+//! SHA-256 hash is kept.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::{LazyLock, RwLock},
+    time::Duration,
+};
+
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tracing::info;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::api_tokens::hash;
+use crate::auth::{hash_password, verify_password};
+use crate::persistence;
+
+const SESSION_BYTES: usize = 32;
+
+const MAX_USERNAME_LEN: usize = 64;
+
+const MIN_PASSWORD_LEN: usize = 8;
+
+/// Longest id tag OCPP 1.6 allows (`CiString20`).
+const MAX_ID_TAG_LEN: usize = 20;
+
+static USERS: LazyLock<RwLock<BTreeMap<String, User>>> =
+    LazyLock::new(|| RwLock::new(BTreeMap::new()));
+
+/// Login sessions by the hash of their cookie.
+static SESSIONS: LazyLock<RwLock<HashMap<String, LoginSession>>> =
+    LazyLock::new(|| RwLock::new(HashMap::new()));
+
+/// Checked against when a username is unknown, so a login takes as long
+/// whether or not the user exists.
+static DUMMY_HASH: LazyLock<String> =
+    LazyLock::new(|| hash_password("not a real password").unwrap_or_default());
+
+#[derive(Serialize, Deserialize, ToSchema, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Role {
+    /// Manages users, tokens, webhooks, registrations and the chargers.
+    Owner,
+    /// Starts and stops charging with their own id tags.
+    Member,
+    /// Sees the chargers and their own sessions.
+    Guest,
+}
+
+#[derive(Serialize, ToSchema, Debug, Clone, PartialEq)]
+pub struct User {
+    pub user_id: String,
+    pub username: String,
+    #[serde(skip)]
+    pub password_hash: String,
+    pub role: Role,
+    /// Id tags the user charges with, ordered.
+    pub id_tags: Vec<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl User {
+    pub fn owns_id_tag(&self, id_tag: &str) -> bool {
+        self.id_tags.iter().any(|tag| tag == id_tag)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LoginSession {
+    /// Hex SHA-256 of the session cookie.
+    pub session_hash: String,
+    pub user_id: String,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+}
+
+/// Changes to a user; fields left `None` are kept.
+#[derive(Deserialize, ToSchema, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub struct UserUpdate {
+    pub role: Option<Role>,
+    pub id_tags: Option<Vec<String>>,
+    pub password: Option<String>,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum UserError {
+    #[error("{0}")]
+    Invalid(String),
+    #[error("user {0} not found")]
+    NotFound(String),
+    #[error("{0}")]
+    Conflict(String),
+}
+
+pub async fn create(
+    username: &str,
+    password: &str,
+    role: Role,
+    id_tags: Vec<String>,
+) -> Result<User, UserError> {
+    let username = valid_username(username)?;
+    let id_tags = valid_id_tags(id_tags)?;
+    let password_hash = hash_new_password(password).await?;
+    let user = {
+        let mut users = USERS.write().expect("users lock poisoned");
+        if users.values().any(|user| user.username == username) {
+            return Err(UserError::Conflict(format!(
+                "username {username} is already taken"
+            )));
+        }
+        ensure_unowned(&users, "", &id_tags)?;
+        let user = User {
+            user_id: Uuid::new_v4().to_string(),
+            username,
+            password_hash,
+            role,
+            id_tags,
+            created_at: Utc::now(),
+        };
+        users.insert(user.user_id.clone(), user.clone());
+        user
+    };
+    persistence::save_user(&user);
+    info!(
+        user_id = user.user_id,
+        username = user.username,
+        "User created"
+    );
+    Ok(user)
+}
+
+/// Every user, ordered by id.
+pub fn list() -> Vec<User> {
+    USERS
+        .read()
+        .expect("users lock poisoned")
+        .values()
+        .cloned()
+        .collect()
+}
+
+pub fn get(user_id: &str) -> Option<User> {
+    USERS
+        .read()
+        .expect("users lock poisoned")
+        .get(user_id)
+        .cloned()
+}
+
+/// Apply `update`. A new password ends the user's logins.
+pub async fn update(user_id: &str, update: UserUpdate) -> Result<User, UserError> {
+    let id_tags = update.id_tags.map(valid_id_tags).transpose()?;
+    let password_hash = match &update.password {
+        Some(password) => Some(hash_new_password(password).await?),
+        None => None,
+    };
+    let user = {
+        let mut users = USERS.write().expect("users lock poisoned");
+        if !users.contains_key(user_id) {
+            return Err(UserError::NotFound(user_id.to_string()));
+        }
+        if let Some(role) = update.role
+            && role != Role::Owner
+        {
+            ensure_other_owner(&users, user_id)?;
+        }
+        if let Some(id_tags) = &id_tags {
+            ensure_unowned(&users, user_id, id_tags)?;
+        }
+        let user = users.get_mut(user_id).expect("user checked above");
+        if let Some(role) = update.role {
+            user.role = role;
+        }
+        if let Some(id_tags) = id_tags {
+            user.id_tags = id_tags;
+        }
+        if let Some(password_hash) = &password_hash {
+            user.password_hash.clone_from(password_hash);
+        }
+        user.clone()
+    };
+    persistence::save_user(&user);
+    if password_hash.is_some() {
+        end_sessions(user_id);
+    }
+    info!(user_id, username = user.username, "User updated");
+    Ok(user)
+}
+
+/// Remove a user and end their logins. The last owner cannot be removed.
+pub fn delete(user_id: &str) -> Result<User, UserError> {
+    let user = {
+        let mut users = USERS.write().expect("users lock poisoned");
+        if !users.contains_key(user_id) {
+            return Err(UserError::NotFound(user_id.to_string()));
+        }
+        ensure_other_owner(&users, user_id)?;
+        users.remove(user_id).expect("user checked above")
+    };
+    SESSIONS
+        .write()
+        .expect("login sessions lock poisoned")
+        .retain(|_, session| session.user_id != user_id);
+    persistence::delete_user(user_id);
+    info!(user_id, username = user.username, "User deleted");
+    Ok(user)
+}
+
+/// Check a username and password. Returns the user and a new session
+/// cookie valid for `ttl`.
+pub async fn login(username: &str, password: &str, ttl: Duration) -> Option<(User, String)> {
+    let username = username.trim().to_lowercase();
+    let user = USERS
+        .read()
+        .expect("users lock poisoned")
+        .values()
+        .find(|user| user.username == username)
+        .cloned();
+    let stored = user
+        .as_ref()
+        .map_or_else(|| DUMMY_HASH.clone(), |user| user.password_hash.clone());
+    let password = password.to_string();
+    let valid = tokio::task::spawn_blocking(move || verify_password(&password, &stored))
+        .await
+        .unwrap_or(false);
+    let user = user.filter(|_| valid)?;
+
+    let mut bytes = [0u8; SESSION_BYTES];
+    OsRng.fill_bytes(&mut bytes);
+    let cookie: String = bytes.iter().map(|b| format!("{b:02x}")).collect();
+    let now = Utc::now();
+    let session = LoginSession {
+        session_hash: hash(&cookie),
+        user_id: user.user_id.clone(),
+        created_at: now,
+        expires_at: now + ttl,
+    };
+    SESSIONS
+        .write()
+        .expect("login sessions lock poisoned")
+        .insert(session.session_hash.clone(), session.clone());
+    persistence::save_login_session(&session);
+    info!(
+        user_id = user.user_id,
+        username = user.username,
+        "User logged in"
+    );
+    Some((user, cookie))
+}
+
+/// End the login behind a session cookie.
+pub fn logout(cookie: &str) {
+    let session_hash = hash(cookie);
+    let removed = SESSIONS
+        .write()
+        .expect("login sessions lock poisoned")
+        .remove(&session_hash);
+    if removed.is_some() {
+        persistence::delete_login_session(&session_hash);
+    }
+}
+
+/// The user logged in with a session cookie, unless the login expired.
+pub fn authenticate(cookie: &str) -> Option<User> {
+    let session_hash = hash(cookie);
+    let session = SESSIONS
+        .read()
+        .expect("login sessions lock poisoned")
+        .get(&session_hash)
+        .cloned()?;
+    if session.expires_at <= Utc::now() {
+        logout(cookie);
+        return None;
+    }
+    get(&session.user_id)
+}
+
+/// Replace users and login sessions with ones loaded from storage.
+pub fn restore(users: Vec<User>, sessions: Vec<LoginSession>) {
+    *USERS.write().expect("users lock poisoned") = users
+        .into_iter()
+        .map(|user| (user.user_id.clone(), user))
+        .collect();
+    *SESSIONS.write().expect("login sessions lock poisoned") = sessions
+        .into_iter()
+        .map(|session| (session.session_hash.clone(), session))
+        .collect();
+}
+
+fn end_sessions(user_id: &str) {
+    let ended: Vec<String> = {
+        let mut sessions = SESSIONS.write().expect("login sessions lock poisoned");
+        let ended = sessions
+            .values()
+            .filter(|session| session.user_id == user_id)
+            .map(|session| session.session_hash.clone())
+            .collect::<Vec<_>>();
+        for session_hash in &ended {
+            sessions.remove(session_hash);
+        }
+        ended
+    };
+    for session_hash in &ended {
+        persistence::delete_login_session(session_hash);
+    }
+}
+
+async fn hash_new_password(password: &str) -> Result<String, UserError> {
+    if password.chars().count() < MIN_PASSWORD_LEN {
+        return Err(UserError::Invalid(format!(
+            "password must be at least {MIN_PASSWORD_LEN} characters"
+        )));
+    }
+    let password = password.to_string();
+    tokio::task::spawn_blocking(move || hash_password(&password))
+        .await
+        .map_err(|err| UserError::Invalid(err.to_string()))?
+        .map_err(|err| UserError::Invalid(err.to_string()))
+}
+
+/// Usernames are compared in lower case.
+fn valid_username(username: &str) -> Result<String, UserError> {
+    let username = username.trim().to_lowercase();
+    let allowed = |c: char| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-');
+    if username.is_empty() || username.len() > MAX_USERNAME_LEN || !username.chars().all(allowed) {
+        return Err(UserError::Invalid(format!(
+            "username must be 1 to {MAX_USERNAME_LEN} letters, digits, '.', '_' or '-'"
+        )));
+    }
+    Ok(username)
+}
+
+fn valid_id_tags(id_tags: Vec<String>) -> Result<Vec<String>, UserError> {
+    let mut id_tags: Vec<String> = id_tags
+        .iter()
+        .map(|tag| tag.trim().to_string())
+        .filter(|tag| !tag.is_empty())
+        .collect();
+    if let Some(tag) = id_tags.iter().find(|tag| tag.len() > MAX_ID_TAG_LEN) {
+        return Err(UserError::Invalid(format!(
+            "id tag {tag:?} is longer than {MAX_ID_TAG_LEN} characters"
+        )));
+    }
+    id_tags.sort();
+    id_tags.dedup();
+    Ok(id_tags)
+}
+
+fn ensure_unowned(
+    users: &BTreeMap<String, User>,
+    user_id: &str,
+    id_tags: &[String],
+) -> Result<(), UserError> {
+    for other in users.values().filter(|other| other.user_id != user_id) {
+        if let Some(tag) = id_tags.iter().find(|tag| other.owns_id_tag(tag)) {
+            return Err(UserError::Conflict(format!(
+                "id tag {tag} belongs to {}",
+                other.username
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Refuse to leave the server without an owner.
+fn ensure_other_owner(users: &BTreeMap<String, User>, user_id: &str) -> Result<(), UserError> {
+    let is_owner = users
+        .get(user_id)
+        .is_some_and(|user| user.role == Role::Owner);
+    let other_owner = users
+        .values()
+        .any(|user| user.user_id != user_id && user.role == Role::Owner);
+    if is_owner && !other_owner {
+        return Err(UserError::Conflict(
+            "the last owner cannot be removed or demoted".to_string(),
+        ));
+    }
+    Ok(())
+}
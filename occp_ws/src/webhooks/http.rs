@@ -0,0 +1,125 @@
+//! A small HTTP/1.1 client for webhook requests, over TLS for `https` URLs.
+
+use std::sync::{Arc, LazyLock};
+
+use bytes::Bytes;
+use http_body_util::Full;
+use hyper::{
+    Request, StatusCode, Uri,
+    header::{CONTENT_TYPE, HOST, USER_AGENT},
+};
+use hyper_util::rt::TokioIo;
+use rustls::{ClientConfig, RootCertStore, pki_types::ServerName};
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    net::TcpStream,
+};
+use tokio_rustls::TlsConnector;
+use tracing::debug;
+
+use crate::tls::install_crypto_provider;
+
+static TLS: LazyLock<TlsConnector> = LazyLock::new(|| {
+    install_crypto_provider();
+    let roots = RootCertStore {
+        roots: webpki_roots::TLS_SERVER_ROOTS.to_vec(),
+    };
+    let config = ClientConfig::builder()
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+    TlsConnector::from(Arc::new(config))
+});
+
+#[derive(Debug, thiserror::Error)]
+pub enum PostError {
+    #[error("invalid URL {0:?}")]
+    Url(String),
+    #[error("connection failed: {0}")]
+    Connect(std::io::Error),
+    #[error("TLS handshake failed: {0}")]
+    Tls(std::io::Error),
+    #[error("HTTP error: {0}")]
+    Http(#[from] hyper::Error),
+}
+
+/// Whether `url` is an absolute `http` or `https` URL with a host.
+pub fn is_valid_url(url: &str) -> bool {
+    target(url).is_ok()
+}
+
+/// POST a JSON body and return the response status. The response body is
+/// not read.
+pub(super) async fn post(
+    url: &str,
+    headers: &[(&'static str, String)],
+    body: String,
+) -> Result<StatusCode, PostError> {
+    let (uri, https, host, port) = target(url)?;
+    let path = uri
+        .path_and_query()
+        .map_or("/", |path_and_query| path_and_query.as_str());
+    let mut request = Request::post(path)
+        .header(HOST, uri.authority().map_or(host.as_str(), |a| a.as_str()))
+        .header(CONTENT_TYPE, "application/json")
+        .header(
+            USER_AGENT,
+            concat!("PlugHome-server/", env!("CARGO_PKG_VERSION")),
+        );
+    for (name, value) in headers {
+        request = request.header(*name, value);
+    }
+    let request = request
+        .body(Full::new(Bytes::from(body)))
+        .map_err(|_| PostError::Url(url.to_string()))?;
+
+    let stream = TcpStream::connect((host.as_str(), port))
+        .await
+        .map_err(PostError::Connect)?;
+    if https {
+        let server_name =
+            ServerName::try_from(host.clone()).map_err(|_| PostError::Url(url.to_string()))?;
+        let stream = TLS
+            .connect(server_name, stream)
+            .await
+            .map_err(PostError::Tls)?;
+        send(stream, request).await
+    } else {
+        send(stream, request).await
+    }
+}
+
+async fn send<S>(stream: S, request: Request<Full<Bytes>>) -> Result<StatusCode, PostError>
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let (mut sender, connection) =
+        hyper::client::conn::http1::handshake(TokioIo::new(stream)).await?;
+    tokio::spawn(async move {
+        if let Err(err) = connection.await {
+            debug!("Webhook connection closed: {err}");
+        }
+    });
+    let response = sender.send_request(request).await?;
+    Ok(response.status())
+}
+
+/// The parsed URL, whether it uses TLS, and the host and port to dial.
+fn target(url: &str) -> Result<(Uri, bool, String, u16), PostError> {
+    let invalid = || PostError::Url(url.to_string());
+    let uri: Uri = url.parse().map_err(|_| invalid())?;
+    let https = match uri.scheme_str() {
+        Some("https") => true,
+        Some("http") => false,
+        _ => return Err(invalid()),
+    };
+    let host = uri
+        .host()
+        .filter(|host| !host.is_empty())
+        .ok_or_else(invalid)?
+        // IPv6 literals come bracketed.
+        .trim_start_matches('[')
+        .trim_end_matches(']')
+        .to_string();
+    let port = uri.port_u16().unwrap_or(if https { 443 } else { 80 });
+    Ok((uri, https, host, port))
+}
@@ -0,0 +1,524 @@
+//! Outgoing webhooks: signed HTTP notifications of important events.
+//!
+//! A subscription names a URL, the events it wants and a secret. Matching
+//! bus events become deliveries, which are stored before the first attempt
+//! so that a restart picks them up again. Failed attempts are retried with
+//! exponential backoff until `WEBHOOK_MAX_ATTEMPTS`; each subscription keeps
+//! its most recent deliveries as a log.
+//!
+//! Every delivery is a JSON `POST` with these headers:
+//!
+//! - `X-PlugHome-Event`: the event name
+//! - `X-PlugHome-Delivery`: the delivery id, the same on every retry
+//! - `X-PlugHome-Timestamp`: Unix time of the attempt
+//! - `X-PlugHome-Signature`: `sha256=` and the hex HMAC-SHA256 of
+//!   `{timestamp}.{body}`, keyed with the secret
+
+use std::{
+    collections::{BTreeMap, HashMap, VecDeque},
+    sync::{LazyLock, Mutex, RwLock},
+    time::Duration,
+};
+
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use chrono::{DateTime, Utc};
+use common::WebhookConfig;
+use futures::StreamExt;
+use ring::hmac;
+use rust_ocpp::v1_6::types::ChargePointStatus;
+use serde::{Serialize, Serializer};
+use serde_json::json;
+use tokio::{sync::Notify, task::JoinHandle};
+use tracing::{debug, info, warn};
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::events::{self, EventFilter, EventRecord, StationEvent};
+use crate::persistence;
+use crate::state::load_webhook_config;
+
+mod http;
+
+pub use http::is_valid_url;
+
+/// Events a subscription can receive.
+pub const WEBHOOK_EVENTS: [&str; 4] = [
+    "session_finished",
+    "connector_faulted",
+    "station_offline",
+    "firmware_failed",
+];
+
+/// Finished deliveries kept per subscription; pending ones are always kept.
+pub const DELIVERY_LOG_CAPACITY: usize = 50;
+
+/// Random bytes in a generated secret.
+const SECRET_BYTES: usize = 32;
+
+const MAX_SECRET_LEN: usize = 256;
+
+/// How long the worker sleeps with nothing queued, unless woken.
+const IDLE_WAIT: Duration = Duration::from_secs(60);
+
+static WEBHOOKS: LazyLock<RwLock<BTreeMap<String, Webhook>>> =
+    LazyLock::new(|| RwLock::new(BTreeMap::new()));
+/// Deliveries per subscription, oldest first.
+static DELIVERIES: LazyLock<Mutex<HashMap<String, VecDeque<Delivery>>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+/// Wakes the worker when deliveries are queued.
+static QUEUED: LazyLock<Notify> = LazyLock::new(Notify::new);
+
+#[derive(Serialize, ToSchema, Debug, Clone, PartialEq)]
+pub struct Webhook {
+    pub webhook_id: String,
+    pub url: String,
+    /// Names from [`WEBHOOK_EVENTS`].
+    pub events: Vec<String>,
+    /// Only shown when the subscription is created.
+    #[serde(skip)]
+    pub secret: String,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Serialize, ToSchema, Debug, Clone, PartialEq)]
+pub struct Delivery {
+    pub delivery_id: String,
+    pub webhook_id: String,
+    pub event: String,
+    /// The request body.
+    #[serde(serialize_with = "json_text")]
+    #[schema(value_type = Object)]
+    pub payload: String,
+    pub status: DeliveryStatus,
+    pub attempts: u32,
+    pub next_attempt_at: Option<DateTime<Utc>>,
+    pub last_attempt_at: Option<DateTime<Utc>>,
+    /// HTTP status of the last attempt, if the endpoint answered.
+    pub response_status: Option<u16>,
+    pub last_error: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Serialize, ToSchema, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum DeliveryStatus {
+    /// Not sent yet, or waiting for a retry.
+    Pending,
+    /// The endpoint answered with a 2xx status.
+    Delivered,
+    /// Every attempt failed.
+    Failed,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum WebhookError {
+    #[error("{0}")]
+    Invalid(String),
+}
+
+/// Add a subscription. Without `events` it receives all of them; without a
+/// secret one is generated.
+pub fn create(
+    url: &str,
+    events: Option<Vec<String>>,
+    secret: Option<String>,
+) -> Result<Webhook, WebhookError> {
+    let url = url.trim();
+    if !is_valid_url(url) {
+        return Err(WebhookError::Invalid(format!(
+            "url must be an absolute http or https URL, got {url:?}"
+        )));
+    }
+    let events = match events {
+        None => WEBHOOK_EVENTS.map(str::to_string).to_vec(),
+        Some(events) if events.is_empty() => {
+            return Err(WebhookError::Invalid(
+                "events must name at least one event".to_string(),
+            ));
+        }
+        Some(events) => {
+            if let Some(unknown) = events
+                .iter()
+                .find(|event| !WEBHOOK_EVENTS.contains(&event.as_str()))
+            {
+                return Err(WebhookError::Invalid(format!(
+                    "unknown webhook event {unknown:?}, expected one of {}",
+                    WEBHOOK_EVENTS.join(", ")
+                )));
+            }
+            // In the canonical order, without duplicates.
+            WEBHOOK_EVENTS
+                .iter()
+                .filter(|known| events.iter().any(|event| event == *known))
+                .map(|event| event.to_string())
+                .collect()
+        }
+    };
+    let secret = match secret {
+        Some(secret) if secret.is_empty() || secret.len() > MAX_SECRET_LEN => {
+            return Err(WebhookError::Invalid(format!(
+                "secret must be 1 to {MAX_SECRET_LEN} bytes"
+            )));
+        }
+        Some(secret) => secret,
+        None => generate_secret(),
+    };
+    let webhook = Webhook {
+        webhook_id: Uuid::new_v4().to_string(),
+        url: url.to_string(),
+        events,
+        secret,
+        created_at: Utc::now(),
+    };
+    WEBHOOKS
+        .write()
+        .expect("webhooks lock poisoned")
+        .insert(webhook.webhook_id.clone(), webhook.clone());
+    persistence::save_webhook(&webhook);
+    info!(
+        webhook_id = webhook.webhook_id,
+        url, "Webhook subscription added"
+    );
+    Ok(webhook)
+}
+
+/// Every subscription, ordered by id.
+pub fn list() -> Vec<Webhook> {
+    WEBHOOKS
+        .read()
+        .expect("webhooks lock poisoned")
+        .values()
+        .cloned()
+        .collect()
+}
+
+pub fn get(webhook_id: &str) -> Option<Webhook> {
+    WEBHOOKS
+        .read()
+        .expect("webhooks lock poisoned")
+        .get(webhook_id)
+        .cloned()
+}
+
+/// Remove a subscription and drop its queued deliveries. Returns whether it
+/// existed.
+pub fn delete(webhook_id: &str) -> bool {
+    let removed = WEBHOOKS
+        .write()
+        .expect("webhooks lock poisoned")
+        .remove(webhook_id)
+        .is_some();
+    if removed {
+        DELIVERIES
+            .lock()
+            .expect("webhook deliveries lock poisoned")
+            .remove(webhook_id);
+        persistence::delete_webhook(webhook_id);
+        info!(webhook_id, "Webhook subscription removed");
+    }
+    removed
+}
+
+/// A subscription's recent deliveries, newest first.
+pub fn deliveries(webhook_id: &str) -> Option<Vec<Delivery>> {
+    get(webhook_id)?;
+    let deliveries = DELIVERIES.lock().expect("webhook deliveries lock poisoned");
+    Some(
+        deliveries
+            .get(webhook_id)
+            .map(|log| log.iter().rev().cloned().collect())
+            .unwrap_or_default(),
+    )
+}
+
+/// Replace subscriptions and deliveries with ones loaded from storage.
+pub fn restore(webhooks: Vec<Webhook>, restored: Vec<Delivery>) {
+    *WEBHOOKS.write().expect("webhooks lock poisoned") = webhooks
+        .into_iter()
+        .map(|webhook| (webhook.webhook_id.clone(), webhook))
+        .collect();
+    let mut deliveries = DELIVERIES.lock().expect("webhook deliveries lock poisoned");
+    deliveries.clear();
+    for delivery in restored {
+        log_delivery(&mut deliveries, delivery);
+    }
+    drop(deliveries);
+    QUEUED.notify_one();
+}
+
+/// The `X-PlugHome-Signature` value for a body sent at `timestamp`.
+pub fn signature(secret: &str, timestamp: i64, body: &str) -> String {
+    let key = hmac::Key::new(hmac::HMAC_SHA256, secret.as_bytes());
+    let mut context = hmac::Context::with_key(&key);
+    context.update(timestamp.to_string().as_bytes());
+    context.update(b".");
+    context.update(body.as_bytes());
+    let tag = context.sign();
+    let hex: String = tag.as_ref().iter().map(|b| format!("{b:02x}")).collect();
+    format!("sha256={hex}")
+}
+
+/// Queue deliveries for matching bus events and send them until the process
+/// exits. Call once, after restoring saved state.
+pub fn spawn() -> JoinHandle<()> {
+    tokio::spawn(async {
+        let config = load_webhook_config().await;
+        tokio::join!(dispatch(), deliver(config));
+    })
+}
+
+async fn dispatch() {
+    let records = events::stream(EventFilter::default(), None);
+    futures::pin_mut!(records);
+    while let Some(record) = records.next().await {
+        if let Some(event) = webhook_event(&record.event) {
+            enqueue(event, &record);
+        }
+    }
+}
+
+/// The webhook event a bus event triggers, if any.
+fn webhook_event(event: &StationEvent) -> Option<&'static str> {
+    match event {
+        StationEvent::SessionStopped { .. } => Some("session_finished"),
+        StationEvent::ConnectorStatus {
+            status: ChargePointStatus::Faulted,
+            ..
+        } => Some("connector_faulted"),
+        StationEvent::Offline { .. } => Some("station_offline"),
+        StationEvent::FirmwareStatus { failed: true, .. } => Some("firmware_failed"),
+        _ => None,
+    }
+}
+
+fn enqueue(event: &str, record: &EventRecord) {
+    let subscribers: Vec<String> = WEBHOOKS
+        .read()
+        .expect("webhooks lock poisoned")
+        .values()
+        .filter(|webhook| webhook.events.iter().any(|wanted| wanted == event))
+        .map(|webhook| webhook.webhook_id.clone())
+        .collect();
+    if subscribers.is_empty() {
+        return;
+    }
+    let now = Utc::now();
+    for webhook_id in subscribers {
+        let delivery_id = Uuid::new_v4().to_string();
+        let payload = json!({
+            "delivery_id": delivery_id,
+            "event": event,
+            "created_at": now,
+            "data": record,
+        });
+        let delivery = Delivery {
+            delivery_id,
+            webhook_id,
+            event: event.to_string(),
+            payload: payload.to_string(),
+            status: DeliveryStatus::Pending,
+            attempts: 0,
+            next_attempt_at: Some(now),
+            last_attempt_at: None,
+            response_status: None,
+            last_error: None,
+            created_at: now,
+        };
+        debug!(
+            delivery_id = delivery.delivery_id,
+            webhook_id = delivery.webhook_id,
+            event,
+            "Webhook delivery queued"
+        );
+        persistence::save_webhook_delivery(&delivery);
+        log_delivery(
+            &mut DELIVERIES.lock().expect("webhook deliveries lock poisoned"),
+            delivery,
+        );
+    }
+    QUEUED.notify_one();
+}
+
+/// Insert or update a delivery in its subscription's log, dropping the
+/// oldest finished ones beyond the capacity here and in storage.
+fn log_delivery(deliveries: &mut HashMap<String, VecDeque<Delivery>>, delivery: Delivery) {
+    let webhook_id = delivery.webhook_id.clone();
+    let log = deliveries.entry(webhook_id.clone()).or_default();
+    match log
+        .iter_mut()
+        .find(|logged| logged.delivery_id == delivery.delivery_id)
+    {
+        Some(logged) => *logged = delivery,
+        None => log.push_back(delivery),
+    }
+    let mut finished = log
+        .iter()
+        .filter(|logged| logged.status != DeliveryStatus::Pending)
+        .count();
+    if finished > DELIVERY_LOG_CAPACITY {
+        persistence::prune_webhook_deliveries(webhook_id, DELIVERY_LOG_CAPACITY);
+    }
+    while finished > DELIVERY_LOG_CAPACITY {
+        if let Some(oldest) = log
+            .iter()
+            .position(|logged| logged.status != DeliveryStatus::Pending)
+        {
+            log.remove(oldest);
+        }
+        finished -= 1;
+    }
+}
+
+async fn deliver(config: &'static WebhookConfig) {
+    loop {
+        let now = Utc::now();
+        let (due, next_at) = due(now);
+        if !due.is_empty() {
+            futures::future::join_all(
+                due.into_iter()
+                    .map(|(webhook, delivery)| attempt(webhook, delivery, config)),
+            )
+            .await;
+            continue;
+        }
+        let wait = next_at.map_or(IDLE_WAIT, |at| (at - now).to_std().unwrap_or_default());
+        tokio::select! {
+            _ = QUEUED.notified() => {}
+            _ = tokio::time::sleep(wait) => {}
+        }
+    }
+}
+
+/// Pending deliveries whose time has come, with their subscription, and
+/// when the next one is due.
+fn due(now: DateTime<Utc>) -> (Vec<(Webhook, Delivery)>, Option<DateTime<Utc>>) {
+    let webhooks = WEBHOOKS.read().expect("webhooks lock poisoned");
+    let deliveries = DELIVERIES.lock().expect("webhook deliveries lock poisoned");
+    let mut due = Vec::new();
+    let mut next_at: Option<DateTime<Utc>> = None;
+    for (webhook_id, log) in deliveries.iter() {
+        let Some(webhook) = webhooks.get(webhook_id) else {
+            continue;
+        };
+        for delivery in log
+            .iter()
+            .filter(|delivery| delivery.status == DeliveryStatus::Pending)
+        {
+            let at = delivery.next_attempt_at.unwrap_or(now);
+            if at <= now {
+                due.push((webhook.clone(), delivery.clone()));
+            } else if next_at.is_none_or(|next_at| at < next_at) {
+                next_at = Some(at);
+            }
+        }
+    }
+    (due, next_at)
+}
+
+async fn attempt(webhook: Webhook, mut delivery: Delivery, config: &WebhookConfig) {
+    let now = Utc::now();
+    let timestamp = now.timestamp();
+    let headers = [
+        ("x-plughome-event", delivery.event.clone()),
+        ("x-plughome-delivery", delivery.delivery_id.clone()),
+        ("x-plughome-timestamp", timestamp.to_string()),
+        (
+            "x-plughome-signature",
+            signature(&webhook.secret, timestamp, &delivery.payload),
+        ),
+    ];
+    let result = tokio::time::timeout(
+        config.timeout,
+        http::post(&webhook.url, &headers, delivery.payload.clone()),
+    )
+    .await;
+
+    delivery.attempts += 1;
+    delivery.last_attempt_at = Some(now);
+    let error = match result {
+        Ok(Ok(status)) => {
+            delivery.response_status = Some(status.as_u16());
+            (!status.is_success()).then(|| format!("endpoint answered {status}"))
+        }
+        Ok(Err(err)) => {
+            delivery.response_status = None;
+            Some(err.to_string())
+        }
+        Err(_) => {
+            delivery.response_status = None;
+            Some(format!(
+                "no answer within {}s",
+                config.timeout.as_secs_f64()
+            ))
+        }
+    };
+    match error {
+        None => {
+            delivery.status = DeliveryStatus::Delivered;
+            delivery.next_attempt_at = None;
+            delivery.last_error = None;
+            debug!(
+                delivery_id = delivery.delivery_id,
+                webhook_id = webhook.webhook_id,
+                "Webhook delivered"
+            );
+        }
+        Some(error) if delivery.attempts >= config.max_attempts => {
+            warn!(
+                delivery_id = delivery.delivery_id,
+                webhook_id = webhook.webhook_id,
+                attempts = delivery.attempts,
+                "Webhook delivery failed for good: {error}"
+            );
+            delivery.status = DeliveryStatus::Failed;
+            delivery.next_attempt_at = None;
+            delivery.last_error = Some(error);
+        }
+        Some(error) => {
+            let delay = backoff(config, delivery.attempts);
+            debug!(
+                delivery_id = delivery.delivery_id,
+                webhook_id = webhook.webhook_id,
+                attempts = delivery.attempts,
+                "Webhook delivery failed, retrying in {delay:?}: {error}"
+            );
+            delivery.next_attempt_at =
+                Some(now + chrono::Duration::from_std(delay).unwrap_or(chrono::Duration::MAX));
+            delivery.last_error = Some(error);
+        }
+    }
+
+    // The subscription may have been removed meanwhile.
+    let webhooks = WEBHOOKS.read().expect("webhooks lock poisoned");
+    if webhooks.contains_key(&webhook.webhook_id) {
+        persistence::save_webhook_delivery(&delivery);
+        log_delivery(
+            &mut DELIVERIES.lock().expect("webhook deliveries lock poisoned"),
+            delivery,
+        );
+    }
+}
+
+/// Wait after `attempts` failed attempts: the initial backoff, doubled per
+/// further attempt, capped at the maximum.
+fn backoff(config: &WebhookConfig, attempts: u32) -> Duration {
+    let doublings = attempts.saturating_sub(1).min(31);
+    config
+        .initial_backoff
+        .saturating_mul(1 << doublings)
+        .min(config.max_backoff)
+}
+
+fn generate_secret() -> String {
+    let mut bytes = [0u8; SECRET_BYTES];
+    OsRng.fill_bytes(&mut bytes);
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+/// Serialize stored JSON text as JSON rather than as a string.
+fn json_text<S: Serializer>(text: &str, serializer: S) -> Result<S::Ok, S::Error> {
+    match serde_json::from_str::<serde_json::Value>(text) {
+        Ok(value) => value.serialize(serializer),
+        Err(_) => serializer.serialize_str(text),
+    }
+}
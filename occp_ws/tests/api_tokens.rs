@@ -0,0 +1,219 @@
+use std::error::Error;
+
+use axum::{
+    body::Body,
+    http::{Method, Request, StatusCode, header},
+};
+use occp_ws::api_tokens::{self, Scope};
+use occp_ws::{persistence, rest};
+use serde_json::{Value, json};
+use storage::MemoryStore;
+use tower::ServiceExt;
+
+/// Send a request through the authenticated v1 API and return its status,
+/// `WWW-Authenticate` header and JSON body.
+async fn request(
+    method: Method,
+    uri: &str,
+    token: Option<&str>,
+    body: Option<Value>,
+) -> Result<(StatusCode, Option<String>, Value), Box<dyn Error>> {
+    let mut builder = Request::builder().method(method).uri(uri);
+    if let Some(token) = token {
+        builder = builder.header(header::AUTHORIZATION, format!("Bearer {token}"));
+    }
+    let body = match body {
+        Some(body) => {
+            builder = builder.header(header::CONTENT_TYPE, "application/json");
+            Body::from(body.to_string())
+        }
+        None => Body::empty(),
+    };
+    let response = rest::v1_router_with_auth()
+        .oneshot(builder.body(body)?)
+        .await?;
+    let status = response.status();
+    let challenge = response
+        .headers()
+        .get(header::WWW_AUTHENTICATE)
+        .map(|value| value.to_str().unwrap_or_default().to_string());
+    let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await?;
+    let body = if bytes.is_empty() {
+        Value::Null
+    } else {
+        serde_json::from_slice(&bytes)?
+    };
+    Ok((status, challenge, body))
+}
+
+#[tokio::test]
+async fn scoped_tokens_guard_the_api() -> Result<(), Box<dyn Error>> {
+    persistence::install(MemoryStore::new());
+    let (setup, admin) = api_tokens::create("setup", vec![Scope::Admin], None)?;
+
+    let (status, challenge, body) = request(Method::GET, "/stations", None, None).await?;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    assert_eq!(challenge.as_deref(), Some("Bearer"));
+    assert_eq!(body["error"]["code"], "unauthorized");
+    let (status, _, _) = request(Method::GET, "/stations", Some("ph_nope"), None).await?;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    // Admins issue tokens; the token is only in the create response.
+    let (status, _, created) = request(
+        Method::POST,
+        "/tokens",
+        Some(&admin),
+        Some(json!({"name": "dashboard", "scopes": ["read"]})),
+    )
+    .await?;
+    assert_eq!(status, StatusCode::CREATED);
+    let reader = created["token"].as_str().expect("token text").to_string();
+    assert!(reader.starts_with(api_tokens::TOKEN_PREFIX));
+    assert_eq!(created["prefix"], reader[..10]);
+    let reader_id = created["token_id"].as_str().expect("token id").to_string();
+
+    let (status, _, listed) = request(Method::GET, "/tokens", Some(&admin), None).await?;
+    assert_eq!(status, StatusCode::OK);
+    let listed_reader = listed
+        .as_array()
+        .expect("token list")
+        .iter()
+        .find(|token| token["token_id"] == reader_id.as_str())
+        .expect("reader listed");
+    assert!(listed_reader.get("token").is_none());
+    assert!(listed_reader.get("token_hash").is_none());
+    assert_eq!(listed_reader["scopes"], json!(["read"]));
+
+    // A read token reads but neither controls nor administers.
+    let (status, _, _) = request(Method::GET, "/stations", Some(&reader), None).await?;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _, body) = request(
+        Method::POST,
+        "/stations/TOK-1/reset",
+        Some(&reader),
+        Some(json!({"reset_type": "Soft"})),
+    )
+    .await?;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    assert_eq!(body["error"]["code"], "forbidden");
+    let (status, _, _) = request(Method::GET, "/tokens", Some(&reader), None).await?;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    let (status, _, body) = request(
+        Method::POST,
+        "/tokens",
+        Some(&admin),
+        Some(json!({"name": "", "scopes": ["read"]})),
+    )
+    .await?;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(body["error"]["code"], "validation_failed");
+
+    // Usage is recorded.
+    let reader_token = api_tokens::get(&reader_id).expect("reader token");
+    assert_eq!(reader_token.use_count, 3);
+    assert!(reader_token.last_used_at.is_some());
+
+    // Revoked tokens are refused but stay listed.
+    let (status, _, _) = request(
+        Method::DELETE,
+        &format!("/tokens/{reader_id}"),
+        Some(&admin),
+        None,
+    )
+    .await?;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+    let (status, _, _) = request(Method::GET, "/stations", Some(&reader), None).await?;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let (status, _, shown) = request(
+        Method::GET,
+        &format!("/tokens/{reader_id}"),
+        Some(&admin),
+        None,
+    )
+    .await?;
+    assert_eq!(status, StatusCode::OK);
+    assert!(shown["revoked_at"].is_string());
+    let (status, _, _) = request(Method::DELETE, "/tokens/missing", Some(&admin), None).await?;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    // Only hashes reach storage.
+    let rows = persistence::store().expect("store").api_tokens()?;
+    let row = rows
+        .iter()
+        .find(|row| row.token_id == reader_id)
+        .expect("reader saved");
+    assert_ne!(row.token_hash, reader);
+    assert!(!row.token_hash.contains(&reader[3..]));
+    assert!(row.revoked_at.is_some());
+    assert_eq!(row.use_count, 3);
+
+    // Usage of live tokens is saved when flushed, not on every request.
+    let saved = |token_id: &str| -> Result<u64, Box<dyn Error>> {
+        let rows = persistence::store().expect("store").api_tokens()?;
+        let row = rows.iter().find(|row| row.token_id == token_id);
+        Ok(row.expect("token saved").use_count)
+    };
+    assert_eq!(saved(&setup.token_id)?, 0);
+    let used = api_tokens::get(&setup.token_id)
+        .expect("admin token")
+        .use_count;
+    assert!(used > 0);
+    api_tokens::flush_usage();
+    persistence::flush();
+    assert_eq!(saved(&setup.token_id)?, used);
+    assert_eq!(
+        api_tokens::get(&setup.token_id)
+            .expect("admin token")
+            .use_count,
+        used
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn station_limited_tokens_stay_on_their_stations() -> Result<(), Box<dyn Error>> {
+    persistence::install(MemoryStore::new());
+    let (_, garage) = api_tokens::create(
+        "garage",
+        vec![Scope::Control],
+        Some(vec!["TOK-GARAGE".to_string()]),
+    )?;
+
+    // Unknown station, but the token may look: the request gets through.
+    let (status, _, _) = request(Method::GET, "/stations/TOK-GARAGE", Some(&garage), None).await?;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let (status, _, _) = request(Method::GET, "/stations/TOK-DRIVE", Some(&garage), None).await?;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    let (status, _, _) = request(Method::GET, "/stations", Some(&garage), None).await?;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let (status, _, _) = request(
+        Method::GET,
+        "/sessions?station_id=TOK-GARAGE",
+        Some(&garage),
+        None,
+    )
+    .await?;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _, _) = request(
+        Method::GET,
+        "/sessions?station_id=TOK-GARAGE,TOK-DRIVE",
+        Some(&garage),
+        None,
+    )
+    .await?;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    let (status, _, _) = request(
+        Method::POST,
+        "/tokens",
+        Some(&garage),
+        Some(json!({"name": "more", "scopes": ["admin"]})),
+    )
+    .await?;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    Ok(())
+}
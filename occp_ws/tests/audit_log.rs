@@ -0,0 +1,388 @@
+use std::{error::Error, net::SocketAddr, time::Duration};
+
+use ::common::{AuditConfig, CommandConfig};
+use axum::{
+    body::Body,
+    extract::connect_info::MockConnectInfo,
+    http::{Method, Request, StatusCode, header},
+};
+use chrono::Utc;
+use futures::SinkExt;
+use occp_ws::api_tokens::{self, Scope};
+use occp_ws::audit::{self, Actor, Outcome};
+use occp_ws::state::{AUDIT_CONFIG, COMMAND_CONFIG};
+use occp_ws::types::*;
+use occp_ws::{persistence, rest};
+use serde_json::{Value, json};
+use storage::{AuditFilter, AuditRow, MemoryStore};
+use tokio::{sync::oneshot, task::JoinHandle};
+use tokio_tungstenite::{connect_async, tungstenite::Message as WsMessage};
+use tower::ServiceExt;
+
+mod common;
+
+use common::{Socket, next_frame};
+
+/// Address API requests appear to come from.
+const CLIENT_ADDR: ([u8; 4], u16) = ([192, 0, 2, 7], 40_000);
+
+async fn start_test_server() -> (SocketAddr, oneshot::Sender<()>, JoinHandle<()>) {
+    COMMAND_CONFIG
+        .get_or_init(|| async {
+            CommandConfig {
+                timeout: Duration::from_secs(1),
+            }
+        })
+        .await;
+    persistence::install(MemoryStore::new());
+    common::start_test_server().await
+}
+
+async fn boot(addr: SocketAddr, station_id: &str) -> Result<Socket, Box<dyn Error>> {
+    let (mut socket, _) = connect_async(format!("ws://{addr}/{station_id}")).await?;
+    let frame = json!([
+        2,
+        "boot",
+        "BootNotification",
+        { "chargePointVendor": "PlugCo", "chargePointModel": "Wallbox 11" }
+    ]);
+    socket.send(WsMessage::Text(frame.to_string())).await?;
+    match next_frame(&mut socket).await? {
+        OcppMessageType::CallResult(3, _, _) => Ok(socket),
+        other => panic!("unexpected response to BootNotification: {other:?}"),
+    }
+}
+
+/// Answer the server's next call with `payload`, returning its action.
+async fn answer(socket: &mut Socket, payload: Value) -> Result<String, Box<dyn Error>> {
+    match next_frame(socket).await? {
+        OcppMessageType::Call(2, message_id, action, _) => {
+            let frame = json!([3, message_id, payload]);
+            socket.send(WsMessage::Text(frame.to_string())).await?;
+            Ok(action)
+        }
+        other => panic!("expected a call from the server, got {other:?}"),
+    }
+}
+
+/// Send a request from [`CLIENT_ADDR`], through the authenticated API when
+/// a token is given, and return its status and JSON body.
+async fn api(
+    method: Method,
+    uri: &str,
+    token: Option<&str>,
+    body: Option<Value>,
+) -> Result<(StatusCode, Value), Box<dyn Error>> {
+    let mut builder = Request::builder().method(method).uri(uri);
+    let router = match token {
+        Some(token) => {
+            builder = builder.header(header::AUTHORIZATION, format!("Bearer {token}"));
+            rest::v1_router_with_auth()
+        }
+        None => rest::v1_router(),
+    };
+    let body = match body {
+        Some(body) => {
+            builder = builder.header(header::CONTENT_TYPE, "application/json");
+            Body::from(body.to_string())
+        }
+        None => Body::empty(),
+    };
+    let response = router
+        .layer(MockConnectInfo(SocketAddr::from(CLIENT_ADDR)))
+        .oneshot(builder.body(body)?)
+        .await?;
+    let status = response.status();
+    let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await?;
+    let body = if bytes.is_empty() {
+        Value::Null
+    } else {
+        serde_json::from_slice(&bytes)?
+    };
+    Ok((status, body))
+}
+
+/// Poll the audit log until `query` matches `count` records.
+async fn wait_for_records(
+    query: &str,
+    token: &str,
+    count: usize,
+) -> Result<Vec<Value>, Box<dyn Error>> {
+    for _ in 0..100 {
+        let (status, page) =
+            api(Method::GET, &format!("/audit?{query}"), Some(token), None).await?;
+        assert_eq!(status, StatusCode::OK);
+        let records = page["records"].as_array().expect("records").clone();
+        if records.len() == count {
+            return Ok(records);
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    panic!("audit log never had {count} record(s) for {query}");
+}
+
+#[tokio::test]
+async fn commands_are_recorded_with_payload_answer_and_actor() -> Result<(), Box<dyn Error>> {
+    let (addr, shutdown, server) = start_test_server().await;
+    let mut socket = boot(addr, "audit-cmd").await?;
+    let (admin_token, admin) = api_tokens::create("audit admin", vec![Scope::Admin], None)?;
+    let (_, reader) = api_tokens::create("audit reader", vec![Scope::Read], None)?;
+
+    let (status, _) = api(
+        Method::POST,
+        "/stations/audit-cmd/reset",
+        Some(&admin),
+        Some(json!({ "reset_type": "Soft" })),
+    )
+    .await?;
+    assert_eq!(status, StatusCode::ACCEPTED);
+    assert_eq!(
+        answer(&mut socket, json!({ "status": "Accepted" })).await?,
+        "Reset"
+    );
+    let records = wait_for_records("station_id=audit-cmd&action=Reset", &admin, 1).await?;
+    let record = &records[0];
+    assert_eq!(record["request"], json!({ "type": "Soft" }));
+    assert_eq!(record["response"], json!({ "status": "Accepted" }));
+    assert_eq!(record["outcome"], "ok");
+    assert_eq!(
+        record["actor"],
+        json!({
+            "kind": "token",
+            "id": admin_token.token_id,
+            "name": "audit admin",
+            "remote_addr": "192.0.2.7"
+        })
+    );
+
+    // Refusals are recorded too, and so are requests without authentication.
+    let (status, _) = api(
+        Method::POST,
+        "/stations/audit-cmd/unlock",
+        None,
+        Some(json!({ "connector_id": 1 })),
+    )
+    .await?;
+    assert_eq!(status, StatusCode::ACCEPTED);
+    answer(&mut socket, json!({ "status": "UnlockFailed" })).await?;
+    let records =
+        wait_for_records("station_id=audit-cmd&action=UnlockConnector", &admin, 1).await?;
+    assert_eq!(records[0]["outcome"], "rejected");
+    assert_eq!(records[0]["actor"]["kind"], "anonymous");
+    assert_eq!(records[0]["actor"]["remote_addr"], "192.0.2.7");
+
+    // No answer at all fails.
+    api(
+        Method::POST,
+        "/stations/audit-cmd/reset",
+        Some(&admin),
+        Some(json!({ "reset_type": "Hard" })),
+    )
+    .await?;
+    next_frame(&mut socket).await?;
+    let records = wait_for_records("station_id=audit-cmd&action=Reset", &admin, 2).await?;
+    assert_eq!(records[0]["request"], json!({ "type": "Hard" }));
+    assert_eq!(
+        records[0]["response"],
+        json!({ "error": "station did not respond within 1s" })
+    );
+    assert_eq!(records[0]["outcome"], "failed");
+
+    // Only admins read the log.
+    let (status, _) = api(Method::GET, "/audit", Some(&reader), None).await?;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    socket.close(None).await?;
+    shutdown.send(()).ok();
+    server.await.expect("server task panicked");
+    Ok(())
+}
+
+#[tokio::test]
+async fn api_changes_are_recorded_without_secrets() -> Result<(), Box<dyn Error>> {
+    let (addr, shutdown, server) = start_test_server().await;
+    let socket = boot(addr, "audit-change").await?;
+    let (admin_token, admin) = api_tokens::create("change admin", vec![Scope::Admin], None)?;
+    let by_admin = format!("actor_id={}", admin_token.token_id);
+
+    let (status, _) = api(
+        Method::PATCH,
+        "/stations/audit-change",
+        Some(&admin),
+        Some(json!({ "name": "Garage", "notes": null })),
+    )
+    .await?;
+    assert_eq!(status, StatusCode::OK);
+    let records = wait_for_records(&format!("{by_admin}&action=UpdateStation"), &admin, 1).await?;
+    assert_eq!(records[0]["station_id"], "audit-change");
+    assert_eq!(
+        records[0]["request"],
+        json!({ "name": "Garage", "notes": null })
+    );
+    assert_eq!(records[0]["response"]["name"], "Garage");
+
+    let (status, user) = api(
+        Method::POST,
+        "/users",
+        Some(&admin),
+        Some(json!({ "username": "audited", "password": "hunter2-secret", "role": "member" })),
+    )
+    .await?;
+    assert_eq!(status, StatusCode::CREATED);
+    let user_id = user["user_id"].as_str().expect("user id");
+    api(
+        Method::PATCH,
+        &format!("/users/{user_id}"),
+        Some(&admin),
+        Some(json!({ "password": "another-secret", "id_tags": ["TAG-AUD"] })),
+    )
+    .await?;
+    let (_, token) = api(
+        Method::POST,
+        "/tokens",
+        Some(&admin),
+        Some(json!({ "name": "audited token", "scopes": ["read"] })),
+    )
+    .await?;
+    let (_, webhook) = api(
+        Method::POST,
+        "/webhooks",
+        Some(&admin),
+        Some(json!({ "url": "https://example.com/hook", "secret": "webhook-secret" })),
+    )
+    .await?;
+
+    let records = wait_for_records(&by_admin, &admin, 5).await?;
+    let actions: Vec<&str> = records
+        .iter()
+        .map(|record| record["action"].as_str().expect("action"))
+        .collect();
+    assert_eq!(
+        actions,
+        vec![
+            "CreateWebhook",
+            "CreateToken",
+            "UpdateUser",
+            "CreateUser",
+            "UpdateStation"
+        ]
+    );
+    assert_eq!(
+        records[2]["request"],
+        json!({ "role": null, "id_tags": ["TAG-AUD"], "password_changed": true })
+    );
+    let log = serde_json::to_string(&records)?;
+    for secret in [
+        "hunter2-secret",
+        "another-secret",
+        "webhook-secret",
+        token["token"].as_str().expect("token text"),
+    ] {
+        assert!(!log.contains(secret), "{secret} leaked into the audit log");
+    }
+    assert_eq!(records[0]["response"]["webhook_id"], webhook["webhook_id"]);
+
+    // Pages follow the cursor, newest first.
+    let (_, first) = api(
+        Method::GET,
+        &format!("/audit?{by_admin}&limit=2"),
+        Some(&admin),
+        None,
+    )
+    .await?;
+    assert_eq!(first["records"].as_array().expect("records").len(), 2);
+    let cursor = first["next_cursor"].as_str().expect("cursor");
+    let (_, rest) = api(
+        Method::GET,
+        &format!("/audit?{by_admin}&limit=3&cursor={cursor}"),
+        Some(&admin),
+        None,
+    )
+    .await?;
+    assert_eq!(rest["records"][0]["action"], "UpdateUser");
+    assert_eq!(rest["records"].as_array().expect("records").len(), 3);
+    assert_eq!(rest["next_cursor"], Value::Null);
+
+    let (status, body) = api(Method::GET, "/audit?cursor=nope", Some(&admin), None).await?;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body["error"]["code"], "bad_request");
+    let (status, _) = api(Method::GET, "/audit?limit=1001", Some(&admin), None).await?;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+
+    drop(socket);
+    shutdown.send(()).ok();
+    server.await.expect("server task panicked");
+    Ok(())
+}
+
+#[tokio::test]
+async fn authorization_keys_are_redacted() -> Result<(), Box<dyn Error>> {
+    persistence::install(MemoryStore::new());
+    audit::record(
+        &Actor::automation("test"),
+        "ChangeConfiguration",
+        Some("audit-key"),
+        Some(json!({ "key": "AuthorizationKey", "value": "0123456789abcdef" })),
+        Some(json!({ "status": "Accepted" })),
+        Outcome::Ok,
+    );
+    let records = audit::search(
+        &AuditFilter {
+            station_id: Some("audit-key".to_string()),
+            ..Default::default()
+        },
+        10,
+    )
+    .await;
+    assert_eq!(records.len(), 1);
+    assert_eq!(
+        records[0].request,
+        Some(json!({ "key": "AuthorizationKey", "value": "[redacted]" }))
+    );
+    assert_eq!(records[0].actor.kind, audit::ActorKind::Automation);
+    Ok(())
+}
+
+#[tokio::test]
+async fn records_past_retention_are_pruned() -> Result<(), Box<dyn Error>> {
+    persistence::install(MemoryStore::new());
+    AUDIT_CONFIG
+        .get_or_init(|| async {
+            AuditConfig {
+                retention: Duration::from_secs(30 * 24 * 3600),
+            }
+        })
+        .await;
+    let store = persistence::store().expect("store installed");
+    for (station_id, age_days) in [("audit-old", 31), ("audit-recent", 29)] {
+        store.append_audit_record(&AuditRow {
+            audit_id: 0,
+            at: Utc::now() - chrono::Duration::days(age_days),
+            actor_kind: "automation".to_string(),
+            actor_id: "test".to_string(),
+            actor_name: "test".to_string(),
+            remote_addr: None,
+            action: "Reset".to_string(),
+            station_id: Some(station_id.to_string()),
+            request: None,
+            response: None,
+            outcome: "ok".to_string(),
+        })?;
+    }
+
+    let pruner = audit::spawn_pruner();
+    let filter = |station_id: &str| AuditFilter {
+        station_id: Some(station_id.to_string()),
+        ..Default::default()
+    };
+    for _ in 0..100 {
+        if audit::search(&filter("audit-old"), 10).await.is_empty() {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    pruner.abort();
+    assert!(audit::search(&filter("audit-old"), 10).await.is_empty());
+    assert_eq!(audit::search(&filter("audit-recent"), 10).await.len(), 1);
+    Ok(())
+}
@@ -0,0 +1,372 @@
+use std::{error::Error, net::SocketAddr, time::Duration};
+
+use ::common::CommandConfig;
+use axum::{
+    body::Body,
+    http::{Method, Request, StatusCode, header},
+};
+use futures::SinkExt;
+use occp_ws::audit::{Actor, ActorKind};
+use occp_ws::commands::{self, Command};
+use occp_ws::connections;
+use occp_ws::rest;
+use occp_ws::state::COMMAND_CONFIG;
+use occp_ws::types::*;
+use serde_json::{Value, json};
+use tokio::{sync::oneshot, task::JoinHandle};
+use tokio_tungstenite::{connect_async, tungstenite::Message as WsMessage};
+use tower::ServiceExt;
+
+mod common;
+
+use common::{Socket, call, next_frame};
+
+async fn start_test_server() -> (SocketAddr, oneshot::Sender<()>, JoinHandle<()>) {
+    COMMAND_CONFIG
+        .get_or_init(|| async {
+            CommandConfig {
+                timeout: Duration::from_secs(1),
+            }
+        })
+        .await;
+    common::start_test_server().await
+}
+
+/// Send a request to the v1 API and return its status and JSON body.
+async fn api(
+    method: Method,
+    path: &str,
+    body: Option<Value>,
+    idempotency_key: Option<&str>,
+) -> Result<(StatusCode, Value), Box<dyn Error>> {
+    let mut request = Request::builder().method(method).uri(path);
+    if let Some(key) = idempotency_key {
+        request = request.header("Idempotency-Key", key);
+    }
+    let request = match body {
+        Some(body) => request
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(body.to_string()))?,
+        None => request.body(Body::empty())?,
+    };
+    let response = rest::v1_router().oneshot(request).await?;
+    let status = response.status();
+    let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await?;
+    Ok((status, serde_json::from_slice(&bytes)?))
+}
+
+async fn boot(addr: SocketAddr, station_id: &str) -> Result<Socket, Box<dyn Error>> {
+    let (mut socket, _) = connect_async(format!("ws://{addr}/{station_id}")).await?;
+    call(
+        &mut socket,
+        "boot",
+        "BootNotification",
+        json!({ "chargePointVendor": "PlugCo", "chargePointModel": "Wallbox 11" }),
+    )
+    .await?;
+    Ok(socket)
+}
+
+/// Wait for the server's next call and return its id, action and payload.
+async fn expect_call(socket: &mut Socket) -> Result<(String, String, Value), Box<dyn Error>> {
+    match next_frame(socket).await? {
+        OcppMessageType::Call(2, message_id, action, payload) => Ok((message_id, action, payload)),
+        other => panic!("expected a call from the server, got {other:?}"),
+    }
+}
+
+async fn reply(
+    socket: &mut Socket,
+    message_id: &str,
+    payload: Value,
+) -> Result<(), Box<dyn Error>> {
+    let frame = json!([3, message_id, payload]);
+    socket.send(WsMessage::Text(frame.to_string())).await?;
+    Ok(())
+}
+
+/// Poll the job until it reaches `status`.
+async fn wait_for_job(job_id: &str, status: &str) -> Result<Value, Box<dyn Error>> {
+    for _ in 0..100 {
+        let (_, job) = api(Method::GET, &format!("/commands/{job_id}"), None, None).await?;
+        if job["status"] == status {
+            return Ok(job);
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    panic!("command {job_id} never became {status}");
+}
+
+fn statuses(job: &Value) -> Vec<&str> {
+    job["history"]
+        .as_array()
+        .expect("job history")
+        .iter()
+        .map(|step| step["status"].as_str().expect("status"))
+        .collect()
+}
+
+#[tokio::test]
+async fn remote_start_and_stop_are_confirmed() -> Result<(), Box<dyn Error>> {
+    let (addr, shutdown, server) = start_test_server().await;
+    let mut socket = boot(addr, "command-start").await?;
+
+    let start = json!({ "connector_id": 1, "id_tag": "TAG-APP" });
+    let (status, job) = api(
+        Method::POST,
+        "/stations/command-start/start",
+        Some(start.clone()),
+        Some("tap-1"),
+    )
+    .await?;
+    assert_eq!(status, StatusCode::ACCEPTED);
+    assert_eq!(job["command"], "remote_start");
+    let job_id = job["job_id"].as_str().expect("job id").to_string();
+
+    // A double tap returns the same job and sends nothing new.
+    let (status, again) = api(
+        Method::POST,
+        "/stations/command-start/start",
+        Some(start),
+        Some("tap-1"),
+    )
+    .await?;
+    assert_eq!(status, StatusCode::ACCEPTED);
+    assert_eq!(again["job_id"], job_id.as_str());
+    let (status, body) = api(
+        Method::POST,
+        "/stations/command-start/start",
+        Some(json!({ "connector_id": 2, "id_tag": "TAG-APP" })),
+        Some("tap-1"),
+    )
+    .await?;
+    assert_eq!(status, StatusCode::CONFLICT);
+    assert_eq!(body["error"]["code"], "conflict");
+
+    let (message_id, action, payload) = expect_call(&mut socket).await?;
+    assert_eq!(action, "RemoteStartTransaction");
+    assert_eq!(payload, json!({ "connectorId": 1, "idTag": "TAG-APP" }));
+    reply(&mut socket, &message_id, json!({ "status": "Accepted" })).await?;
+    wait_for_job(&job_id, "accepted").await?;
+
+    let started = call(
+        &mut socket,
+        "start",
+        "StartTransaction",
+        json!({
+            "connectorId": 1,
+            "idTag": "TAG-APP",
+            "meterStart": 0,
+            "timestamp": "2024-07-01T12:00:00Z"
+        }),
+    )
+    .await?;
+    let transaction_id = started["transactionId"].as_i64().expect("transaction id");
+    let job = wait_for_job(&job_id, "confirmed").await?;
+    assert_eq!(job["transaction_id"], transaction_id);
+    assert_eq!(
+        statuses(&job),
+        vec!["queued", "sent", "accepted", "confirmed"]
+    );
+
+    // Stop finds the station's only running transaction by itself.
+    let (status, job) = api(
+        Method::POST,
+        "/stations/command-start/stop",
+        Some(json!({})),
+        None,
+    )
+    .await?;
+    assert_eq!(status, StatusCode::ACCEPTED);
+    assert_eq!(job["transaction_id"], Value::Null);
+    let job_id = job["job_id"].as_str().expect("job id").to_string();
+    let (message_id, action, payload) = expect_call(&mut socket).await?;
+    assert_eq!(action, "RemoteStopTransaction");
+    assert_eq!(payload, json!({ "transactionId": transaction_id }));
+    reply(&mut socket, &message_id, json!({ "status": "Accepted" })).await?;
+    call(
+        &mut socket,
+        "stop",
+        "StopTransaction",
+        json!({
+            "transactionId": transaction_id,
+            "meterStop": 7000,
+            "timestamp": "2024-07-01T13:00:00Z",
+            "reason": "Remote"
+        }),
+    )
+    .await?;
+    let job = wait_for_job(&job_id, "confirmed").await?;
+    assert_eq!(job["transaction_id"], transaction_id);
+
+    let (_, jobs) = api(
+        Method::GET,
+        "/commands?station_id=command-start",
+        None,
+        None,
+    )
+    .await?;
+    assert_eq!(jobs.as_array().expect("job list").len(), 2);
+
+    socket.close(None).await?;
+    shutdown.send(()).ok();
+    server.await.expect("server task panicked");
+    Ok(())
+}
+
+#[tokio::test]
+async fn commands_report_rejections_timeouts_and_offline_stations() -> Result<(), Box<dyn Error>> {
+    let (addr, shutdown, server) = start_test_server().await;
+    let mut socket = boot(addr, "command-other").await?;
+
+    let (status, job) = api(
+        Method::POST,
+        "/stations/command-other/current-limit",
+        Some(json!({ "connector_id": 1, "limit_a": 16 })),
+        None,
+    )
+    .await?;
+    assert_eq!(status, StatusCode::ACCEPTED);
+    let job_id = job["job_id"].as_str().expect("job id").to_string();
+    let (message_id, action, payload) = expect_call(&mut socket).await?;
+    assert_eq!(action, "SetChargingProfile");
+    assert_eq!(payload["connectorId"], 1);
+    let profile = &payload["csChargingProfiles"];
+    assert_eq!(profile["chargingProfilePurpose"], "TxDefaultProfile");
+    assert_eq!(profile["chargingSchedule"]["chargingRateUnit"], "A");
+    assert_eq!(
+        profile["chargingSchedule"]["chargingSchedulePeriod"][0]["limit"],
+        16.0
+    );
+    reply(&mut socket, &message_id, json!({ "status": "Accepted" })).await?;
+    wait_for_job(&job_id, "accepted").await?;
+
+    let (_, job) = api(
+        Method::POST,
+        "/stations/command-other/unlock",
+        Some(json!({ "connector_id": 1 })),
+        None,
+    )
+    .await?;
+    let job_id = job["job_id"].as_str().expect("job id").to_string();
+    let (message_id, action, _) = expect_call(&mut socket).await?;
+    assert_eq!(action, "UnlockConnector");
+    reply(
+        &mut socket,
+        &message_id,
+        json!({ "status": "UnlockFailed" }),
+    )
+    .await?;
+    let job = wait_for_job(&job_id, "rejected").await?;
+    assert_eq!(job["error"], "charger answered UnlockFailed");
+
+    let (_, job) = api(
+        Method::POST,
+        "/stations/command-other/reset",
+        Some(json!({ "reset_type": "Hard" })),
+        None,
+    )
+    .await?;
+    let job_id = job["job_id"].as_str().expect("job id").to_string();
+    let (_, action, payload) = expect_call(&mut socket).await?;
+    assert_eq!(action, "Reset");
+    assert_eq!(payload, json!({ "type": "Hard" }));
+    // No answer: the job times out.
+    let job = wait_for_job(&job_id, "timed_out").await?;
+    assert_eq!(statuses(&job), vec!["queued", "sent", "timed_out"]);
+
+    let invalid = [
+        (
+            "/stations/command-other/current-limit",
+            json!({ "limit_a": -1 }),
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "validation_failed",
+        ),
+        (
+            "/stations/command-other/start",
+            json!({ "id_tag": "" }),
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "validation_failed",
+        ),
+        (
+            "/stations/command-other/stop",
+            json!({}),
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "validation_failed",
+        ),
+        (
+            "/stations/command-nobody/unlock",
+            json!({ "connector_id": 1 }),
+            StatusCode::NOT_FOUND,
+            "not_found",
+        ),
+    ];
+    for (path, body, expected, code) in invalid {
+        let (status, body) = api(Method::POST, path, Some(body), None).await?;
+        assert_eq!(status, expected, "{path}: {body}");
+        assert_eq!(body["error"]["code"], code);
+    }
+
+    socket.close(None).await?;
+    for _ in 0..100 {
+        if !connections::is_connected("command-other") {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    let (status, body) = api(
+        Method::POST,
+        "/stations/command-other/availability",
+        Some(json!({ "availability": "Inoperative" })),
+        None,
+    )
+    .await?;
+    assert_eq!(status, StatusCode::CONFLICT);
+    assert_eq!(body["error"]["code"], "station_offline");
+
+    shutdown.send(()).ok();
+    server.await.expect("server task panicked");
+    Ok(())
+}
+
+#[tokio::test]
+async fn idempotency_keys_are_per_caller() -> Result<(), Box<dyn Error>> {
+    let (addr, shutdown, server) = start_test_server().await;
+    let mut socket = boot(addr, "command-keys").await?;
+
+    let token = |token_id: &str, remote_addr: &str| Actor {
+        kind: ActorKind::Token,
+        id: token_id.to_string(),
+        name: format!("{token_id} app"),
+        remote_addr: Some(remote_addr.to_string()),
+    };
+    let kitchen = token("token-kitchen", "192.0.2.10");
+    let garage = token("token-garage", "192.0.2.20");
+    let unlock = Command::Unlock { connector_id: 1 };
+
+    let first = commands::submit("command-keys", unlock.clone(), Some("retry-1"), &kitchen)?;
+    let again = commands::submit("command-keys", unlock.clone(), Some("retry-1"), &kitchen)?;
+    assert_eq!(again.job_id, first.job_id);
+
+    // Another caller with the same key gets a job of its own.
+    let other = commands::submit("command-keys", unlock, Some("retry-1"), &garage)?;
+    assert_ne!(other.job_id, first.job_id);
+    assert_eq!(other.requested_by, garage);
+    assert_eq!(
+        commands::get(&first.job_id).expect("job").requested_by,
+        kitchen
+    );
+
+    for _ in 0..2 {
+        let (message_id, action, _) = expect_call(&mut socket).await?;
+        assert_eq!(action, "UnlockConnector");
+        reply(&mut socket, &message_id, json!({ "status": "Unlocked" })).await?;
+    }
+    wait_for_job(&first.job_id, "accepted").await?;
+    wait_for_job(&other.job_id, "accepted").await?;
+
+    socket.close(None).await?;
+    shutdown.send(()).ok();
+    server.await.expect("server task panicked");
+    Ok(())
+}
@@ -0,0 +1,89 @@
+//! Test server and OCPP client helpers shared by the integration tests.
+
+// Each test crate uses only some of these.
+#![allow(dead_code)]
+
+use std::{error::Error, net::SocketAddr, time::Duration};
+
+use axum::{Router, routing::get};
+use chrono::Utc;
+use futures::{SinkExt, StreamExt};
+use occp_ws::routes::{healthcheck_route, upgrade_to_ws};
+use occp_ws::state::START_TIME;
+use occp_ws::types::*;
+use serde_json::{Value, json};
+use tokio::{net::TcpListener, sync::oneshot, task::JoinHandle, time::timeout};
+use tokio_tungstenite::tungstenite::Message as WsMessage;
+
+pub type Socket =
+    tokio_tungstenite::WebSocketStream<tokio_tungstenite::MaybeTlsStream<tokio::net::TcpStream>>;
+
+/// Serve the charger WebSocket endpoint on a free port until the sender is
+/// used or dropped.
+pub async fn start_test_server() -> (SocketAddr, oneshot::Sender<()>, JoinHandle<()>) {
+    start_test_server_with(Router::new()).await
+}
+
+/// Like [`start_test_server`], with `routes` served alongside.
+pub async fn start_test_server_with(
+    routes: Router,
+) -> (SocketAddr, oneshot::Sender<()>, JoinHandle<()>) {
+    START_TIME.get_or_init(|| async { Utc::now() }).await;
+
+    let listener = TcpListener::bind("127.0.0.1:0")
+        .await
+        .expect("bind test listener");
+    let addr = listener.local_addr().expect("listener addr");
+
+    let router = routes
+        .route("/:station_id", get(upgrade_to_ws))
+        .route("/", get(healthcheck_route));
+
+    let (shutdown_tx, shutdown_rx) = oneshot::channel();
+
+    let server = axum::serve(
+        listener,
+        router.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .with_graceful_shutdown(async {
+        let _ = shutdown_rx.await;
+    });
+
+    let handle = tokio::spawn(async move {
+        if let Err(err) = server.await {
+            panic!("test server error: {err}");
+        }
+    });
+
+    (addr, shutdown_tx, handle)
+}
+
+/// The next OCPP frame from the server, skipping pings.
+pub async fn next_frame(socket: &mut Socket) -> Result<OcppMessageType, Box<dyn Error>> {
+    loop {
+        let frame = timeout(Duration::from_secs(5), socket.next())
+            .await?
+            .expect("socket open")?;
+        if let WsMessage::Text(text) = frame {
+            return Ok(serde_json::from_str(&text)?);
+        }
+    }
+}
+
+/// Send a Call as the charger and return the CallResult payload.
+pub async fn call(
+    socket: &mut Socket,
+    message_id: &str,
+    action: &str,
+    payload: Value,
+) -> Result<Value, Box<dyn Error>> {
+    let frame = json!([2, message_id, action, payload]);
+    socket.send(WsMessage::Text(frame.to_string())).await?;
+    match next_frame(socket).await? {
+        OcppMessageType::CallResult(3, id, payload) => {
+            assert_eq!(id, message_id);
+            Ok(payload)
+        }
+        other => panic!("unexpected response to {action}: {other:?}"),
+    }
+}
@@ -0,0 +1,173 @@
+use std::{error::Error, net::SocketAddr, sync::Arc, time::Duration};
+
+use ::common::DuplicateConnectionPolicy;
+use futures::{SinkExt, StreamExt};
+use occp_ws::audit::Actor;
+use occp_ws::connections::{self, CallError, StationConnection};
+use occp_ws::events::{self, StationEvent};
+use occp_ws::presence;
+use occp_ws::state::DUPLICATE_CONNECTION_POLICY;
+use occp_ws::types::*;
+use rust_ocpp::v1_6::messages::get_configuration::GetConfigurationRequest;
+use serde_json::{Value, json};
+use tokio::{sync::mpsc, sync::oneshot, task::JoinHandle, time::timeout};
+use tokio_tungstenite::{
+    connect_async,
+    tungstenite::{Message as WsMessage, protocol::frame::coding::CloseCode},
+};
+
+mod common;
+
+use common::Socket;
+
+async fn start_test_server() -> (SocketAddr, oneshot::Sender<()>, JoinHandle<()>) {
+    DUPLICATE_CONNECTION_POLICY
+        .get_or_init(|| async { DuplicateConnectionPolicy::NewestWins })
+        .await;
+    common::start_test_server().await
+}
+
+async fn next_message(socket: &mut Socket) -> Result<WsMessage, Box<dyn Error>> {
+    loop {
+        let frame = timeout(Duration::from_secs(5), socket.next())
+            .await?
+            .expect("socket open")?;
+        if !matches!(frame, WsMessage::Ping(_) | WsMessage::Pong(_)) {
+            return Ok(frame);
+        }
+    }
+}
+
+async fn heartbeat(socket: &mut Socket, message_id: &str) -> Result<(), Box<dyn Error>> {
+    let frame = json!([2, message_id, "Heartbeat", {}]);
+    socket.send(WsMessage::Text(frame.to_string())).await?;
+    match next_message(socket).await? {
+        WsMessage::Text(text) => {
+            let reply: Value = serde_json::from_str(&text)?;
+            assert_eq!(reply[0], 3);
+            assert_eq!(reply[1], message_id);
+            Ok(())
+        }
+        other => panic!("expected Heartbeat result, got {other:?}"),
+    }
+}
+
+#[tokio::test]
+async fn newest_connection_replaces_the_old_one() -> Result<(), Box<dyn Error>> {
+    let (addr, shutdown, server) = start_test_server().await;
+    let mut events = events::subscribe();
+
+    let (mut old, _) = connect_async(format!("ws://{addr}/dup-station")).await?;
+    heartbeat(&mut old, "hb-old").await?;
+
+    // A server call still waiting on the old session when it is replaced.
+    let pending = tokio::spawn(async {
+        connections::call::<Value>(
+            "dup-station",
+            OcppActionEnum::GetConfiguration,
+            OcppPayload::GetConfiguration(GetConfigurationKind::Request(GetConfigurationRequest {
+                key: None,
+            })),
+            &Actor::automation("test"),
+        )
+        .await
+    });
+    match next_message(&mut old).await? {
+        WsMessage::Text(text) => assert!(text.contains("GetConfiguration")),
+        other => panic!("expected GetConfiguration, got {other:?}"),
+    }
+
+    let (mut new, _) = connect_async(format!("ws://{addr}/dup-station")).await?;
+
+    match next_message(&mut old).await? {
+        WsMessage::Close(Some(frame)) => assert_eq!(frame.code, CloseCode::Normal),
+        other => panic!("expected Close on the replaced socket, got {other:?}"),
+    }
+    assert!(matches!(pending.await?, Err(CallError::ConnectionClosed)));
+
+    let reconnect = timeout(Duration::from_secs(5), async {
+        loop {
+            match events.recv().await.expect("event stream") {
+                event @ StationEvent::Reconnected { .. } => return event,
+                StationEvent::Offline { station_id, .. } if station_id == "dup-station" => {
+                    panic!("takeover must not report the station offline")
+                }
+                _ => {}
+            }
+        }
+    })
+    .await?;
+    let StationEvent::Reconnected {
+        station_id,
+        previous_addr,
+        addr: new_addr,
+        ..
+    } = reconnect
+    else {
+        unreachable!();
+    };
+    assert_eq!(station_id, "dup-station");
+    assert_ne!(previous_addr, new_addr);
+
+    heartbeat(&mut new, "hb-new").await?;
+    let active = connections::get("dup-station").expect("active connection");
+    assert_eq!(active.addr, new_addr);
+    assert!(presence::get("dup-station").expect("presence").online);
+
+    new.close(None).await?;
+    shutdown.send(()).ok();
+    server.await.expect("server task panicked");
+    Ok(())
+}
+
+#[tokio::test]
+async fn reject_newcomer_keeps_the_existing_connection() {
+    let addr: SocketAddr = "127.0.0.1:1".parse().expect("addr");
+    let (tx, _rx) = mpsc::channel(1);
+    let first = Arc::new(StationConnection::new(
+        "dup-reject".into(),
+        addr,
+        tx.clone(),
+    ));
+    let second = Arc::new(StationConnection::new("dup-reject".into(), addr, tx));
+
+    let policy = DuplicateConnectionPolicy::RejectNewcomer;
+    assert!(matches!(
+        connections::register(first.clone(), policy),
+        Ok(None)
+    ));
+    assert!(connections::register(second, policy).is_err());
+    assert!(Arc::ptr_eq(
+        &connections::get("dup-reject").expect("connection"),
+        &first
+    ));
+
+    connections::unregister(&first);
+    assert!(!connections::is_connected("dup-reject"));
+}
+
+#[tokio::test]
+async fn stuck_connection_does_not_block_its_replacement() -> Result<(), Box<dyn Error>> {
+    let (addr, shutdown, server) = start_test_server().await;
+
+    // A half-open session whose writer no longer drains its queue.
+    let (tx, _rx) = mpsc::channel(1);
+    tx.try_send(axum::extract::ws::Message::Text("queued".into()))?;
+    let stuck = Arc::new(StationConnection::new(
+        "dup-stuck".into(),
+        "127.0.0.1:1".parse()?,
+        tx,
+    ));
+    connections::register(stuck.clone(), DuplicateConnectionPolicy::NewestWins)?;
+
+    let (mut new, _) = connect_async(format!("ws://{addr}/dup-stuck")).await?;
+    heartbeat(&mut new, "hb-after-stuck").await?;
+    timeout(Duration::from_secs(1), stuck.closed()).await?;
+    let active = connections::get("dup-stuck").expect("active connection");
+    assert!(!Arc::ptr_eq(&active, &stuck));
+
+    new.close(None).await?;
+    shutdown.send(()).ok();
+    server.await.expect("server task panicked");
+    Ok(())
+}
@@ -0,0 +1,316 @@
+use std::{error::Error, net::SocketAddr, time::Duration};
+
+use axum::{
+    Router,
+    body::{Body, BodyDataStream},
+    http::{Request, StatusCode, header},
+};
+use futures::StreamExt;
+use occp_ws::events::{self, EventFilter};
+use occp_ws::rest;
+use serde_json::{Value, json};
+use tokio::{sync::oneshot, task::JoinHandle, time::timeout};
+use tokio_tungstenite::{connect_async, tungstenite::Message as WsMessage};
+use tower::ServiceExt;
+
+mod common;
+
+use common::{Socket, call};
+
+async fn start_test_server() -> (SocketAddr, oneshot::Sender<()>, JoinHandle<()>) {
+    common::start_test_server_with(Router::new().nest("/api/v1", rest::v1_router())).await
+}
+
+async fn boot(addr: SocketAddr, station_id: &str) -> Result<Socket, Box<dyn Error>> {
+    let (mut socket, _) = connect_async(format!("ws://{addr}/{station_id}")).await?;
+    call(
+        &mut socket,
+        "boot",
+        "BootNotification",
+        json!({ "chargePointVendor": "PlugCo", "chargePointModel": "Wallbox 11" }),
+    )
+    .await?;
+    Ok(socket)
+}
+
+/// One Server-Sent Event: its id, type and JSON data.
+#[derive(Debug)]
+struct SseEvent {
+    id: u64,
+    kind: String,
+    data: Value,
+}
+
+/// Reads events off an SSE response body.
+struct SseReader {
+    body: BodyDataStream,
+    buffer: String,
+}
+
+impl SseReader {
+    async fn open(path: &str, last_event_id: Option<u64>) -> Result<Self, Box<dyn Error>> {
+        let mut request = Request::builder().uri(path);
+        if let Some(id) = last_event_id {
+            request = request.header("last-event-id", id.to_string());
+        }
+        let response = rest::v1_router()
+            .oneshot(request.body(Body::empty())?)
+            .await?;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response.headers()[header::CONTENT_TYPE],
+            "text/event-stream"
+        );
+        Ok(Self {
+            body: response.into_body().into_data_stream(),
+            buffer: String::new(),
+        })
+    }
+
+    async fn next(&mut self) -> Result<SseEvent, Box<dyn Error>> {
+        loop {
+            if let Some(end) = self.buffer.find("\n\n") {
+                let block = self.buffer[..end].to_string();
+                self.buffer.drain(..end + 2);
+                let (mut id, mut kind, mut data) = (None, None, None);
+                for line in block.lines() {
+                    match line.split_once(':') {
+                        Some(("id", value)) => id = Some(value.trim().parse()?),
+                        Some(("event", value)) => kind = Some(value.trim().to_string()),
+                        Some(("data", value)) => data = Some(serde_json::from_str(value)?),
+                        // Comments, e.g. keep-alives.
+                        _ => {}
+                    }
+                }
+                if let (Some(id), Some(kind), Some(data)) = (id, kind, data) {
+                    return Ok(SseEvent { id, kind, data });
+                }
+                continue;
+            }
+            let chunk = timeout(Duration::from_secs(5), self.body.next())
+                .await?
+                .expect("event stream open")?;
+            self.buffer.push_str(std::str::from_utf8(&chunk)?);
+        }
+    }
+}
+
+#[tokio::test]
+async fn sse_streams_station_events_and_resumes() -> Result<(), Box<dyn Error>> {
+    let (addr, shutdown, server) = start_test_server().await;
+    let mut events = SseReader::open("/events?station_id=es-sse", None).await?;
+
+    let mut socket = boot(addr, "es-sse").await?;
+    call(
+        &mut socket,
+        "status",
+        "StatusNotification",
+        json!({ "connectorId": 1, "errorCode": "NoError", "status": "Charging" }),
+    )
+    .await?;
+    let started = call(
+        &mut socket,
+        "start",
+        "StartTransaction",
+        json!({
+            "connectorId": 1,
+            "idTag": "CARD-1",
+            "meterStart": 1000,
+            "timestamp": "2026-03-01T10:00:00Z"
+        }),
+    )
+    .await?;
+    let transaction_id = started["transactionId"].as_i64().expect("transaction id");
+    call(
+        &mut socket,
+        "meter",
+        "MeterValues",
+        json!({
+            "connectorId": 1,
+            "transactionId": transaction_id,
+            "meterValue": [{
+                "timestamp": "2026-03-01T10:15:00Z",
+                "sampledValue": [
+                    { "value": "2.5", "unit": "kWh" },
+                    { "value": "7200", "measurand": "Power.Active.Import", "unit": "W" }
+                ]
+            }]
+        }),
+    )
+    .await?;
+    call(
+        &mut socket,
+        "stop",
+        "StopTransaction",
+        json!({
+            "transactionId": transaction_id,
+            "meterStop": 4000,
+            "timestamp": "2026-03-01T10:30:00Z",
+            "reason": "EVDisconnected"
+        }),
+    )
+    .await?;
+
+    let online = events.next().await?;
+    assert_eq!(online.kind, "online");
+    assert_eq!(online.data["type"], "online");
+    assert_eq!(online.data["station_id"], "es-sse");
+    assert_eq!(online.data["id"], online.id);
+
+    let booted = events.next().await?;
+    assert_eq!(booted.kind, "booted");
+    assert_eq!(booted.data["vendor"], "PlugCo");
+    assert_eq!(booted.data["registration_status"], "Accepted");
+
+    let status = events.next().await?;
+    assert_eq!(status.kind, "connector_status");
+    assert_eq!(status.data["connector_id"], 1);
+    assert_eq!(status.data["status"], "Charging");
+
+    let session_started = events.next().await?;
+    assert_eq!(session_started.kind, "session_started");
+    assert_eq!(
+        session_started.data["session"]["transaction_id"],
+        transaction_id
+    );
+    assert_eq!(session_started.data["session"]["id_tag"], "CARD-1");
+
+    let meter = events.next().await?;
+    assert_eq!(meter.kind, "meter_values");
+    assert_eq!(meter.data["transaction_id"], transaction_id);
+    assert_eq!(meter.data["energy_wh"], 2500.0);
+    assert_eq!(meter.data["power_w"], 7200.0);
+
+    let stopped = events.next().await?;
+    assert_eq!(stopped.kind, "session_stopped");
+    assert_eq!(stopped.data["session"]["energy_wh"], 3000.0);
+    assert_eq!(stopped.data["session"]["stop_reason"], "EVDisconnected");
+    assert!(online.id < status.id && meter.id < stopped.id);
+
+    // A reconnecting client gets what it missed, filtered by type.
+    let mut resumed = SseReader::open(
+        "/events?station_id=es-sse&type=meter_values,session_stopped",
+        Some(session_started.id),
+    )
+    .await?;
+    assert_eq!(resumed.next().await?.id, meter.id);
+    assert_eq!(resumed.next().await?.id, stopped.id);
+
+    let request = Request::builder()
+        .uri("/events?type=heartbeat")
+        .body(Body::empty())?;
+    let response = rest::v1_router().oneshot(request).await?;
+    assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+    socket.close(None).await?;
+    shutdown.send(()).ok();
+    server.await.expect("server task panicked");
+    Ok(())
+}
+
+#[tokio::test]
+async fn websocket_streams_filtered_events() -> Result<(), Box<dyn Error>> {
+    let (addr, shutdown, server) = start_test_server().await;
+    let (mut client, _) = connect_async(format!(
+        "ws://{addr}/api/v1/events/ws?station_id=es-ws&type=connector_status"
+    ))
+    .await?;
+
+    let mut socket = boot(addr, "es-ws").await?;
+    call(
+        &mut socket,
+        "status",
+        "StatusNotification",
+        json!({ "connectorId": 2, "errorCode": "NoError", "status": "Available" }),
+    )
+    .await?;
+
+    let frame = timeout(Duration::from_secs(5), client.next())
+        .await?
+        .expect("event socket open")?;
+    let WsMessage::Text(text) = frame else {
+        panic!("expected a text frame, got {frame:?}");
+    };
+    let event: Value = serde_json::from_str(&text)?;
+    assert_eq!(event["type"], "connector_status");
+    assert_eq!(event["station_id"], "es-ws");
+    assert_eq!(event["connector_id"], 2);
+    assert!(event["id"].is_u64());
+
+    client.close(None).await?;
+    socket.close(None).await?;
+    shutdown.send(()).ok();
+    server.await.expect("server task panicked");
+    Ok(())
+}
+
+#[tokio::test]
+async fn bus_subscribers_see_boot_authorize_security_and_firmware_events()
+-> Result<(), Box<dyn Error>> {
+    let (addr, shutdown, server) = start_test_server().await;
+    let filter = EventFilter {
+        station_ids: vec!["es-bus".to_string()],
+        kinds: vec![
+            "booted".to_string(),
+            "authorized".to_string(),
+            "security_event".to_string(),
+            "firmware_status".to_string(),
+        ],
+        id_tags: None,
+    };
+    let stream = events::stream(filter, None);
+    futures::pin_mut!(stream);
+
+    let mut socket = boot(addr, "es-bus").await?;
+    call(
+        &mut socket,
+        "auth",
+        "Authorize",
+        json!({ "idTag": "CARD-7" }),
+    )
+    .await?;
+    call(
+        &mut socket,
+        "security",
+        "SecurityEventNotification",
+        json!({
+            "type": "TamperDetectionActivated",
+            "timestamp": "2026-03-01T10:00:00Z",
+            "techInfo": "lid opened"
+        }),
+    )
+    .await?;
+    call(
+        &mut socket,
+        "firmware",
+        "FirmwareStatusNotification",
+        json!({ "status": "InstallationFailed" }),
+    )
+    .await?;
+
+    let mut next = async || {
+        timeout(Duration::from_secs(5), stream.next())
+            .await
+            .map(|record| record.expect("bus open"))
+    };
+    let booted = next().await?;
+    assert_eq!(booted.event.kind(), "booted");
+    let authorized = serde_json::to_value(next().await?)?;
+    assert_eq!(authorized["type"], "authorized");
+    assert_eq!(authorized["id_tag"], "CARD-7");
+    assert_eq!(authorized["status"], "Accepted");
+    let security = serde_json::to_value(next().await?)?;
+    assert_eq!(security["type"], "security_event");
+    assert_eq!(security["event_type"], "TamperDetectionActivated");
+    assert_eq!(security["critical"], true);
+    let firmware = serde_json::to_value(next().await?)?;
+    assert_eq!(firmware["type"], "firmware_status");
+    assert_eq!(firmware["status"], "InstallationFailed");
+    assert_eq!(firmware["failed"], true);
+    assert!(booted.id < authorized["id"].as_u64().expect("event id"));
+
+    socket.close(None).await?;
+    shutdown.send(()).ok();
+    server.await.expect("server task panicked");
+    Ok(())
+}
@@ -0,0 +1,83 @@
+use std::{error::Error, net::SocketAddr, time::Duration};
+
+use ::common::KeepaliveConfig;
+use futures::StreamExt;
+use occp_ws::connections;
+use occp_ws::events::{self, OfflineReason, StationEvent};
+use occp_ws::presence;
+use occp_ws::state::KEEPALIVE_CONFIG;
+use tokio::{sync::oneshot, task::JoinHandle, time::timeout};
+use tokio_tungstenite::connect_async;
+
+mod common;
+
+async fn start_test_server() -> (SocketAddr, oneshot::Sender<()>, JoinHandle<()>) {
+    KEEPALIVE_CONFIG
+        .get_or_init(|| async {
+            KeepaliveConfig {
+                ping_interval: Some(Duration::from_millis(200)),
+                pong_timeout: Duration::from_millis(300),
+            }
+        })
+        .await;
+    common::start_test_server().await
+}
+
+#[tokio::test]
+async fn records_round_trip_time_from_pongs() -> Result<(), Box<dyn Error>> {
+    let (addr, shutdown, server) = start_test_server().await;
+    let (mut socket, _) = connect_async(format!("ws://{addr}/keepalive-alive")).await?;
+
+    // Reading lets the client answer the server's pings.
+    let measured = timeout(Duration::from_secs(5), async {
+        loop {
+            let _ = timeout(Duration::from_millis(50), socket.next()).await;
+            if let Some(rtt) = presence::get("keepalive-alive").and_then(|p| p.avg_rtt_ms) {
+                return rtt;
+            }
+        }
+    })
+    .await?;
+    assert!(measured >= 0.0);
+
+    // A responsive charger outlives several pong deadlines.
+    let until = tokio::time::Instant::now() + Duration::from_secs(1);
+    while tokio::time::Instant::now() < until {
+        let _ = timeout(Duration::from_millis(50), socket.next()).await;
+    }
+    assert!(connections::is_connected("keepalive-alive"));
+    assert!(presence::get("keepalive-alive").expect("presence").online);
+
+    socket.close(None).await?;
+    shutdown.send(()).ok();
+    server.await.expect("server task panicked");
+    Ok(())
+}
+
+#[tokio::test]
+async fn closes_connection_that_stops_answering_pings() -> Result<(), Box<dyn Error>> {
+    let (addr, shutdown, server) = start_test_server().await;
+    let mut events = events::subscribe();
+
+    // Never polling the socket means pings are never answered.
+    let (_socket, _) = connect_async(format!("ws://{addr}/keepalive-dead")).await?;
+
+    let reason = timeout(Duration::from_secs(5), async {
+        loop {
+            if let StationEvent::Offline {
+                station_id, reason, ..
+            } = events.recv().await.expect("event stream")
+                && station_id == "keepalive-dead"
+            {
+                return reason;
+            }
+        }
+    })
+    .await?;
+    assert_eq!(reason, OfflineReason::PongTimeout);
+    assert!(!connections::is_connected("keepalive-dead"));
+
+    shutdown.send(()).ok();
+    server.await.expect("server task panicked");
+    Ok(())
+}
@@ -0,0 +1,98 @@
+use std::{error::Error, net::SocketAddr, path::PathBuf, sync::Arc};
+
+use axum::{Router, routing::get};
+use chrono::Utc;
+use common::LocalCaConfig;
+use occp_ws::pki::{self, LocalCa};
+use occp_ws::routes::{ca_certificate_route, healthcheck_route, upgrade_to_ws};
+use occp_ws::state::START_TIME;
+use occp_ws::tls;
+use rustls::{
+    ClientConfig, RootCertStore,
+    pki_types::{CertificateDer, PrivateKeyDer, ServerName, pem::PemObject},
+};
+use tokio::net::{TcpListener, TcpStream};
+use tokio_rustls::TlsConnector;
+use tokio_tungstenite::client_async;
+
+fn fresh_dir(name: &str) -> PathBuf {
+    let dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join(name);
+    let _ = std::fs::remove_dir_all(&dir);
+    dir
+}
+
+fn names(list: &[&str]) -> Vec<String> {
+    list.iter().map(|name| name.to_string()).collect()
+}
+
+#[test]
+fn persists_ca_and_renews_server_certificate_when_names_change() -> Result<(), Box<dyn Error>> {
+    let dir = fresh_dir("local-ca-persist");
+
+    let ca = LocalCa::load_or_create(&dir)?;
+    let ca_pem = ca.ca_certificate_pem().to_string();
+    assert!(ca.ensure_server_certificate(&names(&["localhost", "192.168.1.20"]))?);
+    assert!(!ca.ensure_server_certificate(&names(&["localhost", "192.168.1.20"]))?);
+
+    let reopened = LocalCa::load_or_create(&dir)?;
+    assert_eq!(reopened.ca_certificate_pem(), ca_pem);
+    assert!(!reopened.ensure_server_certificate(&names(&["localhost", "192.168.1.20"]))?);
+    assert!(reopened.ensure_server_certificate(&names(&["localhost", "plughome.lan"]))?);
+
+    assert!(reopened.issue_client_certificate("../escape").is_err());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn serves_mutual_tls_with_certificates_from_local_ca() -> Result<(), Box<dyn Error>> {
+    START_TIME.get_or_init(|| async { Utc::now() }).await;
+    let dir = fresh_dir("local-ca-mtls");
+
+    let config = LocalCaConfig {
+        enabled: true,
+        dir: dir.clone(),
+        server_names: names(&["localhost"]),
+        client_auth: true,
+        client_cert_required: true,
+    };
+    let server_config = pki::server_config(&config)?;
+
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let addr: SocketAddr = listener.local_addr()?;
+    let router = Router::new()
+        .route("/:station_id", get(upgrade_to_ws))
+        .route("/pki/ca.pem", get(ca_certificate_route))
+        .route("/", get(healthcheck_route));
+    let server = tokio::spawn(async move {
+        tls::serve(listener, router, server_config)
+            .await
+            .expect("TLS server");
+    });
+
+    // Chargers trust the exported CA certificate and use an issued client cert.
+    let ca = LocalCa::load_or_create(&dir)?;
+    let issued = ca.issue_client_certificate("local-ca-station")?;
+
+    let mut roots = RootCertStore::empty();
+    roots.add(CertificateDer::from_pem_slice(
+        ca.ca_certificate_pem().as_bytes(),
+    )?)?;
+    let client_config = ClientConfig::builder()
+        .with_root_certificates(roots)
+        .with_client_auth_cert(
+            vec![CertificateDer::from_pem_slice(issued.cert_pem.as_bytes())?],
+            PrivateKeyDer::from_pem_slice(issued.key_pem.as_bytes())?,
+        )?;
+
+    let tcp = TcpStream::connect(addr).await?;
+    let tls_stream = TlsConnector::from(Arc::new(client_config))
+        .connect(ServerName::try_from("localhost")?, tcp)
+        .await?;
+    let url = format!("wss://localhost:{}/local-ca-station", addr.port());
+    let (mut socket, _) = client_async(url, tls_stream).await?;
+    socket.close(None).await?;
+
+    server.abort();
+    Ok(())
+}
@@ -0,0 +1,418 @@
+use std::{error::Error, time::Duration};
+
+use ::common::MqttConfig;
+use bytes::BytesMut;
+use futures::SinkExt;
+use occp_ws::mqtt;
+use occp_ws::types::*;
+use rumqttc::{
+    ConnAck, ConnectReturnCode, Packet, PubAck, Publish, QoS, SubAck, SubscribeReasonCode,
+};
+use serde_json::{Value, json};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpListener,
+    sync::mpsc,
+    time::timeout,
+};
+use tokio_tungstenite::{connect_async, tungstenite::Message as WsMessage};
+
+mod common;
+
+use common::{call, next_frame, start_test_server};
+
+const MAX_PACKET_SIZE: usize = 1024 * 1024;
+
+/// A minimal MQTT 3.1.1 broker for one client: it acknowledges what the
+/// client sends, hands every packet to the test and forwards the test's
+/// publishes to the client.
+struct Broker {
+    port: u16,
+    received: mpsc::UnboundedReceiver<Packet>,
+    outgoing: mpsc::UnboundedSender<Publish>,
+}
+
+async fn start_broker() -> Broker {
+    let listener = TcpListener::bind("127.0.0.1:0")
+        .await
+        .expect("bind broker listener");
+    let port = listener.local_addr().expect("broker addr").port();
+    let (received_tx, received) = mpsc::unbounded_channel();
+    let (outgoing, mut outgoing_rx) = mpsc::unbounded_channel::<Publish>();
+
+    tokio::spawn(async move {
+        let (mut stream, _) = listener.accept().await.expect("broker accept");
+        let mut buffer = BytesMut::new();
+        loop {
+            let mut replies = Vec::new();
+            tokio::select! {
+                read = stream.read_buf(&mut buffer) => {
+                    if read.expect("broker read") == 0 {
+                        break;
+                    }
+                    while let Ok(packet) = Packet::read(&mut buffer, MAX_PACKET_SIZE) {
+                        match &packet {
+                            Packet::Connect(_) => replies.push(Packet::ConnAck(ConnAck::new(
+                                ConnectReturnCode::Success,
+                                false,
+                            ))),
+                            Packet::Subscribe(subscribe) => replies.push(Packet::SubAck(SubAck::new(
+                                subscribe.pkid,
+                                vec![
+                                    SubscribeReasonCode::Success(QoS::AtLeastOnce);
+                                    subscribe.filters.len()
+                                ],
+                            ))),
+                            Packet::Publish(publish) if publish.qos == QoS::AtLeastOnce => {
+                                replies.push(Packet::PubAck(PubAck::new(publish.pkid)));
+                            }
+                            Packet::PingReq => replies.push(Packet::PingResp),
+                            _ => {}
+                        }
+                        let _ = received_tx.send(packet);
+                    }
+                }
+                publish = outgoing_rx.recv() => match publish {
+                    Some(publish) => replies.push(Packet::Publish(publish)),
+                    None => break,
+                },
+            }
+            for reply in replies {
+                let mut bytes = BytesMut::new();
+                reply
+                    .write(&mut bytes, MAX_PACKET_SIZE)
+                    .expect("encode packet");
+                stream.write_all(&bytes).await.expect("broker write");
+            }
+        }
+    });
+
+    Broker {
+        port,
+        received,
+        outgoing,
+    }
+}
+
+impl Broker {
+    async fn next_packet(&mut self) -> Packet {
+        timeout(Duration::from_secs(5), self.received.recv())
+            .await
+            .expect("broker packet in time")
+            .expect("broker running")
+    }
+
+    /// Next publish to `topic`, skipping everything else.
+    async fn expect_publish(&mut self, topic: &str) -> Publish {
+        loop {
+            if let Packet::Publish(publish) = self.next_packet().await
+                && publish.topic == topic
+            {
+                return publish;
+            }
+        }
+    }
+
+    /// Next JSON publish to `topic` for which `done` holds.
+    async fn expect_json(&mut self, topic: &str, done: impl Fn(&Value) -> bool) -> Value {
+        loop {
+            let publish = self.expect_publish(topic).await;
+            let value: Value = serde_json::from_slice(&publish.payload).expect("JSON payload");
+            if done(&value) {
+                return value;
+            }
+        }
+    }
+
+    fn send(&self, topic: &str, payload: Value) {
+        let publish = Publish::new(topic, QoS::AtMostOnce, payload.to_string());
+        self.outgoing.send(publish).expect("broker running");
+    }
+}
+
+fn text(publish: &Publish) -> &str {
+    std::str::from_utf8(&publish.payload).expect("UTF-8 payload")
+}
+
+#[tokio::test]
+async fn bridge_publishes_state_and_runs_commands() -> Result<(), Box<dyn Error>> {
+    let (addr, shutdown, server) = start_test_server().await;
+    let mut broker = start_broker().await;
+    mqtt::spawn(MqttConfig {
+        host: "127.0.0.1".to_string(),
+        port: broker.port,
+        client_id: "plughome-test".to_string(),
+        topic_prefix: "home".to_string(),
+        username: None,
+        password: None,
+        keep_alive: Duration::from_secs(30),
+        discovery_prefix: None,
+        remote_id_tag: "HomeAssistant".to_string(),
+    });
+
+    let Packet::Connect(connect) = broker.next_packet().await else {
+        panic!("expected CONNECT first");
+    };
+    let will = connect.last_will.expect("last will set");
+    assert_eq!(will.topic, "home/status");
+    assert_eq!(&will.message[..], b"offline");
+    assert!(will.retain);
+    let status = broker.expect_publish("home/status").await;
+    assert_eq!(text(&status), "online");
+    assert!(status.retain);
+    loop {
+        if let Packet::Subscribe(subscribe) = broker.next_packet().await {
+            assert_eq!(subscribe.filters[0].path, "home/stations/+/command/+");
+            break;
+        }
+    }
+
+    let (mut socket, _) = connect_async(format!("ws://{addr}/mqtt-1")).await?;
+    let online = broker.expect_publish("home/stations/mqtt-1/online").await;
+    assert_eq!(text(&online), "true");
+    assert!(online.retain);
+    call(
+        &mut socket,
+        "boot",
+        "BootNotification",
+        json!({ "chargePointVendor": "PlugCo", "chargePointModel": "Wallbox 11" }),
+    )
+    .await?;
+    let info = broker
+        .expect_json("home/stations/mqtt-1/info", |_| true)
+        .await;
+    assert_eq!(info["vendor"], "PlugCo");
+    assert_eq!(info["registration_status"], "Accepted");
+
+    call(
+        &mut socket,
+        "status",
+        "StatusNotification",
+        json!({ "connectorId": 1, "errorCode": "NoError", "status": "Charging" }),
+    )
+    .await?;
+    let status = broker
+        .expect_json("home/stations/mqtt-1/connectors/1/status", |_| true)
+        .await;
+    assert_eq!(status["status"], "Charging");
+
+    let started = call(
+        &mut socket,
+        "start",
+        "StartTransaction",
+        json!({
+            "connectorId": 1,
+            "idTag": "CARD-1",
+            "meterStart": 1000,
+            "timestamp": "2026-03-01T10:00:00Z"
+        }),
+    )
+    .await?;
+    call(
+        &mut socket,
+        "meter",
+        "MeterValues",
+        json!({
+            "connectorId": 1,
+            "transactionId": started["transactionId"],
+            "meterValue": [{
+                "timestamp": "2026-03-01T10:15:00Z",
+                "sampledValue": [
+                    { "value": "2.5", "unit": "kWh" },
+                    { "value": "7200", "measurand": "Power.Active.Import", "unit": "W" }
+                ]
+            }]
+        }),
+    )
+    .await?;
+    let power = broker
+        .expect_publish("home/stations/mqtt-1/connectors/1/power_w")
+        .await;
+    assert_eq!(text(&power), "7200");
+    loop {
+        let energy = broker
+            .expect_publish("home/stations/mqtt-1/connectors/1/energy_wh")
+            .await;
+        if text(&energy) == "1500" {
+            break;
+        }
+    }
+
+    broker.send(
+        "home/stations/mqtt-1/command/current_limit",
+        json!({ "limit_a": 16 }),
+    );
+    match next_frame(&mut socket).await? {
+        OcppMessageType::Call(2, message_id, action, _) => {
+            assert_eq!(action, "SetChargingProfile");
+            let reply = json!([3, message_id, { "status": "Accepted" }]);
+            socket.send(WsMessage::Text(reply.to_string())).await?;
+        }
+        other => panic!("expected SetChargingProfile, got {other:?}"),
+    }
+    let job = broker
+        .expect_json("home/stations/mqtt-1/command/result", |job| {
+            job["status"] == "accepted"
+        })
+        .await;
+    assert_eq!(job["command"], "set_current_limit");
+    assert_eq!(job["limit_a"], 16.0);
+
+    broker.send(
+        "home/stations/mqtt-1/command/start",
+        json!({ "id_tag": "" }),
+    );
+    let refused = broker
+        .expect_json("home/stations/mqtt-1/command/result", |result| {
+            result["error"].is_string()
+        })
+        .await;
+    assert_eq!(refused["command"], "start");
+
+    socket.close(None).await?;
+    let offline = broker.expect_publish("home/stations/mqtt-1/online").await;
+    assert_eq!(text(&offline), "false");
+
+    shutdown.send(()).ok();
+    server.await.expect("server task panicked");
+    Ok(())
+}
+
+#[tokio::test]
+async fn home_assistant_discovery_groups_entities_under_one_device() -> Result<(), Box<dyn Error>> {
+    let (addr, shutdown, server) = start_test_server().await;
+    let mut broker = start_broker().await;
+    mqtt::spawn(MqttConfig {
+        host: "127.0.0.1".to_string(),
+        port: broker.port,
+        client_id: "plughome-ha".to_string(),
+        topic_prefix: "ha".to_string(),
+        username: None,
+        password: None,
+        keep_alive: Duration::from_secs(30),
+        discovery_prefix: Some("homeassistant".to_string()),
+        remote_id_tag: "HA".to_string(),
+    });
+    broker.expect_publish("ha/status").await;
+
+    let (mut socket, _) = connect_async(format!("ws://{addr}/mqtt-ha")).await?;
+    call(
+        &mut socket,
+        "boot",
+        "BootNotification",
+        json!({
+            "chargePointVendor": "PlugCo",
+            "chargePointModel": "Wallbox 11",
+            "chargePointSerialNumber": "SN-HA",
+            "firmwareVersion": "2.4.1"
+        }),
+    )
+    .await?;
+    let online = broker
+        .expect_json(
+            "homeassistant/binary_sensor/plughome_mqtt_ha/online/config",
+            |_| true,
+        )
+        .await;
+    assert_eq!(online["device_class"], "connectivity");
+    assert_eq!(online["state_topic"], "ha/stations/mqtt-ha/online");
+    assert_eq!(online["unique_id"], "plughome_mqtt_ha_online");
+    let device = online["device"].clone();
+    assert_eq!(device["name"], "PlugCo Wallbox 11");
+    assert_eq!(device["manufacturer"], "PlugCo");
+    assert_eq!(device["model"], "Wallbox 11");
+    assert_eq!(device["sw_version"], "2.4.1");
+    assert_eq!(device["serial_number"], "SN-HA");
+
+    call(
+        &mut socket,
+        "status",
+        "StatusNotification",
+        json!({ "connectorId": 1, "errorCode": "NoError", "status": "Preparing" }),
+    )
+    .await?;
+    let config = |component: &str, object: &str| {
+        format!("homeassistant/{component}/plughome_mqtt_ha/connector_1_{object}/config")
+    };
+    let status = broker
+        .expect_json(&config("sensor", "status"), |_| true)
+        .await;
+    assert_eq!(
+        status["state_topic"],
+        "ha/stations/mqtt-ha/connectors/1/status"
+    );
+    assert_eq!(status["value_template"], "{{ value_json.status }}");
+    let power = broker
+        .expect_json(&config("sensor", "power"), |_| true)
+        .await;
+    assert_eq!(power["device_class"], "power");
+    assert_eq!(power["state_class"], "measurement");
+    assert_eq!(power["unit_of_measurement"], "W");
+    let energy = broker
+        .expect_json(&config("sensor", "energy"), |_| true)
+        .await;
+    assert_eq!(energy["device_class"], "energy");
+    assert_eq!(energy["state_class"], "total_increasing");
+    assert_eq!(energy["unit_of_measurement"], "Wh");
+    let limit = broker
+        .expect_json(&config("number", "current_limit"), |_| true)
+        .await;
+    assert_eq!(
+        limit["command_topic"],
+        "ha/stations/mqtt-ha/command/current_limit"
+    );
+    assert_eq!(
+        limit["command_template"],
+        "{\"connector_id\": 1, \"limit_a\": {{ value }}}"
+    );
+    let switch = broker
+        .expect_json(&config("switch", "charging"), |_| true)
+        .await;
+    assert_eq!(
+        switch["state_topic"],
+        "ha/stations/mqtt-ha/connectors/1/charging"
+    );
+    for entity in [&status, &power, &energy, &limit, &switch] {
+        assert_eq!(entity["device"]["identifiers"], device["identifiers"]);
+        assert_eq!(entity["availability_mode"], "all");
+    }
+
+    // Turning the switch on starts charging on that connector.
+    let payload_on: Value = serde_json::from_str(switch["payload_on"].as_str().expect("payload"))?;
+    broker.send("ha/stations/mqtt-ha/command/charging", payload_on);
+    match next_frame(&mut socket).await? {
+        OcppMessageType::Call(2, message_id, action, payload) => {
+            assert_eq!(action, "RemoteStartTransaction");
+            assert_eq!(payload["connectorId"], 1);
+            assert_eq!(payload["idTag"], "HA");
+            let reply = json!([3, message_id, { "status": "Accepted" }]);
+            socket.send(WsMessage::Text(reply.to_string())).await?;
+        }
+        other => panic!("expected RemoteStartTransaction, got {other:?}"),
+    }
+    call(
+        &mut socket,
+        "start",
+        "StartTransaction",
+        json!({
+            "connectorId": 1,
+            "idTag": "HA",
+            "meterStart": 0,
+            "timestamp": "2026-03-02T18:00:00Z"
+        }),
+    )
+    .await?;
+    loop {
+        let charging = broker
+            .expect_publish("ha/stations/mqtt-ha/connectors/1/charging")
+            .await;
+        if text(&charging) == "ON" {
+            break;
+        }
+    }
+
+    socket.close(None).await?;
+    shutdown.send(()).ok();
+    server.await.expect("server task panicked");
+    Ok(())
+}
@@ -70,3 +70,38 @@ fn serializes_call_result_as_ocpp_array() {
         json!([3, "abc", {"status": "Accepted", "currentTime": "2024-01-01T00:00:00Z", "interval": 300}])
     );
 }
+
+#[test]
+fn parses_call_payload_by_action() {
+    // An untagged parse would settle on an earlier variant with optional fields.
+    let raw = json!({"connectorId": 1, "errorCode": "NoError", "status": "Available"});
+
+    let payload = OcppPayload::from_request(&OcppActionEnum::StatusNotification, raw)
+        .expect("parse StatusNotification payload");
+
+    match payload {
+        OcppPayload::StatusNotification(StatusNotificationKind::Request(body)) => {
+            assert_eq!(body.connector_id, 1);
+        }
+        other => panic!("unexpected payload: {other:?}"),
+    }
+}
+
+#[test]
+fn parses_security_extension_actions() {
+    let action: OcppActionEnum = "SecurityEventNotification".parse().expect("known action");
+
+    let payload = OcppPayload::from_request(
+        &action,
+        json!({"type": "TamperDetectionActivated", "timestamp": "2024-01-01T00:00:00Z"}),
+    )
+    .expect("parse SecurityEventNotification payload");
+
+    match payload {
+        OcppPayload::SecurityEventNotification(SecurityEventNotificationKind::Request(body)) => {
+            assert_eq!(body.kind, "TamperDetectionActivated");
+            assert!(body.tech_info.is_none());
+        }
+        other => panic!("unexpected payload: {other:?}"),
+    }
+}
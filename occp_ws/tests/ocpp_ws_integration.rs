@@ -5,52 +5,26 @@ use std::{
     time::Duration,
 };
 
-use axum::{Router, routing::get};
-use chrono::Utc;
 use futures::{SinkExt, StreamExt};
-use occp_ws::routes::{healthcheck_route, upgrade_to_ws};
-use occp_ws::state::START_TIME;
+use occp_ws::persistence;
 use occp_ws::types::*;
 use rust_ocpp::v1_6::messages::boot_notification::BootNotificationRequest;
 use rust_ocpp::v1_6::messages::heart_beat::HeartbeatRequest;
-use tokio::{net::TcpListener, sync::oneshot, task::JoinHandle, time::timeout};
+use storage::MemoryStore;
+use tokio::{sync::oneshot, task::JoinHandle, time::timeout};
 use tokio_tungstenite::{connect_async, tungstenite::Message as WsMessage};
 use tracing_subscriber::EnvFilter;
 
+mod common;
+
 async fn start_test_server() -> (SocketAddr, oneshot::Sender<()>, JoinHandle<()>) {
     let _ = tracing_subscriber::fmt()
         .with_env_filter(EnvFilter::from_default_env())
         .with_test_writer()
         .try_init();
 
-    START_TIME.get_or_init(|| async { Utc::now() }).await;
-
-    let listener = TcpListener::bind("127.0.0.1:0")
-        .await
-        .expect("bind test listener");
-    let addr = listener.local_addr().expect("listener addr");
-
-    let router = Router::new()
-        .route("/:station_id", get(upgrade_to_ws))
-        .route("/", get(healthcheck_route));
-
-    let (shutdown_tx, shutdown_rx) = oneshot::channel();
-
-    let server = axum::serve(
-        listener,
-        router.into_make_service_with_connect_info::<SocketAddr>(),
-    )
-    .with_graceful_shutdown(async {
-        let _ = shutdown_rx.await;
-    });
-
-    let handle = tokio::spawn(async move {
-        if let Err(err) = server.await {
-            panic!("test server error: {err}");
-        }
-    });
-
-    (addr, shutdown_tx, handle)
+    persistence::install(MemoryStore::new());
+    common::start_test_server().await
 }
 
 async fn recv_text_within(
@@ -125,6 +99,13 @@ async fn handles_heartbeat_call_over_websocket() -> Result<(), Box<dyn Error>> {
         }
         other => panic!("unexpected boot response: {other:?}"),
     }
+    let stored = persistence::store()
+        .expect("store installed")
+        .stations()?
+        .into_iter()
+        .find(|station| station.station_id == "station-123")
+        .expect("boot persisted");
+    assert_eq!(stored.serial_number.as_deref(), Some("SN-hb-123"));
 
     let message_id = "hb-1".to_string();
     let payload = OcppPayload::Heartbeat(HeartbeatKind::Request(HeartbeatRequest {}));
@@ -0,0 +1,234 @@
+use std::error::Error;
+
+use occp_ws::events::{self, StationEvent};
+use occp_ws::transactions::{self, TransactionStatus};
+use serde_json::{Value, json};
+use tokio_tungstenite::connect_async;
+
+mod common;
+
+use common::{call, start_test_server};
+
+fn energy(timestamp: &str, kwh: &str) -> Value {
+    json!({
+        "timestamp": timestamp,
+        "sampledValue": [{ "value": kwh, "unit": "kWh" }]
+    })
+}
+
+#[tokio::test]
+async fn replayed_transaction_messages_are_applied_once() -> Result<(), Box<dyn Error>> {
+    let (addr, shutdown, server) = start_test_server().await;
+    let (mut socket, _) = connect_async(format!("ws://{addr}/replay-queued")).await?;
+
+    let start = json!({
+        "connectorId": 1,
+        "idTag": "TAG-1",
+        "meterStart": 2000,
+        "timestamp": "2024-05-01T08:00:00Z"
+    });
+    let first = call(&mut socket, "q-1", "StartTransaction", start.clone()).await?;
+    // The same Call again, then the same content under a new message id.
+    let replayed = call(&mut socket, "q-1", "StartTransaction", start.clone()).await?;
+    let resent = call(&mut socket, "q-9", "StartTransaction", start).await?;
+    assert_eq!(first, replayed);
+    assert_eq!(first["transactionId"], resent["transactionId"]);
+    let transaction_id = first["transactionId"].as_i64().expect("transaction id") as i32;
+    assert_eq!(
+        transactions::open_transactions(Some("replay-queued")).len(),
+        1
+    );
+
+    // Samples arrive newest first and one batch is sent twice.
+    let late = json!({
+        "connectorId": 1,
+        "transactionId": transaction_id,
+        "meterValue": [energy("2024-05-01T08:30:00Z", "5.0")]
+    });
+    call(&mut socket, "q-2", "MeterValues", late.clone()).await?;
+    call(
+        &mut socket,
+        "q-3",
+        "MeterValues",
+        json!({
+            "connectorId": 1,
+            "transactionId": transaction_id,
+            "meterValue": [energy("2024-05-01T08:15:00Z", "3.0")]
+        }),
+    )
+    .await?;
+    call(&mut socket, "q-4", "MeterValues", late).await?;
+
+    let transaction = transactions::get(transaction_id).expect("transaction");
+    let readings: Vec<f64> = transaction.meter_samples.iter().map(|s| s.wh).collect();
+    assert_eq!(readings, vec![3000.0, 5000.0]);
+    assert_eq!(transaction.last_meter_value, Some(5000.0));
+
+    let stop = json!({
+        "transactionId": transaction_id,
+        "meterStop": 6000,
+        "timestamp": "2024-05-01T09:00:00Z",
+        "reason": "EVDisconnected"
+    });
+    call(&mut socket, "q-5", "StopTransaction", stop.clone()).await?;
+    call(&mut socket, "q-5", "StopTransaction", stop).await?;
+    let transaction = transactions::get(transaction_id).expect("transaction");
+    assert_eq!(transaction.status, TransactionStatus::Completed);
+    assert_eq!(transaction.meter_stop, Some(6000));
+
+    socket.close(None).await?;
+    shutdown.send(()).ok();
+    server.await.expect("server task panicked");
+    Ok(())
+}
+
+#[tokio::test]
+async fn stop_resent_under_a_new_message_id_is_published_once() -> Result<(), Box<dyn Error>> {
+    let (addr, shutdown, server) = start_test_server().await;
+    let (mut socket, _) = connect_async(format!("ws://{addr}/replay-stop")).await?;
+    let mut events = events::subscribe();
+
+    let started = call(
+        &mut socket,
+        "s-1",
+        "StartTransaction",
+        json!({
+            "connectorId": 1,
+            "idTag": "TAG-3",
+            "meterStart": 1000,
+            "timestamp": "2024-05-03T18:00:00Z"
+        }),
+    )
+    .await?;
+    let transaction_id = started["transactionId"].as_i64().expect("transaction id") as i32;
+    let stop = json!({
+        "transactionId": transaction_id,
+        "meterStop": 8000,
+        "timestamp": "2024-05-03T20:00:00Z",
+        "reason": "EVDisconnected"
+    });
+    call(&mut socket, "s-2", "StopTransaction", stop.clone()).await?;
+    call(&mut socket, "s-7", "StopTransaction", stop).await?;
+
+    let mut stopped = 0;
+    while let Ok(event) = events.try_recv() {
+        if let StationEvent::SessionStopped { session, .. } = event
+            && session.transaction_id == transaction_id
+        {
+            stopped += 1;
+        }
+    }
+    assert_eq!(stopped, 1);
+    let transaction = transactions::get(transaction_id).expect("transaction");
+    assert_eq!(transaction.meter_stop, Some(8000));
+
+    socket.close(None).await?;
+    shutdown.send(()).ok();
+    server.await.expect("server task panicked");
+    Ok(())
+}
+
+#[tokio::test]
+async fn stop_for_unknown_transaction_is_recorded() -> Result<(), Box<dyn Error>> {
+    let (addr, shutdown, server) = start_test_server().await;
+    let (mut socket, _) = connect_async(format!("ws://{addr}/replay-lost-start")).await?;
+
+    let response = call(
+        &mut socket,
+        "lost-stop",
+        "StopTransaction",
+        json!({
+            "transactionId": 90210,
+            "idTag": "TAG-2",
+            "meterStop": 12000,
+            "timestamp": "2024-05-02T07:00:00Z",
+            "transactionData": [
+                energy("2024-05-02T06:00:00Z", "11.0"),
+                energy("2024-05-02T05:00:00Z", "10.0")
+            ]
+        }),
+    )
+    .await?;
+    assert_eq!(response["idTagInfo"]["status"], "Accepted");
+
+    let transaction = transactions::get(90210).expect("recorded from the stop");
+    assert_eq!(transaction.station_id, "replay-lost-start");
+    assert_eq!(transaction.status, TransactionStatus::Completed);
+    assert_eq!(transaction.meter_start, 10000);
+    assert_eq!(transaction.meter_stop, Some(12000));
+    assert_eq!(
+        transaction.started_at.to_rfc3339(),
+        "2024-05-02T05:00:00+00:00"
+    );
+    assert!(transaction.flag.is_some());
+
+    // New transactions do not reuse the id.
+    let started = call(
+        &mut socket,
+        "next-start",
+        "StartTransaction",
+        json!({
+            "connectorId": 1,
+            "idTag": "TAG-2",
+            "meterStart": 12000,
+            "timestamp": "2024-05-02T08:00:00Z"
+        }),
+    )
+    .await?;
+    assert_ne!(started["transactionId"].as_i64(), Some(90210));
+
+    socket.close(None).await?;
+    shutdown.send(()).ok();
+    server.await.expect("server task panicked");
+    Ok(())
+}
+
+#[tokio::test]
+async fn charger_ids_at_i32_max_do_not_overflow_new_ids() -> Result<(), Box<dyn Error>> {
+    let (addr, shutdown, server) = start_test_server().await;
+    let (mut socket, _) = connect_async(format!("ws://{addr}/replay-max-id")).await?;
+    let start = |meter_start: i32| {
+        json!({
+            "connectorId": 1,
+            "idTag": "TAG-3",
+            "meterStart": meter_start,
+            "timestamp": "2024-05-03T08:00:00Z"
+        })
+    };
+
+    call(
+        &mut socket,
+        "max-stop",
+        "StopTransaction",
+        json!({
+            "transactionId": i32::MAX,
+            "meterStop": 500,
+            "timestamp": "2024-05-03T07:00:00Z"
+        }),
+    )
+    .await?;
+    let started = call(&mut socket, "after-stop", "StartTransaction", start(1000)).await?;
+    let after_stop = started["transactionId"].as_i64().expect("transaction id");
+    assert!(after_stop > 0 && after_stop < i64::from(i32::MAX));
+
+    // Restoring a stored transaction with the highest id wraps the sequence.
+    let mut restored = transactions::get(i32::MAX).expect("recorded from the stop");
+    restored.station_id = "replay-max-id-restored".to_string();
+    transactions::restore(vec![restored]);
+    let started = call(
+        &mut socket,
+        "after-restore",
+        "StartTransaction",
+        start(2000),
+    )
+    .await?;
+    let after_restore = started["transactionId"].as_i64().expect("transaction id");
+    assert!(after_restore > 0 && after_restore != after_stop);
+    let transaction = transactions::get(after_restore as i32).expect("transaction");
+    assert_eq!(transaction.station_id, "replay-max-id");
+
+    socket.close(None).await?;
+    shutdown.send(()).ok();
+    server.await.expect("server task panicked");
+    Ok(())
+}
@@ -0,0 +1,247 @@
+use std::{collections::BTreeSet, error::Error, net::SocketAddr};
+
+use axum::{
+    body::Body,
+    extract::connect_info::MockConnectInfo,
+    http::{Method, Request, StatusCode, header},
+};
+use occp_ws::{persistence, rest};
+use serde_json::Value;
+use storage::MemoryStore;
+use tower::ServiceExt;
+use utoipa::OpenApi;
+
+/// Source of the v1 router, the routes the description must match.
+const ROUTER_SOURCE: &str = include_str!("../src/rest/mod.rs");
+
+/// Routes serving the description itself, which it leaves out.
+const UNDOCUMENTED: [&str; 4] = ["/openapi.json", "/docs", "/docs/", "/docs/*file"];
+
+const METHODS: [&str; 5] = ["get", "post", "put", "patch", "delete"];
+
+fn spec() -> Result<Value, Box<dyn Error>> {
+    Ok(serde_json::to_value(rest::openapi::ApiDoc::openapi())?)
+}
+
+/// The `(path, method)` pairs registered in `v1_router`, with axum's
+/// `:param` segments written the OpenAPI way.
+fn routed_operations() -> BTreeSet<(String, String)> {
+    let start = ROUTER_SOURCE
+        .find("pub fn v1_router()")
+        .expect("v1_router in rest/mod.rs");
+    let body = &ROUTER_SOURCE[start..];
+    let body = &body[..body.find(".fallback(").expect("router fallback")];
+
+    let mut operations = BTreeSet::new();
+    for route in body.split(".route(").skip(1) {
+        let path = route.split('"').nth(1).expect("route path literal");
+        if UNDOCUMENTED.contains(&path) {
+            continue;
+        }
+        let path = path
+            .split('/')
+            .map(|segment| match segment.strip_prefix(':') {
+                Some(param) => format!("{{{param}}}"),
+                None => segment.to_string(),
+            })
+            .collect::<Vec<_>>()
+            .join("/");
+        for method in METHODS {
+            let call = format!("{method}(");
+            let called = route.match_indices(&call).any(|(index, _)| {
+                !route[..index].ends_with(|c: char| c.is_alphanumeric() || c == '_' || c == ':')
+            });
+            if called {
+                operations.insert((path.clone(), method.to_string()));
+            }
+        }
+    }
+    operations
+}
+
+fn documented_operations(spec: &Value) -> BTreeSet<(String, String)> {
+    let mut operations = BTreeSet::new();
+    for (path, item) in spec["paths"].as_object().expect("paths") {
+        for method in METHODS {
+            if item.get(method).is_some() {
+                operations.insert((path.clone(), method.to_string()));
+            }
+        }
+    }
+    operations
+}
+
+fn collect_refs(value: &Value, refs: &mut BTreeSet<String>) {
+    match value {
+        Value::Object(map) => {
+            for (key, value) in map {
+                match (key.as_str(), value) {
+                    ("$ref", Value::String(target)) => {
+                        refs.insert(target.clone());
+                    }
+                    _ => collect_refs(value, refs),
+                }
+            }
+        }
+        Value::Array(values) => values.iter().for_each(|value| collect_refs(value, refs)),
+        _ => {}
+    }
+}
+
+#[test]
+fn description_matches_the_routes() -> Result<(), Box<dyn Error>> {
+    let spec = spec()?;
+    let routed = routed_operations();
+    let documented = documented_operations(&spec);
+    assert!(routed.len() > 30, "router parsing found {routed:?}");
+
+    let undocumented: Vec<_> = routed.difference(&documented).collect();
+    let unrouted: Vec<_> = documented.difference(&routed).collect();
+    assert!(
+        undocumented.is_empty() && unrouted.is_empty(),
+        "routes missing from the description: {undocumented:?}; \
+         described operations without a route: {unrouted:?}"
+    );
+    Ok(())
+}
+
+#[test]
+fn description_is_self_contained() -> Result<(), Box<dyn Error>> {
+    let spec = spec()?;
+    assert_eq!(spec["openapi"], "3.1.0");
+    assert_eq!(spec["servers"][0]["url"], "/api/v1");
+
+    let mut operation_ids = BTreeSet::new();
+    for (path, item) in spec["paths"].as_object().expect("paths") {
+        for method in METHODS {
+            let Some(operation) = item.get(method) else {
+                continue;
+            };
+            let id = operation["operationId"]
+                .as_str()
+                .unwrap_or_else(|| panic!("{method} {path} has no operation id"));
+            assert!(operation_ids.insert(id.to_string()), "duplicate {id}");
+            assert!(
+                operation["tags"]
+                    .as_array()
+                    .is_some_and(|tags| !tags.is_empty()),
+                "{method} {path} has no tag"
+            );
+            assert!(
+                operation["responses"]
+                    .as_object()
+                    .is_some_and(|responses| !responses.is_empty()),
+                "{method} {path} has no responses"
+            );
+        }
+    }
+
+    let mut refs = BTreeSet::new();
+    collect_refs(&spec, &mut refs);
+    let schemas = spec["components"]["schemas"].as_object().expect("schemas");
+    for target in refs {
+        let name = target
+            .strip_prefix("#/components/schemas/")
+            .unwrap_or_else(|| panic!("unexpected reference {target}"));
+        assert!(schemas.contains_key(name), "dangling reference {target}");
+    }
+
+    // Logging in is the one operation that needs no credentials.
+    assert_eq!(
+        spec["paths"]["/auth/login"]["post"]["security"][0],
+        serde_json::json!({})
+    );
+    assert!(spec["components"]["securitySchemes"]["bearer_token"].is_object());
+    assert!(spec["components"]["securitySchemes"]["session_cookie"].is_object());
+    Ok(())
+}
+
+#[tokio::test]
+async fn described_operations_are_routed() -> Result<(), Box<dyn Error>> {
+    persistence::install(MemoryStore::new());
+    let spec = spec()?;
+    let router =
+        rest::v1_router().layer(MockConnectInfo(SocketAddr::from(([192, 0, 2, 7], 40_000))));
+
+    for (path, method) in documented_operations(&spec) {
+        let uri = path
+            .split('/')
+            .map(|segment| {
+                if segment.starts_with('{') {
+                    "1"
+                } else {
+                    segment
+                }
+            })
+            .collect::<Vec<_>>()
+            .join("/");
+        let request = Request::builder()
+            .method(Method::from_bytes(method.to_uppercase().as_bytes())?)
+            .uri(&uri)
+            .body(Body::empty())?;
+        let response = router.clone().oneshot(request).await?;
+        let status = response.status();
+        assert_ne!(status, StatusCode::METHOD_NOT_ALLOWED, "{method} {uri}");
+        if status == StatusCode::NOT_FOUND {
+            let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await?;
+            let body: Value = serde_json::from_slice(&bytes)?;
+            assert_ne!(
+                body["error"]["message"], "No such API endpoint",
+                "{method} {uri} is not routed"
+            );
+        }
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn description_and_docs_need_no_credentials() -> Result<(), Box<dyn Error>> {
+    persistence::install(MemoryStore::new());
+    let router = rest::v1_router_with_auth();
+
+    let response = router
+        .clone()
+        .oneshot(Request::get("/openapi.json").body(Body::empty())?)
+        .await?;
+    assert_eq!(response.status(), StatusCode::OK);
+    let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await?;
+    let served: Value = serde_json::from_slice(&bytes)?;
+    assert_eq!(served, spec()?);
+
+    let response = router
+        .clone()
+        .oneshot(Request::get("/docs").body(Body::empty())?)
+        .await?;
+    assert_eq!(response.status(), StatusCode::SEE_OTHER);
+    assert_eq!(response.headers()[header::LOCATION], "docs/");
+
+    let response = router
+        .clone()
+        .oneshot(Request::get("/docs/").body(Body::empty())?)
+        .await?;
+    assert_eq!(response.status(), StatusCode::OK);
+    let content_type = response.headers()[header::CONTENT_TYPE]
+        .to_str()?
+        .to_string();
+    assert!(content_type.starts_with("text/html"));
+    let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await?;
+    let page = String::from_utf8(bytes.to_vec())?;
+    assert!(page.contains("swagger-ui"));
+    assert!(!page.contains("https://"));
+
+    // The viewer's files come from the binary and point at the description.
+    let response = router
+        .clone()
+        .oneshot(Request::get("/docs/swagger-initializer.js").body(Body::empty())?)
+        .await?;
+    assert_eq!(response.status(), StatusCode::OK);
+    let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await?;
+    assert!(String::from_utf8(bytes.to_vec())?.contains("\"../openapi.json\""));
+
+    // Everything else still needs a token.
+    let response = router
+        .oneshot(Request::get("/stations").body(Body::empty())?)
+        .await?;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    Ok(())
+}
@@ -0,0 +1,200 @@
+use std::{error::Error, net::SocketAddr};
+
+use chrono::{TimeZone, Utc};
+use occp_ws::transactions::{self, TransactionStatus};
+use occp_ws::{connectors, persistence, stations};
+use rust_ocpp::v1_6::types::{ChargePointStatus, RegistrationStatus};
+use serde_json::json;
+use storage::{
+    ConnectorRow, MemoryStore, MeterReading, StationMetadataRow, StationRow, TransactionRow,
+};
+use tokio::{sync::oneshot, task::JoinHandle};
+use tokio_tungstenite::connect_async;
+
+mod common;
+
+use common::call;
+
+async fn start_test_server() -> (SocketAddr, oneshot::Sender<()>, JoinHandle<()>) {
+    persistence::install(MemoryStore::new());
+    common::start_test_server().await
+}
+
+#[tokio::test]
+async fn charger_messages_are_written_through() -> Result<(), Box<dyn Error>> {
+    let (addr, shutdown, server) = start_test_server().await;
+    let (mut socket, _) = connect_async(format!("ws://{addr}/persist-live")).await?;
+
+    call(
+        &mut socket,
+        "boot",
+        "BootNotification",
+        json!({
+            "chargePointVendor": "PlugCo",
+            "chargePointModel": "Wallbox 11",
+            "chargePointSerialNumber": "SN-LIVE"
+        }),
+    )
+    .await?;
+    call(
+        &mut socket,
+        "status",
+        "StatusNotification",
+        json!({ "connectorId": 1, "errorCode": "NoError", "status": "Charging" }),
+    )
+    .await?;
+    let started = call(
+        &mut socket,
+        "start",
+        "StartTransaction",
+        json!({
+            "connectorId": 1,
+            "idTag": "TAG-LIVE",
+            "meterStart": 500,
+            "timestamp": "2024-05-01T10:00:00Z"
+        }),
+    )
+    .await?;
+    let transaction_id = started["transactionId"].as_i64().expect("transaction id") as i32;
+    call(
+        &mut socket,
+        "meter",
+        "MeterValues",
+        json!({
+            "connectorId": 1,
+            "transactionId": transaction_id,
+            "meterValue": [{
+                "timestamp": "2024-05-01T10:15:00Z",
+                "sampledValue": [
+                    { "value": "1.5", "unit": "kWh" },
+                    { "value": "7400", "measurand": "Power.Active.Import", "unit": "W" }
+                ]
+            }]
+        }),
+    )
+    .await?;
+
+    // Waits for the writes queued so far.
+    let store = persistence::store().expect("store installed");
+    let station = store
+        .stations()?
+        .into_iter()
+        .find(|row| row.station_id == "persist-live")
+        .expect("station saved");
+    assert_eq!(station.serial_number.as_deref(), Some("SN-LIVE"));
+    assert_eq!(station.registration_status, "Accepted");
+    assert_eq!(
+        store.status_history("persist-live", 10)?[0].status,
+        "Charging"
+    );
+    let saved = store
+        .transaction(transaction_id)?
+        .expect("transaction saved");
+    assert_eq!(saved.status, "active");
+    assert_eq!(saved.last_meter_value, Some(1500.0));
+    let measurands: Vec<String> = store
+        .meter_readings(transaction_id)?
+        .into_iter()
+        .map(|reading| reading.measurand)
+        .collect();
+    assert_eq!(
+        measurands,
+        vec!["Energy.Active.Import.Register", "Power.Active.Import"]
+    );
+    assert!(store.id_tags()?.iter().any(|tag| tag.id_tag == "TAG-LIVE"));
+
+    socket.close(None).await?;
+    shutdown.send(()).ok();
+    server.await.expect("server task panicked");
+    Ok(())
+}
+
+#[tokio::test]
+async fn restore_loads_saved_state() -> Result<(), Box<dyn Error>> {
+    let (_addr, shutdown, server) = start_test_server().await;
+    let store = persistence::store().expect("store installed");
+    let at = Utc.with_ymd_and_hms(2024, 4, 30, 20, 0, 0).unwrap();
+
+    store.save_station(&StationRow {
+        station_id: "persist-restored".to_string(),
+        registration_status: "Accepted".to_string(),
+        vendor: "PlugCo".to_string(),
+        model: "Wallbox 22".to_string(),
+        serial_number: Some("SN-OLD".to_string()),
+        firmware_version: Some("1.2.3".to_string()),
+        first_seen: at,
+        last_boot: at,
+    })?;
+    store.save_station_metadata(&StationMetadataRow {
+        station_id: "persist-restored".to_string(),
+        name: Some("Carport".to_string()),
+        location: None,
+        notes: None,
+        heartbeat_interval: Some(90),
+        updated_at: at,
+    })?;
+    store.save_connector_status(&ConnectorRow {
+        station_id: "persist-restored".to_string(),
+        connector_id: 1,
+        status: "SuspendedEV".to_string(),
+        error_code: "NoError".to_string(),
+        info: None,
+        timestamp: Some(at),
+        updated_at: at,
+    })?;
+    store.save_transaction(&TransactionRow {
+        transaction_id: 50_000,
+        station_id: "persist-restored".to_string(),
+        connector_id: 1,
+        id_tag: "TAG-OLD".to_string(),
+        meter_start: 100,
+        started_at: at,
+        last_meter_value: Some(4100.0),
+        last_meter_at: Some(at),
+        peak_power_w: None,
+        status: "active".to_string(),
+        meter_stop: None,
+        stopped_at: None,
+        stop_reason: None,
+        flag: None,
+    })?;
+    store.save_meter_readings(&[MeterReading {
+        station_id: "persist-restored".to_string(),
+        connector_id: 1,
+        transaction_id: Some(50_000),
+        timestamp: at,
+        measurand: "Energy.Active.Import.Register".to_string(),
+        phase: None,
+        location: None,
+        context: None,
+        format: None,
+        unit: Some("kWh".to_string()),
+        value: "4.1".to_string(),
+    }])?;
+
+    let summary = persistence::restore()?;
+    assert!(summary.stations >= 1 && summary.open_transactions >= 1);
+
+    let station = stations::get("persist-restored").expect("station restored");
+    assert_eq!(station.status, RegistrationStatus::Accepted);
+    assert_eq!(station.firmware_version.as_deref(), Some("1.2.3"));
+    let heartbeat_config = occp_ws::state::load_heartbeat_config().await;
+    assert_eq!(
+        stations::heartbeat_interval("persist-restored", heartbeat_config),
+        90
+    );
+    assert_eq!(
+        connectors::get("persist-restored", 1)
+            .expect("connector restored")
+            .status,
+        ChargePointStatus::SuspendedEV
+    );
+    let transaction = transactions::get(50_000).expect("transaction restored");
+    assert_eq!(transaction.status, TransactionStatus::Active);
+    assert_eq!(transaction.meter_samples.len(), 1);
+    assert_eq!(transaction.meter_samples[0].wh, 4100.0);
+
+    shutdown.send(()).ok();
+    server.await.expect("server task panicked");
+    Ok(())
+}
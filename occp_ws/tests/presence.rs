@@ -0,0 +1,168 @@
+use std::{error::Error, net::SocketAddr, num::NonZeroU32, time::Duration};
+
+use ::common::HeartbeatConfig;
+use futures::SinkExt;
+use occp_ws::audit::Actor;
+use occp_ws::events::{self, OfflineReason, StationEvent};
+use occp_ws::presence;
+use occp_ws::state::HEARTBEAT_CONFIG;
+use occp_ws::stations;
+use occp_ws::types::*;
+use rust_ocpp::v1_6::types::ConfigurationStatus;
+use serde_json::json;
+use tokio::{
+    sync::{broadcast, oneshot},
+    task::JoinHandle,
+    time::timeout,
+};
+use tokio_tungstenite::{connect_async, tungstenite::Message as WsMessage};
+
+mod common;
+
+use common::{call, next_frame};
+
+async fn start_test_server() -> (SocketAddr, oneshot::Sender<()>, JoinHandle<()>) {
+    HEARTBEAT_CONFIG
+        .get_or_init(|| async {
+            HeartbeatConfig {
+                interval: 120,
+                offline_multiplier: 2,
+            }
+        })
+        .await;
+    common::start_test_server().await
+}
+
+/// Next online/offline event for `station_id`, skipping other events and
+/// events from other tests.
+async fn next_event(
+    events: &mut broadcast::Receiver<StationEvent>,
+    station_id: &str,
+) -> Result<StationEvent, Box<dyn Error>> {
+    loop {
+        let event = timeout(Duration::from_secs(5), events.recv()).await??;
+        if event.station_id() == station_id
+            && matches!(
+                event,
+                StationEvent::Online { .. } | StationEvent::Offline { .. }
+            )
+        {
+            return Ok(event);
+        }
+    }
+}
+
+#[tokio::test]
+async fn watchdog_marks_silent_station_offline_until_it_speaks_again() -> Result<(), Box<dyn Error>>
+{
+    let (addr, shutdown, server) = start_test_server().await;
+    let config = HEARTBEAT_CONFIG.get().expect("heartbeat config");
+    let mut events = events::subscribe();
+
+    // Not connected yet: the interval is stored for the next BootNotification.
+    let applied = stations::set_heartbeat_interval(
+        "presence-quiet",
+        NonZeroU32::new(10),
+        config,
+        &Actor::automation("test"),
+    )
+    .await?;
+    assert_eq!(applied, None);
+
+    let (mut socket, _) = connect_async(format!("ws://{addr}/presence-quiet")).await?;
+    assert!(matches!(
+        next_event(&mut events, "presence-quiet").await?,
+        StationEvent::Online { .. }
+    ));
+
+    let boot = call(
+        &mut socket,
+        "boot",
+        "BootNotification",
+        json!({ "chargePointVendor": "PlugCo", "chargePointModel": "Wallbox 11" }),
+    )
+    .await?;
+    assert_eq!(boot["interval"], 10);
+    let last_seen = presence::get("presence-quiet").expect("presence").last_seen;
+
+    // Within 2 x 10 s nothing happens; after that the station is offline.
+    let now = last_seen + chrono::Duration::seconds(15);
+    assert!(presence::check_heartbeats(now, config).is_empty());
+    let now = last_seen + chrono::Duration::seconds(25);
+    assert_eq!(
+        presence::check_heartbeats(now, config),
+        vec!["presence-quiet"]
+    );
+    assert!(!presence::get("presence-quiet").expect("presence").online);
+    match next_event(&mut events, "presence-quiet").await? {
+        StationEvent::Offline {
+            reason,
+            last_seen: seen,
+            ..
+        } => {
+            assert_eq!(reason, OfflineReason::HeartbeatTimeout);
+            assert_eq!(seen, last_seen);
+        }
+        other => panic!("expected offline event, got {other:?}"),
+    }
+
+    // Any frame, even a WebSocket ping, brings it back.
+    socket.send(WsMessage::Ping(b"alive".to_vec())).await?;
+    assert!(matches!(
+        next_event(&mut events, "presence-quiet").await?,
+        StationEvent::Online { .. }
+    ));
+    assert!(presence::get("presence-quiet").expect("presence").last_seen > last_seen);
+
+    socket.close(None).await?;
+    match next_event(&mut events, "presence-quiet").await? {
+        StationEvent::Offline { reason, .. } => assert_eq!(reason, OfflineReason::Disconnected),
+        other => panic!("expected offline event, got {other:?}"),
+    }
+
+    shutdown.send(()).ok();
+    server.await.expect("server task panicked");
+    Ok(())
+}
+
+#[tokio::test]
+async fn pushes_heartbeat_interval_to_connected_station() -> Result<(), Box<dyn Error>> {
+    let (addr, shutdown, server) = start_test_server().await;
+    let config = HEARTBEAT_CONFIG.get().expect("heartbeat config");
+    let (mut socket, _) = connect_async(format!("ws://{addr}/presence-push")).await?;
+    // Let the server register the connection before calling into it.
+    call(&mut socket, "hb", "Heartbeat", json!({})).await?;
+
+    let update = tokio::spawn(async move {
+        stations::set_heartbeat_interval(
+            "presence-push",
+            NonZeroU32::new(45),
+            config,
+            &Actor::automation("test"),
+        )
+        .await
+    });
+    match next_frame(&mut socket).await? {
+        OcppMessageType::Call(2, message_id, action, payload) => {
+            assert_eq!(action, "ChangeConfiguration");
+            assert_eq!(
+                payload,
+                json!({ "key": "HeartbeatInterval", "value": "45" })
+            );
+            let reply = json!([3, message_id, { "status": "Accepted" }]);
+            socket.send(WsMessage::Text(reply.to_string())).await?;
+        }
+        other => panic!("expected ChangeConfiguration, got {other:?}"),
+    }
+    assert_eq!(update.await??, Some(ConfigurationStatus::Accepted));
+    assert_eq!(stations::heartbeat_interval("presence-push", config), 45);
+
+    stations::set_heartbeat_interval("presence-other", None, config, &Actor::automation("test"))
+        .await?;
+    assert_eq!(stations::heartbeat_interval("presence-other", config), 120);
+
+    socket.close(None).await?;
+    shutdown.send(()).ok();
+    server.await.expect("server task panicked");
+    Ok(())
+}
@@ -0,0 +1,161 @@
+use std::{error::Error, net::SocketAddr};
+
+use ::common::RegistrationConfig;
+use futures::SinkExt;
+use occp_ws::state::{ALLOWED_SERIAL_NUMBERS, REGISTRATION_CONFIG};
+use occp_ws::stations;
+use occp_ws::types::*;
+use serde_json::{Value, json};
+use tokio::{sync::oneshot, task::JoinHandle};
+use tokio_tungstenite::{connect_async, tungstenite::Message as WsMessage};
+
+mod common;
+
+use common::{Socket, next_frame};
+
+async fn start_test_server() -> (SocketAddr, oneshot::Sender<()>, JoinHandle<()>) {
+    REGISTRATION_CONFIG
+        .get_or_init(|| async {
+            RegistrationConfig {
+                approval_required: true,
+                pending_retry_interval: 30,
+                rejected_retry_interval: 900,
+            }
+        })
+        .await;
+    ALLOWED_SERIAL_NUMBERS
+        .get_or_init(|| async { vec!["SN-ALLOWED".to_string()] })
+        .await;
+    common::start_test_server().await
+}
+
+async fn send_call(
+    socket: &mut Socket,
+    message_id: &str,
+    action: &str,
+    payload: Value,
+) -> Result<OcppMessageType, Box<dyn Error>> {
+    let frame = json!([2, message_id, action, payload]);
+    socket.send(WsMessage::Text(frame.to_string())).await?;
+    next_frame(socket).await
+}
+
+async fn boot(
+    socket: &mut Socket,
+    message_id: &str,
+    serial: &str,
+) -> Result<Value, Box<dyn Error>> {
+    let payload = json!({
+        "chargePointVendor": "PlugCo",
+        "chargePointModel": "Wallbox 11",
+        "chargePointSerialNumber": serial,
+        "firmwareVersion": "1.2.3"
+    });
+    match send_call(socket, message_id, "BootNotification", payload).await? {
+        OcppMessageType::CallResult(3, id, payload) => {
+            assert_eq!(id, message_id);
+            Ok(payload)
+        }
+        other => panic!("unexpected BootNotification response: {other:?}"),
+    }
+}
+
+/// Answer the TriggerMessage the server sends after an operator decision.
+async fn accept_trigger(socket: &mut Socket) -> Result<(), Box<dyn Error>> {
+    match next_frame(socket).await? {
+        OcppMessageType::Call(2, message_id, action, payload) => {
+            assert_eq!(action, "TriggerMessage");
+            assert_eq!(payload["requestedMessage"], "BootNotification");
+            let reply = json!([3, message_id, { "status": "Accepted" }]);
+            socket.send(WsMessage::Text(reply.to_string())).await?;
+            Ok(())
+        }
+        other => panic!("expected TriggerMessage, got {other:?}"),
+    }
+}
+
+#[tokio::test]
+async fn queues_unknown_charger_until_operator_approves() -> Result<(), Box<dyn Error>> {
+    let (addr, shutdown, server) = start_test_server().await;
+    let (mut socket, _) = connect_async(format!("ws://{addr}/reg-approve")).await?;
+
+    let response = boot(&mut socket, "boot-1", "SN-UNKNOWN").await?;
+    assert_eq!(response["status"], "Pending");
+    assert_eq!(response["interval"], 30);
+
+    let queued = stations::pending_registrations();
+    let record = queued
+        .iter()
+        .find(|record| record.station_id == "reg-approve")
+        .expect("station in approval queue");
+    assert_eq!(record.vendor, "PlugCo");
+    assert_eq!(record.model, "Wallbox 11");
+    assert_eq!(record.serial_number.as_deref(), Some("SN-UNKNOWN"));
+    assert_eq!(record.firmware_version.as_deref(), Some("1.2.3"));
+
+    match send_call(&mut socket, "hb-1", "Heartbeat", json!({})).await? {
+        OcppMessageType::CallError(4, id, code, _, _) => {
+            assert_eq!(id, "hb-1");
+            assert_eq!(code, "SecurityError");
+        }
+        other => panic!("expected CallError for a pending station, got {other:?}"),
+    }
+
+    stations::approve("reg-approve")?;
+    accept_trigger(&mut socket).await?;
+
+    let response = boot(&mut socket, "boot-2", "SN-UNKNOWN").await?;
+    assert_eq!(response["status"], "Accepted");
+    assert!(
+        stations::pending_registrations()
+            .iter()
+            .all(|record| record.station_id != "reg-approve")
+    );
+
+    match send_call(&mut socket, "hb-2", "Heartbeat", json!({})).await? {
+        OcppMessageType::CallResult(3, id, _) => assert_eq!(id, "hb-2"),
+        other => panic!("expected Heartbeat result, got {other:?}"),
+    }
+
+    // Approval is tied to the hardware that was approved.
+    let response = boot(&mut socket, "boot-3", "SN-SWAPPED").await?;
+    assert_eq!(response["status"], "Pending");
+
+    socket.close(None).await?;
+    shutdown.send(()).ok();
+    server.await.expect("server task panicked");
+    Ok(())
+}
+
+#[tokio::test]
+async fn rejected_charger_stays_connected_and_allow_list_skips_queue() -> Result<(), Box<dyn Error>>
+{
+    let (addr, shutdown, server) = start_test_server().await;
+
+    let (mut allowed, _) = connect_async(format!("ws://{addr}/reg-allowed")).await?;
+    let response = boot(&mut allowed, "boot-1", "SN-ALLOWED").await?;
+    assert_eq!(response["status"], "Accepted");
+    allowed.close(None).await?;
+
+    let (mut socket, _) = connect_async(format!("ws://{addr}/reg-reject")).await?;
+    let response = boot(&mut socket, "boot-1", "SN-ROGUE").await?;
+    assert_eq!(response["status"], "Pending");
+
+    stations::reject("reg-reject")?;
+    accept_trigger(&mut socket).await?;
+
+    let response = boot(&mut socket, "boot-2", "SN-ROGUE").await?;
+    assert_eq!(response["status"], "Rejected");
+    assert_eq!(response["interval"], 900);
+
+    // The socket is still open: the charger just keeps getting Rejected.
+    let response = boot(&mut socket, "boot-3", "SN-ROGUE").await?;
+    assert_eq!(response["status"], "Rejected");
+
+    assert!(stations::approve("reg-never-booted").is_err());
+
+    socket.close(None).await?;
+    shutdown.send(()).ok();
+    server.await.expect("server task panicked");
+    Ok(())
+}
@@ -0,0 +1,294 @@
+use std::{error::Error, net::SocketAddr, time::Duration};
+
+use axum::{
+    body::Body,
+    http::{Method, Request, StatusCode, header},
+};
+use futures::{SinkExt, StreamExt};
+use occp_ws::routes;
+use occp_ws::types::*;
+use occp_ws::{persistence, rest};
+use serde_json::{Value, json};
+use storage::MemoryStore;
+use tokio::{sync::oneshot, task::JoinHandle, time::timeout};
+use tokio_tungstenite::{connect_async, tungstenite::Message as WsMessage};
+use tower::ServiceExt;
+
+mod common;
+
+use common::{Socket, call};
+
+async fn start_test_server() -> (SocketAddr, oneshot::Sender<()>, JoinHandle<()>) {
+    persistence::install(MemoryStore::new());
+    common::start_test_server().await
+}
+
+/// Send a request to the v1 API and return its status and JSON body.
+async fn api(
+    method: Method,
+    path: &str,
+    body: Option<&str>,
+) -> Result<(StatusCode, Value), Box<dyn Error>> {
+    let mut request = Request::builder().method(method).uri(path);
+    if body.is_some() {
+        request = request.header(header::CONTENT_TYPE, "application/json");
+    }
+    let request =
+        request.body(body.map_or_else(Body::empty, |body| Body::from(body.to_string())))?;
+    let response = rest::v1_router().oneshot(request).await?;
+    let status = response.status();
+    let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await?;
+    Ok((status, serde_json::from_slice(&bytes)?))
+}
+
+async fn boot(addr: SocketAddr, station_id: &str) -> Result<Socket, Box<dyn Error>> {
+    let (mut socket, _) = connect_async(format!("ws://{addr}/{station_id}")).await?;
+    call(
+        &mut socket,
+        "boot",
+        "BootNotification",
+        json!({
+            "chargePointVendor": "PlugCo",
+            "chargePointModel": "Wallbox 11",
+            "chargePointSerialNumber": "SN-REST",
+            "firmwareVersion": "2.4.1"
+        }),
+    )
+    .await?;
+    Ok(socket)
+}
+
+fn assert_error(status: StatusCode, body: &Value, expected: StatusCode, code: &str) {
+    assert_eq!(status, expected, "unexpected status for {body}");
+    assert_eq!(body["error"]["code"], code);
+    assert!(body["error"]["message"].is_string());
+}
+
+#[tokio::test]
+async fn stations_and_connectors_are_listed() -> Result<(), Box<dyn Error>> {
+    let (addr, shutdown, server) = start_test_server().await;
+    let mut socket = boot(addr, "rest-list").await?;
+    call(
+        &mut socket,
+        "status",
+        "StatusNotification",
+        json!({ "connectorId": 1, "errorCode": "NoError", "status": "Preparing" }),
+    )
+    .await?;
+
+    let (status, stations) = api(Method::GET, "/stations", None).await?;
+    assert_eq!(status, StatusCode::OK);
+    let station = stations
+        .as_array()
+        .expect("station list")
+        .iter()
+        .find(|station| station["station_id"] == "rest-list")
+        .expect("booted station listed");
+    assert_eq!(station["vendor"], "PlugCo");
+    assert_eq!(station["registration_status"], "Accepted");
+    assert_eq!(station["online"], true);
+
+    let (status, details) = api(Method::GET, "/stations/rest-list", None).await?;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(details["firmware_version"], "2.4.1");
+    assert_eq!(details["serial_number"], "SN-REST");
+    assert_eq!(details["connected"], true);
+    assert!(details["last_seen"].is_string());
+    assert_eq!(details["connectors"][0]["connector_id"], 1);
+    assert_eq!(details["connectors"][0]["status"], "Preparing");
+
+    let (status, connector) = api(Method::GET, "/stations/rest-list/connectors/1", None).await?;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(connector["status"], "Preparing");
+    assert_eq!(connector["transaction_id"], Value::Null);
+
+    let (status, body) = api(Method::GET, "/stations/rest-list/connectors/7", None).await?;
+    assert_error(status, &body, StatusCode::NOT_FOUND, "not_found");
+    let (status, body) = api(Method::GET, "/stations/rest-list/connectors/first", None).await?;
+    assert_error(status, &body, StatusCode::BAD_REQUEST, "bad_request");
+    let (status, body) = api(Method::GET, "/stations/rest-unknown", None).await?;
+    assert_error(status, &body, StatusCode::NOT_FOUND, "not_found");
+    let (status, body) = api(Method::GET, "/chargers", None).await?;
+    assert_error(status, &body, StatusCode::NOT_FOUND, "not_found");
+    let (status, body) = api(Method::DELETE, "/stations", None).await?;
+    assert_error(
+        status,
+        &body,
+        StatusCode::METHOD_NOT_ALLOWED,
+        "method_not_allowed",
+    );
+
+    socket.close(None).await?;
+    shutdown.send(()).ok();
+    server.await.expect("server task panicked");
+    Ok(())
+}
+
+#[tokio::test]
+async fn station_metadata_can_be_edited() -> Result<(), Box<dyn Error>> {
+    let (addr, shutdown, server) = start_test_server().await;
+    let mut socket = boot(addr, "rest-edit").await?;
+
+    let (status, details) = api(
+        Method::PATCH,
+        "/stations/rest-edit",
+        Some(r#"{"name": " Garage ", "location": "Driveway", "notes": "Fuse 16 A"}"#),
+    )
+    .await?;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(details["name"], "Garage");
+    assert_eq!(details["location"], "Driveway");
+    assert_eq!(details["notes"], "Fuse 16 A");
+
+    // Missing fields stay, null clears.
+    let (status, details) = api(
+        Method::PATCH,
+        "/stations/rest-edit",
+        Some(r#"{"notes": null}"#),
+    )
+    .await?;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(details["name"], "Garage");
+    assert_eq!(details["notes"], Value::Null);
+
+    let (_, stations) = api(Method::GET, "/stations", None).await?;
+    assert!(
+        stations
+            .as_array()
+            .expect("station list")
+            .iter()
+            .any(|station| station["station_id"] == "rest-edit" && station["name"] == "Garage")
+    );
+    let saved = persistence::store()
+        .expect("store installed")
+        .station_metadata()?
+        .into_iter()
+        .find(|row| row.station_id == "rest-edit")
+        .expect("metadata saved");
+    assert_eq!(saved.location.as_deref(), Some("Driveway"));
+    assert_eq!(saved.notes, None);
+
+    let long_name = json!({ "name": "x".repeat(65) }).to_string();
+    let (status, body) = api(Method::PATCH, "/stations/rest-edit", Some(&long_name)).await?;
+    assert_error(
+        status,
+        &body,
+        StatusCode::UNPROCESSABLE_ENTITY,
+        "validation_failed",
+    );
+    let (status, body) = api(
+        Method::PATCH,
+        "/stations/rest-edit",
+        Some(r#"{"nickname": "Garage"}"#),
+    )
+    .await?;
+    assert_error(
+        status,
+        &body,
+        StatusCode::UNPROCESSABLE_ENTITY,
+        "validation_failed",
+    );
+    let (status, body) = api(Method::PATCH, "/stations/rest-edit", Some("{name")).await?;
+    assert_error(status, &body, StatusCode::BAD_REQUEST, "bad_request");
+    let (status, body) = api(
+        Method::PATCH,
+        "/stations/rest-missing",
+        Some(r#"{"name": "Shed"}"#),
+    )
+    .await?;
+    assert_error(status, &body, StatusCode::NOT_FOUND, "not_found");
+
+    socket.close(None).await?;
+    shutdown.send(()).ok();
+    server.await.expect("server task panicked");
+    Ok(())
+}
+
+#[tokio::test]
+async fn heartbeat_interval_is_set_per_station() -> Result<(), Box<dyn Error>> {
+    let (addr, shutdown, server) = start_test_server().await;
+    let mut socket = boot(addr, "rest-heartbeat").await?;
+
+    // The new interval is pushed to the connected charger.
+    let (response, pushed) = tokio::join!(
+        api(
+            Method::PATCH,
+            "/stations/rest-heartbeat",
+            Some(r#"{"heartbeat_interval": 45}"#),
+        ),
+        async {
+            let frame = loop {
+                let frame = timeout(Duration::from_secs(5), socket.next())
+                    .await?
+                    .expect("socket open")?;
+                if let WsMessage::Text(text) = frame {
+                    break text;
+                }
+            };
+            let OcppMessageType::Call(2, message_id, action, payload) =
+                serde_json::from_str(&frame)?
+            else {
+                panic!("expected a call from the server, got {frame}");
+            };
+            let answer = json!([3, message_id, { "status": "Accepted" }]);
+            socket.send(WsMessage::Text(answer.to_string())).await?;
+            Ok::<_, Box<dyn Error>>((action, payload))
+        }
+    );
+    let (status, details) = response?;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(details["heartbeat_interval"], 45);
+    let (action, payload) = pushed?;
+    assert_eq!(action, "ChangeConfiguration");
+    assert_eq!(
+        payload,
+        json!({ "key": "HeartbeatInterval", "value": "45" })
+    );
+
+    let saved = persistence::store()
+        .expect("store installed")
+        .station_metadata()?
+        .into_iter()
+        .find(|row| row.station_id == "rest-heartbeat")
+        .expect("metadata saved");
+    assert_eq!(saved.heartbeat_interval, Some(45));
+
+    let (status, body) = api(
+        Method::PATCH,
+        "/stations/rest-heartbeat",
+        Some(r#"{"heartbeat_interval": 0}"#),
+    )
+    .await?;
+    assert_error(
+        status,
+        &body,
+        StatusCode::UNPROCESSABLE_ENTITY,
+        "validation_failed",
+    );
+
+    socket.close(None).await?;
+    shutdown.send(()).ok();
+    server.await.expect("server task panicked");
+    Ok(())
+}
+
+#[tokio::test]
+async fn old_registration_paths_redirect_to_api_v1() -> Result<(), Box<dyn Error>> {
+    for (method, path) in [
+        (Method::GET, "/registrations/pending"),
+        (Method::POST, "/registrations/old-station/approve"),
+        (Method::POST, "/registrations/old-station/reject"),
+    ] {
+        let request = Request::builder()
+            .method(method)
+            .uri(path)
+            .body(Body::empty())?;
+        let response = routes::registration_redirects().oneshot(request).await?;
+        assert_eq!(response.status(), StatusCode::PERMANENT_REDIRECT);
+        assert_eq!(
+            response.headers()[header::LOCATION],
+            format!("/api/v1{path}").as_str()
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,215 @@
+use std::{error::Error, time::Duration};
+
+use futures::SinkExt;
+use occp_ws::resync;
+use occp_ws::transactions::{self, TransactionStatus};
+use occp_ws::types::*;
+use serde_json::{Value, json};
+use tokio::time::timeout;
+use tokio_tungstenite::{connect_async, tungstenite::Message as WsMessage};
+
+mod common;
+
+use common::{Socket, call, next_frame, start_test_server};
+
+/// Accept `count` TriggerMessages and return what they asked for.
+async fn accept_triggers(
+    socket: &mut Socket,
+    count: usize,
+) -> Result<Vec<(String, Option<u64>)>, Box<dyn Error>> {
+    let mut requested = Vec::new();
+    while requested.len() < count {
+        match next_frame(socket).await? {
+            OcppMessageType::Call(2, message_id, action, payload) => {
+                assert_eq!(action, "TriggerMessage");
+                requested.push((
+                    payload["requestedMessage"]
+                        .as_str()
+                        .unwrap_or_default()
+                        .to_string(),
+                    payload["connectorId"].as_u64(),
+                ));
+                let reply = json!([3, message_id, { "status": "Accepted" }]);
+                socket.send(WsMessage::Text(reply.to_string())).await?;
+            }
+            other => panic!("expected TriggerMessage, got {other:?}"),
+        }
+    }
+    Ok(requested)
+}
+
+async fn start_transaction(
+    socket: &mut Socket,
+    message_id: &str,
+    connector_id: u32,
+) -> Result<i32, Box<dyn Error>> {
+    let response = call(
+        socket,
+        message_id,
+        "StartTransaction",
+        json!({
+            "connectorId": connector_id,
+            "idTag": "TAG-1",
+            "meterStart": 1000,
+            "timestamp": "2024-05-01T10:00:00Z"
+        }),
+    )
+    .await?;
+    Ok(response["transactionId"].as_i64().expect("transaction id") as i32)
+}
+
+fn status(connector_id: u32, status: &str) -> Value {
+    json!({ "connectorId": connector_id, "errorCode": "NoError", "status": status })
+}
+
+#[tokio::test]
+async fn reconnect_without_boot_reconciles_open_transactions() -> Result<(), Box<dyn Error>> {
+    let (addr, shutdown, server) = start_test_server().await;
+
+    let (mut socket, _) = connect_async(format!("ws://{addr}/resync-reconnect")).await?;
+    call(
+        &mut socket,
+        "boot",
+        "BootNotification",
+        json!({ "chargePointVendor": "PlugCo", "chargePointModel": "Wallbox 11" }),
+    )
+    .await?;
+    let charging = start_transaction(&mut socket, "start-1", 1).await?;
+    let stale = start_transaction(&mut socket, "start-2", 2).await?;
+    call(
+        &mut socket,
+        "meter",
+        "MeterValues",
+        json!({
+            "connectorId": 2,
+            "transactionId": stale,
+            "meterValue": [{
+                "timestamp": "2024-05-01T10:30:00Z",
+                "sampledValue": [{ "value": "3.5", "unit": "kWh" }]
+            }]
+        }),
+    )
+    .await?;
+    socket.close(None).await?;
+
+    // The charger comes back without rebooting.
+    let (mut socket, _) = connect_async(format!("ws://{addr}/resync-reconnect")).await?;
+    call(&mut socket, "hb", "Heartbeat", json!({})).await?;
+
+    let requested = accept_triggers(&mut socket, 3).await?;
+    assert_eq!(
+        requested,
+        vec![
+            ("StatusNotification".to_string(), None),
+            ("MeterValues".to_string(), Some(1)),
+            ("MeterValues".to_string(), Some(2)),
+        ]
+    );
+    call(
+        &mut socket,
+        "sn-1",
+        "StatusNotification",
+        status(1, "Charging"),
+    )
+    .await?;
+    call(
+        &mut socket,
+        "sn-2",
+        "StatusNotification",
+        status(2, "Available"),
+    )
+    .await?;
+
+    let closed = timeout(Duration::from_secs(5), async {
+        loop {
+            let transaction = transactions::get(stale).expect("transaction");
+            if transaction.status != TransactionStatus::Active {
+                return transaction;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+    })
+    .await?;
+    assert_eq!(closed.status, TransactionStatus::Orphaned);
+    assert_eq!(closed.meter_stop, Some(3500));
+    assert!(closed.flag.is_some());
+    assert_eq!(
+        transactions::get(charging).expect("transaction").status,
+        TransactionStatus::Active
+    );
+
+    socket.close(None).await?;
+    shutdown.send(()).ok();
+    server.await.expect("server task panicked");
+    Ok(())
+}
+
+#[tokio::test]
+async fn flags_transactions_the_charger_does_not_report() -> Result<(), Box<dyn Error>> {
+    let (addr, shutdown, server) = start_test_server().await;
+    let (mut socket, _) = connect_async(format!("ws://{addr}/resync-silent")).await?;
+    // Unknown station on its first connection: no automatic resync.
+    call(&mut socket, "hb", "Heartbeat", json!({})).await?;
+    let transaction_id = start_transaction(&mut socket, "start", 1).await?;
+
+    let run = tokio::spawn(resync::resync("resync-silent", Duration::from_millis(300)));
+    accept_triggers(&mut socket, 2).await?;
+    call(
+        &mut socket,
+        "sn-2",
+        "StatusNotification",
+        status(2, "Charging"),
+    )
+    .await?;
+
+    let report = run.await??;
+    assert_eq!(report.connectors_reported, vec![2]);
+    assert_eq!(report.flagged, vec![transaction_id]);
+    assert_eq!(report.unknown_sessions, vec![2]);
+    let transaction = transactions::get(transaction_id).expect("transaction");
+    assert_eq!(transaction.status, TransactionStatus::Active);
+    assert!(transaction.flag.is_some());
+
+    socket.close(None).await?;
+    shutdown.send(()).ok();
+    server.await.expect("server task panicked");
+    Ok(())
+}
+
+#[tokio::test]
+async fn replayed_first_call_after_reconnect_still_resyncs() -> Result<(), Box<dyn Error>> {
+    let (addr, shutdown, server) = start_test_server().await;
+
+    let (mut socket, _) = connect_async(format!("ws://{addr}/resync-replay")).await?;
+    call(
+        &mut socket,
+        "boot",
+        "BootNotification",
+        json!({ "chargePointVendor": "PlugCo", "chargePointModel": "Wallbox 11" }),
+    )
+    .await?;
+    let transaction_id = start_transaction(&mut socket, "start-1", 1).await?;
+    socket.close(None).await?;
+
+    // The charger reconnects and first resends the StartTransaction it never
+    // saw answered.
+    let (mut socket, _) = connect_async(format!("ws://{addr}/resync-replay")).await?;
+    assert_eq!(
+        start_transaction(&mut socket, "start-1", 1).await?,
+        transaction_id
+    );
+
+    let requested = accept_triggers(&mut socket, 2).await?;
+    assert_eq!(
+        requested,
+        vec![
+            ("StatusNotification".to_string(), None),
+            ("MeterValues".to_string(), Some(1)),
+        ]
+    );
+
+    socket.close(None).await?;
+    shutdown.send(()).ok();
+    server.await.expect("server task panicked");
+    Ok(())
+}
@@ -0,0 +1,146 @@
+use std::{error::Error, path::PathBuf, sync::Arc};
+
+use futures::SinkExt;
+use occp_ws::audit::Actor;
+use occp_ws::pki::LocalCa;
+use occp_ws::security::{self, GetLogRequest, LogParameters, LogStatus, LogType};
+use occp_ws::state::LOCAL_CA;
+use occp_ws::types::*;
+use rcgen::{CertificateParams, DnType, KeyPair};
+use serde_json::json;
+use tokio_tungstenite::{connect_async, tungstenite::Message as WsMessage};
+
+mod common;
+
+use common::{call, next_frame, start_test_server};
+
+#[tokio::test]
+async fn records_security_event_notifications() -> Result<(), Box<dyn Error>> {
+    let (addr, shutdown, server) = start_test_server().await;
+    let (mut socket, _) = connect_async(format!("ws://{addr}/sec-events")).await?;
+
+    let response = call(
+        &mut socket,
+        "sec-1",
+        "SecurityEventNotification",
+        json!({
+            "type": "TamperDetectionActivated",
+            "timestamp": "2024-05-01T10:00:00Z",
+            "techInfo": "enclosure opened"
+        }),
+    )
+    .await?;
+    assert_eq!(response, json!({}));
+
+    let events = security::recent_security_events(Some("sec-events"));
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].kind, "TamperDetectionActivated");
+    assert!(events[0].critical);
+
+    socket.close(None).await?;
+    shutdown.send(()).ok();
+    server.await.expect("server task panicked");
+    Ok(())
+}
+
+#[tokio::test]
+async fn signs_charger_csr_and_sends_certificate_signed() -> Result<(), Box<dyn Error>> {
+    let dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("security-extension-ca");
+    let _ = std::fs::remove_dir_all(&dir);
+    LOCAL_CA
+        .set(Arc::new(LocalCa::load_or_create(&dir)?))
+        .expect("local CA not yet set");
+
+    let (addr, shutdown, server) = start_test_server().await;
+    let (mut socket, _) = connect_async(format!("ws://{addr}/sec-csr")).await?;
+
+    let mut params = CertificateParams::new(Vec::<String>::new())?;
+    params
+        .distinguished_name
+        .push(DnType::CommonName, "sec-csr");
+    let csr = params.serialize_request(&KeyPair::generate()?)?.pem()?;
+
+    let response = call(
+        &mut socket,
+        "csr-1",
+        "SignCertificate",
+        json!({ "csr": csr }),
+    )
+    .await?;
+    assert_eq!(response, json!({ "status": "Accepted" }));
+
+    match next_frame(&mut socket).await? {
+        OcppMessageType::Call(2, message_id, action, payload) => {
+            assert_eq!(action, "CertificateSigned");
+            let chain = payload["certificateChain"].as_str().expect("chain");
+            assert_eq!(chain.matches("BEGIN CERTIFICATE").count(), 2);
+            let reply = json!([3, message_id, { "status": "Accepted" }]);
+            socket.send(WsMessage::Text(reply.to_string())).await?;
+        }
+        other => panic!("expected CertificateSigned, got {other:?}"),
+    }
+
+    let rejected = call(
+        &mut socket,
+        "csr-2",
+        "SignCertificate",
+        json!({ "csr": "not a csr" }),
+    )
+    .await?;
+    assert_eq!(rejected, json!({ "status": "Rejected" }));
+
+    socket.close(None).await?;
+    shutdown.send(()).ok();
+    server.await.expect("server task panicked");
+    Ok(())
+}
+
+#[tokio::test]
+async fn requests_log_upload_and_accepts_status_notifications() -> Result<(), Box<dyn Error>> {
+    let (addr, shutdown, server) = start_test_server().await;
+    let (mut socket, _) = connect_async(format!("ws://{addr}/sec-log")).await?;
+    // Let the server register the connection before calling into it.
+    call(&mut socket, "hb", "Heartbeat", json!({})).await?;
+
+    let request = GetLogRequest {
+        log: LogParameters {
+            remote_location: "https://logs.example/upload".to_string(),
+            ..Default::default()
+        },
+        log_type: LogType::SecurityLog,
+        request_id: 7,
+        ..Default::default()
+    };
+    let get_log = tokio::spawn(async move {
+        security::get_log("sec-log", request, &Actor::automation("test")).await
+    });
+
+    match next_frame(&mut socket).await? {
+        OcppMessageType::Call(2, message_id, action, payload) => {
+            assert_eq!(action, "GetLog");
+            assert_eq!(payload["logType"], "SecurityLog");
+            assert_eq!(payload["requestId"], 7);
+            let reply = json!([3, message_id, { "status": "Accepted", "filename": "sec.log" }]);
+            socket.send(WsMessage::Text(reply.to_string())).await?;
+        }
+        other => panic!("expected GetLog, got {other:?}"),
+    }
+
+    let response = get_log.await??;
+    assert_eq!(response.status, LogStatus::Accepted);
+    assert_eq!(response.filename.as_deref(), Some("sec.log"));
+
+    let status = call(
+        &mut socket,
+        "log-status",
+        "LogStatusNotification",
+        json!({ "status": "Uploaded", "requestId": 7 }),
+    )
+    .await?;
+    assert_eq!(status, json!({}));
+
+    socket.close(None).await?;
+    shutdown.send(()).ok();
+    server.await.expect("server task panicked");
+    Ok(())
+}
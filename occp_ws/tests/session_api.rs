@@ -0,0 +1,300 @@
+use std::{error::Error, net::SocketAddr};
+
+use axum::{
+    body::Body,
+    http::{HeaderMap, Request, StatusCode, header},
+};
+use occp_ws::rest;
+use serde_json::{Value, json};
+use tokio_tungstenite::connect_async;
+use tower::ServiceExt;
+
+mod common;
+
+use common::{Socket, call, start_test_server};
+
+/// GET a path of the v1 API and return its status, headers and body.
+async fn get_raw(path: &str) -> Result<(StatusCode, HeaderMap, String), Box<dyn Error>> {
+    let request = Request::builder().uri(path).body(Body::empty())?;
+    let response = rest::v1_router().oneshot(request).await?;
+    let status = response.status();
+    let headers = response.headers().clone();
+    let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await?;
+    Ok((status, headers, String::from_utf8(bytes.to_vec())?))
+}
+
+async fn get_json(path: &str) -> Result<(StatusCode, Value), Box<dyn Error>> {
+    let (status, _, body) = get_raw(path).await?;
+    Ok((status, serde_json::from_str(&body)?))
+}
+
+fn meter_value(timestamp: &str, kwh: &str, watts: Option<&str>) -> Value {
+    let mut samples = vec![json!({ "value": kwh, "unit": "kWh" })];
+    if let Some(watts) = watts {
+        samples.push(json!({ "value": watts, "measurand": "Power.Active.Import", "unit": "W" }));
+    }
+    json!({ "timestamp": timestamp, "sampledValue": samples })
+}
+
+async fn start(
+    socket: &mut Socket,
+    connector_id: u32,
+    id_tag: &str,
+    meter_start: i32,
+    timestamp: &str,
+) -> Result<i64, Box<dyn Error>> {
+    let started = call(
+        socket,
+        &format!("start-{timestamp}"),
+        "StartTransaction",
+        json!({
+            "connectorId": connector_id,
+            "idTag": id_tag,
+            "meterStart": meter_start,
+            "timestamp": timestamp
+        }),
+    )
+    .await?;
+    Ok(started["transactionId"].as_i64().expect("transaction id"))
+}
+
+async fn stop(
+    socket: &mut Socket,
+    transaction_id: i64,
+    meter_stop: i32,
+    timestamp: &str,
+    reason: &str,
+) -> Result<(), Box<dyn Error>> {
+    call(
+        socket,
+        &format!("stop-{timestamp}"),
+        "StopTransaction",
+        json!({
+            "transactionId": transaction_id,
+            "meterStop": meter_stop,
+            "timestamp": timestamp,
+            "reason": reason
+        }),
+    )
+    .await?;
+    Ok(())
+}
+
+async fn meter_values(
+    socket: &mut Socket,
+    connector_id: u32,
+    transaction_id: i64,
+    meter_value: Value,
+) -> Result<(), Box<dyn Error>> {
+    call(
+        socket,
+        &format!("meter-{}", meter_value["timestamp"]),
+        "MeterValues",
+        json!({
+            "connectorId": connector_id,
+            "transactionId": transaction_id,
+            "meterValue": [meter_value]
+        }),
+    )
+    .await?;
+    Ok(())
+}
+
+/// Record three sessions on the station, oldest first: a finished one with
+/// power readings, a running one without, and a finished remote stop.
+async fn record_sessions(
+    addr: SocketAddr,
+    station_id: &str,
+) -> Result<(Socket, [i64; 3]), Box<dyn Error>> {
+    let (mut socket, _) = connect_async(format!("ws://{addr}/{station_id}")).await?;
+    call(
+        &mut socket,
+        "boot",
+        "BootNotification",
+        json!({ "chargePointVendor": "PlugCo", "chargePointModel": "Wallbox 11" }),
+    )
+    .await?;
+
+    let first = start(&mut socket, 1, "TAG-A", 1000, "2024-06-01T18:00:00Z").await?;
+    let readings = [
+        meter_value("2024-06-01T18:15:00Z", "2.5", Some("6000")),
+        meter_value("2024-06-01T18:30:00Z", "4.0", Some("7000")),
+    ];
+    for reading in readings {
+        meter_values(&mut socket, 1, first, reading).await?;
+    }
+    stop(
+        &mut socket,
+        first,
+        5000,
+        "2024-06-01T18:45:00Z",
+        "EVDisconnected",
+    )
+    .await?;
+
+    let second = start(&mut socket, 2, "TAG-B", 0, "2024-06-02T08:00:00Z").await?;
+    let readings = [
+        meter_value("2024-06-02T08:15:00Z", "1.0", None),
+        meter_value("2024-06-02T08:30:00Z", "3.0", None),
+    ];
+    for reading in readings {
+        meter_values(&mut socket, 2, second, reading).await?;
+    }
+
+    let third = start(&mut socket, 1, "=SUM(A1)", 5000, "2024-06-03T09:00:00Z").await?;
+    stop(&mut socket, third, 6000, "2024-06-03T09:30:00Z", "Remote").await?;
+
+    Ok((socket, [first, second, third]))
+}
+
+fn transaction_ids(page: &Value) -> Vec<i64> {
+    page["sessions"]
+        .as_array()
+        .expect("session list")
+        .iter()
+        .map(|session| session["transaction_id"].as_i64().expect("transaction id"))
+        .collect()
+}
+
+fn assert_error(status: StatusCode, body: &Value, expected: StatusCode, code: &str) {
+    assert_eq!(status, expected, "unexpected status for {body}");
+    assert_eq!(body["error"]["code"], code);
+}
+
+#[tokio::test]
+async fn sessions_are_filtered_and_paged() -> Result<(), Box<dyn Error>> {
+    let (addr, shutdown, server) = start_test_server().await;
+    let (mut socket, [first, second, third]) = record_sessions(addr, "history-a").await?;
+
+    let (status, page) = get_json("/sessions?station_id=history-a").await?;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(transaction_ids(&page), vec![third, second, first]);
+    assert_eq!(page["next_cursor"], Value::Null);
+
+    let filters = [
+        ("id_tag=TAG-A", vec![first]),
+        ("connector_id=2", vec![second]),
+        ("status=in_progress", vec![second]),
+        ("status=completed&connector_id=1", vec![third, first]),
+        (
+            "from=2024-06-02T00:00:00Z&to=2024-06-03T00:00:00Z",
+            vec![second],
+        ),
+    ];
+    for (query, expected) in filters {
+        let (status, page) = get_json(&format!("/sessions?station_id=history-a&{query}")).await?;
+        assert_eq!(status, StatusCode::OK, "{query}");
+        assert_eq!(transaction_ids(&page), expected, "{query}");
+    }
+
+    let (_, page) = get_json("/sessions?station_id=history-a&limit=2").await?;
+    assert_eq!(transaction_ids(&page), vec![third, second]);
+    let cursor = page["next_cursor"].as_str().expect("next cursor");
+    let (_, page) = get_json(&format!(
+        "/sessions?station_id=history-a&limit=2&cursor={cursor}"
+    ))
+    .await?;
+    assert_eq!(transaction_ids(&page), vec![first]);
+    assert_eq!(page["next_cursor"], Value::Null);
+
+    let (status, detail) = get_json(&format!("/sessions/{first}")).await?;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(detail["energy_wh"], 4000.0);
+    assert_eq!(detail["stop_reason"], "EVDisconnected");
+    assert_eq!(detail["power_curve_estimated"], false);
+    assert_eq!(
+        detail["power_curve"],
+        json!([
+            { "at": "2024-06-01T18:15:00Z", "power_w": 6000.0 },
+            { "at": "2024-06-01T18:30:00Z", "power_w": 7000.0 }
+        ])
+    );
+    let (_, detail) = get_json(&format!("/sessions/{second}")).await?;
+    assert_eq!(detail["status"], "in_progress");
+    assert_eq!(detail["power_curve_estimated"], true);
+    assert_eq!(
+        detail["power_curve"],
+        json!([{ "at": "2024-06-02T08:30:00Z", "power_w": 8000.0 }])
+    );
+
+    let invalid = [
+        (
+            "/sessions?limit=0",
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "validation_failed",
+        ),
+        (
+            "/sessions?from=2024-06-03T00:00:00Z&to=2024-06-02T00:00:00Z",
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "validation_failed",
+        ),
+        (
+            "/sessions?cursor=yesterday",
+            StatusCode::BAD_REQUEST,
+            "bad_request",
+        ),
+        (
+            "/sessions?status=paused",
+            StatusCode::BAD_REQUEST,
+            "bad_request",
+        ),
+        (
+            "/sessions?station=history-a",
+            StatusCode::BAD_REQUEST,
+            "bad_request",
+        ),
+        ("/sessions/999999", StatusCode::NOT_FOUND, "not_found"),
+        ("/sessions/latest", StatusCode::BAD_REQUEST, "bad_request"),
+    ];
+    for (path, expected, code) in invalid {
+        let (status, body) = get_json(path).await?;
+        assert_error(status, &body, expected, code);
+    }
+
+    socket.close(None).await?;
+    shutdown.send(()).ok();
+    server.await.expect("server task panicked");
+    Ok(())
+}
+
+#[tokio::test]
+async fn sessions_export_as_csv_and_json() -> Result<(), Box<dyn Error>> {
+    let (addr, shutdown, server) = start_test_server().await;
+    let (mut socket, [first, _, third]) = record_sessions(addr, "history-b").await?;
+
+    let (status, headers, csv) = get_raw("/sessions/export?station_id=history-b").await?;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(headers[header::CONTENT_TYPE], "text/csv; charset=utf-8");
+    assert_eq!(
+        headers[header::CONTENT_DISPOSITION],
+        "attachment; filename=\"sessions.csv\""
+    );
+    let lines: Vec<&str> = csv.lines().collect();
+    assert_eq!(lines.len(), 4);
+    assert!(lines[0].starts_with("transaction_id,station_id,connector_id,id_tag,status"));
+    assert!(lines[1].starts_with(&format!("{third},history-b,1,'=SUM(A1),completed,")));
+    assert!(lines[3].starts_with(&format!(
+        "{first},history-b,1,TAG-A,completed,2024-06-01T18:00:00Z,2024-06-01T18:45:00Z,1000,5000,4000,4,7000,EVDisconnected,,2700"
+    )));
+
+    let (status, headers, json) =
+        get_raw("/sessions/export?station_id=history-b&status=completed&format=json").await?;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(headers[header::CONTENT_TYPE], "application/json");
+    let sessions: Value = serde_json::from_str(&json)?;
+    let ids: Vec<i64> = sessions
+        .as_array()
+        .expect("session array")
+        .iter()
+        .map(|session| session["transaction_id"].as_i64().expect("transaction id"))
+        .collect();
+    assert_eq!(ids, vec![third, first]);
+
+    let (status, body) = get_json("/sessions/export?format=xlsx").await?;
+    assert_error(status, &body, StatusCode::BAD_REQUEST, "bad_request");
+
+    socket.close(None).await?;
+    shutdown.send(()).ok();
+    server.await.expect("server task panicked");
+    Ok(())
+}
@@ -0,0 +1,161 @@
+use std::error::Error;
+
+use occp_ws::sessions::{self, SessionStatus, StopReason};
+use occp_ws::transactions;
+use serde_json::{Value, json};
+use tokio_tungstenite::connect_async;
+
+mod common;
+
+use common::{call, start_test_server};
+
+fn meter_value(timestamp: &str, kwh: &str, watts: Option<&str>) -> Value {
+    let mut samples = vec![json!({ "value": kwh, "unit": "kWh" })];
+    if let Some(watts) = watts {
+        samples.push(json!({ "value": watts, "measurand": "Power.Active.Import", "unit": "W" }));
+    }
+    json!({ "timestamp": timestamp, "sampledValue": samples })
+}
+
+#[tokio::test]
+async fn completed_session_records_energy_power_and_reason() -> Result<(), Box<dyn Error>> {
+    let (addr, shutdown, server) = start_test_server().await;
+    let (mut socket, _) = connect_async(format!("ws://{addr}/sessions-complete")).await?;
+
+    let started = call(
+        &mut socket,
+        "start",
+        "StartTransaction",
+        json!({
+            "connectorId": 2,
+            "idTag": "TAG-CAR",
+            "meterStart": 10000,
+            "timestamp": "2024-05-03T18:00:00Z"
+        }),
+    )
+    .await?;
+    let transaction_id = started["transactionId"].as_i64().expect("transaction id") as i32;
+    assert_eq!(
+        sessions::get(transaction_id).expect("session").status,
+        SessionStatus::InProgress
+    );
+
+    call(
+        &mut socket,
+        "meter-1",
+        "MeterValues",
+        json!({
+            "connectorId": 2,
+            "transactionId": transaction_id,
+            "meterValue": [
+                meter_value("2024-05-03T18:30:00Z", "13.5", Some("7200")),
+                meter_value("2024-05-03T19:00:00Z", "17.2", Some("7400"))
+            ]
+        }),
+    )
+    .await?;
+    call(
+        &mut socket,
+        "stop",
+        "StopTransaction",
+        json!({
+            "transactionId": transaction_id,
+            "meterStop": 18000,
+            "timestamp": "2024-05-03T19:10:00Z",
+            "reason": "EVDisconnected",
+            "transactionData": [meter_value("2024-05-03T19:05:00Z", "17.9", Some("3100"))]
+        }),
+    )
+    .await?;
+
+    let session = sessions::get(transaction_id).expect("session");
+    assert_eq!(session.status, SessionStatus::Completed);
+    assert_eq!(session.connector_id, 2);
+    assert_eq!(session.id_tag, "TAG-CAR");
+    assert_eq!(session.meter_start, 10000);
+    assert_eq!(session.meter_stop, Some(18000));
+    assert_eq!(session.energy_wh, Some(8000.0));
+    assert_eq!(session.peak_power_w, Some(7400.0));
+    assert_eq!(session.stop_reason, Some(StopReason::EvDisconnected));
+    assert_eq!(
+        session.stopped_at.expect("stopped").to_rfc3339(),
+        "2024-05-03T19:10:00+00:00"
+    );
+    assert!(
+        sessions::list(Some("sessions-complete"))
+            .iter()
+            .any(|listed| listed.transaction_id == transaction_id)
+    );
+
+    socket.close(None).await?;
+    shutdown.send(()).ok();
+    server.await.expect("server task panicked");
+    Ok(())
+}
+
+#[tokio::test]
+async fn partial_sessions_are_marked_incomplete() -> Result<(), Box<dyn Error>> {
+    let (addr, shutdown, server) = start_test_server().await;
+    let (mut socket, _) = connect_async(format!("ws://{addr}/sessions-partial")).await?;
+
+    // The stop arrives for a session whose start was lost.
+    call(
+        &mut socket,
+        "lost-start",
+        "StopTransaction",
+        json!({
+            "transactionId": 70001,
+            "meterStop": 5000,
+            "timestamp": "2024-05-04T07:00:00Z",
+            "reason": "SoftReset"
+        }),
+    )
+    .await?;
+    let session = sessions::get(70001).expect("session");
+    assert_eq!(session.status, SessionStatus::Incomplete);
+    assert!(session.incomplete_reason.is_some());
+    assert_eq!(session.stop_reason, Some(StopReason::Reboot));
+
+    // The charger never stops this one; the server closes it.
+    let started = call(
+        &mut socket,
+        "start",
+        "StartTransaction",
+        json!({
+            "connectorId": 1,
+            "idTag": "TAG-VAN",
+            "meterStart": 1000,
+            "timestamp": "2024-05-04T08:00:00Z"
+        }),
+    )
+    .await?;
+    let transaction_id = started["transactionId"].as_i64().expect("transaction id") as i32;
+    call(
+        &mut socket,
+        "meter",
+        "MeterValues",
+        json!({
+            "connectorId": 1,
+            "transactionId": transaction_id,
+            "meterValue": [
+                meter_value("2024-05-04T08:00:00Z", "1.0", None),
+                meter_value("2024-05-04T08:30:00Z", "3.5", None),
+                meter_value("2024-05-04T09:00:00Z", "4.5", None)
+            ]
+        }),
+    )
+    .await?;
+    transactions::close_orphaned(transaction_id, "Charger reported Available");
+
+    let session = sessions::get(transaction_id).expect("session");
+    assert_eq!(session.status, SessionStatus::Incomplete);
+    assert_eq!(session.energy_wh, Some(3500.0));
+    // Without power readings the peak comes from the fastest energy interval.
+    assert_eq!(session.peak_power_w, Some(5000.0));
+    assert_eq!(session.stop_reason, Some(StopReason::Other));
+
+    socket.close(None).await?;
+    shutdown.send(()).ok();
+    server.await.expect("server task panicked");
+    Ok(())
+}
@@ -0,0 +1,191 @@
+use std::{error::Error, net::SocketAddr, time::Duration};
+
+use axum::{
+    body::Body,
+    http::{Method, Request, StatusCode, header},
+};
+use futures::{SinkExt, StreamExt};
+use headers::{Authorization, HeaderMapExt};
+use occp_ws::api_tokens::{self, Scope};
+use occp_ws::audit::Actor;
+use occp_ws::auth::{AUTHORIZATION_KEY, rotate_authorization_key};
+use occp_ws::connections;
+use occp_ws::state::load_station_credentials;
+use occp_ws::types::*;
+use occp_ws::{persistence, rest};
+use serde_json::{Value, json};
+use storage::MemoryStore;
+use tokio::time::timeout;
+use tokio_tungstenite::{
+    connect_async,
+    tungstenite::{self, Message as WsMessage, client::IntoClientRequest},
+};
+use tower::ServiceExt;
+
+mod common;
+
+use common::{Socket, call, start_test_server};
+
+async fn connect(
+    addr: SocketAddr,
+    station_id: &str,
+    password: Option<&str>,
+) -> Result<Socket, tungstenite::Error> {
+    let mut request = format!("ws://{addr}/{station_id}").into_client_request()?;
+    if let Some(password) = password {
+        request
+            .headers_mut()
+            .typed_insert(Authorization::basic(station_id, password));
+    }
+    connect_async(request).await.map(|(socket, _)| socket)
+}
+
+/// Read the ChangeConfiguration(AuthorizationKey) sent to the charger and
+/// return its message id and the new key.
+async fn expect_authorization_key(socket: &mut Socket) -> Result<(String, String), Box<dyn Error>> {
+    let frame = timeout(Duration::from_secs(5), socket.next())
+        .await
+        .expect("ChangeConfiguration within timeout")
+        .expect("socket open")?;
+    let WsMessage::Text(text) = frame else {
+        panic!("expected text frame, got {frame:?}");
+    };
+    match serde_json::from_str::<OcppMessageType>(&text)? {
+        OcppMessageType::Call(2, message_id, action, payload) => {
+            assert_eq!(action, "ChangeConfiguration");
+            assert_eq!(payload["key"], AUTHORIZATION_KEY);
+            let value = payload["value"].as_str().expect("key value").to_string();
+            Ok((message_id, value))
+        }
+        other => panic!("unexpected frame: {other:?}"),
+    }
+}
+
+/// `POST /stations/{station_id}/password` through the authenticated API.
+async fn rotate_through_api(
+    station_id: &str,
+    token: &str,
+) -> Result<(StatusCode, Value), Box<dyn Error>> {
+    let request = Request::builder()
+        .method(Method::POST)
+        .uri(format!("/stations/{station_id}/password"))
+        .header(header::AUTHORIZATION, format!("Bearer {token}"))
+        .body(Body::empty())?;
+    let response = rest::v1_router_with_auth().oneshot(request).await?;
+    let status = response.status();
+    let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await?;
+    Ok((status, serde_json::from_slice(&bytes)?))
+}
+
+fn assert_unauthorized(result: Result<Socket, tungstenite::Error>) {
+    match result {
+        Err(tungstenite::Error::Http(response)) => {
+            assert_eq!(response.status(), StatusCode::UNAUTHORIZED)
+        }
+        Err(other) => panic!("expected 401, got error {other:?}"),
+        Ok(_) => panic!("expected 401, connection was accepted"),
+    }
+}
+
+#[tokio::test]
+async fn rejects_missing_or_wrong_basic_credentials() -> Result<(), Box<dyn Error>> {
+    let (addr, shutdown, server) = start_test_server().await;
+    load_station_credentials()
+        .await
+        .set_password("auth-station-1", "correct horse")
+        .await?;
+
+    assert_unauthorized(connect(addr, "auth-station-1", None).await);
+    assert_unauthorized(connect(addr, "auth-station-1", Some("wrong")).await);
+
+    let mut socket = connect(addr, "auth-station-1", Some("correct horse")).await?;
+    socket.close(None).await?;
+
+    shutdown.send(()).ok();
+    server.await.expect("server task panicked");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn rotates_authorization_key_through_change_configuration() -> Result<(), Box<dyn Error>> {
+    let (addr, shutdown, server) = start_test_server().await;
+    load_station_credentials()
+        .await
+        .set_password("auth-station-2", "initial-key")
+        .await?;
+
+    let mut socket = connect(addr, "auth-station-2", Some("initial-key")).await?;
+
+    let rotation = tokio::spawn(async {
+        rotate_authorization_key("auth-station-2", &Actor::automation("test")).await
+    });
+
+    let (message_id, new_key) = expect_authorization_key(&mut socket).await?;
+    let reply = json!([3, message_id, { "status": "Accepted" }]);
+    socket.send(WsMessage::Text(reply.to_string())).await?;
+
+    let rotated = rotation.await??;
+    assert_eq!(rotated, new_key);
+    assert_eq!(rotated.len(), 40);
+
+    socket.close(None).await?;
+
+    assert_unauthorized(connect(addr, "auth-station-2", Some("initial-key")).await);
+    let mut socket = connect(addr, "auth-station-2", Some(&new_key)).await?;
+    socket.close(None).await?;
+
+    shutdown.send(()).ok();
+    server.await.expect("server task panicked");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn admins_rotate_the_password_through_the_api() -> Result<(), Box<dyn Error>> {
+    let (addr, shutdown, server) = start_test_server().await;
+    persistence::install(MemoryStore::new());
+    let (_, admin) = api_tokens::create("rotation", vec![Scope::Admin], None)?;
+    let (_, control) = api_tokens::create("dashboard", vec![Scope::Control], None)?;
+    load_station_credentials()
+        .await
+        .set_password("auth-station-3", "initial-key")
+        .await?;
+
+    let mut socket = connect(addr, "auth-station-3", Some("initial-key")).await?;
+    call(&mut socket, "hb", "Heartbeat", json!({})).await?;
+
+    let (status, body) = rotate_through_api("auth-station-3", &control).await?;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    assert_eq!(body["error"]["code"], "forbidden");
+
+    let ((status, body), new_key) =
+        tokio::try_join!(rotate_through_api("auth-station-3", &admin), async {
+            let (message_id, new_key) = expect_authorization_key(&mut socket).await?;
+            let reply = json!([3, message_id, { "status": "Accepted" }]);
+            socket.send(WsMessage::Text(reply.to_string())).await?;
+            Ok::<_, Box<dyn Error>>(new_key)
+        })?;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["password"], new_key.as_str());
+    socket.close(None).await?;
+
+    assert_unauthorized(connect(addr, "auth-station-3", Some("initial-key")).await);
+    let mut socket = connect(addr, "auth-station-3", Some(&new_key)).await?;
+    socket.close(None).await?;
+
+    // Without a connection there is nothing to send the key to.
+    timeout(Duration::from_secs(5), async {
+        while connections::is_connected("auth-station-3") {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+    })
+    .await?;
+    let (status, body) = rotate_through_api("auth-station-3", &admin).await?;
+    assert_eq!(status, StatusCode::CONFLICT);
+    assert_eq!(body["error"]["code"], "station_offline");
+
+    shutdown.send(()).ok();
+    server.await.expect("server task panicked");
+    Ok(())
+}
@@ -0,0 +1,211 @@
+use std::{error::Error, net::SocketAddr, path::PathBuf, sync::Arc, time::Duration};
+
+use axum::{Router, routing::get};
+use chrono::Utc;
+use common::TlsConfig;
+use futures::{SinkExt, StreamExt};
+use occp_ws::routes::{healthcheck_route, upgrade_to_ws};
+use occp_ws::state::START_TIME;
+use occp_ws::tls;
+use occp_ws::types::*;
+use rcgen::{
+    BasicConstraints, Certificate, CertificateParams, DnType, ExtendedKeyUsagePurpose, IsCa,
+    KeyPair,
+};
+use rust_ocpp::v1_6::messages::heart_beat::HeartbeatRequest;
+use rustls::{ClientConfig, RootCertStore, pki_types::ServerName};
+use tokio::{net::TcpListener, net::TcpStream, task::JoinHandle, time::timeout};
+use tokio_rustls::{TlsConnector, client::TlsStream};
+use tokio_tungstenite::{
+    WebSocketStream, client_async,
+    tungstenite::{self, Message as WsMessage, http::StatusCode},
+};
+
+struct TestPki {
+    dir: PathBuf,
+    ca_cert: Certificate,
+    ca_key: KeyPair,
+}
+
+impl TestPki {
+    fn new(name: &str) -> Result<Self, Box<dyn Error>> {
+        let dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join(name);
+        std::fs::create_dir_all(&dir)?;
+
+        let mut params = CertificateParams::new(Vec::<String>::new())?;
+        params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+        params
+            .distinguished_name
+            .push(DnType::CommonName, "PlugHome Test CA");
+        let ca_key = KeyPair::generate()?;
+        let ca_cert = params.self_signed(&ca_key)?;
+        std::fs::write(dir.join("ca.pem"), ca_cert.pem())?;
+
+        Ok(Self {
+            dir,
+            ca_cert,
+            ca_key,
+        })
+    }
+
+    fn issue(
+        &self,
+        common_name: &str,
+        sans: Vec<String>,
+        usage: ExtendedKeyUsagePurpose,
+    ) -> Result<(Certificate, KeyPair), Box<dyn Error>> {
+        let mut params = CertificateParams::new(sans)?;
+        params
+            .distinguished_name
+            .push(DnType::CommonName, common_name);
+        params.extended_key_usages = vec![usage];
+        let key = KeyPair::generate()?;
+        let cert = params.signed_by(&key, &self.ca_cert, &self.ca_key)?;
+        Ok((cert, key))
+    }
+
+    fn server_tls_config(&self, client_ca: bool) -> Result<TlsConfig, Box<dyn Error>> {
+        let (cert, key) = self.issue(
+            "localhost",
+            vec!["localhost".to_string()],
+            ExtendedKeyUsagePurpose::ServerAuth,
+        )?;
+        std::fs::write(self.dir.join("server.pem"), cert.pem())?;
+        std::fs::write(self.dir.join("server.key"), key.serialize_pem())?;
+
+        Ok(TlsConfig {
+            cert_file: self.dir.join("server.pem"),
+            key_file: self.dir.join("server.key"),
+            client_ca_file: client_ca.then(|| self.dir.join("ca.pem")),
+            client_cert_required: true,
+        })
+    }
+
+    fn client_config(
+        &self,
+        client_cert_cn: Option<&str>,
+    ) -> Result<Arc<ClientConfig>, Box<dyn Error>> {
+        let mut roots = RootCertStore::empty();
+        roots.add(self.ca_cert.der().clone())?;
+        let builder = ClientConfig::builder().with_root_certificates(roots);
+
+        let config = match client_cert_cn {
+            Some(cn) => {
+                let (cert, key) =
+                    self.issue(cn, Vec::new(), ExtendedKeyUsagePurpose::ClientAuth)?;
+                builder.with_client_auth_cert(
+                    vec![cert.der().clone()],
+                    key.serialize_der().try_into()?,
+                )?
+            }
+            None => builder.with_no_client_auth(),
+        };
+        Ok(Arc::new(config))
+    }
+}
+
+async fn start_tls_server(config: &TlsConfig) -> (SocketAddr, JoinHandle<()>) {
+    START_TIME.get_or_init(|| async { Utc::now() }).await;
+
+    let listener = TcpListener::bind("127.0.0.1:0")
+        .await
+        .expect("bind test listener");
+    let addr = listener.local_addr().expect("listener addr");
+
+    let router = Router::new()
+        .route("/:station_id", get(upgrade_to_ws))
+        .route("/", get(healthcheck_route));
+    let server_config = tls::server_config(config).expect("server TLS config");
+
+    let handle = tokio::spawn(async move {
+        tls::serve(listener, router, server_config)
+            .await
+            .expect("TLS server");
+    });
+
+    (addr, handle)
+}
+
+async fn connect_wss(
+    addr: SocketAddr,
+    station_id: &str,
+    client_config: Arc<ClientConfig>,
+) -> Result<WebSocketStream<TlsStream<TcpStream>>, Box<dyn Error>> {
+    let tcp = TcpStream::connect(addr).await?;
+    let tls = TlsConnector::from(client_config)
+        .connect(ServerName::try_from("localhost")?, tcp)
+        .await?;
+    let url = format!("wss://localhost:{}/{station_id}", addr.port());
+    let (socket, _) = client_async(url, tls).await?;
+    Ok(socket)
+}
+
+async fn assert_heartbeat(
+    socket: &mut WebSocketStream<TlsStream<TcpStream>>,
+) -> Result<(), Box<dyn Error>> {
+    let call = OcppCall(
+        2,
+        "tls-hb".to_string(),
+        OcppActionEnum::Heartbeat,
+        OcppPayload::Heartbeat(HeartbeatKind::Request(HeartbeatRequest {})),
+    );
+    socket
+        .send(WsMessage::Text(serde_json::to_string(&call)?))
+        .await?;
+
+    let frame = timeout(Duration::from_secs(5), socket.next())
+        .await?
+        .expect("socket open")?;
+    let WsMessage::Text(text) = frame else {
+        panic!("expected text frame, got {frame:?}");
+    };
+    match serde_json::from_str::<OcppMessageType>(&text)? {
+        OcppMessageType::CallResult(3, id, _) => assert_eq!(id, "tls-hb"),
+        other => panic!("unexpected heartbeat response: {other:?}"),
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn serves_websocket_over_server_tls() -> Result<(), Box<dyn Error>> {
+    let pki = TestPki::new("tls-profile-2")?;
+    let (addr, server) = start_tls_server(&pki.server_tls_config(false)?).await;
+
+    let mut socket = connect_wss(addr, "tls-station-open", pki.client_config(None)?).await?;
+    assert_heartbeat(&mut socket).await?;
+    socket.close(None).await?;
+
+    server.abort();
+    Ok(())
+}
+
+#[tokio::test]
+async fn ties_client_certificate_cn_to_station_id() -> Result<(), Box<dyn Error>> {
+    let pki = TestPki::new("tls-profile-3")?;
+    let (addr, server) = start_tls_server(&pki.server_tls_config(true)?).await;
+
+    let client = pki.client_config(Some("tls-station-mtls"))?;
+    let mut socket = connect_wss(addr, "tls-station-mtls", client.clone()).await?;
+    assert_heartbeat(&mut socket).await?;
+    socket.close(None).await?;
+
+    let impostor = connect_wss(addr, "some-other-station", client).await;
+    match impostor {
+        Err(err) => match err.downcast_ref::<tungstenite::Error>() {
+            Some(tungstenite::Error::Http(response)) => {
+                assert_eq!(response.status(), StatusCode::UNAUTHORIZED)
+            }
+            _ => panic!("expected 401, got {err}"),
+        },
+        Ok(_) => panic!("certificate for another station was accepted"),
+    }
+
+    let anonymous = connect_wss(addr, "tls-station-mtls", pki.client_config(None)?).await;
+    assert!(
+        anonymous.is_err(),
+        "handshake without client cert succeeded"
+    );
+
+    server.abort();
+    Ok(())
+}
@@ -0,0 +1,496 @@
+use std::{error::Error, net::SocketAddr, time::Duration};
+
+use axum::{
+    body::Body,
+    http::{HeaderMap, Method, Request, StatusCode, header},
+};
+use chrono::Utc;
+use futures::StreamExt;
+use occp_ws::audit::Actor;
+use occp_ws::commands::{self, Command, JobStatus};
+use occp_ws::users::{self, Role};
+use occp_ws::{persistence, rest};
+use serde_json::{Value, json};
+use storage::MemoryStore;
+use tokio::{sync::oneshot, task::JoinHandle, time::timeout};
+use tokio_tungstenite::connect_async;
+use tower::ServiceExt;
+
+mod common;
+
+use common::{Socket, call};
+
+async fn start_test_server() -> (SocketAddr, oneshot::Sender<()>, JoinHandle<()>) {
+    persistence::install(MemoryStore::new());
+    common::start_test_server().await
+}
+
+/// Send a request through the authenticated v1 API with a session cookie.
+async fn api(
+    method: Method,
+    path: &str,
+    cookie: Option<&str>,
+    body: Option<Value>,
+) -> Result<(StatusCode, HeaderMap, Value), Box<dyn Error>> {
+    let mut request = Request::builder().method(method).uri(path);
+    if let Some(cookie) = cookie {
+        request = request.header(header::COOKIE, format!("theme=dark; {cookie}"));
+    }
+    let body = match body {
+        Some(body) => {
+            request = request.header(header::CONTENT_TYPE, "application/json");
+            Body::from(body.to_string())
+        }
+        None => Body::empty(),
+    };
+    let response = rest::v1_router_with_auth()
+        .oneshot(request.body(body)?)
+        .await?;
+    let status = response.status();
+    let headers = response.headers().clone();
+    let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await?;
+    let body = if bytes.is_empty() || !headers.contains_key(header::CONTENT_TYPE) {
+        Value::Null
+    } else if headers[header::CONTENT_TYPE]
+        .to_str()?
+        .starts_with("application/json")
+    {
+        serde_json::from_slice(&bytes)?
+    } else {
+        Value::String(String::from_utf8(bytes.to_vec())?)
+    };
+    Ok((status, headers, body))
+}
+
+/// Log in and return the `name=value` part of the session cookie.
+async fn login(username: &str, password: &str) -> Result<String, Box<dyn Error>> {
+    let (status, headers, body) = api(
+        Method::POST,
+        "/auth/login",
+        None,
+        Some(json!({"username": username, "password": password})),
+    )
+    .await?;
+    assert_eq!(status, StatusCode::OK, "login failed: {body}");
+    let set_cookie = headers[header::SET_COOKIE].to_str()?;
+    assert!(set_cookie.contains("HttpOnly"));
+    assert!(set_cookie.contains("SameSite=Strict"));
+    Ok(set_cookie
+        .split(';')
+        .next()
+        .expect("cookie pair")
+        .to_string())
+}
+
+async fn start_transaction(socket: &mut Socket, id_tag: &str) -> Result<i64, Box<dyn Error>> {
+    let response = call(
+        socket,
+        &format!("start-{id_tag}"),
+        "StartTransaction",
+        json!({
+            "connectorId": 1,
+            "idTag": id_tag,
+            "meterStart": 1000,
+            "timestamp": Utc::now().to_rfc3339()
+        }),
+    )
+    .await?;
+    Ok(response["transactionId"].as_i64().expect("transaction id"))
+}
+
+fn session_tags(page: &Value) -> Vec<String> {
+    page["sessions"]
+        .as_array()
+        .expect("session list")
+        .iter()
+        .map(|session| session["id_tag"].as_str().expect("id tag").to_string())
+        .collect()
+}
+
+#[tokio::test]
+async fn household_roles_limit_what_users_do_and_see() -> Result<(), Box<dyn Error>> {
+    let (addr, shutdown, server) = start_test_server().await;
+    let (mut socket, _) = connect_async(format!("ws://{addr}/users-garage")).await?;
+    call(
+        &mut socket,
+        "boot",
+        "BootNotification",
+        json!({"chargePointVendor": "PlugCo", "chargePointModel": "Wallbox 11"}),
+    )
+    .await?;
+    let alice_tx = start_transaction(&mut socket, "USR-ALICE").await?;
+    let bob_tx = start_transaction(&mut socket, "USR-BOB").await?;
+
+    users::create("Owner", "owner-password", Role::Owner, Vec::new()).await?;
+    let (status, _, body) = api(Method::GET, "/stations", None, None).await?;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    assert_eq!(body["error"]["code"], "unauthorized");
+    let (status, _, _) = api(
+        Method::POST,
+        "/auth/login",
+        None,
+        Some(json!({"username": "owner", "password": "wrong-password"})),
+    )
+    .await?;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let owner = login("OWNER", "owner-password").await?;
+
+    // The owner adds household members.
+    let (status, _, alice) = api(
+        Method::POST,
+        "/users",
+        Some(&owner),
+        Some(json!({
+            "username": "alice",
+            "password": "alice-password",
+            "role": "member",
+            "id_tags": ["USR-ALICE"]
+        })),
+    )
+    .await?;
+    assert_eq!(status, StatusCode::CREATED);
+    assert!(alice.get("password_hash").is_none());
+    let alice_id = alice["user_id"].as_str().expect("user id").to_string();
+    let (status, _, _) = api(
+        Method::POST,
+        "/users",
+        Some(&owner),
+        Some(json!({
+            "username": "bob",
+            "password": "bob-password",
+            "role": "guest",
+            "id_tags": ["USR-BOB"]
+        })),
+    )
+    .await?;
+    assert_eq!(status, StatusCode::CREATED);
+    let (status, _, body) = api(
+        Method::POST,
+        "/users",
+        Some(&owner),
+        Some(json!({
+            "username": "carol",
+            "password": "carol-password",
+            "role": "member",
+            "id_tags": ["USR-ALICE"]
+        })),
+    )
+    .await?;
+    assert_eq!(status, StatusCode::CONFLICT);
+    assert_eq!(body["error"]["code"], "conflict");
+    let (status, _, _) = api(
+        Method::POST,
+        "/users",
+        Some(&owner),
+        Some(json!({"username": "dave", "password": "short", "role": "guest"})),
+    )
+    .await?;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+
+    let alice = login("alice", "alice-password").await?;
+    let bob = login("bob", "bob-password").await?;
+    let (status, _, me) = api(Method::GET, "/auth/me", Some(&alice), None).await?;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(me["username"], "alice");
+    assert_eq!(me["role"], "member");
+
+    // Everyone sees their own charging history; the owner sees all of it.
+    let path = "/sessions?station_id=users-garage";
+    let (_, _, page) = api(Method::GET, path, Some(&alice), None).await?;
+    assert_eq!(session_tags(&page), ["USR-ALICE"]);
+    let (_, _, page) = api(Method::GET, path, Some(&bob), None).await?;
+    assert_eq!(session_tags(&page), ["USR-BOB"]);
+    let (_, _, page) = api(Method::GET, path, Some(&owner), None).await?;
+    assert_eq!(session_tags(&page), ["USR-BOB", "USR-ALICE"]);
+    let (status, _, _) = api(
+        Method::GET,
+        &format!("/sessions/{bob_tx}"),
+        Some(&alice),
+        None,
+    )
+    .await?;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let (status, _, csv) = api(
+        Method::GET,
+        "/sessions/export?station_id=users-garage",
+        Some(&alice),
+        None,
+    )
+    .await?;
+    assert_eq!(status, StatusCode::OK);
+    let csv = csv.as_str().expect("csv text");
+    assert!(csv.contains("USR-ALICE") && !csv.contains("USR-BOB"));
+
+    // Members stop their own charging only; guests only look.
+    let (status, _, _) = api(
+        Method::POST,
+        "/stations/users-garage/stop",
+        Some(&alice),
+        Some(json!({"transaction_id": bob_tx})),
+    )
+    .await?;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let (status, _, _) = api(
+        Method::POST,
+        "/stations/users-garage/start",
+        Some(&alice),
+        Some(json!({"id_tag": "USR-BOB"})),
+    )
+    .await?;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let (status, _, job) = api(
+        Method::POST,
+        "/stations/users-garage/stop",
+        Some(&alice),
+        Some(json!({"transaction_id": alice_tx})),
+    )
+    .await?;
+    assert_eq!(status, StatusCode::ACCEPTED);
+    assert_eq!(job["station_id"], "users-garage");
+    for (cookie, method, path) in [
+        (&alice, Method::POST, "/stations/users-garage/reset"),
+        (&alice, Method::PATCH, "/stations/users-garage"),
+        (&alice, Method::GET, "/users"),
+        (&bob, Method::POST, "/stations/users-garage/stop"),
+        (&bob, Method::GET, "/tokens"),
+    ] {
+        let (status, _, body) = api(method, path, Some(cookie), Some(json!({}))).await?;
+        assert_eq!(status, StatusCode::FORBIDDEN, "{path}: {body}");
+        assert_eq!(body["error"]["code"], "forbidden");
+    }
+    let (status, _, _) = api(Method::GET, "/stations/users-garage", Some(&bob), None).await?;
+    assert_eq!(status, StatusCode::OK);
+
+    // The last owner stays.
+    let (_, _, listed) = api(Method::GET, "/users", Some(&owner), None).await?;
+    let owner_id = listed
+        .as_array()
+        .expect("user list")
+        .iter()
+        .find(|user| user["username"] == "owner")
+        .expect("owner listed")["user_id"]
+        .as_str()
+        .expect("user id")
+        .to_string();
+    let (status, _, _) = api(
+        Method::DELETE,
+        &format!("/users/{owner_id}"),
+        Some(&owner),
+        None,
+    )
+    .await?;
+    assert_eq!(status, StatusCode::CONFLICT);
+
+    // A new password ends existing logins; logging out ends one.
+    let (status, _, _) = api(
+        Method::PATCH,
+        &format!("/users/{alice_id}"),
+        Some(&owner),
+        Some(json!({"password": "alice-new-password"})),
+    )
+    .await?;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _, _) = api(Method::GET, "/stations", Some(&alice), None).await?;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    login("alice", "alice-new-password").await?;
+    let (status, headers, _) = api(Method::POST, "/auth/logout", Some(&bob), None).await?;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+    assert!(headers[header::SET_COOKIE].to_str()?.contains("Max-Age=0"));
+    let (status, _, _) = api(Method::GET, "/stations", Some(&bob), None).await?;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    // Users are stored with password hashes only.
+    let rows = persistence::store().expect("store").users()?;
+    let stored = rows
+        .iter()
+        .find(|row| row.user_id == alice_id)
+        .expect("alice saved");
+    assert_eq!(stored.id_tags, ["USR-ALICE"]);
+    assert_eq!(stored.role, "member");
+    assert!(stored.password_hash.starts_with("$argon2"));
+
+    socket.close(None).await?;
+    shutdown.send(()).ok();
+    server.await.expect("server task panicked");
+    Ok(())
+}
+
+/// Event records replayed by `GET /events`, read until the stream goes quiet.
+async fn replayed_events(path: &str, cookie: &str) -> Result<Vec<Value>, Box<dyn Error>> {
+    let request = Request::get(path)
+        .header(header::COOKIE, cookie)
+        .body(Body::empty())?;
+    let response = rest::v1_router_with_auth().oneshot(request).await?;
+    assert_eq!(response.status(), StatusCode::OK);
+    let mut body = response.into_body().into_data_stream();
+    let mut text = String::new();
+    while let Ok(Some(chunk)) = timeout(Duration::from_millis(300), body.next()).await {
+        text.push_str(std::str::from_utf8(&chunk?)?);
+    }
+    text.lines()
+        .filter_map(|line| line.strip_prefix("data:"))
+        .map(|data| Ok(serde_json::from_str(data.trim())?))
+        .collect()
+}
+
+#[tokio::test]
+async fn members_and_guests_only_see_their_own_commands_and_events() -> Result<(), Box<dyn Error>> {
+    let (addr, shutdown, server) = start_test_server().await;
+    let (mut socket, _) = connect_async(format!("ws://{addr}/users-carport")).await?;
+    call(
+        &mut socket,
+        "boot",
+        "BootNotification",
+        json!({"chargePointVendor": "PlugCo", "chargePointModel": "Wallbox 11"}),
+    )
+    .await?;
+    for id_tag in ["USR-ERIN", "USR-FRANK"] {
+        call(
+            &mut socket,
+            &format!("authorize-{id_tag}"),
+            "Authorize",
+            json!({"idTag": id_tag}),
+        )
+        .await?;
+        let transaction_id = start_transaction(&mut socket, id_tag).await?;
+        call(
+            &mut socket,
+            &format!("meter-{id_tag}"),
+            "MeterValues",
+            json!({
+                "connectorId": 1,
+                "transactionId": transaction_id,
+                "meterValue": [{
+                    "timestamp": Utc::now().to_rfc3339(),
+                    "sampledValue": [{"value": "1500"}]
+                }]
+            }),
+        )
+        .await?;
+    }
+
+    let erin_user = users::create(
+        "erin",
+        "erin-password",
+        Role::Member,
+        vec!["USR-ERIN".to_string()],
+    )
+    .await?;
+    let frank_user = users::create(
+        "frank",
+        "frank-password",
+        Role::Guest,
+        vec!["USR-FRANK".to_string()],
+    )
+    .await?;
+    let erin = login("erin", "erin-password").await?;
+    let frank = login("frank", "frank-password").await?;
+
+    let (status, _, erin_job) = api(
+        Method::POST,
+        "/stations/users-carport/start",
+        Some(&erin),
+        Some(json!({"id_tag": "USR-ERIN"})),
+    )
+    .await?;
+    assert_eq!(status, StatusCode::ACCEPTED);
+    let erin_job = erin_job["job_id"].as_str().expect("job id").to_string();
+    let actor = Actor::user(&frank_user, None);
+    let frank_job = commands::submit(
+        "users-carport",
+        Command::RemoteStart {
+            connector_id: None,
+            id_tag: "USR-FRANK".to_string(),
+        },
+        None,
+        &actor,
+    )?
+    .job_id;
+    let unlock_job = commands::submit(
+        "users-carport",
+        Command::Unlock { connector_id: 1 },
+        None,
+        &Actor::user(&erin_user, None),
+    )?
+    .job_id;
+    for job_id in [&erin_job, &frank_job, &unlock_job] {
+        timeout(Duration::from_secs(5), async {
+            while commands::get(job_id).expect("job").status == JobStatus::Queued {
+                tokio::time::sleep(Duration::from_millis(20)).await;
+            }
+        })
+        .await?;
+    }
+
+    // Command jobs are limited to starts and stops of their own id tags.
+    for (cookie, own, other) in [
+        (&erin, &erin_job, &frank_job),
+        (&frank, &frank_job, &erin_job),
+    ] {
+        let (status, _, jobs) = api(
+            Method::GET,
+            "/commands?station_id=users-carport",
+            Some(cookie),
+            None,
+        )
+        .await?;
+        assert_eq!(status, StatusCode::OK);
+        let ids: Vec<_> = jobs
+            .as_array()
+            .expect("job list")
+            .iter()
+            .map(|job| job["job_id"].as_str().expect("job id"))
+            .collect();
+        assert_eq!(ids, [own.as_str()]);
+        let (status, _, _) =
+            api(Method::GET, &format!("/commands/{own}"), Some(cookie), None).await?;
+        assert_eq!(status, StatusCode::OK);
+        for hidden in [other, &unlock_job] {
+            let (status, _, _) = api(
+                Method::GET,
+                &format!("/commands/{hidden}"),
+                Some(cookie),
+                None,
+            )
+            .await?;
+            assert_eq!(status, StatusCode::NOT_FOUND);
+        }
+    }
+
+    // So are session, meter, authorization and command events.
+    let path = "/events?station_id=users-carport&last_event_id=0";
+    for (cookie, own_tag, own_job, other_job) in [
+        (&erin, "USR-ERIN", &erin_job, &frank_job),
+        (&frank, "USR-FRANK", &frank_job, &erin_job),
+    ] {
+        let events = replayed_events(path, cookie).await?;
+        let of_type = |kind: &str| -> Vec<&Value> {
+            events
+                .iter()
+                .filter(|event| event["type"] == kind)
+                .collect()
+        };
+        assert_eq!(of_type("booted").len(), 1);
+        let started = of_type("session_started");
+        assert_eq!(started.len(), 1);
+        assert_eq!(started[0]["session"]["id_tag"], own_tag);
+        let authorized = of_type("authorized");
+        assert_eq!(authorized.len(), 1);
+        assert_eq!(authorized[0]["id_tag"], own_tag);
+        assert_eq!(of_type("meter_values").len(), 1);
+        let commands = of_type("command_updated");
+        assert!(
+            commands
+                .iter()
+                .any(|event| event["job_id"] == own_job.as_str())
+        );
+        assert!(commands.iter().all(|event| {
+            event["job_id"] != other_job.as_str() && event["job_id"] != unlock_job.as_str()
+        }));
+    }
+
+    socket.close(None).await?;
+    shutdown.send(()).ok();
+    server.await.expect("server task panicked");
+    Ok(())
+}
@@ -0,0 +1,310 @@
+use std::{
+    error::Error,
+    net::SocketAddr,
+    sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+    },
+    time::Duration,
+};
+
+use ::common::WebhookConfig;
+use axum::{
+    Router,
+    body::Body,
+    extract::State,
+    http::{HeaderMap, Method, Request, StatusCode},
+    routing::post,
+};
+use chrono::{TimeZone, Utc};
+use occp_ws::state::WEBHOOK_CONFIG;
+use occp_ws::{persistence, rest, webhooks};
+use ring::hmac;
+use serde_json::{Value, json};
+use storage::{DeliveryStatus, MemoryStore, WebhookDeliveryRow, WebhookRow};
+use tokio::{
+    net::TcpListener,
+    sync::{mpsc, oneshot},
+    task::JoinHandle,
+    time::timeout,
+};
+use tokio_tungstenite::connect_async;
+use tower::ServiceExt;
+
+mod common;
+
+use common::call;
+
+async fn start_test_server() -> (SocketAddr, oneshot::Sender<()>, JoinHandle<()>) {
+    persistence::install(MemoryStore::new());
+    common::start_test_server().await
+}
+
+/// A request the stand-in endpoint received.
+#[derive(Debug)]
+struct Received {
+    headers: HeaderMap,
+    body: String,
+}
+
+#[derive(Clone)]
+struct Endpoint {
+    calls: Arc<AtomicUsize>,
+    /// Requests answered with 503 before the endpoint starts accepting.
+    failures: usize,
+    received: mpsc::UnboundedSender<Received>,
+}
+
+async fn receive(State(endpoint): State<Endpoint>, headers: HeaderMap, body: String) -> StatusCode {
+    let call = endpoint.calls.fetch_add(1, Ordering::SeqCst);
+    endpoint.received.send(Received { headers, body }).ok();
+    if call < endpoint.failures {
+        StatusCode::SERVICE_UNAVAILABLE
+    } else {
+        StatusCode::NO_CONTENT
+    }
+}
+
+/// A local HTTP server standing in for the webhook receiver.
+async fn start_endpoint(failures: usize) -> (String, mpsc::UnboundedReceiver<Received>) {
+    let listener = TcpListener::bind("127.0.0.1:0")
+        .await
+        .expect("bind endpoint listener");
+    let addr = listener.local_addr().expect("endpoint addr");
+    let (received, requests) = mpsc::unbounded_channel();
+    let endpoint = Endpoint {
+        calls: Arc::new(AtomicUsize::new(0)),
+        failures,
+        received,
+    };
+    let router = Router::new()
+        .route("/hook", post(receive))
+        .with_state(endpoint);
+    tokio::spawn(async move {
+        axum::serve(listener, router)
+            .await
+            .expect("endpoint server");
+    });
+    (format!("http://{addr}/hook"), requests)
+}
+
+async fn next_request(
+    requests: &mut mpsc::UnboundedReceiver<Received>,
+) -> Result<Received, Box<dyn Error>> {
+    Ok(timeout(Duration::from_secs(5), requests.recv())
+        .await?
+        .expect("endpoint running"))
+}
+
+/// Check the signature headers against an HMAC computed here.
+fn assert_signed(request: &Received, secret: &str) {
+    let header = |name: &str| {
+        request.headers[name]
+            .to_str()
+            .expect("ASCII header")
+            .to_string()
+    };
+    let timestamp = header("x-plughome-timestamp");
+    let key = hmac::Key::new(hmac::HMAC_SHA256, secret.as_bytes());
+    let tag = hmac::sign(&key, format!("{timestamp}.{}", request.body).as_bytes());
+    let expected: String = tag.as_ref().iter().map(|b| format!("{b:02x}")).collect();
+    assert_eq!(header("x-plughome-signature"), format!("sha256={expected}"));
+    assert_eq!(request.headers["content-type"], "application/json");
+}
+
+async fn api(
+    method: Method,
+    path: &str,
+    body: Option<Value>,
+) -> Result<(StatusCode, Value), Box<dyn Error>> {
+    let mut request = Request::builder().method(method).uri(path);
+    let body = match body {
+        Some(body) => {
+            request = request.header("content-type", "application/json");
+            Body::from(body.to_string())
+        }
+        None => Body::empty(),
+    };
+    let response = rest::v1_router().oneshot(request.body(body)?).await?;
+    let status = response.status();
+    let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await?;
+    let json = if bytes.is_empty() {
+        Value::Null
+    } else {
+        serde_json::from_slice(&bytes)?
+    };
+    Ok((status, json))
+}
+
+/// Poll a subscription's delivery log until its newest entry is finished.
+async fn finished_delivery(webhook_id: &str) -> Result<Value, Box<dyn Error>> {
+    for _ in 0..100 {
+        let (status, deliveries) = api(
+            Method::GET,
+            &format!("/webhooks/{webhook_id}/deliveries"),
+            None,
+        )
+        .await?;
+        assert_eq!(status, StatusCode::OK);
+        if let Some(newest) = deliveries.as_array().and_then(|log| log.first())
+            && newest["status"] != "pending"
+        {
+            return Ok(newest.clone());
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    panic!("delivery for {webhook_id} never finished");
+}
+
+// One test, since the worker and the store are process-wide.
+#[tokio::test]
+async fn deliveries_are_signed_retried_logged_and_survive_restarts() -> Result<(), Box<dyn Error>> {
+    WEBHOOK_CONFIG
+        .set(WebhookConfig {
+            max_attempts: 3,
+            initial_backoff: Duration::from_millis(50),
+            max_backoff: Duration::from_millis(200),
+            timeout: Duration::from_secs(2),
+        })
+        .expect("webhook config unset");
+    let (addr, shutdown, server) = start_test_server().await;
+    let (url, mut requests) = start_endpoint(1).await;
+
+    // A delivery left queued by an earlier run is sent after a restart,
+    // retried once the endpoint has failed.
+    let store = persistence::store().expect("store installed");
+    let created_at = Utc.with_ymd_and_hms(2026, 3, 1, 10, 0, 0).unwrap();
+    store.save_webhook(&WebhookRow {
+        webhook_id: "wh-restored".to_string(),
+        url: url.clone(),
+        events: vec!["station_offline".to_string()],
+        secret: "restored-secret".to_string(),
+        created_at,
+    })?;
+    store.save_webhook_delivery(&WebhookDeliveryRow {
+        delivery_id: "dl-restored".to_string(),
+        webhook_id: "wh-restored".to_string(),
+        event: "station_offline".to_string(),
+        payload: r#"{"event":"station_offline","data":{"station_id":"wh-gone"}}"#.to_string(),
+        status: DeliveryStatus::Pending,
+        attempts: 0,
+        next_attempt_at: Some(created_at),
+        last_attempt_at: None,
+        response_status: None,
+        last_error: None,
+        created_at,
+    })?;
+    let restored = persistence::restore()?;
+    assert_eq!((restored.webhooks, restored.pending_deliveries), (1, 1));
+    webhooks::spawn();
+
+    let first = next_request(&mut requests).await?;
+    let retry = next_request(&mut requests).await?;
+    for request in [&first, &retry] {
+        assert_signed(request, "restored-secret");
+        assert_eq!(request.headers["x-plughome-delivery"], "dl-restored");
+        assert_eq!(request.headers["x-plughome-event"], "station_offline");
+    }
+    let delivered = finished_delivery("wh-restored").await?;
+    assert_eq!(delivered["status"], "delivered");
+    assert_eq!(delivered["attempts"], 2);
+    assert_eq!(delivered["response_status"], 204);
+    assert_eq!(delivered["payload"]["data"]["station_id"], "wh-gone");
+    let stored = store.webhook_deliveries("wh-restored", 10)?;
+    assert_eq!(stored[0].status, DeliveryStatus::Delivered);
+
+    let (status, _) = api(Method::DELETE, "/webhooks/wh-restored", None).await?;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+    assert!(store.webhooks()?.is_empty());
+
+    // Subscriptions are validated.
+    for body in [
+        json!({ "url": "ftp://example.com/hook" }),
+        json!({ "url": url, "events": ["heartbeat"] }),
+        json!({ "url": url, "events": [] }),
+        json!({ "url": url, "secret": "" }),
+    ] {
+        let (status, error) = api(Method::POST, "/webhooks", Some(body)).await?;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(error["error"]["code"], "validation_failed");
+    }
+    let (status, _) = api(Method::GET, "/webhooks/missing/deliveries", None).await?;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    // A new subscription gets a generated secret, shown once.
+    let (status, created) = api(
+        Method::POST,
+        "/webhooks",
+        Some(json!({ "url": url, "events": ["station_offline", "session_finished"] })),
+    )
+    .await?;
+    assert_eq!(status, StatusCode::CREATED);
+    let webhook_id = created["webhook_id"].as_str().expect("webhook id");
+    let secret = created["secret"].as_str().expect("generated secret");
+    assert_eq!(secret.len(), 64);
+    assert_eq!(
+        created["events"],
+        json!(["session_finished", "station_offline"])
+    );
+    let (status, shown) = api(Method::GET, &format!("/webhooks/{webhook_id}"), None).await?;
+    assert_eq!(status, StatusCode::OK);
+    assert!(shown.get("secret").is_none());
+
+    // An endpoint that never answers is given up after the last attempt.
+    let unreachable = TcpListener::bind("127.0.0.1:0").await?.local_addr()?;
+    let (_, broken) = api(
+        Method::POST,
+        "/webhooks",
+        Some(json!({
+            "url": format!("http://{unreachable}/hook"),
+            "events": ["station_offline"],
+            "secret": "broken-secret"
+        })),
+    )
+    .await?;
+    let broken_id = broken["webhook_id"].as_str().expect("webhook id");
+
+    let (mut socket, _) = connect_async(format!("ws://{addr}/wh-offline")).await?;
+    call(
+        &mut socket,
+        "boot",
+        "BootNotification",
+        json!({ "chargePointVendor": "PlugCo", "chargePointModel": "Wallbox 11" }),
+    )
+    .await?;
+    socket.close(None).await?;
+
+    let request = next_request(&mut requests).await?;
+    assert_signed(&request, secret);
+    assert_eq!(request.headers["x-plughome-event"], "station_offline");
+    let body: Value = serde_json::from_str(&request.body)?;
+    assert_eq!(body["event"], "station_offline");
+    assert_eq!(
+        body["delivery_id"],
+        request.headers["x-plughome-delivery"].to_str()?
+    );
+    assert_eq!(body["data"]["type"], "offline");
+    assert_eq!(body["data"]["station_id"], "wh-offline");
+    assert_eq!(body["data"]["reason"], "disconnected");
+
+    let delivered = finished_delivery(webhook_id).await?;
+    assert_eq!(delivered["status"], "delivered");
+    assert_eq!(delivered["attempts"], 1);
+
+    let failed = finished_delivery(broken_id).await?;
+    assert_eq!(failed["status"], "failed");
+    assert_eq!(failed["attempts"], 3);
+    assert!(failed["response_status"].is_null());
+    assert!(
+        failed["last_error"]
+            .as_str()
+            .is_some_and(|error| error.starts_with("connection failed"))
+    );
+    let stored = store.webhook_deliveries(broken_id, 10)?;
+    assert_eq!(stored[0].status, DeliveryStatus::Failed);
+    assert_eq!(stored[0].attempts, 3);
+
+    shutdown.send(()).ok();
+    server.await.expect("server task panicked");
+    Ok(())
+}
@@ -0,0 +1,97 @@
+CREATE TABLE stations (
+    station_id TEXT PRIMARY KEY,
+    registration_status TEXT NOT NULL,
+    vendor TEXT NOT NULL,
+    model TEXT NOT NULL,
+    serial_number TEXT,
+    firmware_version TEXT,
+    first_seen TEXT NOT NULL,
+    last_boot TEXT NOT NULL
+);
+
+CREATE TABLE connectors (
+    station_id TEXT NOT NULL,
+    connector_id INTEGER NOT NULL,
+    status TEXT NOT NULL,
+    error_code TEXT NOT NULL,
+    info TEXT,
+    timestamp TEXT,
+    updated_at TEXT NOT NULL,
+    PRIMARY KEY (station_id, connector_id)
+);
+
+CREATE TABLE status_history (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    station_id TEXT NOT NULL,
+    connector_id INTEGER NOT NULL,
+    status TEXT NOT NULL,
+    error_code TEXT NOT NULL,
+    info TEXT,
+    timestamp TEXT,
+    updated_at TEXT NOT NULL
+);
+CREATE INDEX status_history_station ON status_history (station_id, connector_id, updated_at);
+
+CREATE TABLE id_tags (
+    id_tag TEXT PRIMARY KEY,
+    status TEXT NOT NULL,
+    expiry_date TEXT,
+    parent_id_tag TEXT,
+    first_seen TEXT NOT NULL,
+    last_seen TEXT NOT NULL
+);
+
+CREATE TABLE transactions (
+    transaction_id INTEGER PRIMARY KEY,
+    station_id TEXT NOT NULL,
+    connector_id INTEGER NOT NULL,
+    id_tag TEXT NOT NULL,
+    meter_start INTEGER NOT NULL,
+    started_at TEXT NOT NULL,
+    last_meter_value REAL,
+    last_meter_at TEXT,
+    status TEXT NOT NULL,
+    meter_stop INTEGER,
+    stopped_at TEXT,
+    stop_reason TEXT,
+    flag TEXT
+);
+CREATE INDEX transactions_station ON transactions (station_id, started_at);
+
+CREATE TABLE meter_values (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    station_id TEXT NOT NULL,
+    connector_id INTEGER NOT NULL,
+    transaction_id INTEGER,
+    timestamp TEXT NOT NULL,
+    measurand TEXT NOT NULL,
+    phase TEXT,
+    location TEXT,
+    context TEXT,
+    format TEXT,
+    unit TEXT,
+    value TEXT NOT NULL
+);
+-- Replayed MeterValues must not add rows twice. SQLite treats NULLs as
+-- distinct in UNIQUE constraints, hence the COALESCEs.
+CREATE UNIQUE INDEX meter_values_sample ON meter_values (
+    station_id,
+    connector_id,
+    COALESCE(transaction_id, -1),
+    timestamp,
+    measurand,
+    COALESCE(phase, ''),
+    COALESCE(location, '')
+);
+
+CREATE TABLE commands (
+    command_id TEXT PRIMARY KEY,
+    station_id TEXT NOT NULL,
+    action TEXT NOT NULL,
+    request TEXT NOT NULL,
+    status TEXT NOT NULL,
+    response TEXT,
+    created_at TEXT NOT NULL,
+    completed_at TEXT
+);
+CREATE INDEX commands_station ON commands (station_id, created_at);
@@ -0,0 +1 @@
+ALTER TABLE transactions ADD COLUMN peak_power_w REAL;
@@ -0,0 +1,7 @@
+CREATE TABLE station_metadata (
+    station_id TEXT PRIMARY KEY,
+    name TEXT,
+    location TEXT,
+    notes TEXT,
+    updated_at TEXT NOT NULL
+);
@@ -0,0 +1,20 @@
+CREATE TABLE api_tokens (
+    token_id TEXT PRIMARY KEY,
+    name TEXT NOT NULL,
+    -- Hex SHA-256 of the token; the token itself is never stored.
+    token_hash TEXT NOT NULL UNIQUE,
+    prefix TEXT NOT NULL,
+    -- Comma-separated scope names.
+    scopes TEXT NOT NULL,
+    created_at TEXT NOT NULL,
+    last_used_at TEXT,
+    use_count INTEGER NOT NULL DEFAULT 0,
+    revoked_at TEXT
+);
+
+-- Stations a token is limited to; tokens without rows here reach every station.
+CREATE TABLE api_token_stations (
+    token_id TEXT NOT NULL,
+    station_id TEXT NOT NULL,
+    PRIMARY KEY (token_id, station_id)
+);
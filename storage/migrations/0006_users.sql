@@ -0,0 +1,24 @@
+CREATE TABLE users (
+    user_id TEXT PRIMARY KEY,
+    username TEXT NOT NULL UNIQUE,
+    -- Argon2 PHC string.
+    password_hash TEXT NOT NULL,
+    role TEXT NOT NULL,
+    created_at TEXT NOT NULL
+);
+
+-- Id tags a user owns; each tag belongs to at most one user.
+CREATE TABLE user_id_tags (
+    id_tag TEXT PRIMARY KEY,
+    user_id TEXT NOT NULL
+);
+
+CREATE TABLE user_sessions (
+    -- Hex SHA-256 of the session cookie; the cookie itself is never stored.
+    session_hash TEXT PRIMARY KEY,
+    user_id TEXT NOT NULL,
+    created_at TEXT NOT NULL,
+    expires_at TEXT NOT NULL
+);
+
+CREATE INDEX user_sessions_user ON user_sessions (user_id);
@@ -0,0 +1,19 @@
+-- Append-only: rows are only ever inserted, and deleted once past retention.
+CREATE TABLE audit_log (
+    audit_id INTEGER PRIMARY KEY AUTOINCREMENT,
+    at TEXT NOT NULL,
+    -- user, token, automation or anonymous.
+    actor_kind TEXT NOT NULL,
+    actor_id TEXT NOT NULL,
+    actor_name TEXT NOT NULL,
+    remote_addr TEXT,
+    action TEXT NOT NULL,
+    station_id TEXT,
+    -- JSON.
+    request TEXT,
+    response TEXT,
+    outcome TEXT NOT NULL
+);
+
+CREATE INDEX audit_log_at ON audit_log (at);
+CREATE INDEX audit_log_station ON audit_log (station_id, audit_id);
@@ -0,0 +1,2 @@
+-- Seconds; NULL uses the server's HEARTBEAT_INTERVAL.
+ALTER TABLE station_metadata ADD COLUMN heartbeat_interval INTEGER;
@@ -0,0 +1,129 @@
+//! Persistence for stations, connectors, id tags, transactions, meter
+//! values, status history, commands, webhooks, API tokens, users and the
+//! audit log.
+//!
+//! Callers go through the [`Storage`] trait. [`SqliteStore`] is the embedded
+//! database used in production; [`MemoryStore`] keeps everything in memory
+//! for tests. Records use plain strings for OCPP enums so the schema does not
+//! depend on a protocol version.
+
+use chrono::{DateTime, Utc};
+
+mod memory;
+pub mod migrations;
+mod records;
+mod sqlite;
+
+pub use memory::MemoryStore;
+pub use records::{
+    ApiTokenRow, AuditFilter, AuditRow, CommandRow, CommandStatus, ConnectorRow, DeliveryStatus,
+    IdTagRow, MeterReading, StationMetadataRow, StationRow, TransactionRow, UserRow,
+    UserSessionRow, WebhookDeliveryRow, WebhookRow,
+};
+pub use sqlite::SqliteStore;
+
+/// A place to keep the server's state. Implementations must behave the same
+/// way; `tests/conformance.rs` runs one suite against each of them.
+pub trait Storage: Send + Sync {
+    /// Insert or replace a station.
+    fn save_station(&self, station: &StationRow) -> Result<()>;
+    /// All stations, ordered by id.
+    fn stations(&self) -> Result<Vec<StationRow>>;
+
+    /// Insert or replace a station's operator metadata.
+    fn save_station_metadata(&self, metadata: &StationMetadataRow) -> Result<()>;
+    /// Metadata of every station that has some, ordered by id.
+    fn station_metadata(&self) -> Result<Vec<StationMetadataRow>>;
+
+    /// Store a connector's latest status and append it to the history.
+    fn save_connector_status(&self, connector: &ConnectorRow) -> Result<()>;
+    /// Latest status of every connector, ordered by station and connector.
+    fn connectors(&self) -> Result<Vec<ConnectorRow>>;
+    /// A station's most recent status changes, newest first.
+    fn status_history(&self, station_id: &str, limit: usize) -> Result<Vec<ConnectorRow>>;
+
+    /// Insert or update an id tag. `first_seen` is kept from the first save.
+    fn save_id_tag(&self, id_tag: &IdTagRow) -> Result<()>;
+    /// All id tags, ordered by tag.
+    fn id_tags(&self) -> Result<Vec<IdTagRow>>;
+
+    /// Insert or replace a transaction.
+    fn save_transaction(&self, transaction: &TransactionRow) -> Result<()>;
+    /// Every transaction, ordered by id.
+    fn transactions(&self) -> Result<Vec<TransactionRow>>;
+    fn transaction(&self, transaction_id: i32) -> Result<Option<TransactionRow>>;
+
+    /// Store sampled values, skipping ones already stored for the same
+    /// station, connector, transaction, timestamp, measurand, phase and
+    /// location. Returns how many were new.
+    fn save_meter_readings(&self, readings: &[MeterReading]) -> Result<usize>;
+    /// A transaction's sampled values in charger-timestamp order.
+    fn meter_readings(&self, transaction_id: i32) -> Result<Vec<MeterReading>>;
+
+    /// Insert or replace a command.
+    fn save_command(&self, command: &CommandRow) -> Result<()>;
+    fn command(&self, command_id: &str) -> Result<Option<CommandRow>>;
+    /// A station's most recent commands, newest first.
+    fn commands(&self, station_id: &str, limit: usize) -> Result<Vec<CommandRow>>;
+
+    /// Insert or replace a webhook subscription.
+    fn save_webhook(&self, webhook: &WebhookRow) -> Result<()>;
+    /// All webhook subscriptions, ordered by id.
+    fn webhooks(&self) -> Result<Vec<WebhookRow>>;
+    /// Remove a webhook subscription and its deliveries.
+    fn delete_webhook(&self, webhook_id: &str) -> Result<()>;
+
+    /// Insert or replace a webhook delivery.
+    fn save_webhook_delivery(&self, delivery: &WebhookDeliveryRow) -> Result<()>;
+    /// A subscription's most recent deliveries, newest first.
+    fn webhook_deliveries(&self, webhook_id: &str, limit: usize)
+    -> Result<Vec<WebhookDeliveryRow>>;
+    /// Deliveries still to be sent, oldest first.
+    fn pending_webhook_deliveries(&self) -> Result<Vec<WebhookDeliveryRow>>;
+    /// Delete a subscription's finished deliveries beyond the newest `keep`.
+    /// Returns how many went.
+    fn prune_webhook_deliveries(&self, webhook_id: &str, keep: usize) -> Result<usize>;
+
+    /// Insert or replace an API token.
+    fn save_api_token(&self, token: &ApiTokenRow) -> Result<()>;
+    /// Every API token, revoked ones included, ordered by id.
+    fn api_tokens(&self) -> Result<Vec<ApiTokenRow>>;
+
+    /// Insert or replace a user and the id tags they own.
+    fn save_user(&self, user: &UserRow) -> Result<()>;
+    /// All users, ordered by id.
+    fn users(&self) -> Result<Vec<UserRow>>;
+    /// Remove a user with their id tags and login sessions.
+    fn delete_user(&self, user_id: &str) -> Result<()>;
+
+    /// Insert or replace a login session.
+    fn save_user_session(&self, session: &UserSessionRow) -> Result<()>;
+    /// Every login session, ordered by expiry.
+    fn user_sessions(&self) -> Result<Vec<UserSessionRow>>;
+    fn delete_user_session(&self, session_hash: &str) -> Result<()>;
+
+    /// Append an audit record, ignoring its `audit_id`. Returns the id it got;
+    /// ids increase with every record.
+    fn append_audit_record(&self, record: &AuditRow) -> Result<i64>;
+    /// Matching audit records, newest first.
+    fn audit_records(&self, filter: &AuditFilter, limit: usize) -> Result<Vec<AuditRow>>;
+    /// Delete audit records made before `before`. Returns how many went.
+    fn prune_audit_records(&self, before: DateTime<Utc>) -> Result<usize>;
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum StorageError {
+    #[error("database error: {0}")]
+    Sqlite(#[from] rusqlite::Error),
+    #[error("migration {version} failed: {source}")]
+    Migration {
+        version: usize,
+        source: rusqlite::Error,
+    },
+    #[error("database schema version {found} is newer than this build supports ({supported})")]
+    SchemaTooNew { found: usize, supported: usize },
+    #[error("invalid value {value:?} in column {column}")]
+    InvalidValue { column: &'static str, value: String },
+}
+
+pub type Result<T> = std::result::Result<T, StorageError>;
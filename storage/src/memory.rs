@@ -0,0 +1,366 @@
+use std::{
+    cmp::Reverse,
+    collections::{BTreeMap, HashMap, HashSet},
+    sync::{Mutex, MutexGuard},
+};
+
+use chrono::{DateTime, Utc};
+
+use crate::records::*;
+use crate::{Result, Storage};
+
+/// Keeps everything in memory and loses it on drop. Meant for tests.
+#[derive(Default)]
+pub struct MemoryStore {
+    data: Mutex<Data>,
+}
+
+#[derive(Default)]
+struct Data {
+    stations: BTreeMap<String, StationRow>,
+    station_metadata: BTreeMap<String, StationMetadataRow>,
+    connectors: BTreeMap<(String, u32), ConnectorRow>,
+    status_history: Vec<ConnectorRow>,
+    id_tags: BTreeMap<String, IdTagRow>,
+    transactions: BTreeMap<i32, TransactionRow>,
+    meter_readings: Vec<MeterReading>,
+    commands: HashMap<String, CommandRow>,
+    webhooks: BTreeMap<String, WebhookRow>,
+    /// In insertion order.
+    webhook_deliveries: Vec<WebhookDeliveryRow>,
+    api_tokens: BTreeMap<String, ApiTokenRow>,
+    users: BTreeMap<String, UserRow>,
+    user_sessions: HashMap<String, UserSessionRow>,
+    /// In id order.
+    audit_log: Vec<AuditRow>,
+    last_audit_id: i64,
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn data(&self) -> MutexGuard<'_, Data> {
+        self.data.lock().expect("memory store lock poisoned")
+    }
+}
+
+/// Readings that the SQLite unique index treats as the same sample.
+fn same_sample(a: &MeterReading, b: &MeterReading) -> bool {
+    a.station_id == b.station_id
+        && a.connector_id == b.connector_id
+        && a.transaction_id == b.transaction_id
+        && a.timestamp == b.timestamp
+        && a.measurand == b.measurand
+        && a.phase == b.phase
+        && a.location == b.location
+}
+
+impl Storage for MemoryStore {
+    fn save_station(&self, station: &StationRow) -> Result<()> {
+        self.data()
+            .stations
+            .insert(station.station_id.clone(), station.clone());
+        Ok(())
+    }
+
+    fn stations(&self) -> Result<Vec<StationRow>> {
+        Ok(self.data().stations.values().cloned().collect())
+    }
+
+    fn save_station_metadata(&self, metadata: &StationMetadataRow) -> Result<()> {
+        self.data()
+            .station_metadata
+            .insert(metadata.station_id.clone(), metadata.clone());
+        Ok(())
+    }
+
+    fn station_metadata(&self) -> Result<Vec<StationMetadataRow>> {
+        Ok(self.data().station_metadata.values().cloned().collect())
+    }
+
+    fn save_connector_status(&self, connector: &ConnectorRow) -> Result<()> {
+        let mut data = self.data();
+        data.connectors.insert(
+            (connector.station_id.clone(), connector.connector_id),
+            connector.clone(),
+        );
+        data.status_history.push(connector.clone());
+        Ok(())
+    }
+
+    fn connectors(&self) -> Result<Vec<ConnectorRow>> {
+        Ok(self.data().connectors.values().cloned().collect())
+    }
+
+    fn status_history(&self, station_id: &str, limit: usize) -> Result<Vec<ConnectorRow>> {
+        Ok(self
+            .data()
+            .status_history
+            .iter()
+            .rev()
+            .filter(|row| row.station_id == station_id)
+            .take(limit)
+            .cloned()
+            .collect())
+    }
+
+    fn save_id_tag(&self, id_tag: &IdTagRow) -> Result<()> {
+        let mut data = self.data();
+        let first_seen = data
+            .id_tags
+            .get(&id_tag.id_tag)
+            .map_or(id_tag.first_seen, |existing| existing.first_seen);
+        data.id_tags.insert(
+            id_tag.id_tag.clone(),
+            IdTagRow {
+                first_seen,
+                ..id_tag.clone()
+            },
+        );
+        Ok(())
+    }
+
+    fn id_tags(&self) -> Result<Vec<IdTagRow>> {
+        Ok(self.data().id_tags.values().cloned().collect())
+    }
+
+    fn save_transaction(&self, transaction: &TransactionRow) -> Result<()> {
+        self.data()
+            .transactions
+            .insert(transaction.transaction_id, transaction.clone());
+        Ok(())
+    }
+
+    fn transactions(&self) -> Result<Vec<TransactionRow>> {
+        Ok(self.data().transactions.values().cloned().collect())
+    }
+
+    fn transaction(&self, transaction_id: i32) -> Result<Option<TransactionRow>> {
+        Ok(self.data().transactions.get(&transaction_id).cloned())
+    }
+
+    fn save_meter_readings(&self, readings: &[MeterReading]) -> Result<usize> {
+        let mut data = self.data();
+        let mut inserted = 0;
+        for reading in readings {
+            if !data
+                .meter_readings
+                .iter()
+                .any(|stored| same_sample(stored, reading))
+            {
+                data.meter_readings.push(reading.clone());
+                inserted += 1;
+            }
+        }
+        Ok(inserted)
+    }
+
+    fn meter_readings(&self, transaction_id: i32) -> Result<Vec<MeterReading>> {
+        let mut readings: Vec<_> = self
+            .data()
+            .meter_readings
+            .iter()
+            .filter(|reading| reading.transaction_id == Some(transaction_id))
+            .cloned()
+            .collect();
+        // Stable, so readings with equal timestamps keep insertion order.
+        readings.sort_by_key(|reading| reading.timestamp);
+        Ok(readings)
+    }
+
+    fn save_command(&self, command: &CommandRow) -> Result<()> {
+        self.data()
+            .commands
+            .insert(command.command_id.clone(), command.clone());
+        Ok(())
+    }
+
+    fn command(&self, command_id: &str) -> Result<Option<CommandRow>> {
+        Ok(self.data().commands.get(command_id).cloned())
+    }
+
+    fn commands(&self, station_id: &str, limit: usize) -> Result<Vec<CommandRow>> {
+        let mut commands: Vec<_> = self
+            .data()
+            .commands
+            .values()
+            .filter(|command| command.station_id == station_id)
+            .cloned()
+            .collect();
+        commands.sort_by_key(|command| Reverse(command.created_at));
+        commands.truncate(limit);
+        Ok(commands)
+    }
+
+    fn save_webhook(&self, webhook: &WebhookRow) -> Result<()> {
+        self.data()
+            .webhooks
+            .insert(webhook.webhook_id.clone(), webhook.clone());
+        Ok(())
+    }
+
+    fn webhooks(&self) -> Result<Vec<WebhookRow>> {
+        Ok(self.data().webhooks.values().cloned().collect())
+    }
+
+    fn delete_webhook(&self, webhook_id: &str) -> Result<()> {
+        let mut data = self.data();
+        data.webhooks.remove(webhook_id);
+        data.webhook_deliveries
+            .retain(|delivery| delivery.webhook_id != webhook_id);
+        Ok(())
+    }
+
+    fn save_webhook_delivery(&self, delivery: &WebhookDeliveryRow) -> Result<()> {
+        let mut data = self.data();
+        match data
+            .webhook_deliveries
+            .iter_mut()
+            .find(|stored| stored.delivery_id == delivery.delivery_id)
+        {
+            Some(stored) => *stored = delivery.clone(),
+            None => data.webhook_deliveries.push(delivery.clone()),
+        }
+        Ok(())
+    }
+
+    fn webhook_deliveries(
+        &self,
+        webhook_id: &str,
+        limit: usize,
+    ) -> Result<Vec<WebhookDeliveryRow>> {
+        let mut deliveries: Vec<_> = self
+            .data()
+            .webhook_deliveries
+            .iter()
+            .rev()
+            .filter(|delivery| delivery.webhook_id == webhook_id)
+            .cloned()
+            .collect();
+        // Stable, so deliveries created together stay newest first.
+        deliveries.sort_by_key(|delivery| Reverse(delivery.created_at));
+        deliveries.truncate(limit);
+        Ok(deliveries)
+    }
+
+    fn pending_webhook_deliveries(&self) -> Result<Vec<WebhookDeliveryRow>> {
+        let mut deliveries: Vec<_> = self
+            .data()
+            .webhook_deliveries
+            .iter()
+            .filter(|delivery| delivery.status == DeliveryStatus::Pending)
+            .cloned()
+            .collect();
+        deliveries.sort_by_key(|delivery| delivery.created_at);
+        Ok(deliveries)
+    }
+
+    fn prune_webhook_deliveries(&self, webhook_id: &str, keep: usize) -> Result<usize> {
+        let mut data = self.data();
+        let mut finished: Vec<_> = data
+            .webhook_deliveries
+            .iter()
+            .rev()
+            .filter(|delivery| {
+                delivery.webhook_id == webhook_id && delivery.status != DeliveryStatus::Pending
+            })
+            .collect();
+        finished.sort_by_key(|delivery| Reverse(delivery.created_at));
+        let pruned: HashSet<String> = finished
+            .into_iter()
+            .skip(keep)
+            .map(|delivery| delivery.delivery_id.clone())
+            .collect();
+        data.webhook_deliveries
+            .retain(|delivery| !pruned.contains(&delivery.delivery_id));
+        Ok(pruned.len())
+    }
+
+    fn save_api_token(&self, token: &ApiTokenRow) -> Result<()> {
+        self.data()
+            .api_tokens
+            .insert(token.token_id.clone(), token.clone());
+        Ok(())
+    }
+
+    fn api_tokens(&self) -> Result<Vec<ApiTokenRow>> {
+        Ok(self.data().api_tokens.values().cloned().collect())
+    }
+
+    fn save_user(&self, user: &UserRow) -> Result<()> {
+        let mut data = self.data();
+        // A tag moves to whoever saved it last, as with the SQLite primary key.
+        for other in data.users.values_mut() {
+            other.id_tags.retain(|tag| !user.id_tags.contains(tag));
+        }
+        let mut user = user.clone();
+        user.id_tags.sort();
+        user.id_tags.dedup();
+        data.users.insert(user.user_id.clone(), user);
+        Ok(())
+    }
+
+    fn users(&self) -> Result<Vec<UserRow>> {
+        Ok(self.data().users.values().cloned().collect())
+    }
+
+    fn delete_user(&self, user_id: &str) -> Result<()> {
+        let mut data = self.data();
+        data.users.remove(user_id);
+        data.user_sessions
+            .retain(|_, session| session.user_id != user_id);
+        Ok(())
+    }
+
+    fn save_user_session(&self, session: &UserSessionRow) -> Result<()> {
+        self.data()
+            .user_sessions
+            .insert(session.session_hash.clone(), session.clone());
+        Ok(())
+    }
+
+    fn user_sessions(&self) -> Result<Vec<UserSessionRow>> {
+        let mut sessions: Vec<_> = self.data().user_sessions.values().cloned().collect();
+        sessions
+            .sort_by(|a, b| (a.expires_at, &a.session_hash).cmp(&(b.expires_at, &b.session_hash)));
+        Ok(sessions)
+    }
+
+    fn delete_user_session(&self, session_hash: &str) -> Result<()> {
+        self.data().user_sessions.remove(session_hash);
+        Ok(())
+    }
+
+    fn append_audit_record(&self, record: &AuditRow) -> Result<i64> {
+        let mut data = self.data();
+        // Like AUTOINCREMENT, ids are not reused after pruning.
+        data.last_audit_id += 1;
+        let audit_id = data.last_audit_id;
+        data.audit_log.push(AuditRow {
+            audit_id,
+            ..record.clone()
+        });
+        Ok(audit_id)
+    }
+
+    fn audit_records(&self, filter: &AuditFilter, limit: usize) -> Result<Vec<AuditRow>> {
+        Ok(self
+            .data()
+            .audit_log
+            .iter()
+            .rev()
+            .filter(|record| filter.matches(record))
+            .take(limit)
+            .cloned()
+            .collect())
+    }
+
+    fn prune_audit_records(&self, before: DateTime<Utc>) -> Result<usize> {
+        let mut data = self.data();
+        let count = data.audit_log.len();
+        data.audit_log.retain(|record| record.at >= before);
+        Ok(count - data.audit_log.len())
+    }
+}
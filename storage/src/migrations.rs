@@ -0,0 +1,56 @@
+//! Versioned schema migrations, tracked in `PRAGMA user_version`.
+//!
+//! Migrations are append-only: never edit one that has shipped, add a new
+//! file instead.
+
+use rusqlite::Connection;
+use tracing::info;
+
+use crate::{Result, StorageError};
+
+/// Schema changes in order; the version after applying `MIGRATIONS[i]` is `i + 1`.
+pub const MIGRATIONS: &[&str] = &[
+    include_str!("../migrations/0001_initial.sql"),
+    include_str!("../migrations/0002_transaction_peak_power.sql"),
+    include_str!("../migrations/0003_station_metadata.sql"),
+    include_str!("../migrations/0004_webhooks.sql"),
+    include_str!("../migrations/0005_api_tokens.sql"),
+    include_str!("../migrations/0006_users.sql"),
+    include_str!("../migrations/0007_audit_log.sql"),
+    include_str!("../migrations/0008_station_heartbeat_interval.sql"),
+];
+
+/// The schema version this build expects.
+pub fn latest_version() -> usize {
+    MIGRATIONS.len()
+}
+
+pub fn schema_version(conn: &Connection) -> Result<usize> {
+    let version: i64 = conn.pragma_query_value(None, "user_version", |row| row.get(0))?;
+    Ok(version as usize)
+}
+
+/// Apply every migration the database has not seen yet, each in its own
+/// transaction. Returns the resulting schema version.
+pub fn migrate(conn: &mut Connection) -> Result<usize> {
+    let current = schema_version(conn)?;
+    if current > latest_version() {
+        return Err(StorageError::SchemaTooNew {
+            found: current,
+            supported: latest_version(),
+        });
+    }
+
+    for (index, sql) in MIGRATIONS.iter().enumerate().skip(current) {
+        let version = index + 1;
+        let apply = |conn: &mut Connection| -> rusqlite::Result<()> {
+            let tx = conn.transaction()?;
+            tx.execute_batch(sql)?;
+            tx.pragma_update(None, "user_version", version as i64)?;
+            tx.commit()
+        };
+        apply(conn).map_err(|source| StorageError::Migration { version, source })?;
+        info!(version, "Applied database migration");
+    }
+    Ok(latest_version())
+}
@@ -0,0 +1,312 @@
+use std::{fmt, str::FromStr};
+
+use chrono::{DateTime, Utc};
+
+use crate::StorageError;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct StationRow {
+    pub station_id: String,
+    pub registration_status: String,
+    pub vendor: String,
+    pub model: String,
+    pub serial_number: Option<String>,
+    pub firmware_version: Option<String>,
+    pub first_seen: DateTime<Utc>,
+    pub last_boot: DateTime<Utc>,
+}
+
+/// Details an operator keeps about a station.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StationMetadataRow {
+    pub station_id: String,
+    pub name: Option<String>,
+    pub location: Option<String>,
+    pub notes: Option<String>,
+    /// Heartbeat interval in seconds; `None` uses the server default.
+    pub heartbeat_interval: Option<u32>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// A connector's reported status. Also the shape of a status history entry.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConnectorRow {
+    pub station_id: String,
+    pub connector_id: u32,
+    pub status: String,
+    pub error_code: String,
+    pub info: Option<String>,
+    pub timestamp: Option<DateTime<Utc>>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct IdTagRow {
+    pub id_tag: String,
+    pub status: String,
+    pub expiry_date: Option<DateTime<Utc>>,
+    pub parent_id_tag: Option<String>,
+    pub first_seen: DateTime<Utc>,
+    pub last_seen: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TransactionRow {
+    pub transaction_id: i32,
+    pub station_id: String,
+    pub connector_id: u32,
+    pub id_tag: String,
+    pub meter_start: i32,
+    pub started_at: DateTime<Utc>,
+    pub last_meter_value: Option<f64>,
+    pub last_meter_at: Option<DateTime<Utc>>,
+    /// Highest power drawn during the transaction, in W.
+    pub peak_power_w: Option<f64>,
+    pub status: String,
+    pub meter_stop: Option<i32>,
+    pub stopped_at: Option<DateTime<Utc>>,
+    pub stop_reason: Option<String>,
+    pub flag: Option<String>,
+}
+
+/// One sampled value from a MeterValues or StopTransaction message.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MeterReading {
+    pub station_id: String,
+    pub connector_id: u32,
+    pub transaction_id: Option<i32>,
+    pub timestamp: DateTime<Utc>,
+    pub measurand: String,
+    pub phase: Option<String>,
+    pub location: Option<String>,
+    pub context: Option<String>,
+    pub format: Option<String>,
+    pub unit: Option<String>,
+    pub value: String,
+}
+
+/// A Call the server sent to a charger.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CommandRow {
+    /// The OCPP message id.
+    pub command_id: String,
+    pub station_id: String,
+    pub action: String,
+    /// Request payload as JSON.
+    pub request: String,
+    pub status: CommandStatus,
+    /// CallResult payload or error description as JSON.
+    pub response: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub completed_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CommandStatus {
+    /// Sent, no answer yet.
+    Pending,
+    /// Answered with a CallResult.
+    Completed,
+    /// Answered with a CallError.
+    Rejected,
+    /// No answer: timeout or the connection closed.
+    Failed,
+}
+
+impl CommandStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Pending => "pending",
+            Self::Completed => "completed",
+            Self::Rejected => "rejected",
+            Self::Failed => "failed",
+        }
+    }
+}
+
+impl fmt::Display for CommandStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for CommandStatus {
+    type Err = StorageError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "pending" => Ok(Self::Pending),
+            "completed" => Ok(Self::Completed),
+            "rejected" => Ok(Self::Rejected),
+            "failed" => Ok(Self::Failed),
+            _ => Err(StorageError::InvalidValue {
+                column: "commands.status",
+                value: value.to_string(),
+            }),
+        }
+    }
+}
+
+/// A webhook subscription.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WebhookRow {
+    pub webhook_id: String,
+    pub url: String,
+    /// Event names the subscription receives.
+    pub events: Vec<String>,
+    /// Key for the HMAC signature of every delivery.
+    pub secret: String,
+    pub created_at: DateTime<Utc>,
+}
+
+/// One event queued for, or sent to, a webhook.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WebhookDeliveryRow {
+    pub delivery_id: String,
+    pub webhook_id: String,
+    pub event: String,
+    /// Request body as JSON.
+    pub payload: String,
+    pub status: DeliveryStatus,
+    pub attempts: u32,
+    pub next_attempt_at: Option<DateTime<Utc>>,
+    pub last_attempt_at: Option<DateTime<Utc>>,
+    /// HTTP status of the last attempt, if the endpoint answered.
+    pub response_status: Option<u16>,
+    pub last_error: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeliveryStatus {
+    /// Not sent yet, or waiting for a retry.
+    Pending,
+    /// The endpoint answered with a 2xx status.
+    Delivered,
+    /// Every attempt failed.
+    Failed,
+}
+
+impl DeliveryStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Pending => "pending",
+            Self::Delivered => "delivered",
+            Self::Failed => "failed",
+        }
+    }
+}
+
+impl fmt::Display for DeliveryStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for DeliveryStatus {
+    type Err = StorageError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "pending" => Ok(Self::Pending),
+            "delivered" => Ok(Self::Delivered),
+            "failed" => Ok(Self::Failed),
+            _ => Err(StorageError::InvalidValue {
+                column: "webhook_deliveries.status",
+                value: value.to_string(),
+            }),
+        }
+    }
+}
+
+/// A bearer token for the HTTP API.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ApiTokenRow {
+    pub token_id: String,
+    pub name: String,
+    /// Hex SHA-256 of the token.
+    pub token_hash: String,
+    /// The token's first characters, to tell tokens apart.
+    pub prefix: String,
+    pub scopes: Vec<String>,
+    /// Stations the token is limited to, never empty; `None` for all of them.
+    pub station_ids: Option<Vec<String>>,
+    pub created_at: DateTime<Utc>,
+    pub last_used_at: Option<DateTime<Utc>>,
+    pub use_count: u64,
+    pub revoked_at: Option<DateTime<Utc>>,
+}
+
+/// A household member who logs in to the HTTP API.
+#[derive(Debug, Clone, PartialEq)]
+pub struct UserRow {
+    pub user_id: String,
+    pub username: String,
+    /// Argon2 PHC string.
+    pub password_hash: String,
+    pub role: String,
+    /// Id tags the user owns, ordered.
+    pub id_tags: Vec<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// A logged in user's cookie session.
+#[derive(Debug, Clone, PartialEq)]
+pub struct UserSessionRow {
+    /// Hex SHA-256 of the session cookie.
+    pub session_hash: String,
+    pub user_id: String,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+}
+
+/// One entry of the audit log. JSON payloads are kept as text.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AuditRow {
+    pub audit_id: i64,
+    pub at: DateTime<Utc>,
+    pub actor_kind: String,
+    /// User or token id, or the name of the automation.
+    pub actor_id: String,
+    pub actor_name: String,
+    pub remote_addr: Option<String>,
+    /// OCPP action sent, or the server-side change made.
+    pub action: String,
+    pub station_id: Option<String>,
+    pub request: Option<String>,
+    pub response: Option<String>,
+    pub outcome: String,
+}
+
+/// Which audit records to return; unset fields match everything.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AuditFilter {
+    pub station_id: Option<String>,
+    pub actor_id: Option<String>,
+    pub action: Option<String>,
+    /// Records made at or after this time.
+    pub from: Option<DateTime<Utc>>,
+    /// Records made before this time.
+    pub to: Option<DateTime<Utc>>,
+    /// Records with a smaller id, to page backwards.
+    pub before_id: Option<i64>,
+}
+
+impl AuditFilter {
+    pub fn matches(&self, record: &AuditRow) -> bool {
+        self.station_id
+            .as_ref()
+            .is_none_or(|id| record.station_id.as_ref() == Some(id))
+            && self
+                .actor_id
+                .as_ref()
+                .is_none_or(|id| record.actor_id == *id)
+            && self
+                .action
+                .as_ref()
+                .is_none_or(|action| record.action == *action)
+            && self.from.is_none_or(|from| record.at >= from)
+            && self.to.is_none_or(|to| record.at < to)
+            && self.before_id.is_none_or(|id| record.audit_id < id)
+    }
+}
@@ -0,0 +1,832 @@
+use std::{collections::HashMap, path::Path, sync::Mutex, time::Duration};
+
+use chrono::{DateTime, Utc};
+use rusqlite::{Connection, OptionalExtension, Row, params};
+
+use crate::migrations;
+use crate::records::*;
+use crate::{Result, Storage};
+
+/// How long a write waits for another connection's lock before failing.
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A SQLite database holding the server's state. Statements run
+/// synchronously behind a mutex, so async callers should keep them off the
+/// executor; a home installation writes a handful of rows per charger
+/// message.
+pub struct SqliteStore {
+    conn: Mutex<Connection>,
+}
+
+impl SqliteStore {
+    /// Open or create the database file and bring its schema up to date.
+    pub fn open(path: &Path) -> Result<Self> {
+        let conn = Connection::open(path)?;
+        conn.pragma_update(None, "journal_mode", "WAL")?;
+        Self::init(conn)
+    }
+
+    /// A throwaway database, e.g. for tests.
+    pub fn open_in_memory() -> Result<Self> {
+        Self::init(Connection::open_in_memory()?)
+    }
+
+    fn init(mut conn: Connection) -> Result<Self> {
+        conn.busy_timeout(BUSY_TIMEOUT)?;
+        migrations::migrate(&mut conn)?;
+        Ok(Self {
+            conn: Mutex::new(conn),
+        })
+    }
+
+    pub fn schema_version(&self) -> Result<usize> {
+        migrations::schema_version(&self.conn())
+    }
+
+    fn conn(&self) -> std::sync::MutexGuard<'_, Connection> {
+        self.conn.lock().expect("sqlite connection lock poisoned")
+    }
+}
+
+impl Storage for SqliteStore {
+    fn save_station(&self, station: &StationRow) -> Result<()> {
+        self.conn().execute(
+            "INSERT INTO stations (station_id, registration_status, vendor, model, serial_number,
+                 firmware_version, first_seen, last_boot)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)
+             ON CONFLICT (station_id) DO UPDATE SET
+                 registration_status = excluded.registration_status,
+                 vendor = excluded.vendor,
+                 model = excluded.model,
+                 serial_number = excluded.serial_number,
+                 firmware_version = excluded.firmware_version,
+                 first_seen = excluded.first_seen,
+                 last_boot = excluded.last_boot",
+            params![
+                station.station_id,
+                station.registration_status,
+                station.vendor,
+                station.model,
+                station.serial_number,
+                station.firmware_version,
+                station.first_seen,
+                station.last_boot,
+            ],
+        )?;
+        Ok(())
+    }
+
+    fn stations(&self) -> Result<Vec<StationRow>> {
+        let conn = self.conn();
+        let mut stmt = conn.prepare(
+            "SELECT station_id, registration_status, vendor, model, serial_number,
+                 firmware_version, first_seen, last_boot
+             FROM stations ORDER BY station_id",
+        )?;
+        let rows = stmt.query_map([], |row| {
+            Ok(StationRow {
+                station_id: row.get(0)?,
+                registration_status: row.get(1)?,
+                vendor: row.get(2)?,
+                model: row.get(3)?,
+                serial_number: row.get(4)?,
+                firmware_version: row.get(5)?,
+                first_seen: row.get(6)?,
+                last_boot: row.get(7)?,
+            })
+        })?;
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+
+    fn save_station_metadata(&self, metadata: &StationMetadataRow) -> Result<()> {
+        self.conn().execute(
+            "INSERT OR REPLACE INTO station_metadata
+                 (station_id, name, location, notes, heartbeat_interval, updated_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![
+                metadata.station_id,
+                metadata.name,
+                metadata.location,
+                metadata.notes,
+                metadata.heartbeat_interval,
+                metadata.updated_at,
+            ],
+        )?;
+        Ok(())
+    }
+
+    fn station_metadata(&self) -> Result<Vec<StationMetadataRow>> {
+        let conn = self.conn();
+        let mut stmt = conn.prepare(
+            "SELECT station_id, name, location, notes, heartbeat_interval, updated_at
+             FROM station_metadata ORDER BY station_id",
+        )?;
+        let rows = stmt.query_map([], |row| {
+            Ok(StationMetadataRow {
+                station_id: row.get(0)?,
+                name: row.get(1)?,
+                location: row.get(2)?,
+                notes: row.get(3)?,
+                heartbeat_interval: row.get(4)?,
+                updated_at: row.get(5)?,
+            })
+        })?;
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+
+    fn save_connector_status(&self, connector: &ConnectorRow) -> Result<()> {
+        let mut conn = self.conn();
+        let tx = conn.transaction()?;
+        let values = params![
+            connector.station_id,
+            connector.connector_id,
+            connector.status,
+            connector.error_code,
+            connector.info,
+            connector.timestamp,
+            connector.updated_at,
+        ];
+        tx.execute(
+            "INSERT INTO connectors (station_id, connector_id, status, error_code, info,
+                 timestamp, updated_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)
+             ON CONFLICT (station_id, connector_id) DO UPDATE SET
+                 status = excluded.status,
+                 error_code = excluded.error_code,
+                 info = excluded.info,
+                 timestamp = excluded.timestamp,
+                 updated_at = excluded.updated_at",
+            values,
+        )?;
+        tx.execute(
+            "INSERT INTO status_history (station_id, connector_id, status, error_code, info,
+                 timestamp, updated_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            values,
+        )?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    fn connectors(&self) -> Result<Vec<ConnectorRow>> {
+        let conn = self.conn();
+        let mut stmt = conn.prepare(
+            "SELECT station_id, connector_id, status, error_code, info, timestamp, updated_at
+             FROM connectors ORDER BY station_id, connector_id",
+        )?;
+        let rows = stmt.query_map([], connector_row)?;
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+
+    fn status_history(&self, station_id: &str, limit: usize) -> Result<Vec<ConnectorRow>> {
+        let conn = self.conn();
+        let mut stmt = conn.prepare(
+            "SELECT station_id, connector_id, status, error_code, info, timestamp, updated_at
+             FROM status_history WHERE station_id = ?1
+             ORDER BY id DESC LIMIT ?2",
+        )?;
+        let rows = stmt.query_map(params![station_id, limit as i64], connector_row)?;
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+
+    fn save_id_tag(&self, id_tag: &IdTagRow) -> Result<()> {
+        self.conn().execute(
+            "INSERT INTO id_tags (id_tag, status, expiry_date, parent_id_tag, first_seen, last_seen)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+             ON CONFLICT (id_tag) DO UPDATE SET
+                 status = excluded.status,
+                 expiry_date = excluded.expiry_date,
+                 parent_id_tag = excluded.parent_id_tag,
+                 last_seen = excluded.last_seen",
+            params![
+                id_tag.id_tag,
+                id_tag.status,
+                id_tag.expiry_date,
+                id_tag.parent_id_tag,
+                id_tag.first_seen,
+                id_tag.last_seen,
+            ],
+        )?;
+        Ok(())
+    }
+
+    fn id_tags(&self) -> Result<Vec<IdTagRow>> {
+        let conn = self.conn();
+        let mut stmt = conn.prepare(
+            "SELECT id_tag, status, expiry_date, parent_id_tag, first_seen, last_seen
+             FROM id_tags ORDER BY id_tag",
+        )?;
+        let rows = stmt.query_map([], |row| {
+            Ok(IdTagRow {
+                id_tag: row.get(0)?,
+                status: row.get(1)?,
+                expiry_date: row.get(2)?,
+                parent_id_tag: row.get(3)?,
+                first_seen: row.get(4)?,
+                last_seen: row.get(5)?,
+            })
+        })?;
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+
+    fn save_transaction(&self, transaction: &TransactionRow) -> Result<()> {
+        self.conn().execute(
+            "INSERT OR REPLACE INTO transactions (transaction_id, station_id, connector_id, id_tag,
+                 meter_start, started_at, last_meter_value, last_meter_at, status, meter_stop,
+                 stopped_at, stop_reason, flag, peak_power_w)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14)",
+            params![
+                transaction.transaction_id,
+                transaction.station_id,
+                transaction.connector_id,
+                transaction.id_tag,
+                transaction.meter_start,
+                transaction.started_at,
+                transaction.last_meter_value,
+                transaction.last_meter_at,
+                transaction.status,
+                transaction.meter_stop,
+                transaction.stopped_at,
+                transaction.stop_reason,
+                transaction.flag,
+                transaction.peak_power_w,
+            ],
+        )?;
+        Ok(())
+    }
+
+    fn transactions(&self) -> Result<Vec<TransactionRow>> {
+        let conn = self.conn();
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {TRANSACTION_COLUMNS} FROM transactions ORDER BY transaction_id"
+        ))?;
+        let rows = stmt.query_map([], transaction_row)?;
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+
+    fn transaction(&self, transaction_id: i32) -> Result<Option<TransactionRow>> {
+        Ok(self
+            .conn()
+            .query_row(
+                &format!(
+                    "SELECT {TRANSACTION_COLUMNS} FROM transactions WHERE transaction_id = ?1"
+                ),
+                [transaction_id],
+                transaction_row,
+            )
+            .optional()?)
+    }
+
+    fn save_meter_readings(&self, readings: &[MeterReading]) -> Result<usize> {
+        let mut conn = self.conn();
+        let tx = conn.transaction()?;
+        let mut inserted = 0;
+        {
+            let mut stmt = tx.prepare(
+                "INSERT OR IGNORE INTO meter_values (station_id, connector_id, transaction_id,
+                     timestamp, measurand, phase, location, context, format, unit, value)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+            )?;
+            for reading in readings {
+                inserted += stmt.execute(params![
+                    reading.station_id,
+                    reading.connector_id,
+                    reading.transaction_id,
+                    reading.timestamp,
+                    reading.measurand,
+                    reading.phase,
+                    reading.location,
+                    reading.context,
+                    reading.format,
+                    reading.unit,
+                    reading.value,
+                ])?;
+            }
+        }
+        tx.commit()?;
+        Ok(inserted)
+    }
+
+    fn meter_readings(&self, transaction_id: i32) -> Result<Vec<MeterReading>> {
+        let conn = self.conn();
+        let mut stmt = conn.prepare(
+            "SELECT station_id, connector_id, transaction_id, timestamp, measurand, phase,
+                 location, context, format, unit, value
+             FROM meter_values WHERE transaction_id = ?1
+             ORDER BY timestamp, id",
+        )?;
+        let rows = stmt.query_map([transaction_id], |row| {
+            Ok(MeterReading {
+                station_id: row.get(0)?,
+                connector_id: row.get(1)?,
+                transaction_id: row.get(2)?,
+                timestamp: row.get(3)?,
+                measurand: row.get(4)?,
+                phase: row.get(5)?,
+                location: row.get(6)?,
+                context: row.get(7)?,
+                format: row.get(8)?,
+                unit: row.get(9)?,
+                value: row.get(10)?,
+            })
+        })?;
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+
+    fn save_command(&self, command: &CommandRow) -> Result<()> {
+        self.conn().execute(
+            "INSERT OR REPLACE INTO commands (command_id, station_id, action, request, status,
+                 response, created_at, completed_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+            params![
+                command.command_id,
+                command.station_id,
+                command.action,
+                command.request,
+                command.status.as_str(),
+                command.response,
+                command.created_at,
+                command.completed_at,
+            ],
+        )?;
+        Ok(())
+    }
+
+    fn command(&self, command_id: &str) -> Result<Option<CommandRow>> {
+        let row = self
+            .conn()
+            .query_row(
+                &format!("SELECT {COMMAND_COLUMNS} FROM commands WHERE command_id = ?1"),
+                [command_id],
+                raw_command_row,
+            )
+            .optional()?;
+        row.map(command_row).transpose()
+    }
+
+    fn commands(&self, station_id: &str, limit: usize) -> Result<Vec<CommandRow>> {
+        let conn = self.conn();
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {COMMAND_COLUMNS} FROM commands WHERE station_id = ?1
+             ORDER BY created_at DESC LIMIT ?2"
+        ))?;
+        let rows = stmt
+            .query_map(params![station_id, limit as i64], raw_command_row)?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        rows.into_iter().map(command_row).collect()
+    }
+
+    fn save_webhook(&self, webhook: &WebhookRow) -> Result<()> {
+        self.conn().execute(
+            "INSERT OR REPLACE INTO webhooks (webhook_id, url, events, secret, created_at)
+             VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![
+                webhook.webhook_id,
+                webhook.url,
+                webhook.events.join(","),
+                webhook.secret,
+                webhook.created_at,
+            ],
+        )?;
+        Ok(())
+    }
+
+    fn webhooks(&self) -> Result<Vec<WebhookRow>> {
+        let conn = self.conn();
+        let mut stmt = conn.prepare(
+            "SELECT webhook_id, url, events, secret, created_at
+             FROM webhooks ORDER BY webhook_id",
+        )?;
+        let rows = stmt.query_map([], |row| {
+            let events: String = row.get(2)?;
+            Ok(WebhookRow {
+                webhook_id: row.get(0)?,
+                url: row.get(1)?,
+                events: events
+                    .split(',')
+                    .filter(|event| !event.is_empty())
+                    .map(str::to_string)
+                    .collect(),
+                secret: row.get(3)?,
+                created_at: row.get(4)?,
+            })
+        })?;
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+
+    fn delete_webhook(&self, webhook_id: &str) -> Result<()> {
+        let mut conn = self.conn();
+        let tx = conn.transaction()?;
+        tx.execute(
+            "DELETE FROM webhook_deliveries WHERE webhook_id = ?1",
+            [webhook_id],
+        )?;
+        tx.execute("DELETE FROM webhooks WHERE webhook_id = ?1", [webhook_id])?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    fn save_webhook_delivery(&self, delivery: &WebhookDeliveryRow) -> Result<()> {
+        self.conn().execute(
+            "INSERT OR REPLACE INTO webhook_deliveries (delivery_id, webhook_id, event, payload,
+                 status, attempts, next_attempt_at, last_attempt_at, response_status, last_error,
+                 created_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+            params![
+                delivery.delivery_id,
+                delivery.webhook_id,
+                delivery.event,
+                delivery.payload,
+                delivery.status.as_str(),
+                delivery.attempts,
+                delivery.next_attempt_at,
+                delivery.last_attempt_at,
+                delivery.response_status,
+                delivery.last_error,
+                delivery.created_at,
+            ],
+        )?;
+        Ok(())
+    }
+
+    fn webhook_deliveries(
+        &self,
+        webhook_id: &str,
+        limit: usize,
+    ) -> Result<Vec<WebhookDeliveryRow>> {
+        let conn = self.conn();
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {DELIVERY_COLUMNS} FROM webhook_deliveries WHERE webhook_id = ?1
+             ORDER BY created_at DESC, rowid DESC LIMIT ?2"
+        ))?;
+        let rows = stmt
+            .query_map(params![webhook_id, limit as i64], raw_delivery_row)?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        rows.into_iter().map(delivery_row).collect()
+    }
+
+    fn pending_webhook_deliveries(&self) -> Result<Vec<WebhookDeliveryRow>> {
+        let conn = self.conn();
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {DELIVERY_COLUMNS} FROM webhook_deliveries WHERE status = ?1
+             ORDER BY created_at, rowid"
+        ))?;
+        let rows = stmt
+            .query_map([DeliveryStatus::Pending.as_str()], raw_delivery_row)?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        rows.into_iter().map(delivery_row).collect()
+    }
+
+    fn prune_webhook_deliveries(&self, webhook_id: &str, keep: usize) -> Result<usize> {
+        Ok(self.conn().execute(
+            "DELETE FROM webhook_deliveries WHERE webhook_id = ?1 AND status != ?2
+             AND rowid NOT IN (
+                 SELECT rowid FROM webhook_deliveries WHERE webhook_id = ?1 AND status != ?2
+                 ORDER BY created_at DESC, rowid DESC LIMIT ?3
+             )",
+            params![webhook_id, DeliveryStatus::Pending.as_str(), keep as i64],
+        )?)
+    }
+
+    fn save_api_token(&self, token: &ApiTokenRow) -> Result<()> {
+        let mut conn = self.conn();
+        let tx = conn.transaction()?;
+        tx.execute(
+            "INSERT OR REPLACE INTO api_tokens (token_id, name, token_hash, prefix, scopes,
+                 created_at, last_used_at, use_count, revoked_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+            params![
+                token.token_id,
+                token.name,
+                token.token_hash,
+                token.prefix,
+                token.scopes.join(","),
+                token.created_at,
+                token.last_used_at,
+                token.use_count as i64,
+                token.revoked_at,
+            ],
+        )?;
+        tx.execute(
+            "DELETE FROM api_token_stations WHERE token_id = ?1",
+            [&token.token_id],
+        )?;
+        for station_id in token.station_ids.iter().flatten() {
+            tx.execute(
+                "INSERT OR IGNORE INTO api_token_stations (token_id, station_id) VALUES (?1, ?2)",
+                params![token.token_id, station_id],
+            )?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    fn api_tokens(&self) -> Result<Vec<ApiTokenRow>> {
+        let conn = self.conn();
+        let mut stations: HashMap<String, Vec<String>> = HashMap::new();
+        let mut stmt = conn.prepare(
+            "SELECT token_id, station_id FROM api_token_stations ORDER BY token_id, station_id",
+        )?;
+        let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        for row in rows {
+            let (token_id, station_id): (String, String) = row?;
+            stations.entry(token_id).or_default().push(station_id);
+        }
+
+        let mut stmt = conn.prepare(
+            "SELECT token_id, name, token_hash, prefix, scopes, created_at, last_used_at,
+                 use_count, revoked_at
+             FROM api_tokens ORDER BY token_id",
+        )?;
+        let rows = stmt.query_map([], |row| {
+            let token_id: String = row.get(0)?;
+            let scopes: String = row.get(4)?;
+            let use_count: i64 = row.get(7)?;
+            Ok(ApiTokenRow {
+                station_ids: stations.remove(&token_id),
+                token_id,
+                name: row.get(1)?,
+                token_hash: row.get(2)?,
+                prefix: row.get(3)?,
+                scopes: scopes
+                    .split(',')
+                    .filter(|scope| !scope.is_empty())
+                    .map(str::to_string)
+                    .collect(),
+                created_at: row.get(5)?,
+                last_used_at: row.get(6)?,
+                use_count: use_count as u64,
+                revoked_at: row.get(8)?,
+            })
+        })?;
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+
+    fn save_user(&self, user: &UserRow) -> Result<()> {
+        let mut conn = self.conn();
+        let tx = conn.transaction()?;
+        tx.execute(
+            "INSERT INTO users (user_id, username, password_hash, role, created_at)
+             VALUES (?1, ?2, ?3, ?4, ?5)
+             ON CONFLICT (user_id) DO UPDATE SET username = excluded.username,
+                 password_hash = excluded.password_hash, role = excluded.role",
+            params![
+                user.user_id,
+                user.username,
+                user.password_hash,
+                user.role,
+                user.created_at,
+            ],
+        )?;
+        tx.execute(
+            "DELETE FROM user_id_tags WHERE user_id = ?1",
+            [&user.user_id],
+        )?;
+        for id_tag in &user.id_tags {
+            tx.execute(
+                "INSERT OR REPLACE INTO user_id_tags (id_tag, user_id) VALUES (?1, ?2)",
+                params![id_tag, user.user_id],
+            )?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    fn users(&self) -> Result<Vec<UserRow>> {
+        let conn = self.conn();
+        let mut id_tags: HashMap<String, Vec<String>> = HashMap::new();
+        let mut stmt = conn.prepare("SELECT user_id, id_tag FROM user_id_tags ORDER BY id_tag")?;
+        let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        for row in rows {
+            let (user_id, id_tag): (String, String) = row?;
+            id_tags.entry(user_id).or_default().push(id_tag);
+        }
+
+        let mut stmt = conn.prepare(
+            "SELECT user_id, username, password_hash, role, created_at
+             FROM users ORDER BY user_id",
+        )?;
+        let rows = stmt.query_map([], |row| {
+            let user_id: String = row.get(0)?;
+            Ok(UserRow {
+                id_tags: id_tags.remove(&user_id).unwrap_or_default(),
+                user_id,
+                username: row.get(1)?,
+                password_hash: row.get(2)?,
+                role: row.get(3)?,
+                created_at: row.get(4)?,
+            })
+        })?;
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+
+    fn delete_user(&self, user_id: &str) -> Result<()> {
+        let mut conn = self.conn();
+        let tx = conn.transaction()?;
+        tx.execute("DELETE FROM user_sessions WHERE user_id = ?1", [user_id])?;
+        tx.execute("DELETE FROM user_id_tags WHERE user_id = ?1", [user_id])?;
+        tx.execute("DELETE FROM users WHERE user_id = ?1", [user_id])?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    fn save_user_session(&self, session: &UserSessionRow) -> Result<()> {
+        self.conn().execute(
+            "INSERT OR REPLACE INTO user_sessions (session_hash, user_id, created_at, expires_at)
+             VALUES (?1, ?2, ?3, ?4)",
+            params![
+                session.session_hash,
+                session.user_id,
+                session.created_at,
+                session.expires_at,
+            ],
+        )?;
+        Ok(())
+    }
+
+    fn user_sessions(&self) -> Result<Vec<UserSessionRow>> {
+        let conn = self.conn();
+        let mut stmt = conn.prepare(
+            "SELECT session_hash, user_id, created_at, expires_at
+             FROM user_sessions ORDER BY expires_at, session_hash",
+        )?;
+        let rows = stmt.query_map([], |row| {
+            Ok(UserSessionRow {
+                session_hash: row.get(0)?,
+                user_id: row.get(1)?,
+                created_at: row.get(2)?,
+                expires_at: row.get(3)?,
+            })
+        })?;
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+
+    fn append_audit_record(&self, record: &AuditRow) -> Result<i64> {
+        let conn = self.conn();
+        conn.execute(
+            "INSERT INTO audit_log (at, actor_kind, actor_id, actor_name, remote_addr, action,
+                 station_id, request, response, outcome)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+            params![
+                record.at,
+                record.actor_kind,
+                record.actor_id,
+                record.actor_name,
+                record.remote_addr,
+                record.action,
+                record.station_id,
+                record.request,
+                record.response,
+                record.outcome,
+            ],
+        )?;
+        Ok(conn.last_insert_rowid())
+    }
+
+    fn audit_records(&self, filter: &AuditFilter, limit: usize) -> Result<Vec<AuditRow>> {
+        let conn = self.conn();
+        let mut stmt = conn.prepare(
+            "SELECT audit_id, at, actor_kind, actor_id, actor_name, remote_addr, action,
+                 station_id, request, response, outcome
+             FROM audit_log
+             WHERE (?1 IS NULL OR station_id = ?1)
+               AND (?2 IS NULL OR actor_id = ?2)
+               AND (?3 IS NULL OR action = ?3)
+               AND (?4 IS NULL OR at >= ?4)
+               AND (?5 IS NULL OR at < ?5)
+               AND (?6 IS NULL OR audit_id < ?6)
+             ORDER BY audit_id DESC LIMIT ?7",
+        )?;
+        let rows = stmt.query_map(
+            params![
+                filter.station_id,
+                filter.actor_id,
+                filter.action,
+                filter.from,
+                filter.to,
+                filter.before_id,
+                limit as i64,
+            ],
+            |row| {
+                Ok(AuditRow {
+                    audit_id: row.get(0)?,
+                    at: row.get(1)?,
+                    actor_kind: row.get(2)?,
+                    actor_id: row.get(3)?,
+                    actor_name: row.get(4)?,
+                    remote_addr: row.get(5)?,
+                    action: row.get(6)?,
+                    station_id: row.get(7)?,
+                    request: row.get(8)?,
+                    response: row.get(9)?,
+                    outcome: row.get(10)?,
+                })
+            },
+        )?;
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+
+    fn prune_audit_records(&self, before: DateTime<Utc>) -> Result<usize> {
+        Ok(self
+            .conn()
+            .execute("DELETE FROM audit_log WHERE at < ?1", [before])?)
+    }
+
+    fn delete_user_session(&self, session_hash: &str) -> Result<()> {
+        self.conn().execute(
+            "DELETE FROM user_sessions WHERE session_hash = ?1",
+            [session_hash],
+        )?;
+        Ok(())
+    }
+}
+
+const TRANSACTION_COLUMNS: &str = "transaction_id, station_id, connector_id, id_tag, meter_start,
+    started_at, last_meter_value, last_meter_at, status, meter_stop, stopped_at, stop_reason, flag,
+    peak_power_w";
+
+const COMMAND_COLUMNS: &str =
+    "command_id, station_id, action, request, status, response, created_at, completed_at";
+
+const DELIVERY_COLUMNS: &str = "delivery_id, webhook_id, event, payload, status, attempts,
+    next_attempt_at, last_attempt_at, response_status, last_error, created_at";
+
+fn connector_row(row: &Row<'_>) -> rusqlite::Result<ConnectorRow> {
+    Ok(ConnectorRow {
+        station_id: row.get(0)?,
+        connector_id: row.get(1)?,
+        status: row.get(2)?,
+        error_code: row.get(3)?,
+        info: row.get(4)?,
+        timestamp: row.get(5)?,
+        updated_at: row.get(6)?,
+    })
+}
+
+fn transaction_row(row: &Row<'_>) -> rusqlite::Result<TransactionRow> {
+    Ok(TransactionRow {
+        transaction_id: row.get(0)?,
+        station_id: row.get(1)?,
+        connector_id: row.get(2)?,
+        id_tag: row.get(3)?,
+        meter_start: row.get(4)?,
+        started_at: row.get(5)?,
+        last_meter_value: row.get(6)?,
+        last_meter_at: row.get(7)?,
+        peak_power_w: row.get(13)?,
+        status: row.get(8)?,
+        meter_stop: row.get(9)?,
+        stopped_at: row.get(10)?,
+        stop_reason: row.get(11)?,
+        flag: row.get(12)?,
+    })
+}
+
+/// A command row with its status still as stored text.
+fn raw_command_row(row: &Row<'_>) -> rusqlite::Result<(CommandRow, String)> {
+    Ok((
+        CommandRow {
+            command_id: row.get(0)?,
+            station_id: row.get(1)?,
+            action: row.get(2)?,
+            request: row.get(3)?,
+            status: CommandStatus::Pending,
+            response: row.get(5)?,
+            created_at: row.get(6)?,
+            completed_at: row.get(7)?,
+        },
+        row.get(4)?,
+    ))
+}
+
+fn command_row((mut command, status): (CommandRow, String)) -> Result<CommandRow> {
+    command.status = status.parse::<CommandStatus>()?;
+    Ok(command)
+}
+
+/// A delivery row with its status still as stored text.
+fn raw_delivery_row(row: &Row<'_>) -> rusqlite::Result<(WebhookDeliveryRow, String)> {
+    Ok((
+        WebhookDeliveryRow {
+            delivery_id: row.get(0)?,
+            webhook_id: row.get(1)?,
+            event: row.get(2)?,
+            payload: row.get(3)?,
+            status: DeliveryStatus::Pending,
+            attempts: row.get(5)?,
+            next_attempt_at: row.get(6)?,
+            last_attempt_at: row.get(7)?,
+            response_status: row.get(8)?,
+            last_error: row.get(9)?,
+            created_at: row.get(10)?,
+        },
+        row.get(4)?,
+    ))
+}
+
+fn delivery_row(
+    (mut delivery, status): (WebhookDeliveryRow, String),
+) -> Result<WebhookDeliveryRow> {
+    delivery.status = status.parse::<DeliveryStatus>()?;
+    Ok(delivery)
+}
@@ -0,0 +1,565 @@
+//! One suite, run against every `Storage` backend.
+
+use chrono::{DateTime, TimeZone, Utc};
+use storage::{
+    ApiTokenRow, AuditFilter, AuditRow, CommandRow, CommandStatus, ConnectorRow, DeliveryStatus,
+    IdTagRow, MemoryStore, MeterReading, SqliteStore, StationMetadataRow, StationRow, Storage,
+    StorageError, TransactionRow, UserRow, UserSessionRow, WebhookDeliveryRow, WebhookRow,
+};
+
+type TestResult = Result<(), StorageError>;
+
+fn at(minute: u32) -> DateTime<Utc> {
+    Utc.with_ymd_and_hms(2024, 5, 1, 9, minute, 0).unwrap()
+}
+
+fn station(station_id: &str) -> StationRow {
+    StationRow {
+        station_id: station_id.to_string(),
+        registration_status: "Pending".to_string(),
+        vendor: "PlugCo".to_string(),
+        model: "Wallbox 11".to_string(),
+        serial_number: Some("SN-1".to_string()),
+        firmware_version: None,
+        first_seen: at(0),
+        last_boot: at(0),
+    }
+}
+
+fn connector(station_id: &str, connector_id: u32, status: &str, minute: u32) -> ConnectorRow {
+    ConnectorRow {
+        station_id: station_id.to_string(),
+        connector_id,
+        status: status.to_string(),
+        error_code: "NoError".to_string(),
+        info: None,
+        timestamp: Some(at(minute)),
+        updated_at: at(minute),
+    }
+}
+
+fn transaction(transaction_id: i32) -> TransactionRow {
+    TransactionRow {
+        transaction_id,
+        station_id: "cp-1".to_string(),
+        connector_id: 1,
+        id_tag: "TAG-1".to_string(),
+        meter_start: 1000,
+        started_at: at(0),
+        last_meter_value: None,
+        last_meter_at: None,
+        peak_power_w: None,
+        status: "active".to_string(),
+        meter_stop: None,
+        stopped_at: None,
+        stop_reason: None,
+        flag: None,
+    }
+}
+
+fn reading(transaction_id: Option<i32>, minute: u32, measurand: &str, value: &str) -> MeterReading {
+    MeterReading {
+        station_id: "cp-1".to_string(),
+        connector_id: 1,
+        transaction_id,
+        timestamp: at(minute),
+        measurand: measurand.to_string(),
+        phase: None,
+        location: None,
+        context: None,
+        format: None,
+        unit: None,
+        value: value.to_string(),
+    }
+}
+
+fn command(command_id: &str, station_id: &str, minute: u32) -> CommandRow {
+    CommandRow {
+        command_id: command_id.to_string(),
+        station_id: station_id.to_string(),
+        action: "TriggerMessage".to_string(),
+        request: r#"{"requestedMessage":"StatusNotification"}"#.to_string(),
+        status: CommandStatus::Pending,
+        response: None,
+        created_at: at(minute),
+        completed_at: None,
+    }
+}
+
+fn webhook(webhook_id: &str) -> WebhookRow {
+    WebhookRow {
+        webhook_id: webhook_id.to_string(),
+        url: "http://192.168.1.20:8123/api/webhook/plughome".to_string(),
+        events: vec![
+            "session_finished".to_string(),
+            "station_offline".to_string(),
+        ],
+        secret: "s3cret".to_string(),
+        created_at: at(0),
+    }
+}
+
+fn delivery(delivery_id: &str, webhook_id: &str, minute: u32) -> WebhookDeliveryRow {
+    WebhookDeliveryRow {
+        delivery_id: delivery_id.to_string(),
+        webhook_id: webhook_id.to_string(),
+        event: "station_offline".to_string(),
+        payload: r#"{"event":"station_offline"}"#.to_string(),
+        status: DeliveryStatus::Pending,
+        attempts: 0,
+        next_attempt_at: Some(at(minute)),
+        last_attempt_at: None,
+        response_status: None,
+        last_error: None,
+        created_at: at(minute),
+    }
+}
+
+fn api_token(token_id: &str, station_ids: Option<Vec<String>>) -> ApiTokenRow {
+    ApiTokenRow {
+        token_id: token_id.to_string(),
+        name: format!("{token_id} dashboard"),
+        token_hash: format!("{token_id}-hash"),
+        prefix: "ph_1a2b3c".to_string(),
+        scopes: vec!["read".to_string(), "control".to_string()],
+        station_ids,
+        created_at: at(0),
+        last_used_at: None,
+        use_count: 0,
+        revoked_at: None,
+    }
+}
+
+fn user(user_id: &str, id_tags: &[&str]) -> UserRow {
+    UserRow {
+        user_id: user_id.to_string(),
+        username: format!("{user_id}-name"),
+        password_hash: "$argon2id$v=19$m=19456,t=2,p=1$c2FsdA$aGFzaA".to_string(),
+        role: "member".to_string(),
+        id_tags: id_tags.iter().map(|tag| tag.to_string()).collect(),
+        created_at: at(0),
+    }
+}
+
+fn user_session(session_hash: &str, user_id: &str, expires_minute: u32) -> UserSessionRow {
+    UserSessionRow {
+        session_hash: session_hash.to_string(),
+        user_id: user_id.to_string(),
+        created_at: at(0),
+        expires_at: at(expires_minute),
+    }
+}
+
+fn audit_row(minute: u32, station_id: Option<&str>, action: &str) -> AuditRow {
+    AuditRow {
+        audit_id: 0,
+        at: at(minute),
+        actor_kind: "user".to_string(),
+        actor_id: "user-1".to_string(),
+        actor_name: "alice".to_string(),
+        remote_addr: Some("192.0.2.7:51234".to_string()),
+        action: action.to_string(),
+        station_id: station_id.map(str::to_string),
+        request: Some(r#"{"idTag":"TAG-1"}"#.to_string()),
+        response: Some(r#"{"status":"Accepted"}"#.to_string()),
+        outcome: "ok".to_string(),
+    }
+}
+
+fn stations_are_upserted_and_ordered(store: &dyn Storage) -> TestResult {
+    store.save_station(&station("cp-2"))?;
+    store.save_station(&station("cp-1"))?;
+    let mut approved = station("cp-2");
+    approved.registration_status = "Accepted".to_string();
+    approved.firmware_version = Some("2.0".to_string());
+    store.save_station(&approved)?;
+
+    assert_eq!(store.stations()?, vec![station("cp-1"), approved]);
+    Ok(())
+}
+
+fn station_metadata_is_replaced(store: &dyn Storage) -> TestResult {
+    let metadata = StationMetadataRow {
+        station_id: "cp-1".to_string(),
+        name: Some("Garage".to_string()),
+        location: None,
+        notes: Some("Left socket".to_string()),
+        heartbeat_interval: Some(60),
+        updated_at: at(0),
+    };
+    store.save_station_metadata(&metadata)?;
+    let renamed = StationMetadataRow {
+        name: Some("Driveway".to_string()),
+        notes: None,
+        heartbeat_interval: None,
+        updated_at: at(1),
+        ..metadata
+    };
+    store.save_station_metadata(&renamed)?;
+
+    assert_eq!(store.station_metadata()?, vec![renamed]);
+    Ok(())
+}
+
+fn connector_status_keeps_latest_and_history(store: &dyn Storage) -> TestResult {
+    store.save_connector_status(&connector("cp-1", 2, "Available", 0))?;
+    store.save_connector_status(&connector("cp-1", 1, "Preparing", 1))?;
+    store.save_connector_status(&connector("cp-1", 1, "Charging", 2))?;
+    store.save_connector_status(&connector("cp-2", 1, "Faulted", 3))?;
+
+    assert_eq!(
+        store.connectors()?,
+        vec![
+            connector("cp-1", 1, "Charging", 2),
+            connector("cp-1", 2, "Available", 0),
+            connector("cp-2", 1, "Faulted", 3),
+        ]
+    );
+    assert_eq!(
+        store.status_history("cp-1", 2)?,
+        vec![
+            connector("cp-1", 1, "Charging", 2),
+            connector("cp-1", 1, "Preparing", 1),
+        ]
+    );
+    assert!(store.status_history("cp-3", 10)?.is_empty());
+    Ok(())
+}
+
+fn id_tags_keep_first_seen(store: &dyn Storage) -> TestResult {
+    let tag = IdTagRow {
+        id_tag: "TAG-1".to_string(),
+        status: "Accepted".to_string(),
+        expiry_date: None,
+        parent_id_tag: None,
+        first_seen: at(0),
+        last_seen: at(0),
+    };
+    store.save_id_tag(&tag)?;
+    store.save_id_tag(&IdTagRow {
+        status: "Blocked".to_string(),
+        first_seen: at(5),
+        last_seen: at(5),
+        ..tag.clone()
+    })?;
+
+    assert_eq!(
+        store.id_tags()?,
+        vec![IdTagRow {
+            status: "Blocked".to_string(),
+            last_seen: at(5),
+            ..tag
+        }]
+    );
+    Ok(())
+}
+
+fn transactions_are_replaced_and_ordered(store: &dyn Storage) -> TestResult {
+    store.save_transaction(&transaction(9))?;
+    store.save_transaction(&transaction(3))?;
+    let stopped = TransactionRow {
+        status: "completed".to_string(),
+        last_meter_value: Some(4200.5),
+        last_meter_at: Some(at(30)),
+        peak_power_w: Some(7400.0),
+        meter_stop: Some(4201),
+        stopped_at: Some(at(31)),
+        stop_reason: Some("EVDisconnected".to_string()),
+        ..transaction(9)
+    };
+    store.save_transaction(&stopped)?;
+
+    assert_eq!(store.transaction(9)?, Some(stopped.clone()));
+    assert_eq!(store.transaction(4)?, None);
+    assert_eq!(store.transactions()?, vec![transaction(3), stopped]);
+    Ok(())
+}
+
+fn meter_readings_are_deduplicated_and_ordered(store: &dyn Storage) -> TestResult {
+    let energy = "Energy.Active.Import.Register";
+    let batch = vec![
+        reading(Some(1), 20, energy, "1400"),
+        reading(Some(1), 10, energy, "1200"),
+        reading(Some(1), 10, "Power.Active.Import", "7000"),
+        reading(None, 10, energy, "1200"),
+    ];
+    assert_eq!(store.save_meter_readings(&batch)?, 4);
+    assert_eq!(store.save_meter_readings(&batch)?, 0);
+
+    let mut on_phase = reading(Some(1), 10, energy, "400");
+    on_phase.phase = Some("L1".to_string());
+    assert_eq!(store.save_meter_readings(&[on_phase.clone()])?, 1);
+
+    assert_eq!(
+        store.meter_readings(1)?,
+        vec![
+            reading(Some(1), 10, energy, "1200"),
+            reading(Some(1), 10, "Power.Active.Import", "7000"),
+            on_phase,
+            reading(Some(1), 20, energy, "1400"),
+        ]
+    );
+    assert!(store.meter_readings(2)?.is_empty());
+    Ok(())
+}
+
+fn commands_are_updated_and_listed_newest_first(store: &dyn Storage) -> TestResult {
+    store.save_command(&command("a", "cp-1", 0))?;
+    store.save_command(&command("b", "cp-1", 5))?;
+    store.save_command(&command("c", "cp-1", 10))?;
+    store.save_command(&command("d", "cp-2", 15))?;
+    let answered = CommandRow {
+        status: CommandStatus::Rejected,
+        response: Some(r#"{"code":"NotSupported"}"#.to_string()),
+        completed_at: Some(at(6)),
+        ..command("b", "cp-1", 5)
+    };
+    store.save_command(&answered)?;
+
+    assert_eq!(store.command("b")?, Some(answered.clone()));
+    assert_eq!(store.command("missing")?, None);
+    assert_eq!(
+        store.commands("cp-1", 2)?,
+        vec![command("c", "cp-1", 10), answered]
+    );
+    Ok(())
+}
+
+fn webhooks_keep_deliveries_until_deleted(store: &dyn Storage) -> TestResult {
+    store.save_webhook(&webhook("wh-b"))?;
+    store.save_webhook(&webhook("wh-a"))?;
+    let ids: Vec<_> = store
+        .webhooks()?
+        .into_iter()
+        .map(|webhook| webhook.webhook_id)
+        .collect();
+    assert_eq!(ids, ["wh-a", "wh-b"]);
+    assert_eq!(store.webhooks()?[0], webhook("wh-a"));
+
+    store.save_webhook_delivery(&delivery("d1", "wh-a", 0))?;
+    store.save_webhook_delivery(&delivery("d2", "wh-a", 5))?;
+    store.save_webhook_delivery(&delivery("d3", "wh-b", 10))?;
+    let retried = WebhookDeliveryRow {
+        status: DeliveryStatus::Failed,
+        attempts: 3,
+        next_attempt_at: None,
+        last_attempt_at: Some(at(7)),
+        response_status: Some(503),
+        last_error: Some("HTTP 503".to_string()),
+        ..delivery("d1", "wh-a", 0)
+    };
+    store.save_webhook_delivery(&retried)?;
+
+    assert_eq!(
+        store.webhook_deliveries("wh-a", 10)?,
+        vec![delivery("d2", "wh-a", 5), retried]
+    );
+    assert_eq!(store.webhook_deliveries("wh-a", 1)?.len(), 1);
+    assert_eq!(
+        store.pending_webhook_deliveries()?,
+        vec![delivery("d2", "wh-a", 5), delivery("d3", "wh-b", 10)]
+    );
+
+    store.delete_webhook("wh-a")?;
+    assert_eq!(store.webhooks()?, vec![webhook("wh-b")]);
+    assert!(store.webhook_deliveries("wh-a", 10)?.is_empty());
+    assert_eq!(
+        store.pending_webhook_deliveries()?,
+        vec![delivery("d3", "wh-b", 10)]
+    );
+
+    // Pruning drops the oldest finished deliveries and keeps pending ones.
+    for minute in 20..24 {
+        let sent = WebhookDeliveryRow {
+            status: DeliveryStatus::Delivered,
+            next_attempt_at: None,
+            ..delivery(&format!("s{minute}"), "wh-b", minute)
+        };
+        store.save_webhook_delivery(&sent)?;
+    }
+    assert_eq!(store.prune_webhook_deliveries("wh-b", 2)?, 2);
+    let ids: Vec<_> = store
+        .webhook_deliveries("wh-b", 10)?
+        .into_iter()
+        .map(|delivery| delivery.delivery_id)
+        .collect();
+    assert_eq!(ids, ["s23", "s22", "d3"]);
+    assert_eq!(store.prune_webhook_deliveries("wh-b", 2)?, 0);
+    Ok(())
+}
+
+fn api_tokens_keep_scopes_stations_and_usage(store: &dyn Storage) -> TestResult {
+    let garage = Some(vec!["cp-1".to_string(), "cp-2".to_string()]);
+    store.save_api_token(&api_token("tok-b", garage.clone()))?;
+    store.save_api_token(&api_token("tok-a", None))?;
+    assert_eq!(
+        store.api_tokens()?,
+        vec![api_token("tok-a", None), api_token("tok-b", garage)]
+    );
+
+    let used = ApiTokenRow {
+        last_used_at: Some(at(5)),
+        use_count: 3,
+        revoked_at: Some(at(9)),
+        ..api_token("tok-b", Some(vec!["cp-2".to_string()]))
+    };
+    store.save_api_token(&used)?;
+    assert_eq!(store.api_tokens()?, vec![api_token("tok-a", None), used]);
+    Ok(())
+}
+
+fn users_own_id_tags_and_sessions(store: &dyn Storage) -> TestResult {
+    store.save_user(&user("user-b", &["TAG-2", "TAG-1"]))?;
+    store.save_user(&user("user-a", &[]))?;
+    assert_eq!(
+        store.users()?,
+        vec![user("user-a", &[]), user("user-b", &["TAG-1", "TAG-2"])]
+    );
+
+    // Saving a tag with another user moves it there.
+    let owner = UserRow {
+        role: "owner".to_string(),
+        ..user("user-a", &["TAG-2"])
+    };
+    store.save_user(&owner)?;
+    assert_eq!(
+        store.users()?,
+        vec![owner.clone(), user("user-b", &["TAG-1"])]
+    );
+
+    store.save_user_session(&user_session("s-late", "user-b", 30))?;
+    store.save_user_session(&user_session("s-early", "user-b", 10))?;
+    store.save_user_session(&user_session("s-owner", "user-a", 20))?;
+    assert_eq!(
+        store.user_sessions()?,
+        vec![
+            user_session("s-early", "user-b", 10),
+            user_session("s-owner", "user-a", 20),
+            user_session("s-late", "user-b", 30),
+        ]
+    );
+    store.delete_user_session("s-owner")?;
+    store.delete_user("user-b")?;
+    assert_eq!(store.users()?, vec![owner]);
+    assert!(store.user_sessions()?.is_empty());
+    Ok(())
+}
+
+fn audit_log_is_appended_filtered_and_pruned(store: &dyn Storage) -> TestResult {
+    let first = store.append_audit_record(&audit_row(1, Some("cp-1"), "RemoteStartTransaction"))?;
+    let second = store.append_audit_record(&audit_row(2, None, "CreateUser"))?;
+    let third = store.append_audit_record(&audit_row(3, Some("cp-1"), "Reset"))?;
+    assert!(first < second && second < third);
+
+    let all = store.audit_records(&AuditFilter::default(), 10)?;
+    let ids: Vec<i64> = all.iter().map(|record| record.audit_id).collect();
+    assert_eq!(ids, [third, second, first]);
+    assert_eq!(
+        all[2],
+        AuditRow {
+            audit_id: first,
+            ..audit_row(1, Some("cp-1"), "RemoteStartTransaction")
+        }
+    );
+
+    let station = AuditFilter {
+        station_id: Some("cp-1".to_string()),
+        ..AuditFilter::default()
+    };
+    let ids: Vec<i64> = store
+        .audit_records(&station, 1)?
+        .iter()
+        .map(|record| record.audit_id)
+        .collect();
+    assert_eq!(ids, [third]);
+    let older = AuditFilter {
+        before_id: Some(third),
+        ..station
+    };
+    let ids: Vec<i64> = store
+        .audit_records(&older, 10)?
+        .iter()
+        .map(|record| record.audit_id)
+        .collect();
+    assert_eq!(ids, [first]);
+    let window = AuditFilter {
+        action: Some("CreateUser".to_string()),
+        from: Some(at(2)),
+        to: Some(at(3)),
+        ..AuditFilter::default()
+    };
+    assert_eq!(store.audit_records(&window, 10)?.len(), 1);
+
+    assert_eq!(store.prune_audit_records(at(2))?, 1);
+    assert_eq!(store.audit_records(&AuditFilter::default(), 10)?.len(), 2);
+    // Ids keep increasing after pruning.
+    let fourth = store.append_audit_record(&audit_row(4, None, "RevokeToken"))?;
+    assert!(fourth > third);
+    Ok(())
+}
+
+macro_rules! conformance_suite {
+    ($backend:ident, $store:expr) => {
+        mod $backend {
+            use super::*;
+
+            #[test]
+            fn stations() -> TestResult {
+                stations_are_upserted_and_ordered(&$store)
+            }
+
+            #[test]
+            fn station_metadata() -> TestResult {
+                station_metadata_is_replaced(&$store)
+            }
+
+            #[test]
+            fn connectors() -> TestResult {
+                connector_status_keeps_latest_and_history(&$store)
+            }
+
+            #[test]
+            fn id_tags() -> TestResult {
+                id_tags_keep_first_seen(&$store)
+            }
+
+            #[test]
+            fn transactions() -> TestResult {
+                transactions_are_replaced_and_ordered(&$store)
+            }
+
+            #[test]
+            fn meter_readings() -> TestResult {
+                meter_readings_are_deduplicated_and_ordered(&$store)
+            }
+
+            #[test]
+            fn commands() -> TestResult {
+                commands_are_updated_and_listed_newest_first(&$store)
+            }
+
+            #[test]
+            fn webhooks() -> TestResult {
+                webhooks_keep_deliveries_until_deleted(&$store)
+            }
+
+            #[test]
+            fn api_tokens() -> TestResult {
+                api_tokens_keep_scopes_stations_and_usage(&$store)
+            }
+
+            #[test]
+            fn users() -> TestResult {
+                users_own_id_tags_and_sessions(&$store)
+            }
+
+            #[test]
+            fn audit_log() -> TestResult {
+                audit_log_is_appended_filtered_and_pruned(&$store)
+            }
+        }
+    };
+}
+
+conformance_suite!(memory, MemoryStore::new());
+conformance_suite!(sqlite, SqliteStore::open_in_memory()?);
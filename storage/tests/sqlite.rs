@@ -0,0 +1,55 @@
+use std::path::PathBuf;
+
+use chrono::{TimeZone, Utc};
+use rusqlite::Connection;
+use storage::{SqliteStore, StationRow, Storage, StorageError, migrations};
+
+fn temp_db(name: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("plughome-{name}-{}.db", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    path
+}
+
+fn station(station_id: &str) -> StationRow {
+    StationRow {
+        station_id: station_id.to_string(),
+        registration_status: "Accepted".to_string(),
+        vendor: "PlugCo".to_string(),
+        model: "Wallbox 11".to_string(),
+        serial_number: Some("SN-1".to_string()),
+        firmware_version: None,
+        first_seen: Utc.with_ymd_and_hms(2024, 5, 1, 8, 0, 0).unwrap(),
+        last_boot: Utc.with_ymd_and_hms(2024, 5, 1, 8, 0, 0).unwrap(),
+    }
+}
+
+#[test]
+fn migrations_run_once_and_data_survives_reopen() -> Result<(), StorageError> {
+    let path = temp_db("reopen");
+    {
+        let store = SqliteStore::open(&path)?;
+        assert_eq!(store.schema_version()?, migrations::latest_version());
+        store.save_station(&station("cp-1"))?;
+    }
+
+    let store = SqliteStore::open(&path)?;
+    assert_eq!(store.schema_version()?, migrations::latest_version());
+    assert_eq!(store.stations()?, vec![station("cp-1")]);
+    drop(store);
+
+    // A database written by a newer build is left alone.
+    let conn = Connection::open(&path)?;
+    conn.pragma_update(
+        None,
+        "user_version",
+        migrations::latest_version() as i64 + 1,
+    )?;
+    drop(conn);
+    assert!(matches!(
+        SqliteStore::open(&path),
+        Err(StorageError::SchemaTooNew { .. })
+    ));
+
+    let _ = std::fs::remove_file(&path);
+    Ok(())
+}